@@ -0,0 +1,9 @@
+-- Schema baseline. `schema_version` is kept at the latest applied migration on startup;
+-- `instance_id` is generated once and identifies this database.
+CREATE TABLE meta (
+    key TEXT PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL
+);
+
+INSERT INTO meta (key, value) VALUES ('schema_version', '1');
+INSERT INTO meta (key, value) VALUES ('instance_id', lower(hex(randomblob(16))));
@@ -0,0 +1,12 @@
+-- One row per visitor, page and day. `visitor` hashes address and user agent under a
+-- salt that is never stored, so rows can't be tied back to a person or across days.
+CREATE TABLE page_views (
+    id INTEGER PRIMARY KEY,
+    slug TEXT NOT NULL,
+    day TEXT NOT NULL,
+    visitor TEXT NOT NULL,
+    referrer_host TEXT,
+    UNIQUE (slug, day, visitor)
+);
+
+CREATE INDEX page_views_day ON page_views (day);
@@ -0,0 +1,14 @@
+-- Comments posted with the native comments provider. `body` is the markdown as posted,
+-- `html` its rendering with raw HTML escaped; only approved comments are shown.
+CREATE TABLE comments (
+    id INTEGER PRIMARY KEY,
+    slug TEXT NOT NULL,
+    name TEXT NOT NULL,
+    email TEXT,
+    body TEXT NOT NULL,
+    html TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved')),
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+);
+
+CREATE INDEX comments_slug ON comments (slug, status, created_at);
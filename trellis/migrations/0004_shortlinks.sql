@@ -0,0 +1,9 @@
+-- Short codes redirecting to a page from /s/{code}. Codes compare case-sensitively.
+CREATE TABLE shortlinks (
+    code TEXT PRIMARY KEY NOT NULL,
+    slug TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+    hits INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX shortlinks_slug ON shortlinks (slug);
@@ -0,0 +1,15 @@
+-- What each page's cached HTML was rendered from. Pages are served from the cache while
+-- these hashes match the current source, theme, config and pipeline. Rows appear on each
+-- page's first render after this migration.
+CREATE TABLE render_cache (
+    slug TEXT PRIMARY KEY NOT NULL,
+    source_hash TEXT NOT NULL,
+    source_mtime_ms INTEGER NOT NULL,
+    source_len INTEGER NOT NULL,
+    theme_hash TEXT NOT NULL,
+    config_hash TEXT NOT NULL,
+    pipeline TEXT NOT NULL,
+    render_ms INTEGER NOT NULL,
+    html_path TEXT NOT NULL,
+    rendered_at TEXT NOT NULL
+);
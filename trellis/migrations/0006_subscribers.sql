@@ -0,0 +1,10 @@
+-- Newsletter subscribers. A row starts `pending` and is confirmed by following the link
+-- carrying `token` that is mailed to `email`.
+CREATE TABLE subscribers (
+    id INTEGER PRIMARY KEY,
+    email TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    token TEXT NOT NULL UNIQUE,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'confirmed')),
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+    confirmed_at TEXT
+);
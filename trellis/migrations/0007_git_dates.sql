@@ -0,0 +1,8 @@
+-- Created and updated dates of content files from their git history, read at commit
+-- `head`. Every row shares one head; the table is rewritten when HEAD moves.
+CREATE TABLE git_dates (
+    path TEXT PRIMARY KEY NOT NULL,
+    head TEXT NOT NULL,
+    created TEXT NOT NULL,
+    updated TEXT NOT NULL
+);
@@ -0,0 +1,51 @@
+use std::env;
+use std::future::{Ready, ready};
+
+use actix_web::dev::Payload;
+use actix_web::error::{ErrorForbidden, ErrorUnauthorized};
+use actix_web::http::header;
+use actix_web::{FromRequest, HttpRequest};
+
+use crate::trellis::trellis_engine;
+
+/// A handler argument that admits only requests carrying `Authorization: Bearer <token>`,
+/// the token being read from the variable `server.admin_token_env` names. Without a
+/// configured token every request is refused with 403.
+pub struct Admin;
+
+impl FromRequest for Admin {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(authorize(req))
+    }
+}
+
+fn authorize(req: &HttpRequest) -> Result<Admin, actix_web::Error> {
+    let engine = trellis_engine();
+    let expected = engine
+        .config
+        .server
+        .admin_token_env
+        .as_deref()
+        .and_then(|name| env::var(name).ok())
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| ErrorForbidden("no admin token configured"))?;
+    let given = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if constant_time_eq(given.as_bytes(), expected.as_bytes()) {
+        Ok(Admin)
+    } else {
+        Err(ErrorUnauthorized("invalid admin token"))
+    }
+}
+
+/// Compares without stopping at the first difference, so timing reveals only the length.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{SecondsFormat, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::trellis::config::ContentFormat;
+use crate::trellis::content_index::{extract_links, generate_content_index, link_graph};
+use crate::trellis::display_names::display_name;
+use crate::trellis::graph_data::{LinkReport, link_report};
+use crate::trellis::plugins::frontmatter::FrontMatter;
+use crate::trellis::plugins::traits::Transformer;
+use crate::trellis::slugs;
+use crate::trellis::types::{Page, slug_from_path};
+use crate::trellis::{SiteConfig, TrellisEngine, trellis_engine};
+
+/// Render every page, the content index, and static files into the cache root.
+pub fn build() -> Result<Vec<String>> {
+    let engine = trellis_engine();
+    generate_content_index(
+        engine.content_root(),
+        engine.cache_root(),
+        engine.ignore_matcher(),
+        engine.registry(),
+        &engine.config,
+    )?;
+    engine.sync_static().context("copying static directories")?;
+    let slugs = engine.prebuild_all()?;
+    info!(
+        "Built {} pages into {}",
+        slugs.len(),
+        engine.cache_root().display()
+    );
+    Ok(slugs)
+}
+
+/// Validate the config and content tree, returning every problem found. Orphaned pages
+/// and dead ends are logged as a report rather than counted as problems; `tag_links`
+/// counts pages sharing a tag as linked.
+pub fn check(tag_links: bool) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let config_path = SiteConfig::config_path();
+    let config = if config_path.exists() {
+        let (config, issues) = SiteConfig::diagnose(&config_path);
+        let strict = SiteConfig::load_options().strict;
+        for issue in issues {
+            if issue.is_error() || strict {
+                problems.push(format!("{}: {issue}", config_path.display()));
+            } else {
+                warn!("{}: {issue}", config_path.display());
+            }
+        }
+        match config {
+            Some(config) if problems.is_empty() => config,
+            _ => return problems,
+        }
+    } else {
+        match SiteConfig::try_load() {
+            Ok(config) => config,
+            Err(err) => {
+                problems.push(format!("{err:#}"));
+                return problems;
+            }
+        }
+    };
+    let engine = match TrellisEngine::new(config) {
+        Ok(engine) => engine,
+        Err(err) => {
+            problems.push(format!("{err:#}"));
+            return problems;
+        }
+    };
+
+    let content_root = engine.content_root();
+    let mut sources: BTreeMap<String, PathBuf> = BTreeMap::new();
+    // Links are resolved once every slug is known, so wikilinks can match by name.
+    let mut bodies: Vec<(String, String)> = Vec::new();
+
+    for entry in WalkDir::new(content_root)
+        .into_iter()
+        .filter_entry(|e| !engine.is_ignored_path(e.path()))
+        .filter_map(Result::ok)
+        .filter(|e| e.path().is_file())
+    {
+        let Some(format) = engine.config.content.format_for(entry.path()) else {
+            continue;
+        };
+
+        let slug = slug_from_path(entry.path(), content_root);
+        if let Some(existing) = sources.get(&slug) {
+            problems.push(format!(
+                "slug collision for {slug}: {} and {}",
+                existing.display(),
+                entry.path().display()
+            ));
+            continue;
+        }
+        sources.insert(slug.clone(), entry.path().to_path_buf());
+        if format == ContentFormat::Plain {
+            continue;
+        }
+
+        let content = match fs::read_to_string(entry.path()) {
+            Ok(content) => content,
+            Err(err) => {
+                problems.push(format!("{}: {err}", entry.path().display()));
+                continue;
+            }
+        };
+
+        let page = Page::new(slug.clone(), entry.path().to_path_buf(), content);
+        match FrontMatter.transform(page) {
+            Ok(page) => {
+                let reasons = engine.registry().exclusions(&page);
+                if !reasons.is_empty() {
+                    info!("{slug} is left out: {}", reasons.join("; "));
+                }
+                bodies.push((slug.clone(), page.content));
+            }
+            Err(err) => problems.push(format!(
+                "{}: invalid frontmatter: {err:#}",
+                entry.path().display()
+            )),
+        }
+    }
+
+    let known: BTreeSet<String> = sources.keys().cloned().collect();
+    let slugs: BTreeSet<&str> = sources.keys().map(String::as_str).collect();
+    for (source, body) in bodies {
+        for link in extract_links(&body, &source, &known) {
+            if !link_resolves(&link, &slugs, &engine) {
+                problems.push(format!("broken link in {source}: {link}"));
+            }
+        }
+    }
+
+    match link_graph(
+        content_root,
+        engine.ignore_matcher(),
+        engine.registry(),
+        &engine.config.content,
+        &engine.config.encryption,
+    ) {
+        Ok(pages) => log_link_report(&link_report(&pages, tag_links)),
+        Err(err) => problems.push(format!("{err:#}")),
+    }
+
+    problems
+}
+
+fn log_link_report(report: &LinkReport) {
+    info!("{} orphaned page(s)", report.orphans.len());
+    for slug in &report.orphans {
+        info!("  orphan: {slug}");
+    }
+    info!("{} dead end(s)", report.dead_ends.len());
+    for slug in &report.dead_ends {
+        info!("  dead end: {slug}");
+    }
+}
+
+fn link_resolves(link: &str, slugs: &BTreeSet<&str>, engine: &TrellisEngine) -> bool {
+    if link == "." || link.contains(':') {
+        return true;
+    }
+    let index = format!("{link}/index");
+    slugs.contains(link)
+        || slugs.contains(index.as_str())
+        || engine.content_root().join(link).exists()
+}
+
+#[derive(Serialize)]
+struct NewNoteFrontmatter<'a> {
+    title: &'a str,
+    created: String,
+    tags: Vec<String>,
+}
+
+/// Scaffold a markdown note for `slug` under the content root, where the notes API would
+/// write it. The slug is checked as the API checks it and shaped by `content.slugify`.
+pub fn new_note(slug: &str, title: Option<&str>) -> Result<PathBuf> {
+    let engine = trellis_engine();
+    let content = &engine.config.content;
+    let slug = slug.trim_matches('/');
+    slugs::validate(slug, content).map_err(|reason| anyhow!("invalid slug {slug:?}: {reason}"))?;
+    let slug = slugs::slugify(slug);
+
+    let path = engine.note_path(&slug);
+    if engine.is_ignored_path(&path) {
+        bail!("{slug} is under an ignored directory");
+    }
+    if path.exists() {
+        bail!("{} already exists", path.display());
+    }
+
+    let default_title = display_name(content, engine.content_root(), &slug, None);
+    let frontmatter = NewNoteFrontmatter {
+        title: title.unwrap_or(&default_title),
+        created: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        tags: Vec::new(),
+    };
+    let yaml = serde_yaml::to_string(&frontmatter)?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("creating directory {}", parent.display()))?;
+    }
+    fs::write(&path, format!("---\n{yaml}---\n\n"))
+        .with_context(|| format!("writing {}", path.display()))?;
+    Ok(path)
+}
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::rate_limit::RateLimiter;
+use crate::trellis::config::NativeCommentsConfig;
+
+/// Longest email address accepted (RFC 5321).
+const MAX_EMAIL_CHARS: usize = 254;
+
+/// A comment as posted, by JSON or by the page's form.
+#[derive(Debug, Deserialize)]
+pub struct NewComment {
+    pub name: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    pub body: String,
+    /// Honeypot: the form hides this field from people, so anything in it came from a bot.
+    #[serde(default)]
+    pub website: Option<String>,
+}
+
+impl NewComment {
+    pub fn is_spam(&self) -> bool {
+        self.website
+            .as_deref()
+            .is_some_and(|site| !site.trim().is_empty())
+    }
+
+    /// Trim the fields and check them against the configured limits; the error is meant
+    /// for the poster.
+    pub fn validate(self, cfg: &NativeCommentsConfig) -> Result<NewComment, String> {
+        let name = self.name.split_whitespace().collect::<Vec<_>>().join(" ");
+        let email = self
+            .email
+            .map(|email| email.trim().to_string())
+            .filter(|email| !email.is_empty());
+        let body = self.body.trim().to_string();
+
+        if name.is_empty() {
+            return Err("a name is required".into());
+        }
+        if name.chars().count() > cfg.max_name_chars {
+            return Err(format!(
+                "names are limited to {} characters",
+                cfg.max_name_chars
+            ));
+        }
+        if email
+            .as_deref()
+            .is_some_and(|email| email.chars().count() > MAX_EMAIL_CHARS || !email.contains('@'))
+        {
+            return Err("the email address is not valid".into());
+        }
+        if body.is_empty() {
+            return Err("the comment is empty".into());
+        }
+        if body.chars().count() > cfg.max_body_chars {
+            return Err(format!(
+                "comments are limited to {} characters",
+                cfg.max_body_chars
+            ));
+        }
+        Ok(NewComment {
+            name,
+            email,
+            body,
+            website: None,
+        })
+    }
+}
+
+/// A comment as shown under a page and returned by `GET /api/comments/{slug}`.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Comment {
+    pub id: i64,
+    pub name: String,
+    /// The posted markdown, rendered with raw HTML escaped.
+    pub html: String,
+    /// UTC, RFC 3339.
+    pub created_at: String,
+    /// The date part of `created_at`, for display.
+    pub day: String,
+}
+
+/// A comment waiting for a moderator, with what only moderators see.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct PendingComment {
+    pub id: i64,
+    pub slug: String,
+    pub name: String,
+    pub email: Option<String>,
+    pub body: String,
+    pub html: String,
+    pub created_at: String,
+}
+
+/// Whether `address` may post another comment, counting this attempt.
+pub fn allow(address: &str, per_hour: u32) -> bool {
+    static RECENT: RateLimiter = RateLimiter::new();
+    RECENT.allow(address, per_hour)
+}
+
+/// Markdown from a visitor as HTML. Raw HTML and images stay literal text, link targets
+/// with unsafe protocols are dropped, and links are marked `nofollow ugc`.
+fn render_body(body: &str) -> String {
+    let mut options = markdown::Options::gfm();
+    options.parse.constructs.html_flow = false;
+    options.parse.constructs.html_text = false;
+    options.parse.constructs.label_start_image = false;
+    let html = markdown::to_html_with_options(body, &options).unwrap_or_default();
+    ammonia::Builder::default()
+        .link_rel(Some("nofollow ugc"))
+        .clean(&html)
+        .to_string()
+}
+
+/// Store a validated comment on `slug`, approved straight away unless `pending`.
+pub async fn insert(
+    pool: &SqlitePool,
+    slug: &str,
+    comment: &NewComment,
+    pending: bool,
+) -> anyhow::Result<Comment> {
+    Ok(sqlx::query_as(
+        "INSERT INTO comments (slug, name, email, body, html, status) \
+         VALUES (?, ?, ?, ?, ?, ?) \
+         RETURNING id, name, html, created_at, substr(created_at, 1, 10) AS day",
+    )
+    .bind(slug)
+    .bind(&comment.name)
+    .bind(&comment.email)
+    .bind(&comment.body)
+    .bind(render_body(&comment.body))
+    .bind(if pending { "pending" } else { "approved" })
+    .fetch_one(pool)
+    .await?)
+}
+
+/// Approved comments on `slug`, oldest first.
+pub async fn approved(pool: &SqlitePool, slug: &str) -> anyhow::Result<Vec<Comment>> {
+    Ok(sqlx::query_as(
+        "SELECT id, name, html, created_at, substr(created_at, 1, 10) AS day FROM comments \
+         WHERE slug = ? AND status = 'approved' ORDER BY created_at, id",
+    )
+    .bind(slug)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Every comment awaiting approval, oldest first.
+pub async fn pending(pool: &SqlitePool) -> anyhow::Result<Vec<PendingComment>> {
+    Ok(sqlx::query_as(
+        "SELECT id, slug, name, email, body, html, created_at FROM comments \
+         WHERE status = 'pending' ORDER BY created_at, id",
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Publish comment `id`; false when there is no such comment.
+pub async fn approve(pool: &SqlitePool, id: i64) -> anyhow::Result<bool> {
+    let result = sqlx::query("UPDATE comments SET status = 'approved' WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Remove comment `id`, approved or not; false when there is no such comment.
+pub async fn delete(pool: &SqlitePool, id: i64) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM comments WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,92 @@
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Context;
+use log::info;
+use sqlx::migrate::Migrator;
+use sqlx::sqlite::{
+    SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteSynchronous,
+};
+
+/// Migrations in `trellis/migrations/`, compiled into the binary.
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Open (creating if needed) the sqlite database at `DATABASE_URL`, or `trellis.db` in the
+/// working directory, and bring its schema up to date.
+pub async fn get_db_pool() -> anyhow::Result<SqlitePool> {
+    // Override database path via .env
+    let url = env::var("DATABASE_URL").unwrap_or_else(|_| {
+        let mut path = env::current_dir().expect("cwd");
+        path.push("trellis.db");
+        path.display().to_string()
+    });
+
+    let uri = format!("sqlite://{}", &url);
+    let db_path = PathBuf::from(&url);
+
+    // Ensure the directories exist; sqlite creates the file itself.
+    if let Some(parent) = db_path.parent() {
+        if !parent.exists() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("creating {}", parent.display()))?;
+        }
+    }
+
+    // WAL lets readers run alongside the single writer; NORMAL sync is safe under WAL.
+    let options = SqliteConnectOptions::from_str(&uri)
+        .with_context(|| format!("parsing database URL {uri}"))?
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .synchronous(SqliteSynchronous::Normal)
+        .foreign_keys(true)
+        .busy_timeout(Duration::from_secs(5));
+
+    info!("Loading Trellis sqlite database: {}", &uri);
+    let pool = SqlitePoolOptions::new()
+        .connect_with(options)
+        .await
+        .with_context(|| format!("opening sqlite database {}", db_path.display()))?;
+
+    MIGRATOR
+        .run(&pool)
+        .await
+        .with_context(|| format!("migrating sqlite database {}", db_path.display()))?;
+    let version = MIGRATOR.iter().map(|m| m.version).max().unwrap_or(0);
+    sqlx::query("UPDATE meta SET value = ? WHERE key = 'schema_version'")
+        .bind(version.to_string())
+        .execute(&pool)
+        .await
+        .context("recording the schema version")?;
+    info!("Database schema at version {version}");
+    Ok(pool)
+}
+
+/// A private in-memory database with the current schema, for tests.
+#[cfg(feature = "test-support")]
+pub async fn memory_pool() -> anyhow::Result<SqlitePool> {
+    let options = SqliteConnectOptions::from_str("sqlite::memory:")?.foreign_keys(true);
+    // Every in-memory connection is its own database, so keep exactly one, forever.
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(options)
+        .await
+        .context("opening an in-memory database")?;
+    MIGRATOR
+        .run(&pool)
+        .await
+        .context("migrating the in-memory database")?;
+    Ok(pool)
+}
+
+/// The schema version recorded in `meta`, for the health endpoint.
+pub async fn schema_version(pool: &SqlitePool) -> anyhow::Result<i64> {
+    let value: String = sqlx::query_scalar("SELECT value FROM meta WHERE key = 'schema_version'")
+        .fetch_one(pool)
+        .await?;
+    Ok(value.parse()?)
+}
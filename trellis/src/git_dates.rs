@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{info, warn};
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+
+use crate::trellis::git_history::{self, FileDates};
+
+/// Dates read at one commit, to replace what the table holds.
+type Snapshot = (String, HashMap<String, FileDates>);
+
+#[derive(sqlx::FromRow)]
+struct Row {
+    path: String,
+    head: String,
+    created: String,
+    updated: String,
+}
+
+/// Load the git dates recorded at the last HEAD from `pool` and save each fresh read back
+/// to it. Must run before pages are prebuilt, or the first build reads the whole log.
+pub async fn start(pool: SqlitePool) -> anyhow::Result<()> {
+    let rows: Vec<Row> = sqlx::query_as("SELECT path, head, created, updated FROM git_dates")
+        .fetch_all(&pool)
+        .await?;
+    if let Some(head) = rows.first().map(|row| row.head.clone()) {
+        info!("Git dates: {} files recorded", rows.len());
+        let files = rows
+            .into_iter()
+            .filter(|row| row.head == head)
+            .filter_map(|row| {
+                let dates = FileDates {
+                    created: parse(&row.created)?,
+                    updated: parse(&row.updated)?,
+                };
+                Some((row.path, dates))
+            });
+        git_history::load(head.clone(), files);
+    }
+
+    let (tx, rx) = mpsc::unbounded_channel::<Snapshot>();
+    git_history::set_sink(Box::new(move |head, files| {
+        let _ = tx.send((head.to_string(), files.clone()));
+    }));
+    tokio::spawn(write_snapshots(pool, rx));
+    Ok(())
+}
+
+fn parse(date: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(date)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+async fn write_snapshots(pool: SqlitePool, mut rx: mpsc::UnboundedReceiver<Snapshot>) {
+    while let Some((head, files)) = rx.recv().await {
+        if let Err(err) = replace(&pool, &head, &files).await {
+            warn!("git dates not saved: {err:#}");
+        }
+    }
+}
+
+async fn replace(
+    pool: &SqlitePool,
+    head: &str,
+    files: &HashMap<String, FileDates>,
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM git_dates")
+        .execute(&mut *tx)
+        .await?;
+    for (path, dates) in files {
+        sqlx::query("INSERT INTO git_dates (path, head, created, updated) VALUES (?, ?, ?, ?)")
+            .bind(path)
+            .bind(head)
+            .bind(dates.created.to_rfc3339_opts(SecondsFormat::Secs, true))
+            .bind(dates.updated.to_rfc3339_opts(SecondsFormat::Secs, true))
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
@@ -1,45 +1,109 @@
-use std::sync::{OnceLock, RwLock};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 use std::time::SystemTime;
 
 use actix_files::Files;
-use actix_web::{HttpResponse, HttpResponseBuilder, Responder, get, web};
-use handlebars::Handlebars;
-use log::error;
-use serde::Serialize;
+use actix_web::http::header;
+use actix_web::middleware::DefaultHeaders;
+use actix_web::{
+    Either, HttpRequest, HttpResponse, HttpResponseBuilder, Responder, delete, get, post, put, web,
+};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
 use serde_json;
 use serde_json::json;
 use serde_yaml;
+use sqlx::SqlitePool;
 use std::fs;
 
-use crate::trellis::bundler::{InlineScripts, ScriptNeeds, inline_scripts};
-use crate::trellis::config::google_font_href;
-use crate::trellis::content_index::{extract_links, generate_content_index};
-use crate::trellis::layout::LayoutComponent;
-use crate::trellis::plugins::frontmatter::FrontMatter;
-use crate::trellis::plugins::traits::Transformer;
-use crate::trellis::styles::compiled_styles;
-use crate::trellis::types::{Page, PageMetadata, RenderedPage, slug_from_path};
+use crate::auth::Admin;
+use crate::comments::{self, NewComment};
+use crate::db::schema_version;
+use crate::newsletter::{self, Subscription};
+use crate::notes;
+use crate::page_views;
+use crate::shortlinks;
+use crate::telemetry;
+use crate::templates::Templates;
+use crate::trellis::analytics::analytics_head;
+use crate::trellis::bundler::{
+    BundleSettings, PageScripts, ScriptNeeds, failed_bundles, page_scripts,
+};
+use crate::trellis::cache;
+use crate::trellis::config::{
+    ContentConfig, ContentFormat, CspMode, EncryptionConfig, JsContentType, JsLoadTime,
+    google_font_href,
+};
+use crate::trellis::content_index::{
+    LinkMention, content_slugs, generate_content_index, link_graph, link_mentions,
+};
+use crate::trellis::csp::{self, PageSources};
+use crate::trellis::display_names::display_name;
+use crate::trellis::error::RenderError;
+use crate::trellis::fonts;
+use crate::trellis::graph_data::link_report;
+use crate::trellis::html::escape_html;
+use crate::trellis::ignore::IgnoreMatcher;
+use crate::trellis::layout::{
+    BacklinkContext, BannerConfig, BannerStyle, CommentsConfig, CommentsProvider,
+    ContentMetaConfig, FlexConfig, FlexItem, FooterLinks, LayoutComponent, PageLayout,
+    PrevNextConfig, PrevNextOrder, RecentNotesConfig, SharedLayout, SubscribeConfig,
+    is_safe_css_value,
+};
+use crate::trellis::plugins::PluginRegistry;
+use crate::trellis::plugins::callouts::render_md;
+use crate::trellis::plugins::frontmatter::nav_hidden;
+use crate::trellis::plugins::headings::collect_headings;
+use crate::trellis::search::{SearchHit, SearchIndex, markdown_text};
+use crate::trellis::slugs;
+use crate::trellis::socials;
+use crate::trellis::styles::{
+    PageStyles, ScssError, page_styles, sanitize_page_css, styles_degraded,
+};
+use crate::trellis::toc::{TocContext, toc_context};
+use crate::trellis::types::{
+    Page, PageMetadata, RenderedPage, cmp_page_dates, count_words, format_date, page_date,
+    reading_minutes, slug_from_path,
+};
 use crate::trellis::{SiteConfig, TrellisEngine, trellis_engine};
 
-use chrono::{Datelike, Utc};
-use std::collections::BTreeMap;
+use chrono::{DateTime, Datelike, Utc};
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
 pub fn config(conf: &mut web::ServiceConfig) {
-    let api_scope = web::scope("/api").service(healthcheck_handler);
+    let api_scope = web::scope("/api")
+        .service(healthcheck_handler)
+        .service(search_api_handler)
+        .service(orphans_api_handler)
+        .service(explorer_api_handler)
+        .service(stats_api_handler)
+        .service(comments_api_handler)
+        .service(post_comment_handler)
+        .service(pending_comments_handler)
+        .service(approve_comment_handler)
+        .service(delete_comment_handler)
+        .service(subscribe_handler)
+        .service(confirm_subscription_handler)
+        .service(subscribers_csv_handler)
+        .service(shortlinks_api_handler)
+        .service(create_shortlink_handler)
+        .service(cache_api_handler)
+        .service(get_note_handler)
+        .service(put_note_handler)
+        .service(delete_note_handler);
 
     // Prebuild markdown to cache and collect slugs
     let engine = trellis_engine();
-    if let Err(err) = generate_content_index(
-        engine.content_root(),
-        engine.cache_root(),
-        &engine.config.configuration.ignore_patterns,
-    ) {
-        error!("failed to generate content index: {err}");
+    regenerate_content_index(&engine);
+    if let Err(err) = engine.sync_static() {
+        error!("failed to copy static directories: {err}");
     }
 
     let mut slugs: Vec<String> = engine.prebuild_all().unwrap_or_default();
+    let pruned = engine.prune_render_cache();
+    if pruned > 0 {
+        info!("removed {pruned} cached pages whose source is gone");
+    }
     if let Ok(mut cached) = engine.cached_slugs() {
         slugs.append(&mut cached);
     }
@@ -48,10 +112,12 @@ pub fn config(conf: &mut web::ServiceConfig) {
 
     let site_scope = web::scope("")
         .service(feed_handler)
+        .service(search_page_handler)
+        .service(shortlink_handler)
         .route(
             "/tags/{tag}",
             web::get().to(
-                move |path: web::Path<String>, hb: web::Data<Handlebars<'static>>| async move {
+                move |path: web::Path<String>, hb: web::Data<Templates>| async move {
                     tags_handler(path, hb).await
                 },
             ),
@@ -60,14 +126,29 @@ pub fn config(conf: &mut web::ServiceConfig) {
         .route(
             "/{slug:.*}",
             web::get().to(
-                move |path: web::Path<String>, hb: web::Data<Handlebars<'static>>| {
+                move |path: web::Path<String>,
+                      hb: web::Data<Templates>,
+                      req: HttpRequest,
+                      pool: web::Data<SqlitePool>| {
                     let slug = path.into_inner();
-                    async move { render_slug(slug, hb).await }
+                    async move { render_slug(slug, hb, req, pool).await }
                 },
             ),
         );
 
     conf.service(api_scope);
+    // Bundle file names carry a content hash, so a given URL never changes.
+    conf.service(
+        web::scope("/static/js")
+            .wrap(
+                DefaultHeaders::new()
+                    .add((header::CACHE_CONTROL, "public, max-age=31536000, immutable")),
+            )
+            .service(Files::new(
+                "",
+                engine.cache_root().join("static").join("js"),
+            )),
+    );
     conf.service(
         Files::new("/static", engine.cache_root().join("static"))
             .prefer_utf8(true)
@@ -76,12 +157,604 @@ pub fn config(conf: &mut web::ServiceConfig) {
     conf.service(site_scope);
 }
 
+#[derive(Deserialize)]
+struct StatsQuery {
+    slug: Option<String>,
+}
+
+/// View totals per page and the last 30 days, for the site or one `slug`.
+#[get("/stats")]
+async fn stats_api_handler(
+    _admin: Admin,
+    query: web::Query<StatsQuery>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    if !trellis_engine().config.page_views.enabled {
+        return HttpResponse::NotFound().finish();
+    }
+    match page_views::stats(&pool, query.slug.as_deref()).await {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(err) => {
+            error!("reading page view stats: {err:#}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Approved comments on a page showing native comments, oldest first.
+#[get("/comments/{slug:.*}")]
+async fn comments_api_handler(
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let slug = path.into_inner();
+    let slug = slug.trim_matches('/');
+    if !native_comments_on(&trellis_engine(), slug) {
+        return HttpResponse::NotFound().finish();
+    }
+    match comments::approved(&pool, slug).await {
+        Ok(comments) => HttpResponse::Ok().json(comments),
+        Err(err) => {
+            error!("reading comments on {slug}: {err:#}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Post a comment as JSON, or from the page's form, which is redirected back to the page.
+/// Over-limit fields answer 400 and over-eager addresses 429; a filled honeypot gets the
+/// usual reply but nothing is stored.
+#[post("/comments/{slug:.*}")]
+async fn post_comment_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: Either<web::Json<NewComment>, web::Form<NewComment>>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let engine = trellis_engine();
+    let slug = path.into_inner();
+    let slug = slug.trim_matches('/');
+    if !native_comments_on(&engine, slug) {
+        return HttpResponse::NotFound().finish();
+    }
+    let (comment, from_form) = match body {
+        Either::Left(json) => (json.into_inner(), false),
+        Either::Right(form) => (form.into_inner(), true),
+    };
+    let cfg = &engine.config.native_comments;
+    if !comments::allow(
+        &page_views::client_address(&req, &engine.config.server),
+        cfg.per_hour,
+    ) {
+        return HttpResponse::TooManyRequests()
+            .json(json!({ "error": "too many comments; try again later" }));
+    }
+
+    let posted = if comment.is_spam() {
+        debug!("honeypot filled; dropping a comment on {slug}");
+        None
+    } else {
+        let comment = match comment.validate(cfg) {
+            Ok(comment) => comment,
+            Err(reason) => return HttpResponse::BadRequest().json(json!({ "error": reason })),
+        };
+        match comments::insert(&pool, slug, &comment, cfg.require_approval).await {
+            Ok(posted) => Some(posted),
+            Err(err) => {
+                error!("storing a comment on {slug}: {err:#}");
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    };
+
+    if from_form {
+        return HttpResponse::SeeOther()
+            .insert_header((header::LOCATION, format!("/{slug}#comments")))
+            .finish();
+    }
+    match posted.filter(|_| !cfg.require_approval) {
+        Some(comment) => {
+            HttpResponse::Created().json(json!({ "status": "approved", "comment": comment }))
+        }
+        None => HttpResponse::Accepted().json(json!({ "status": "pending" })),
+    }
+}
+
+/// Comments awaiting approval across the site, oldest first.
+#[get("/moderation/comments")]
+async fn pending_comments_handler(_admin: Admin, pool: web::Data<SqlitePool>) -> impl Responder {
+    match comments::pending(&pool).await {
+        Ok(pending) => HttpResponse::Ok().json(pending),
+        Err(err) => {
+            error!("reading pending comments: {err:#}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/moderation/comments/{id}/approve")]
+async fn approve_comment_handler(
+    _admin: Admin,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let id = path.into_inner();
+    match comments::approve(&pool, id).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(err) => {
+            error!("approving comment {id}: {err:#}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[delete("/moderation/comments/{id}")]
+async fn delete_comment_handler(
+    _admin: Admin,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let id = path.into_inner();
+    match comments::delete(&pool, id).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(err) => {
+            error!("deleting comment {id}: {err:#}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Why a sign-up failed, as JSON or, for a plain form post, as a page.
+fn subscribe_error(
+    engine: &TrellisEngine,
+    hb: web::Data<Templates>,
+    from_form: bool,
+    mut builder: HttpResponseBuilder,
+    reason: &str,
+) -> HttpResponse {
+    if from_form {
+        let html = format!("<p>Not subscribed: {}.</p>", escape_html(reason));
+        return status_page(engine, hb, "subscribe", "Not subscribed", &html, builder);
+    }
+    builder.json(json!({ "error": reason }))
+}
+
+/// Sign an address up for the newsletter. It is mailed a confirmation link, or confirmed
+/// at once when no mail transport is configured. Addresses already signed up get the same
+/// reply as new ones, so the form cannot be used to find out who subscribes.
+#[post("/subscribe")]
+async fn subscribe_handler(
+    req: HttpRequest,
+    hb: web::Data<Templates>,
+    body: Either<web::Json<Subscription>, web::Form<Subscription>>,
+    pool: web::Data<SqlitePool>,
+) -> HttpResponse {
+    let engine = trellis_engine();
+    let cfg = &engine.config.newsletter;
+    if !cfg.enabled {
+        return HttpResponse::NotFound().finish();
+    }
+    let (signup, from_form) = match body {
+        Either::Left(json) => (json.into_inner(), false),
+        Either::Right(form) => (form.into_inner(), true),
+    };
+    let fail =
+        |builder, reason: &str| subscribe_error(&engine, hb.clone(), from_form, builder, reason);
+    if !newsletter::allow(
+        &page_views::client_address(&req, &engine.config.server),
+        cfg.per_hour,
+    ) {
+        return fail(
+            HttpResponse::TooManyRequests(),
+            "too many sign-ups from here; try again later",
+        );
+    }
+
+    let mailed = newsletter::can_mail(cfg);
+    if signup.is_spam() {
+        debug!("honeypot filled; dropping a newsletter sign-up");
+    } else {
+        let email = match signup.validate() {
+            Ok(email) => email,
+            Err(reason) => return fail(HttpResponse::BadRequest(), &reason),
+        };
+        if !mailed {
+            warn!("newsletter has no sendmail_command or smtp; confirming a sign-up unmailed");
+        }
+        let token = match newsletter::subscribe(&pool, &email, !mailed).await {
+            Ok(token) => token,
+            Err(err) => {
+                error!("storing a newsletter sign-up: {err:#}");
+                return HttpResponse::InternalServerError().finish();
+            }
+        };
+        if let Some(token) = token {
+            let Some(link) = subscribe_confirm_url(&engine, &token) else {
+                error!("newsletter mail needs configuration.base_url; not mailing {email}");
+                return fail(
+                    HttpResponse::InternalServerError(),
+                    "the confirmation mail could not be sent; please try again later",
+                );
+            };
+            let site = &engine.config.configuration.page_title;
+            if let Err(err) = newsletter::send_confirmation(cfg, site, &email, &link).await {
+                error!("mailing a newsletter confirmation to {email}: {err:#}");
+                return fail(
+                    HttpResponse::BadGateway(),
+                    "the confirmation mail could not be sent; please try again later",
+                );
+            }
+        }
+    }
+
+    let (status, title, message) = if mailed {
+        (
+            "pending",
+            "Almost there",
+            "Check your inbox for a link to confirm your subscription.",
+        )
+    } else {
+        ("confirmed", "Subscribed", "You're subscribed. Thanks!")
+    };
+    if from_form {
+        let html = format!("<p>{message}</p>");
+        return status_page(&engine, hb, "subscribe", title, &html, HttpResponse::Ok());
+    }
+    let mut builder = if mailed {
+        HttpResponse::Accepted()
+    } else {
+        HttpResponse::Ok()
+    };
+    builder.json(json!({ "status": status, "message": message }))
+}
+
+/// Absolute link to the confirmation endpoint, or `None` without `configuration.base_url`.
+/// The request's `Host` is never used: whoever signs up would choose where the token goes.
+fn subscribe_confirm_url(engine: &TrellisEngine, token: &str) -> Option<String> {
+    let url = engine
+        .urls()
+        .absolute(&format!("api/subscribe/confirm?token={token}"));
+    (!url.starts_with('/')).then_some(url)
+}
+
+#[derive(Deserialize)]
+struct ConfirmQuery {
+    #[serde(default)]
+    token: String,
+}
+
+/// Where the mailed link lands: confirms the subscription and says so on a page.
+#[get("/subscribe/confirm")]
+async fn confirm_subscription_handler(
+    query: web::Query<ConfirmQuery>,
+    hb: web::Data<Templates>,
+    pool: web::Data<SqlitePool>,
+) -> HttpResponse {
+    let engine = trellis_engine();
+    if !engine.config.newsletter.enabled {
+        return HttpResponse::NotFound().finish();
+    }
+    let confirmed = if query.token.is_empty() {
+        Ok(false)
+    } else {
+        newsletter::confirm(&pool, &query.token).await
+    };
+    match confirmed {
+        Ok(true) => status_page(
+            &engine,
+            hb,
+            "subscribe",
+            "Subscription confirmed",
+            "<p>Thanks! You'll get new posts by email.</p>",
+            HttpResponse::Ok(),
+        ),
+        Ok(false) => status_page(
+            &engine,
+            hb,
+            "subscribe",
+            "Link not recognised",
+            "<p>This confirmation link is not valid. Try signing up again.</p>",
+            HttpResponse::NotFound(),
+        ),
+        Err(err) => {
+            error!("confirming a newsletter subscription: {err:#}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Confirmed subscribers, as CSV for a mailing tool.
+#[get("/subscribers.csv")]
+async fn subscribers_csv_handler(_admin: Admin, pool: web::Data<SqlitePool>) -> HttpResponse {
+    match newsletter::confirmed_csv(&pool).await {
+        Ok(csv) => HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"subscribers.csv\"",
+            ))
+            .body(csv),
+        Err(err) => {
+            error!("exporting newsletter subscribers: {err:#}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct NewShortlink {
+    slug: String,
+    /// A vanity code; a random one is generated when left out.
+    #[serde(default)]
+    code: Option<String>,
+}
+
+/// Every short link with its target and hit count, newest first.
+#[get("/shortlinks")]
+async fn shortlinks_api_handler(_admin: Admin, pool: web::Data<SqlitePool>) -> impl Responder {
+    match shortlinks::all(&pool).await {
+        Ok(links) => HttpResponse::Ok().json(links),
+        Err(err) => {
+            error!("reading short links: {err:#}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Mint a short link to an existing page. A vanity code that is malformed answers 400 and
+/// one already in use 409.
+#[post("/shortlinks")]
+async fn create_shortlink_handler(
+    _admin: Admin,
+    body: web::Json<NewShortlink>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let engine = trellis_engine();
+    let NewShortlink { slug, code } = body.into_inner();
+    let slug = match slug.trim_matches('/') {
+        "" => "index",
+        slug => slug,
+    };
+    if !engine.page_exists(slug) {
+        return HttpResponse::NotFound().json(json!({ "error": format!("no page at {slug}") }));
+    }
+    let code = code
+        .as_deref()
+        .map(str::trim)
+        .filter(|code| !code.is_empty());
+    if code.is_some_and(|code| !shortlinks::is_valid_code(code)) {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!(
+                "codes are 1-{} letters, digits, '-' or '_'",
+                shortlinks::MAX_CODE_LEN
+            )
+        }));
+    }
+    match shortlinks::create(&pool, slug, code).await {
+        Ok(Some(link)) => HttpResponse::Created().json(json!({
+            "url": engine.urls().absolute(&format!("s/{}", link.code)),
+            "link": link,
+        })),
+        Ok(None) => HttpResponse::Conflict().json(json!({ "error": "code already in use" })),
+        Err(err) => {
+            error!("creating a short link to {slug}: {err:#}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Whether a page's cached HTML is current and, when it isn't, why; with what the last
+/// render recorded.
+#[get("/cache/{slug:.*}")]
+async fn cache_api_handler(_admin: Admin, path: web::Path<String>) -> impl Responder {
+    let engine = trellis_engine();
+    let slug = match path.trim_matches('/') {
+        "" => "index".to_string(),
+        slug => slug.to_string(),
+    };
+    if !engine.page_exists(&slug) {
+        return HttpResponse::NotFound().finish();
+    }
+    match engine.cache_status(&slug) {
+        Ok(status) => HttpResponse::Ok().json(json!({
+            "slug": slug,
+            "fresh": status.is_fresh(),
+            "staleness": status.staleness,
+            "reason": status.staleness.reason(),
+            "recorded": status.recorded,
+        })),
+        Err(err) => {
+            error!("checking the render cache of {slug}: {err:#}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Rebuild the content index after the notes API changed a note. It reads every note, so
+/// it runs on the blocking pool, and one rebuild at a time so the last to finish saw the
+/// last change.
+async fn reindex_notes(engine: Arc<TrellisEngine>) {
+    static REINDEX: Mutex<()> = Mutex::new(());
+    let rebuilt = web::block(move || {
+        let _turn = REINDEX
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        regenerate_content_index(&engine);
+    })
+    .await;
+    if let Err(err) = rebuilt {
+        error!("failed to generate content index: {err}");
+    }
+}
+
+fn regenerate_content_index(engine: &TrellisEngine) {
+    if let Err(err) = generate_content_index(
+        engine.content_root(),
+        engine.cache_root(),
+        engine.ignore_matcher(),
+        engine.registry(),
+        &engine.config,
+    ) {
+        error!("failed to generate content index: {err}");
+    }
+}
+
+/// The note at a notes API slug, or the response refusing it: 400 for a malformed slug
+/// and 403 for one under an ignored directory.
+fn note_file(engine: &TrellisEngine, slug: &str) -> Result<PathBuf, HttpResponse> {
+    if let Err(reason) = slugs::validate(slug, &engine.config.content) {
+        return Err(HttpResponse::BadRequest().json(json!({ "error": reason })));
+    }
+    let file = engine.note_path(slug);
+    if engine.is_ignored_path(&file) {
+        return Err(HttpResponse::Forbidden()
+            .json(json!({ "error": "the slug is under an ignored directory" })));
+    }
+    Ok(file)
+}
+
+/// `If-Match` sent with a notes API request, if any.
+fn if_match(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::IF_MATCH)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// A note's source as written, with its content hash as the `ETag` for `If-Match`.
+#[get("/notes/{slug:.*}")]
+async fn get_note_handler(_admin: Admin, path: web::Path<String>) -> impl Responder {
+    let engine = trellis_engine();
+    let slug = path.trim_matches('/');
+    let file = match note_file(&engine, slug) {
+        Ok(file) => file,
+        Err(response) => return response,
+    };
+    match fs::read(&file) {
+        Ok(bytes) => HttpResponse::Ok()
+            .insert_header((header::ETAG, format!("\"{}\"", notes::content_hash(&bytes))))
+            .content_type("text/markdown; charset=utf-8")
+            .body(bytes),
+        Err(_) => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Create or replace the note at `slug` from markdown (frontmatter included) in the body,
+/// then answer with the rendered page. Writes to one slug are serialized; with `If-Match`
+/// the note must exist and still hash to the given value, or the answer is 412.
+#[put("/notes/{slug:.*}")]
+async fn put_note_handler(
+    _admin: Admin,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Bytes,
+) -> impl Responder {
+    let engine = trellis_engine();
+    let slug = path.trim_matches('/');
+    let file = match note_file(&engine, slug) {
+        Ok(file) => file,
+        Err(response) => return response,
+    };
+    let Ok(markdown) = std::str::from_utf8(&body) else {
+        return HttpResponse::BadRequest().json(json!({ "error": "the body is not UTF-8" }));
+    };
+    if markdown.trim().is_empty() {
+        return HttpResponse::BadRequest().json(json!({ "error": "the note is empty" }));
+    }
+
+    let guard = notes::lock(slug).await;
+    let current = fs::read(&file)
+        .ok()
+        .map(|bytes| notes::content_hash(&bytes));
+    if if_match(&req).is_some_and(|header| !notes::if_match_allows(header, current.as_deref())) {
+        return HttpResponse::PreconditionFailed().finish();
+    }
+    if let Err(err) = cache::write_atomic(&file, markdown.as_bytes(), true) {
+        error!("writing note {}: {err}", file.display());
+        return HttpResponse::InternalServerError().finish();
+    }
+    if let Err(err) = engine.drop_cached(slug) {
+        warn!("removing the cached HTML of {slug}: {err}");
+    }
+    drop(guard);
+    reindex_notes(Arc::clone(&engine)).await;
+    info!("note {slug} written through the API");
+
+    let hash = notes::content_hash(markdown.as_bytes());
+    let mut response = match current {
+        Some(_) => HttpResponse::Ok(),
+        None => HttpResponse::Created(),
+    };
+    response.insert_header((header::ETAG, format!("\"{hash}\"")));
+    match engine.render_page(slug) {
+        Ok(page) => response.json(json!({ "slug": slug, "hash": hash, "page": page })),
+        // Saved, but not published: a draft, say, or markdown the pipeline rejects.
+        Err(err) => response.json(json!({
+            "slug": slug,
+            "hash": hash,
+            "page": null,
+            "error": format!("{err:#}"),
+        })),
+    }
+}
+
+/// Delete the note at `slug` and its cached HTML. `If-Match` works as for `PUT`.
+#[delete("/notes/{slug:.*}")]
+async fn delete_note_handler(
+    _admin: Admin,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> impl Responder {
+    let engine = trellis_engine();
+    let slug = path.trim_matches('/');
+    let file = match note_file(&engine, slug) {
+        Ok(file) => file,
+        Err(response) => return response,
+    };
+
+    let guard = notes::lock(slug).await;
+    let Ok(bytes) = fs::read(&file) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let current = notes::content_hash(&bytes);
+    if if_match(&req).is_some_and(|header| !notes::if_match_allows(header, Some(&current))) {
+        return HttpResponse::PreconditionFailed().finish();
+    }
+    if let Err(err) = fs::remove_file(&file) {
+        error!("deleting note {}: {err}", file.display());
+        return HttpResponse::InternalServerError().finish();
+    }
+    if let Err(err) = engine.drop_cached(slug) {
+        warn!("removing the cached HTML of {slug}: {err}");
+    }
+    drop(guard);
+    reindex_notes(Arc::clone(&engine)).await;
+    info!("note {slug} deleted through the API");
+    HttpResponse::NoContent().finish()
+}
+
 #[get("/health")]
-pub async fn healthcheck_handler() -> impl Responder {
-    HttpResponse::Ok().json(json!({ "message": "pong" }))
+pub async fn healthcheck_handler(pool: web::Data<SqlitePool>) -> impl Responder {
+    let schema_version = schema_version(&pool)
+        .await
+        .map_err(|err| error!("reading the schema version: {err:#}"))
+        .ok();
+    HttpResponse::Ok().json(json!({
+        "message": "pong",
+        "profile": SiteConfig::profile(),
+        "schema_version": schema_version,
+        "failed_bundles": failed_bundles(),
+        "styles": if styles_degraded() { "degraded" } else { "ok" },
+    }))
 }
 
-async fn render_slug(slug: String, hb: web::Data<Handlebars<'static>>) -> impl Responder {
+async fn render_slug(
+    slug: String,
+    hb: web::Data<Templates>,
+    req: HttpRequest,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
     let engine = trellis_engine();
     let raw_slug = slug;
     let trimmed = raw_slug.trim_matches('/');
@@ -93,72 +766,402 @@ async fn render_slug(slug: String, hb: web::Data<Handlebars<'static>>) -> impl R
     } else {
         trimmed.to_string()
     };
+    telemetry::record_slug(&canonical_slug);
 
     if !engine.page_exists(&canonical_slug) {
-        return HttpResponse::NotFound().finish();
+        // Addresses from before `content.slugify` was set lead on to the page's new one.
+        if let Some(renamed) = engine.renamed_slug(&canonical_slug) {
+            return HttpResponse::MovedPermanently()
+                .insert_header((header::LOCATION, engine.urls().canonical(&renamed)))
+                .finish();
+        }
+        return not_found(&engine, hb, &canonical_slug);
     }
     let page = match engine.render_page(&canonical_slug) {
         Ok(page) => page,
+        Err(err) if err.is_not_found() => {
+            debug!("not serving {canonical_slug}: {err}");
+            return missing_page(&engine, hb, &canonical_slug, &err);
+        }
         Err(err) => {
-            error!("failed to render page {}: {}", canonical_slug, err);
-            return HttpResponse::NotFound().finish();
+            error!("failed to render page {canonical_slug}: {err}");
+            return error_page(&engine, hb, &canonical_slug, &err);
         }
     };
 
-    let ctx = build_home_context(engine, page);
-    let template = if canonical_slug == "index" {
-        "index"
+    let template = page_template(&hb, &canonical_slug, page.frontmatter.template.as_deref());
+    let mut ctx = build_home_context(&engine, page);
+    let views = &engine.config.page_views;
+    if views.enabled {
+        page_views::record(&req, &canonical_slug);
+        if views.show_in_article {
+            ctx.article.views = page_views::total(&pool, &canonical_slug)
+                .await
+                .map_err(|err| warn!("reading views of {canonical_slug}: {err:#}"))
+                .ok();
+        }
+    }
+    if let Some(native) = ctx.comments.as_mut().and_then(|c| c.native.as_mut()) {
+        native.comments = comments::approved(&pool, &canonical_slug)
+            .await
+            .unwrap_or_else(|err| {
+                warn!("reading comments on {canonical_slug}: {err:#}");
+                Vec::new()
+            });
+    }
+    let csp = ctx.csp.take();
+    render(hb, &template, json!(ctx), with_csp(HttpResponse::Ok(), csp))
+}
+
+/// The template a page renders with: `index` for the site root and `page` for the rest,
+/// unless its `template:` frontmatter names another registered top-level template. The
+/// built-in `list` is for folder index pages only. Other names fall back with a warning.
+fn page_template(hb: &Templates, slug: &str, requested: Option<&str>) -> String {
+    let default = if slug == "index" { "index" } else { "page" };
+    let Some(name) = requested else {
+        return default.to_string();
+    };
+    if name == "list" && !slug.ends_with("/index") {
+        warn!("{slug}: template \"list\" is for folder index pages, using {default}");
+        return default.to_string();
+    }
+    if !hb.has_template(name) {
+        warn!("{slug}: no template {name:?} under templates/, using {default}");
+        return default.to_string();
+    }
+    name.to_string()
+}
+
+/// Redirect a short code to its page's canonical URL, counting the hit in the background.
+/// Codes whose page has since been deleted answer 410.
+#[get("/s/{code}")]
+async fn shortlink_handler(
+    path: web::Path<String>,
+    hb: web::Data<Templates>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let engine = trellis_engine();
+    let code = path.into_inner();
+    let slug = match shortlinks::target(&pool, &code).await {
+        Ok(Some(slug)) => slug,
+        Ok(None) => return not_found(&engine, hb, &format!("s/{code}")),
+        Err(err) => {
+            error!("reading short link {code}: {err:#}");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let slug = if engine.page_exists(&slug) {
+        slug
+    } else if let Some(renamed) = engine.renamed_slug(&slug) {
+        renamed
     } else {
-        "page"
+        return gone(&engine, hb, &format!("s/{code}"));
     };
-    render(hb, template, json!(ctx), HttpResponse::Ok())
+
+    let pool = pool.get_ref().clone();
+    tokio::spawn(async move {
+        if let Err(err) = shortlinks::hit(&pool, &code).await {
+            warn!("counting a hit on short link {code}: {err:#}");
+        }
+    });
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, engine.urls().canonical(&slug)))
+        .finish()
 }
 
 #[get("/feed")]
-pub async fn feed_handler(hb: web::Data<Handlebars<'static>>) -> impl Responder {
+pub async fn feed_handler(hb: web::Data<Templates>) -> impl Responder {
+    let remote_fonts = !trellis_engine()
+        .config
+        .configuration
+        .theme
+        .uses_local_fonts();
     render(
         hb,
         "feed",
-        json!({ "user": "Guest", "data": "your feed goes here" }),
+        json!({ "user": "Guest", "data": "your feed goes here", "remote_fonts": remote_fonts }),
         HttpResponse::Ok(),
     )
 }
 
-async fn tags_handler(
-    path: web::Path<String>,
-    hb: web::Data<Handlebars<'static>>,
-) -> impl Responder {
+async fn tags_handler(path: web::Path<String>, hb: web::Data<Templates>) -> impl Responder {
     let tag = path.into_inner().trim().to_string();
     let engine = trellis_engine();
-    let posts = pages_with_tag(engine, &tag);
+    let posts = pages_with_tag(&engine, &tag);
     let body = render_tag_list_html(&tag, &posts);
 
     let mut meta = PageMetadata::default();
     meta.title = Some(format!("Tag: {}", tag));
     meta.description = Some(format!("Pages tagged with {}", tag));
     meta.tags = Some(vec![tag.clone()]);
-    meta.word_count = Some(body.split_whitespace().count() as u64);
+    meta.comments = Some(false);
+    meta.word_count = Some(count_words(&body));
 
     let page = RenderedPage {
         slug: format!("tags/{}", tag),
+        headings: collect_headings(&body),
         html: body,
         frontmatter: meta,
+        extra: serde_json::Map::new(),
+        links: Vec::new(),
+        excerpt: None,
+        cached: Some(false),
+    };
+
+    let mut ctx = build_home_context(&engine, page);
+    let csp = ctx.csp.take();
+    render(hb, "page", json!(ctx), with_csp(HttpResponse::Ok(), csp))
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    #[serde(default)]
+    q: String,
+    limit: Option<usize>,
+}
+
+/// Results cap for both endpoints, whatever `limit` asks for.
+const MAX_SEARCH_RESULTS: usize = 50;
+
+#[get("/search")]
+async fn search_api_handler(query: web::Query<SearchQuery>) -> impl Responder {
+    let engine = trellis_engine();
+    let limit = query
+        .limit
+        .unwrap_or(engine.config.layout.search.limit)
+        .min(MAX_SEARCH_RESULTS);
+    let results = search_index(&engine).search(&query.q, limit);
+    HttpResponse::Ok().json(json!({ "query": query.q, "results": results }))
+}
+
+/// The explorer's file tree without the rest of the content index, from the nav cache.
+#[get("/explorer")]
+async fn explorer_api_handler() -> impl Responder {
+    let engine = trellis_engine();
+    let latest = latest_mtime_recursive(engine.content_root(), engine.ignore_matcher());
+    let (_, tree) = build_nav_from_content(&engine, "index", latest);
+    HttpResponse::Ok().json(&*tree)
+}
+
+#[derive(Deserialize)]
+struct OrphansQuery {
+    /// Count pages that share a tag as linked.
+    #[serde(default)]
+    tag_links: bool,
+}
+
+/// Orphaned pages and dead ends, built from the current content on every request.
+#[get("/orphans")]
+async fn orphans_api_handler(query: web::Query<OrphansQuery>) -> impl Responder {
+    let engine = trellis_engine();
+    match link_graph(
+        engine.content_root(),
+        engine.ignore_matcher(),
+        engine.registry(),
+        &engine.config.content,
+        &engine.config.encryption,
+    ) {
+        Ok(pages) => HttpResponse::Ok().json(link_report(&pages, query.tag_links)),
+        Err(err) => {
+            error!("building the orphan report: {err:#}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Server-rendered results for the search form, so it works without JavaScript.
+#[get("/search")]
+async fn search_page_handler(
+    query: web::Query<SearchQuery>,
+    hb: web::Data<Templates>,
+) -> impl Responder {
+    let engine = trellis_engine();
+    let q = query.q.trim();
+    let limit = (engine.config.layout.search.limit * 4).min(MAX_SEARCH_RESULTS);
+    let hits = search_index(&engine).search(q, limit);
+    let body = render_search_results_html(&engine.config.layout.search.placeholder, q, &hits);
+
+    let mut meta = PageMetadata::default();
+    meta.title = Some(if q.is_empty() {
+        "Search".into()
+    } else {
+        format!("Search: {q}")
+    });
+    meta.comments = Some(false);
+    meta.word_count = Some(count_words(&body));
+
+    let page = RenderedPage {
+        slug: "search".into(),
+        headings: collect_headings(&body),
+        html: body,
+        frontmatter: meta,
+        extra: serde_json::Map::new(),
+        links: Vec::new(),
+        excerpt: None,
+        cached: Some(false),
+    };
+
+    let mut ctx = build_home_context(&engine, page);
+    let csp = ctx.csp.take();
+    render(hb, "page", json!(ctx), with_csp(HttpResponse::Ok(), csp))
+}
+
+fn render_search_results_html(placeholder: &str, query: &str, hits: &[SearchHit]) -> String {
+    let mut html = format!(
+        "<form class=\"search-page-form\" action=\"/search\" method=\"get\" role=\"search\">\
+         <input type=\"search\" name=\"q\" value=\"{}\" placeholder=\"{}\" aria-label=\"{}\" />\
+         <button type=\"submit\">Search</button></form>",
+        escape_html(query),
+        escape_html(placeholder),
+        escape_html(placeholder)
+    );
+    if query.is_empty() {
+        return html;
+    }
+    if hits.is_empty() {
+        html.push_str(&format!(
+            "<p>No results for \"{}\".</p>",
+            escape_html(query)
+        ));
+        return html;
+    }
+
+    html.push_str("<ul class=\"search-page-results\">");
+    for hit in hits {
+        html.push_str(&format!(
+            "<li><a class=\"internal\" href=\"{}\">{}</a><p>{}</p></li>",
+            escape_html(&hit.href),
+            escape_html(&hit.title),
+            hit.snippet
+        ));
+    }
+    html.push_str("</ul>");
+    html
+}
+
+fn not_found(engine: &TrellisEngine, hb: web::Data<Templates>, slug: &str) -> HttpResponse {
+    status_page(
+        engine,
+        hb,
+        slug,
+        "Page not found",
+        "<p>Either this page is private or doesn't exist.</p>",
+        HttpResponse::NotFound(),
+    )
+}
+
+/// The 404 for a page the engine would not serve. Dev mode says why, such as which
+/// filter left it out.
+fn missing_page(
+    engine: &TrellisEngine,
+    hb: web::Data<Templates>,
+    slug: &str,
+    err: &RenderError,
+) -> HttpResponse {
+    if !engine.config.server.dev_mode {
+        return not_found(engine, hb, slug);
+    }
+    let html = format!(
+        "<p>Either this page is private or doesn't exist.</p>{}",
+        dev_error_detail(err)
+    );
+    status_page(
+        engine,
+        hb,
+        slug,
+        "Page not found",
+        &html,
+        HttpResponse::NotFound(),
+    )
+}
+
+/// A 500 for a page that exists but would not render. Dev mode shows the error itself.
+fn error_page(
+    engine: &TrellisEngine,
+    hb: web::Data<Templates>,
+    slug: &str,
+    err: &RenderError,
+) -> HttpResponse {
+    let mut html = String::from("<p>This page could not be rendered.</p>");
+    if engine.config.server.dev_mode {
+        html.push_str(&dev_error_detail(err));
+    }
+    status_page(
+        engine,
+        hb,
+        slug,
+        "Something went wrong",
+        &html,
+        HttpResponse::InternalServerError(),
+    )
+}
+
+fn dev_error_detail(err: &RenderError) -> String {
+    format!("<pre>{}</pre>", escape_html(&err.to_string()))
+}
+
+/// A 410 for links to pages that existed once, such as short links whose note was deleted.
+fn gone(engine: &TrellisEngine, hb: web::Data<Templates>, slug: &str) -> HttpResponse {
+    status_page(
+        engine,
+        hb,
+        slug,
+        "Page removed",
+        "<p>The page this link pointed to has been removed.</p>",
+        HttpResponse::Gone(),
+    )
+}
+
+/// A status page in the site's layout, rendered as if it were a note at `slug`.
+fn status_page(
+    engine: &TrellisEngine,
+    hb: web::Data<Templates>,
+    slug: &str,
+    title: &str,
+    html: &str,
+    builder: HttpResponseBuilder,
+) -> HttpResponse {
+    let mut meta = PageMetadata::default();
+    meta.title = Some(title.into());
+    meta.comments = Some(false);
+
+    let page = RenderedPage {
+        slug: slug.to_string(),
+        headings: collect_headings(html),
+        html: html.into(),
+        frontmatter: meta,
+        extra: serde_json::Map::new(),
+        links: Vec::new(),
+        excerpt: None,
         cached: Some(false),
     };
 
-    let ctx = build_home_context(engine, page);
-    render(hb, "page", json!(ctx), HttpResponse::Ok())
+    let mut ctx = build_home_context(engine, page);
+    let csp = ctx.csp.take();
+    render(hb, "page", json!(ctx), with_csp(builder, csp))
+}
+
+/// Attach the page's Content-Security-Policy, when one is configured.
+fn with_csp(mut builder: HttpResponseBuilder, csp: Option<String>) -> HttpResponseBuilder {
+    if let Some(policy) = csp {
+        builder.insert_header((header::CONTENT_SECURITY_POLICY, policy));
+    }
+    builder
 }
 
 fn render(
-    hb: web::Data<Handlebars<'static>>,
+    hb: web::Data<Templates>,
     template: &str,
     data: serde_json::Value,
     mut builder: HttpResponseBuilder,
 ) -> HttpResponse {
     match hb.render(template, &data) {
         Ok(body) => builder.content_type("text/html; charset=utf-8").body(body),
-        Err(err) => HttpResponse::InternalServerError().body(format!("Template error: {}", err)),
+        Err(err) => {
+            let err = RenderError::Template(err.to_string());
+            error!("rendering {template}: {err}");
+            HttpResponse::InternalServerError().body(err.to_string())
+        }
     }
 }
 
@@ -167,27 +1170,21 @@ struct TagResult {
     slug: String,
     title: String,
     description: Option<String>,
-    created: Option<String>,
+    date: Option<DateTime<Utc>>,
 }
 
 fn pages_with_tag(engine: &TrellisEngine, tag: &str) -> Vec<TagResult> {
     let content_root = engine.content_root();
-    let ignore_patterns = &engine.config.configuration.ignore_patterns;
+    let date_type = engine.config.configuration.default_date_type;
     let mut results = Vec::new();
 
     for entry in WalkDir::new(content_root)
         .into_iter()
-        .filter_entry(|e| !is_ignored(e.path(), content_root, ignore_patterns))
+        .filter_entry(|e| !engine.is_ignored_path(e.path()))
         .filter_map(Result::ok)
         .filter(|e| e.path().is_file())
     {
-        if entry
-            .path()
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|ext| ext.eq_ignore_ascii_case("md"))
-            != Some(true)
-        {
+        if !engine.config.content.is_content_file(entry.path()) {
             continue;
         }
 
@@ -209,44 +1206,43 @@ fn pages_with_tag(engine: &TrellisEngine, tag: &str) -> Vec<TagResult> {
             Err(_) => continue,
         };
 
-        let mut page = Page::new(slug.clone(), entry.path().to_path_buf(), content);
-        let Ok(frontmatter_page) = FrontMatter.transform(page) else {
+        let page = Page::new(slug.clone(), entry.path().to_path_buf(), content);
+        let Ok(Some(page)) = engine.registry().front_matter(page) else {
             continue;
         };
-        page = frontmatter_page;
 
+        // Encrypted notes publish no tags, so no tag page lists them.
+        if engine.config.encryption.protects(&slug, &page.frontmatter) {
+            continue;
+        }
         let tags = page.frontmatter.tags.clone().unwrap_or_default();
         if !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
             continue;
         }
 
-        let title = page
-            .frontmatter
-            .title
-            .clone()
-            .unwrap_or_else(|| slug.rsplit('/').next().unwrap_or(&slug).replace('-', " "));
+        let title = display_name(
+            &engine.config.content,
+            content_root,
+            &slug,
+            page.frontmatter.title.clone(),
+        );
         let description = page.frontmatter.description.clone();
-        let created = page
-            .frontmatter
-            .created
-            .map(|d| d.format("%Y-%m-%d").to_string());
+        let date = entry
+            .metadata()
+            .ok()
+            .and_then(|file| page_date(&page.frontmatter, &file, date_type));
 
         results.push(TagResult {
             slug,
             title,
             description,
-            created,
+            date,
         });
     }
 
     results.sort_by(|a, b| {
-        match (b.created.as_ref(), a.created.as_ref()) {
-            (Some(bc), Some(ac)) => bc.cmp(ac),
-            (Some(_), None) => std::cmp::Ordering::Less,
-            (None, Some(_)) => std::cmp::Ordering::Greater,
-            (None, None) => std::cmp::Ordering::Equal,
-        }
-        .then_with(|| a.title.to_lowercase().cmp(&b.title.to_lowercase()))
+        cmp_page_dates(a.date, b.date)
+            .then_with(|| a.title.to_lowercase().cmp(&b.title.to_lowercase()))
     });
 
     results
@@ -280,10 +1276,10 @@ fn render_tag_list_html(tag: &str, pages: &[TagResult]) -> String {
             href,
             escape_html(&page.title)
         ));
-        if let Some(created) = &page.created {
+        if let Some(date) = &page.date {
             html.push_str(&format!(
                 "<span class=\"tag-result-date\"> — {}</span>",
-                created
+                date.format("%Y-%m-%d")
             ));
         }
         if let Some(desc) = &page.description {
@@ -298,75 +1294,303 @@ fn render_tag_list_html(tag: &str, pages: &[TagResult]) -> String {
     html
 }
 
-fn escape_html(input: &str) -> String {
-    input
-        .replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
-        .replace('"', "&quot;")
-        .replace('\'', "&#39;")
-}
-
 #[derive(Serialize, Clone)]
 struct NavItem {
     title: String,
     path: String,
     #[serde(skip_serializing_if = "is_false")]
     open: bool,
+    /// The page being rendered; top-level pages are leaves like any other.
+    #[serde(skip_serializing_if = "is_false")]
+    active: bool,
+    /// Folders only; the partial recurses into them.
     #[serde(skip_serializing_if = "Option::is_none")]
-    children: Option<Vec<NavLeaf>>,
-}
-
-#[derive(Serialize, Clone)]
-struct NavLeaf {
-    title: String,
-    path: String,
+    children: Option<Vec<NavItem>>,
 }
 
 #[derive(Serialize)]
 struct HomeContext<'a> {
     site: SiteContext,
+    document_title: String,
     nav: Vec<NavItem>,
     article: ArticleContext,
-    explorer: ExplorerContext,
-    graph: GraphContext,
+    /// Only set when the page's resolved layout contains the component.
+    explorer: Option<ExplorerContext>,
+    graph: Option<GraphContext>,
+    search: Option<SearchContext>,
     backlinks: BacklinksContext,
-    layout: LayoutContext<'a>,
+    toc: Option<TocContext>,
+    content_meta: ContentMetaContext,
+    comments: Option<CommentsContext>,
+    prev_next: Option<PrevNextContext>,
+    banners: Vec<BannerContext>,
+    breadcrumbs: Vec<Crumb>,
+    breadcrumb_spacer: String,
+    /// The content layout's sidebars, component by component.
+    sidebars: SidebarSlots,
+    /// schema.org `BreadcrumbList` for the head, already safe to inline in a `<script>`.
+    breadcrumbs_json_ld: Option<String>,
+    layout: LayoutContext,
     configuration: &'a SiteConfig,
-    styles: String,
-    fonts_href: String,
-    scripts: InlineScripts,
+    /// Site CSS inlined into the page, unless it is linked via `stylesheet_href`.
+    styles: Option<String>,
+    stylesheet_href: Option<String>,
+    /// SCSS compile error shown as an overlay; only set in dev mode.
+    style_error: Option<ScssError>,
+    /// The page's own `style` frontmatter, sanitized.
+    page_style: Option<String>,
+    /// Rules for the `Flex` components in the sidebars; kept out of `style` attributes so
+    /// the CSP never needs `unsafe-inline`.
+    layout_style: Option<String>,
+    fonts_href: Option<String>,
+    font_preconnect: bool,
+    /// Self-hosted woff2 files to preload.
+    font_preloads: Vec<String>,
+    resources: ResourcesContext,
+    analytics: Option<String>,
+    canonical_url: String,
+    /// A locked note's password hint, when `encryption.hint_in_description` allows it.
+    meta_description: Option<String>,
+    #[serde(flatten)]
+    scripts: PageScripts,
     footer: FooterContext,
+    /// Nonce every inline script and style tag carries when `server.csp.mode` is `nonce`.
+    csp_nonce: Option<String>,
+    /// The Content-Security-Policy header value; sent with the response, not rendered.
+    #[serde(skip)]
+    csp: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SiteContext {
+    name: String,
+    tagline: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ExplorerContext {
+    id: String,
+    title: String,
+    folder_default_state: String,
+    folder_click_behavior: String,
+    use_saved_state: bool,
+    data_fns_json: String,
+    /// `api` when `GET /api/explorer` carries everything the data functions read,
+    /// `index` when they need the full content index.
+    data_source: &'static str,
+    /// Server-rendered folder/file tree; the explorer script replaces it once the content
+    /// index loads, and it is all that non-JS clients see.
+    tree: Vec<ExplorerNode>,
+}
+
+/// One file or folder of the server-rendered explorer tree, as seen from the current page.
+#[derive(Serialize)]
+struct ExplorerNode {
+    title: String,
+    href: String,
+    /// `data-folderpath` the explorer script keys saved folder state by.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    folder_path: Option<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    open: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    active: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    children: Vec<ExplorerNode>,
+}
+
+/// The page-independent explorer tree, cached alongside the nav. Serialized as is for
+/// `GET /api/explorer`; a folder's `slug` has no `/index`.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExplorerEntry {
+    slug: String,
+    #[serde(rename = "name")]
+    title: String,
+    is_folder: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    weight: Option<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    children: Vec<ExplorerEntry>,
+}
+
+#[derive(Serialize)]
+struct SearchContext {
+    placeholder: String,
+    hotkeys_json: String,
+    limit: usize,
+}
+
+#[derive(Serialize, Clone)]
+struct GraphContext {
+    title: String,
+    local_cfg_json: String,
+    global_cfg_json: String,
+}
+
+#[derive(Serialize, Clone)]
+struct BacklinkEntry {
+    title: String,
+    slug: String,
+    href: String,
+    /// Escaped text around each quoted mention, the link itself in `<mark>`.
+    context: Vec<String>,
+    /// Mentions beyond the quoted one, with `context: first`.
+    more: usize,
+}
+
+/// The comments widget for one page; `comments.inline.ts` injects the provider script.
+#[derive(Serialize)]
+struct CommentsContext {
+    provider: CommentsProvider,
+    src: Option<&'static str>,
+    /// The provider's `data-*` / plain attributes, minus the theme, as JSON.
+    attrs_json: String,
+    light_theme: String,
+    dark_theme: String,
+    /// Native comments are rendered with the page instead, and posted to `/api/comments`.
+    native: Option<NativeCommentsContext>,
+}
+
+#[derive(Serialize)]
+struct NativeCommentsContext {
+    slug: String,
+    /// Approved comments, oldest first; read from the database after the page renders.
+    comments: Vec<comments::Comment>,
+    max_name_chars: usize,
+    max_body_chars: usize,
+    require_approval: bool,
+}
+
+/// An announcement bar from `shared.header`; `banner.inline.ts` handles dismissal.
+#[derive(Serialize)]
+struct BannerContext {
+    id: String,
+    html: String,
+    dismissible: bool,
+    style: BannerStyle,
+}
+
+/// The line under the article title, already filtered to the facts the page has.
+#[derive(Serialize)]
+struct ContentMetaContext {
+    separator: String,
+    segments: Vec<MetaSegment>,
+}
+
+#[derive(Serialize)]
+struct MetaSegment {
+    text: String,
+    /// RFC 3339 for date segments, rendered as `<time datetime>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    datetime: Option<String>,
+}
+
+/// The content layout's sidebars in config order, with components that render nothing
+/// there (or nothing yet) left out.
+#[derive(Serialize, Default)]
+struct SidebarSlots {
+    left: Vec<SlotNode>,
+    right: Vec<SlotNode>,
+}
+
+/// One sidebar component for `components/layout_node.hbs`. Handlebars has no string
+/// comparison, so the partial branches on which key is present; exactly one is set.
+#[derive(Serialize, Default)]
+struct SlotNode {
+    #[serde(skip_serializing_if = "is_false")]
+    page_title: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    spacer: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    search: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    explorer: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    graph: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    toc: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    backlinks: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recent_notes: Option<RecentNotesContext>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subscribe: Option<SubscribeConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flex: Option<FlexContext>,
+    /// `mobile-only` or `desktop-only`, wrapping `child`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    breakpoint: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    child: Option<Box<SlotNode>>,
+}
+
+/// A `Flex` component; its container and item rules live in `layout_style`, keyed by
+/// these classes.
+#[derive(Serialize)]
+struct FlexContext {
+    class: String,
+    items: Vec<FlexItemContext>,
+}
+
+#[derive(Serialize)]
+struct FlexItemContext {
+    class: String,
+    node: SlotNode,
+}
+
+#[derive(Serialize)]
+struct RecentNotesContext {
+    title: String,
+    items: Vec<RecentNote>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    see_more: Option<String>,
+    /// Matching notes left out by `limit`.
+    remaining: usize,
+}
+
+#[derive(Serialize)]
+struct RecentNote {
+    title: String,
+    href: String,
+    /// `%Y-%m-%d`, empty when the note has no date.
+    date: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
 }
 
-#[derive(Serialize)]
-struct SiteContext {
-    name: String,
-    tagline: Option<String>,
+/// One published note as the recent-notes lists see it.
+#[derive(Clone)]
+struct NoteSummary {
+    slug: String,
+    title: String,
+    description: Option<String>,
+    date: Option<DateTime<Utc>>,
+    tags: Vec<String>,
 }
 
+/// A linked page as the prev/next partial shows it.
 #[derive(Serialize)]
-struct ExplorerContext {
-    id: String,
+struct PageLink {
     title: String,
-    folder_default_state: String,
-    folder_click_behavior: String,
-    use_saved_state: bool,
-    data_fns_json: String,
+    href: String,
 }
 
-#[derive(Serialize, Clone)]
-struct GraphContext {
-    title: String,
-    local_cfg_json: String,
-    global_cfg_json: String,
+/// Neighbouring notes in the current note's folder; either side may be missing.
+#[derive(Serialize)]
+struct PrevNextContext {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prev: Option<PageLink>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next: Option<PageLink>,
 }
 
+/// One step of the breadcrumb trail; the current page has no `href`.
 #[derive(Serialize, Clone)]
-struct BacklinkEntry {
+struct Crumb {
     title: String,
-    slug: String,
-    href: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    href: Option<String>,
 }
 
 #[derive(Serialize, Clone)]
@@ -378,12 +1602,51 @@ struct BacklinksContext {
     has_backlinks: bool,
 }
 
+/// `plugins.resources` plus the core content index fetch, split into the tags each template
+/// slot emits.
+#[derive(Serialize)]
+struct ResourcesContext {
+    css: Vec<CssTag>,
+    head_js: Vec<ScriptTag>,
+    body_js: Vec<ScriptTag>,
+    additional_head: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct CssTag {
+    href: Option<String>,
+    inline: Option<String>,
+    spa_preserve: bool,
+}
+
+#[derive(Serialize)]
+struct ScriptTag {
+    src: Option<String>,
+    script: Option<String>,
+    module_type: Option<String>,
+    spa_preserve: bool,
+}
+
 #[derive(Serialize)]
 struct FooterContext {
     year: i32,
     version: String,
+    show_credit: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     links: Option<Vec<FooterLink>>,
+    /// Rendered `markdown` followed by the verbatim `html`; emitted unescaped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blurb: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    socials: Vec<SocialContext>,
+}
+
+#[derive(Serialize)]
+struct SocialContext {
+    label: String,
+    href: String,
+    /// Inner markup of the 24×24 stroke icon.
+    icon: &'static str,
 }
 
 #[derive(Serialize)]
@@ -399,27 +1662,122 @@ struct ArticleContext {
     intro: String,
     created: String,
     updated: String,
+    /// The page date chosen by `default_date_type`; empty when the page has none.
+    date: String,
     read_time: String,
     body: String,
     tags: Vec<String>,
+    /// Frontmatter keys without a field of their own, as `{{article.frontmatter.author}}`.
+    frontmatter: serde_json::Map<String, serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     html: Option<String>,
+    /// Recorded views, when `page_views.show_in_article` is on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    views: Option<i64>,
 }
 
+/// The layout slots as one page sees them, with `ConditionalRender` wrappers resolved.
 #[derive(Serialize)]
-struct LayoutContext<'a> {
-    shared: &'a crate::trellis::layout::SharedLayout,
-    content: &'a crate::trellis::layout::PageLayout,
-    list: &'a crate::trellis::layout::PageLayout,
+struct LayoutContext {
+    shared: SharedLayout,
+    content: PageLayout,
+    list: PageLayout,
+}
+
+impl LayoutContext {
+    /// A page's `layout:` frontmatter swaps in a named layout from `layouts:` for both the
+    /// content and list slots; unknown names fall back to the defaults.
+    fn for_page(engine: &TrellisEngine, page: &RenderedPage) -> Self {
+        let shared = &engine.shared_layout;
+        let named = page.frontmatter.layout.as_deref().and_then(|name| {
+            let layout = engine.config.layouts.get(name);
+            if layout.is_none() {
+                warn!(
+                    "{}: layout {name:?} is not defined under layouts:, using the default",
+                    page.slug
+                );
+            }
+            layout
+        });
+        let resolve = |list: &[LayoutComponent]| resolve_components(list, page);
+        let resolve_page = |layout: &PageLayout| PageLayout {
+            before_body: resolve(&layout.before_body),
+            left: resolve(&layout.left),
+            right: resolve(&layout.right),
+            after_body: resolve(&layout.after_body),
+        };
+        Self {
+            shared: SharedLayout {
+                head: resolve_component(&shared.head, page).unwrap_or(LayoutComponent::Spacer),
+                header: resolve(&shared.header),
+                footer: resolve_component(&shared.footer, page).unwrap_or(LayoutComponent::Spacer),
+                after_body: resolve(&shared.after_body),
+            },
+            content: resolve_page(named.unwrap_or(&engine.content_layout)),
+            list: resolve_page(named.unwrap_or(&engine.list_layout)),
+        }
+    }
+}
+
+fn resolve_components(list: &[LayoutComponent], page: &RenderedPage) -> Vec<LayoutComponent> {
+    list.iter()
+        .filter_map(|component| resolve_component(component, page))
+        .collect()
+}
+
+/// `component` with every `ConditionalRender` inside it replaced by its inner component
+/// when the condition holds for `page`, or `None` when nothing is left to render.
+fn resolve_component(component: &LayoutComponent, page: &RenderedPage) -> Option<LayoutComponent> {
+    match component {
+        LayoutComponent::ConditionalRender(cfg) => cfg
+            .condition
+            .matches(&page.slug, &page.frontmatter, &page.extra)
+            .then(|| resolve_component(&cfg.component, page))
+            .flatten(),
+        LayoutComponent::Flex(cfg) => {
+            let components: Vec<FlexItem> = cfg
+                .components
+                .iter()
+                .filter_map(|item| {
+                    resolve_component(&item.component, page).map(|component| FlexItem {
+                        component,
+                        ..item.clone()
+                    })
+                })
+                .collect();
+            (!components.is_empty()).then(|| {
+                LayoutComponent::Flex(FlexConfig {
+                    components,
+                    ..cfg.clone()
+                })
+            })
+        }
+        LayoutComponent::MobileOnly(inner) => {
+            resolve_component(inner, page).map(|inner| LayoutComponent::MobileOnly(Box::new(inner)))
+        }
+        LayoutComponent::DesktopOnly(inner) => resolve_component(inner, page)
+            .map(|inner| LayoutComponent::DesktopOnly(Box::new(inner))),
+        other => Some(other.clone()),
+    }
 }
 
-fn script_needs(page: &RenderedPage, layout: &LayoutContext) -> ScriptNeeds {
+fn script_needs(
+    page: &RenderedPage,
+    layout: &LayoutContext,
+    toc: Option<&TocContext>,
+    comments: Option<&CommentsContext>,
+    banners: &[BannerContext],
+    popovers: bool,
+    newsletter: bool,
+) -> ScriptNeeds {
     let html = &page.html;
     let has_mermaid = html.contains("class=\"mermaid\"");
     let has_callouts = html.contains("class=\"callout ");
     let encrypted = page.frontmatter.encrypted.unwrap_or(false);
-    let has_explorer = layout_contains_explorer(layout);
-    let has_graph = layout_contains_graph(layout);
+    let has_explorer = layout_has_component(layout, "explorer");
+    let has_graph = layout_has_component(layout, "graph");
+    let has_search = layout_has_component(layout, "search");
+    let has_subscribe = newsletter && layout_has_component(layout, "subscribe");
 
     ScriptNeeds {
         explorer: has_explorer,
@@ -428,113 +1786,339 @@ fn script_needs(page: &RenderedPage, layout: &LayoutContext) -> ScriptNeeds {
         mermaid: has_mermaid,
         callouts: has_callouts,
         graph: has_graph,
+        toc: toc.is_some(),
+        comments: comments.is_some(),
+        banner: banners.iter().any(|banner| banner.dismissible),
+        search: has_search,
+        subscribe: has_subscribe,
+        popover: popovers,
+        custom: page.frontmatter.scripts.clone().unwrap_or_default(),
     }
 }
 
-fn layout_contains_explorer(layout: &LayoutContext) -> bool {
-    component_list_has_explorer(&layout.shared.header)
-        || component_list_has_explorer(&layout.content.left)
-        || component_list_has_explorer(&layout.content.before_body)
-        || component_list_has_explorer(&layout.content.right)
-        || component_list_has_explorer(&layout.list.left)
-        || component_list_has_explorer(&layout.list.before_body)
-        || component_list_has_explorer(&layout.list.right)
-        || matches!(layout.shared.head, LayoutComponent::Explorer(_))
-        || matches!(layout.shared.footer, LayoutComponent::Explorer(_))
-        || component_list_has_explorer(&layout.shared.after_body)
-}
-
-fn component_list_has_explorer(list: &[LayoutComponent]) -> bool {
-    list.iter().any(component_has_explorer)
+/// Whether any slot of `layout` holds the component written `type: {name}` in
+/// `config.yml`, directly or nested in another component.
+fn layout_has_component(layout: &LayoutContext, name: &str) -> bool {
+    let shared = &layout.shared;
+    let lists = [
+        &shared.header,
+        &layout.content.left,
+        &layout.content.before_body,
+        &layout.content.right,
+        &layout.content.after_body,
+        &layout.list.left,
+        &layout.list.before_body,
+        &layout.list.right,
+        &layout.list.after_body,
+        &shared.after_body,
+    ];
+    lists
+        .into_iter()
+        .flat_map(|list| components(list))
+        .chain(components(std::slice::from_ref(&shared.head)))
+        .chain(components(std::slice::from_ref(&shared.footer)))
+        .any(|component| component.name() == name)
 }
 
-fn component_has_explorer(component: &LayoutComponent) -> bool {
-    match component {
-        LayoutComponent::Explorer(_) => true,
-        LayoutComponent::Flex(cfg) => cfg
-            .components
-            .iter()
-            .any(|item| component_has_explorer(&item.component)),
-        LayoutComponent::MobileOnly(inner) | LayoutComponent::DesktopOnly(inner) => {
-            component_has_explorer(inner)
+/// `list` and everything nested in its `Flex`, `MobileOnly`, `DesktopOnly` and
+/// `ConditionalRender` components, in document order.
+fn components(list: &[LayoutComponent]) -> Vec<&LayoutComponent> {
+    fn push<'a>(component: &'a LayoutComponent, found: &mut Vec<&'a LayoutComponent>) {
+        found.push(component);
+        match component {
+            LayoutComponent::Flex(cfg) => {
+                for item in &cfg.components {
+                    push(&item.component, found);
+                }
+            }
+            LayoutComponent::MobileOnly(inner) | LayoutComponent::DesktopOnly(inner) => {
+                push(inner, found);
+            }
+            LayoutComponent::ConditionalRender(cfg) => push(&cfg.component, found),
+            _ => {}
         }
-        _ => false,
     }
-}
-
-fn layout_contains_graph(layout: &LayoutContext) -> bool {
-    component_list_has_graph(&layout.shared.header)
-        || component_list_has_graph(&layout.content.left)
-        || component_list_has_graph(&layout.content.before_body)
-        || component_list_has_graph(&layout.content.right)
-        || component_list_has_graph(&layout.list.left)
-        || component_list_has_graph(&layout.list.before_body)
-        || component_list_has_graph(&layout.list.right)
-        || matches!(layout.shared.head, LayoutComponent::Graph)
-        || matches!(layout.shared.footer, LayoutComponent::Graph)
-        || component_list_has_graph(&layout.shared.after_body)
-}
-
-fn component_list_has_graph(list: &[LayoutComponent]) -> bool {
-    list.iter().any(component_has_graph)
-}
 
-fn component_has_graph(component: &LayoutComponent) -> bool {
-    match component {
-        LayoutComponent::Graph => true,
-        LayoutComponent::Flex(cfg) => cfg
-            .components
-            .iter()
-            .any(|item| component_has_graph(&item.component)),
-        LayoutComponent::MobileOnly(inner) | LayoutComponent::DesktopOnly(inner) => {
-            component_has_graph(inner)
-        }
-        _ => false,
+    let mut found = Vec::new();
+    for component in list {
+        push(component, &mut found);
     }
+    found
 }
 
 fn build_home_context<'a>(engine: &'a TrellisEngine, page: RenderedPage) -> HomeContext<'a> {
-    let article = to_article(&page);
-    let nav = build_nav_from_content(&engine.config, &article.slug);
-    let styles = compiled_styles(&engine.config);
-    let fonts_href = google_font_href(&engine.config.configuration.theme);
+    let article = to_article(engine, &page);
+    let canonical_url = engine.urls().canonical(&article.slug);
+    let meta_description = if page.frontmatter.encrypted.unwrap_or(false) {
+        page.frontmatter
+            .password_hint
+            .clone()
+            .filter(|_| engine.config.encryption.hint_in_description)
+    } else {
+        page.frontmatter
+            .description
+            .clone()
+            .or_else(|| page.excerpt.clone())
+    };
+    let document_title = engine
+        .config
+        .configuration
+        .document_title(&article.slug, &article.title);
+    let content_mtime = latest_mtime_recursive(engine.content_root(), engine.ignore_matcher());
+    let (nav, explorer_tree) = build_nav_from_content(engine, &article.slug, content_mtime);
+    let PageStyles {
+        inline: styles,
+        href: stylesheet_href,
+        error: style_error,
+    } = page_styles(&engine.config);
+    let page_style = page.frontmatter.style.as_deref().map(sanitize_page_css);
+    let theme = &engine.config.configuration.theme;
+    let fonts_href = google_font_href(theme, theme.font_display);
+    let font_preconnect = fonts_href.is_some() && theme.cdn_caching;
+    let font_preloads = if theme.uses_local_fonts() {
+        fonts::preload_hrefs(theme, &engine.config.fonts_dir())
+    } else {
+        Vec::new()
+    };
+    let csp_nonce = match engine.config.server.csp.mode {
+        CspMode::Nonce => csp::nonce()
+            .map_err(|err| error!("CSP nonce unavailable, sending no policy: {err:#}"))
+            .ok(),
+        _ => None,
+    };
+    let analytics = analytics_head(
+        &engine.config.analytics,
+        engine.config.configuration.enable_spa,
+    )
+    .map(|html| match &csp_nonce {
+        Some(nonce) => csp::add_nonce(&html, nonce),
+        None => html,
+    });
     let footer = footer_context(&engine.config);
-    let graph = graph_context();
-    let backlinks = backlinks_context(engine, &article.slug);
+    let backlinks = backlinks_context(engine, &article.slug, content_mtime);
+    let layout_ctx = LayoutContext::for_page(engine, &page);
+    let content_meta = content_meta_context(engine, &layout_ctx.content, &page);
+    let breadcrumbs = breadcrumbs(engine, &article.slug, &article.title);
+    let (sidebars, layout_style) =
+        sidebars_context(engine, &layout_ctx.content, &article.slug, content_mtime);
+    let breadcrumbs_json_ld = breadcrumbs_json_ld(&breadcrumbs);
+    let mut resources = resources_context(&engine.config, &article.slug);
+    if let Some(nonce) = &csp_nonce {
+        for html in &mut resources.additional_head {
+            *html = csp::add_nonce(html, nonce);
+        }
+    }
 
-    let layout_ctx = LayoutContext {
-        shared: &engine.shared_layout,
-        content: &engine.content_layout,
-        list: &engine.list_layout,
-    };
-    let scripts = inline_scripts(script_needs(&page, &layout_ctx));
+    let toc = layout_has_component(&layout_ctx, "table-of-contents")
+        .then(|| toc_context(&page.headings, &engine.config.layout.toc))
+        .flatten();
+    let comments = comments_context(engine, &layout_ctx, &page);
+    let prev_next = prev_next_context(engine, &layout_ctx, &page.slug, content_mtime);
+    let banners = banners_context(&layout_ctx.shared.header);
+    let needs = script_needs(
+        &page,
+        &layout_ctx,
+        toc.as_ref(),
+        comments.as_ref(),
+        &banners,
+        engine.config.configuration.enable_popovers,
+        engine.config.newsletter.enabled,
+    );
+    let templates_root = engine.config.templates_root();
+    let scripts = page_scripts(
+        &needs,
+        &BundleSettings {
+            bundler: &engine.config.bundler,
+            cache_root: engine.cache_root(),
+            dev_mode: engine.config.server.dev_mode,
+            scripts: &engine.config.scripts,
+            templates_root: templates_root.as_deref(),
+        },
+    );
+    let csp = content_security_policy(
+        engine,
+        csp_nonce.as_deref(),
+        &needs,
+        PageInline {
+            styles: styles.as_deref(),
+            page_style: page_style.as_deref(),
+            layout_style: layout_style.as_deref(),
+            fonts_href: fonts_href.as_deref(),
+            analytics: analytics.as_deref(),
+            resources: &resources,
+            scripts: &scripts,
+            comments_src: comments.as_ref().and_then(|comments| comments.src),
+        },
+    );
 
     HomeContext {
         site: SiteContext {
             name: engine.config.configuration.page_title.clone(),
             tagline: None,
         },
+        document_title,
         nav,
         article,
-        explorer: explorer_context(&engine.config),
-        graph,
+        explorer: needs
+            .explorer
+            .then(|| explorer_context(engine, &explorer_tree, &page.slug)),
+        graph: needs.graph.then(|| graph_context(engine)),
+        search: needs.search.then(|| search_context(engine)),
         backlinks,
+        toc,
+        content_meta,
+        comments,
+        prev_next,
+        banners,
+        breadcrumbs,
+        sidebars,
+        breadcrumb_spacer: engine.config.layout.breadcrumbs.spacer.clone(),
+        breadcrumbs_json_ld,
         layout: layout_ctx,
         configuration: &engine.config,
         styles,
+        stylesheet_href,
+        style_error,
+        page_style,
+        layout_style,
         fonts_href,
+        font_preconnect,
+        font_preloads,
+        resources,
+        analytics,
+        canonical_url,
+        meta_description,
         scripts,
         footer,
+        csp_nonce,
+        csp,
+    }
+}
+
+/// What a page emits that its Content-Security-Policy has to allow.
+struct PageInline<'a> {
+    styles: Option<&'a str>,
+    page_style: Option<&'a str>,
+    layout_style: Option<&'a str>,
+    fonts_href: Option<&'a str>,
+    analytics: Option<&'a str>,
+    resources: &'a ResourcesContext,
+    scripts: &'a PageScripts,
+    /// Provider script the comments component injects at runtime.
+    comments_src: Option<&'a str>,
+}
+
+/// The policy for one page, or `None` when `server.csp.mode` is `off` (or a nonce could
+/// not be generated). Hosts of external modules and resources are allowed by origin;
+/// inline tags by the response nonce or, in hash mode, by the SHA-256 of their contents.
+fn content_security_policy(
+    engine: &TrellisEngine,
+    nonce: Option<&str>,
+    needs: &ScriptNeeds,
+    page: PageInline,
+) -> Option<String> {
+    let cfg = &engine.config.server.csp;
+    let bundler = &engine.config.bundler;
+    let mut sources = PageSources {
+        eval: needs.explorer || needs.overlay_explorer,
+        ..Default::default()
+    };
+
+    let module_hosts = bundler
+        .external_modules
+        .iter()
+        .chain(bundler.script_externals.values().flatten());
+    let resource_hosts = page
+        .resources
+        .head_js
+        .iter()
+        .chain(&page.resources.body_js)
+        .filter_map(|tag| tag.src.as_ref());
+    sources.scripts.extend(
+        module_hosts
+            .chain(resource_hosts)
+            .map(String::as_str)
+            .chain(page.comments_src)
+            .filter_map(csp::origin),
+    );
+    sources.styles.extend(
+        page.resources
+            .css
+            .iter()
+            .filter_map(|tag| tag.href.as_deref())
+            .chain(page.fonts_href)
+            .filter_map(csp::origin),
+    );
+
+    match cfg.mode {
+        CspMode::Off => return None,
+        CspMode::Nonce => {
+            let source = format!("'nonce-{}'", nonce?);
+            sources.scripts.push(source.clone());
+            sources.styles.push(source);
+        }
+        CspMode::Hash => {
+            let raw_head: Vec<&str> = page
+                .resources
+                .additional_head
+                .iter()
+                .map(String::as_str)
+                .chain(page.analytics)
+                .collect();
+            let inline_scripts = page
+                .scripts
+                .scripts
+                .iter()
+                .filter_map(|script| script.code.as_deref())
+                .chain(
+                    page.resources
+                        .head_js
+                        .iter()
+                        .chain(&page.resources.body_js)
+                        .filter_map(|tag| tag.script.as_deref()),
+                )
+                .chain(
+                    raw_head
+                        .iter()
+                        .flat_map(|html| csp::inline_contents(html, "script")),
+                );
+            sources.scripts.extend(inline_scripts.map(csp::hash_source));
+
+            let inline_styles = page
+                .styles
+                .into_iter()
+                .chain(page.page_style)
+                .chain(page.layout_style)
+                .chain(
+                    page.resources
+                        .css
+                        .iter()
+                        .filter_map(|tag| tag.inline.as_deref()),
+                )
+                .chain(
+                    raw_head
+                        .iter()
+                        .flat_map(|html| csp::inline_contents(html, "style")),
+                );
+            sources.styles.extend(inline_styles.map(csp::hash_source));
+        }
     }
+
+    Some(csp::header_value(cfg, &sources))
 }
 
-fn explorer_context(config: &SiteConfig) -> ExplorerContext {
-    let cfg = &config.layout.explorer;
+fn explorer_context(
+    engine: &TrellisEngine,
+    tree: &[ExplorerEntry],
+    current_slug: &str,
+) -> ExplorerContext {
+    let cfg = &engine.config.layout.explorer;
+    let fns = engine.explorer_fns();
     let data_fns_json = serde_json::json!({
         "order": cfg.order,
-        "sortFn": cfg.sort_fn,
-        "filterFn": cfg.filter_fn,
-        "mapFn": cfg.map_fn,
+        "sortFn": fns.sort,
+        "filterFn": fns.filter,
+        "mapFn": fns.map,
     })
     .to_string();
 
@@ -545,101 +2129,236 @@ fn explorer_context(config: &SiteConfig) -> ExplorerContext {
         folder_click_behavior: cfg.folder_click_behavior.clone(),
         use_saved_state: cfg.use_saved_state,
         data_fns_json,
+        data_source: if fns.reads_index_fields() {
+            "index"
+        } else {
+            "api"
+        },
+        tree: explorer_nodes(engine, tree, current_slug),
     }
 }
 
-fn graph_context() -> GraphContext {
-    #[derive(Serialize)]
-    #[serde(rename_all = "camelCase")]
-    struct D3Config<'a> {
-        drag: bool,
-        zoom: bool,
-        depth: i32,
-        scale: f32,
-        repel_force: f32,
-        center_force: f32,
-        link_distance: i32,
-        font_size: f32,
-        opacity_scale: f32,
-        remove_tags: &'a [&'a str],
-        show_tags: bool,
-        focus_on_hover: bool,
-        enable_radial: bool,
-    }
-
-    let local = D3Config {
-        drag: true,
-        zoom: true,
-        depth: 1,
-        scale: 1.1,
-        repel_force: 0.5,
-        center_force: 0.3,
-        link_distance: 30,
-        font_size: 0.6,
-        opacity_scale: 1.0,
-        remove_tags: &[],
-        show_tags: true,
-        focus_on_hover: false,
-        enable_radial: false,
-    };
-
-    let global = D3Config {
-        drag: true,
-        zoom: true,
-        depth: -1,
-        scale: 0.9,
-        repel_force: 0.5,
-        center_force: 0.2,
-        link_distance: 30,
-        font_size: 0.6,
-        opacity_scale: 1.0,
-        remove_tags: &[],
-        show_tags: true,
-        focus_on_hover: true,
-        enable_radial: true,
+/// The cached tree rendered for `current_slug`: the current page is marked active, its
+/// folders are open, and `layout.explorer.max_depth`/`max_entries` cap the output so large
+/// vaults keep their HTML small. Capped folders still link to their index page.
+fn explorer_nodes(
+    engine: &TrellisEngine,
+    tree: &[ExplorerEntry],
+    current_slug: &str,
+) -> Vec<ExplorerNode> {
+    let cfg = &engine.config.layout.explorer;
+    let current = current_slug.trim_matches('/');
+    let current = current.strip_suffix("/index").unwrap_or(current);
+    let mut budget = if cfg.max_entries == 0 {
+        usize::MAX
+    } else {
+        cfg.max_entries
     };
 
+    fn walk(
+        engine: &TrellisEngine,
+        entries: &[ExplorerEntry],
+        current: &str,
+        depth: usize,
+        budget: &mut usize,
+    ) -> Vec<ExplorerNode> {
+        let cfg = &engine.config.layout.explorer;
+        let urls = engine.urls();
+        let mut nodes = Vec::new();
+        for entry in entries {
+            if *budget == 0 {
+                break;
+            }
+            *budget -= 1;
+
+            let active = entry.slug == current;
+            let mut node = ExplorerNode {
+                title: entry.title.clone(),
+                href: urls.canonical(&entry.slug),
+                folder_path: None,
+                open: false,
+                active,
+                children: Vec::new(),
+            };
+            if entry.is_folder {
+                let on_path = current.starts_with(&format!("{}/", entry.slug));
+                node.href = urls.canonical(&format!("{}/index", entry.slug));
+                node.folder_path = Some(format!("{}/index", entry.slug));
+                node.open = on_path || cfg.folder_default_state == "open";
+                if cfg.max_depth == 0 || depth < cfg.max_depth {
+                    node.children = walk(engine, &entry.children, current, depth + 1, budget);
+                }
+            }
+            nodes.push(node);
+        }
+        nodes
+    }
+
+    walk(engine, tree, current, 1, &mut budget)
+}
+
+fn graph_context(engine: &TrellisEngine) -> GraphContext {
+    let cfg = &engine.config.layout.graph;
     GraphContext {
-        title: "Graph".into(),
-        local_cfg_json: serde_json::to_string(&local).unwrap_or_else(|_| "{}".into()),
-        global_cfg_json: serde_json::to_string(&global).unwrap_or_else(|_| "{}".into()),
+        title: cfg.title.clone(),
+        local_cfg_json: serde_json::to_string(&cfg.local).unwrap_or_else(|_| "{}".into()),
+        global_cfg_json: serde_json::to_string(&cfg.global).unwrap_or_else(|_| "{}".into()),
     }
 }
 
-fn backlinks_context(engine: &TrellisEngine, current_slug: &str) -> BacklinksContext {
-    let content_root = engine.content_root();
-    let ignore_patterns = &engine.config.configuration.ignore_patterns;
-    let targets = backlink_targets(current_slug);
+fn search_context(engine: &TrellisEngine) -> SearchContext {
+    let cfg = &engine.config.layout.search;
+    SearchContext {
+        placeholder: cfg.placeholder.clone(),
+        hotkeys_json: serde_json::to_string(&cfg.hotkeys).unwrap_or_else(|_| "[]".into()),
+        limit: cfg.limit.min(MAX_SEARCH_RESULTS),
+    }
+}
+
+/// Pages linking to `current_slug`, looked up in the backlink index built for content
+/// as of `content_mtime`.
+fn backlinks_context(
+    engine: &TrellisEngine,
+    current_slug: &str,
+    content_mtime: SystemTime,
+) -> BacklinksContext {
+    let cfg = &engine.config.layout.backlinks;
+    let index = backlink_index(engine, content_mtime);
+
+    let mut items: Vec<BacklinkEntry> = Vec::new();
+    for target in backlink_targets(current_slug) {
+        for source in index.get(&target).into_iter().flatten() {
+            if source.source_slug == current_slug
+                || items.iter().any(|item| item.slug == source.slug)
+            {
+                continue;
+            }
+            let (context, more) = match cfg.context {
+                BacklinkContext::None => (Vec::new(), 0),
+                BacklinkContext::First => match source.contexts.first() {
+                    Some(first) => (vec![first.clone()], source.mentions - 1),
+                    None => (Vec::new(), 0),
+                },
+                BacklinkContext::All => (source.contexts.clone(), 0),
+            };
+            items.push(BacklinkEntry {
+                title: source.title.clone(),
+                slug: source.slug.clone(),
+                href: source.href.clone(),
+                context,
+                more,
+            });
+        }
+    }
+
+    items.sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase()));
+    let has_backlinks = !items.is_empty();
+
+    BacklinksContext {
+        title: cfg.title.clone(),
+        items,
+        empty_text: cfg.empty_text.clone(),
+        hide_when_empty: cfg.hide_when_empty,
+        has_backlinks,
+    }
+}
+
+/// A page linking to some target, with each of its mentions of that target quoted.
+struct BacklinkSource {
+    source_slug: String,
+    /// `source_slug` without a trailing `/index`.
+    slug: String,
+    href: String,
+    title: String,
+    /// Empty for encrypted sources, whose text stays hidden even around a link.
+    contexts: Vec<String>,
+    mentions: usize,
+}
+
+/// Link target, as [`backlink_targets`] spells it, to the pages linking there.
+type BacklinkIndex = std::collections::HashMap<String, Vec<BacklinkSource>>;
+
+/// The backlink index, rebuilt when the content mtime moves (the same signal the nav
+/// cache uses), so a page request costs a hash lookup rather than a walk of the tree.
+fn backlink_index(engine: &TrellisEngine, latest: SystemTime) -> Arc<BacklinkIndex> {
+    static BACKLINK_CACHE: OnceLock<RwLock<BacklinkCache>> = OnceLock::new();
+    let cache = BACKLINK_CACHE.get_or_init(|| {
+        RwLock::new(BacklinkCache {
+            mtime: SystemTime::UNIX_EPOCH,
+            index: None,
+        })
+    });
+
+    if let Ok(guard) = cache.read() {
+        if guard.mtime >= latest {
+            if let Some(index) = &guard.index {
+                return Arc::clone(index);
+            }
+        }
+    }
+
+    let _span = tracing::debug_span!("backlink_index").entered();
+    let started = std::time::Instant::now();
+    let index = Arc::new(compute_backlink_index(engine));
+    debug!(
+        "backlink index built for {} targets in {:?}",
+        index.len(),
+        started.elapsed()
+    );
+    if let Ok(mut guard) = cache.write() {
+        if latest >= guard.mtime {
+            guard.mtime = latest;
+            guard.index = Some(Arc::clone(&index));
+        }
+    }
+    index
+}
+
+struct BacklinkCache {
+    mtime: SystemTime,
+    index: Option<Arc<BacklinkIndex>>,
+}
 
-    let mut items = Vec::new();
+fn compute_backlink_index(engine: &TrellisEngine) -> BacklinkIndex {
+    let content_root = engine.content_root();
+    let slugs = content_slugs(
+        content_root,
+        engine.ignore_matcher(),
+        &engine.config.content,
+    );
+    let mut index = BacklinkIndex::new();
 
     for entry in WalkDir::new(content_root)
         .into_iter()
-        .filter_entry(|e| !is_ignored(e.path(), content_root, ignore_patterns))
+        .filter_entry(|e| !engine.is_ignored_path(e.path()))
         .filter_map(Result::ok)
         .filter(|e| e.path().is_file())
     {
-        if entry
-            .path()
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|ext| ext.eq_ignore_ascii_case("md"))
-            != Some(true)
-        {
+        if !engine.config.content.is_content_file(entry.path()) {
             continue;
         }
 
         let source_slug = slug_from_path(entry.path(), content_root);
-        if source_slug == current_slug {
-            continue;
-        }
-
         let Ok(content) = fs::read_to_string(entry.path()) else {
             continue;
         };
+        let page = Page::new(source_slug.clone(), entry.path().to_path_buf(), content);
+        let Ok(Some(page)) = engine.registry().front_matter(page) else {
+            continue;
+        };
+        // An encrypted note's links would reveal what it is about.
+        if engine
+            .config
+            .encryption
+            .protects(&source_slug, &page.frontmatter)
+        {
+            continue;
+        }
 
-        let links = extract_links(&content);
-        if !links.iter().any(|link| targets.contains(link)) {
+        // Links inside %% comments %% are not mentions.
+        let body = strip_comments(&page.content);
+        let mentions = link_mentions(&body, &source_slug, &slugs);
+        if mentions.is_empty() {
             continue;
         }
 
@@ -654,55 +2373,335 @@ fn backlinks_context(engine: &TrellisEngine, current_slug: &str) -> BacklinksCon
             format!("/{}", backlink_slug)
         };
 
-        let title = frontmatter_title(entry.path()).unwrap_or_else(|| {
-            humanize_segment(backlink_slug.rsplit('/').next().unwrap_or(&backlink_slug))
-        });
+        let title = display_name(
+            &engine.config.content,
+            content_root,
+            &backlink_slug,
+            page.frontmatter.title.clone(),
+        );
 
-        items.push(BacklinkEntry {
-            title,
-            slug: backlink_slug,
-            href,
-        });
+        let mut by_target: std::collections::BTreeMap<&str, Vec<&LinkMention>> =
+            std::collections::BTreeMap::new();
+        for mention in &mentions {
+            by_target
+                .entry(mention.target.as_str())
+                .or_default()
+                .push(mention);
+        }
+        for (target, matching) in by_target {
+            let contexts = matching
+                .iter()
+                .filter_map(|mention| mention_context(&body, &mentions, mention))
+                .collect();
+            index
+                .entry(target.to_string())
+                .or_default()
+                .push(BacklinkSource {
+                    source_slug: source_slug.clone(),
+                    slug: backlink_slug.clone(),
+                    href: href.clone(),
+                    title: title.clone(),
+                    contexts,
+                    mentions: matching.len(),
+                });
+        }
     }
 
-    items.sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase()));
-    let has_backlinks = !items.is_empty();
-    let cfg = &engine.config.layout.backlinks;
+    index
+}
 
-    BacklinksContext {
-        title: cfg.title.clone(),
-        items,
-        empty_text: cfg.empty_text.clone(),
-        hide_when_empty: cfg.hide_when_empty,
-        has_backlinks,
+fn resources_context(config: &SiteConfig, slug: &str) -> ResourcesContext {
+    let resources = config.plugins.page_resources(&relative_root(slug));
+
+    let css = resources
+        .css
+        .into_iter()
+        .map(|css| {
+            let (href, inline) = if css.inline {
+                (None, Some(css.content))
+            } else {
+                (Some(css.content), None)
+            };
+            CssTag {
+                href,
+                inline,
+                spa_preserve: css.spa_preserve,
+            }
+        })
+        .collect();
+
+    let mut head_js = Vec::new();
+    let mut body_js = Vec::new();
+    for js in resources.js {
+        let tag = match js.content_type {
+            JsContentType::External => ScriptTag {
+                src: js.src,
+                script: None,
+                module_type: js.module_type,
+                spa_preserve: js.spa_preserve,
+            },
+            JsContentType::Inline => ScriptTag {
+                src: None,
+                script: js.script,
+                module_type: js.module_type,
+                spa_preserve: js.spa_preserve,
+            },
+        };
+        match js.load_time {
+            JsLoadTime::BeforeDomReady => head_js.push(tag),
+            JsLoadTime::AfterDomReady => body_js.push(tag),
+        }
+    }
+
+    ResourcesContext {
+        css,
+        head_js,
+        body_js,
+        additional_head: resources.additional_head,
+    }
+}
+
+/// Relative path from the page at `slug` back to the site root: `.` at the top level,
+/// then `..` per folder.
+fn relative_root(slug: &str) -> String {
+    let depth = slug.trim_matches('/').matches('/').count();
+    if depth == 0 {
+        ".".into()
+    } else {
+        vec![".."; depth].join("/")
     }
 }
 
 fn footer_context(config: &SiteConfig) -> FooterContext {
-    let links = links_from_config(&config.layout.footer.links);
+    let footer = &config.layout.footer;
     FooterContext {
         year: Utc::now().year(),
         version: env!("CARGO_PKG_VERSION").to_string(),
-        links,
+        show_credit: footer.show_credit,
+        links: links_from_config(&footer.links),
+        blurb: footer_blurb(footer.markdown.as_deref(), footer.html.as_deref()),
+        socials: footer
+            .socials
+            .iter()
+            .filter(|social| !social.url.trim().is_empty())
+            .map(|social| {
+                let icon = socials::icon(&social.platform);
+                SocialContext {
+                    label: social
+                        .label
+                        .clone()
+                        .unwrap_or_else(|| icon.label.to_string()),
+                    href: social.url.clone(),
+                    icon: icon.svg,
+                }
+            })
+            .collect(),
+    }
+}
+
+/// The footer blurb: `markdown` rendered with raw HTML escaped, then `html` as written.
+fn footer_blurb(markdown: Option<&str>, html: Option<&str>) -> Option<String> {
+    let mut blurb = String::new();
+    if let Some(src) = markdown.map(str::trim).filter(|src| !src.is_empty()) {
+        // CompileOptions::gfm() leaves allow_dangerous_html off, so inline tags are escaped.
+        blurb.push_str(
+            &markdown::to_html_with_options(src, &markdown::Options::gfm()).unwrap_or_default(),
+        );
+    }
+    if let Some(raw) = html.map(str::trim).filter(|raw| !raw.is_empty()) {
+        blurb.push_str(raw);
     }
+    (!blurb.is_empty()).then_some(blurb)
 }
 
-fn links_from_config(links: &BTreeMap<String, String>) -> Option<Vec<FooterLink>> {
+fn links_from_config(links: &FooterLinks) -> Option<Vec<FooterLink>> {
     if links.is_empty() {
         return None;
     }
-
-    Some(
-        links
+
+    Some(
+        links
+            .iter()
+            .map(|link| FooterLink {
+                text: link.text.clone(),
+                href: link.href.clone(),
+            })
+            .collect(),
+    )
+}
+
+/// The trail from the site root to the page at `slug`: root label, one crumb per
+/// ancestor folder (titled from its `index` frontmatter), then the page itself. A folder's
+/// own index page ends the trail instead of appearing twice; the root index has none.
+fn breadcrumbs(engine: &TrellisEngine, slug: &str, title: &str) -> Vec<Crumb> {
+    let cfg = &engine.config.layout.breadcrumbs;
+    let path = slug.trim_matches('/');
+    let path = path.strip_suffix("/index").unwrap_or(path);
+    if path.is_empty() || path == "index" {
+        return Vec::new();
+    }
+
+    let segments: Vec<&str> = path.split('/').collect();
+    let Some((current, folders)) = segments.split_last() else {
+        return Vec::new();
+    };
+
+    let urls = engine.urls();
+    let mut crumbs = vec![Crumb {
+        title: cfg.root_label.clone(),
+        href: Some(urls.canonical("index")),
+    }];
+    for depth in 1..=folders.len() {
+        let folder = folders[..depth].join("/");
+        let index_title = engine
+            .config
+            .content
+            .find_source(&engine.content_root().join(&folder).join("index"))
+            .and_then(|path| frontmatter_title(&path));
+        let title = display_name(
+            &engine.config.content,
+            engine.content_root(),
+            &folder,
+            index_title,
+        );
+        crumbs.push(Crumb {
+            title,
+            href: Some(urls.canonical(&format!("{folder}/index"))),
+        });
+    }
+
+    if cfg.show_current_page {
+        let title = if title.is_empty() {
+            display_name(&engine.config.content, engine.content_root(), path, None)
+        } else {
+            title.to_string()
+        };
+        crumbs.push(Crumb { title, href: None });
+    }
+    crumbs
+}
+
+fn breadcrumbs_json_ld(crumbs: &[Crumb]) -> Option<String> {
+    if crumbs.is_empty() {
+        return None;
+    }
+    let items: Vec<_> = crumbs
+        .iter()
+        .enumerate()
+        .map(|(i, crumb)| {
+            let mut item = json!({
+                "@type": "ListItem",
+                "position": i + 1,
+                "name": crumb.title,
+            });
+            if let Some(href) = &crumb.href {
+                item["item"] = json!(href);
+            }
+            item
+        })
+        .collect();
+    let ld = json!({
+        "@context": "https://schema.org",
+        "@type": "BreadcrumbList",
+        "itemListElement": items,
+    });
+    // `</script>` inside a string would end the element early.
+    Some(ld.to_string().replace("</", "<\\/"))
+}
+
+/// Characters of text quoted around a backlink mention.
+const MENTION_CONTEXT_CHARS: usize = 160;
+/// Lines shorter than this (list items, bare links) are quoted with their neighbours.
+const MENTION_SHORT_LINE: usize = 40;
+const MARK_OPEN: char = '\u{E000}';
+const MARK_CLOSE: char = '\u{E001}';
+
+/// Obsidian `%% comments %%` removed; an unclosed one runs to the end.
+fn strip_comments(body: &str) -> String {
+    static COMMENT: OnceLock<regex::Regex> = OnceLock::new();
+    COMMENT
+        .get_or_init(|| regex::Regex::new(r"(?s)%%.*?(?:%%|\z)").expect("valid pattern"))
+        .replace_all(body, "")
+        .into_owned()
+}
+
+/// The line holding `mention` (and its neighbours when short) as plain text, escaped, with
+/// the mention in `<mark>` and cut to about [`MENTION_CONTEXT_CHARS`] around it. Other
+/// links in the quote are reduced to their labels.
+fn mention_context(body: &str, mentions: &[LinkMention], mention: &LinkMention) -> Option<String> {
+    let line_start = |at: usize| body[..at].rfind('\n').map_or(0, |nl| nl + 1);
+    let line_end = |at: usize| body[at..].find('\n').map_or(body.len(), |nl| at + nl);
+    let mut start = line_start(mention.span.start);
+    let mut end = line_end(mention.span.end);
+    if body[start..end].trim().chars().count() < MENTION_SHORT_LINE {
+        start = line_start(start.saturating_sub(1));
+        end = line_end((end + 1).min(body.len()));
+    }
+
+    let mut source = String::new();
+    let mut pos = start;
+    for other in mentions
+        .iter()
+        .filter(|other| other.span.start >= pos && other.span.end <= end)
+    {
+        source.push_str(&body[pos..other.span.start]);
+        let quoted = other.span == mention.span;
+        if quoted {
+            source.push(MARK_OPEN);
+        }
+        source.push_str(&other.label);
+        if quoted {
+            source.push(MARK_CLOSE);
+        }
+        pos = other.span.end;
+    }
+    source.push_str(&body[pos..end]);
+
+    let text: Vec<char> = markdown_text(&source)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .collect();
+    let open = text.iter().position(|&c| c == MARK_OPEN)?;
+    let close = text.iter().position(|&c| c == MARK_CLOSE)?;
+
+    // Start a little before the mention, on a word boundary, and keep the whole mention.
+    let mut from = open.saturating_sub(MENTION_CONTEXT_CHARS / 3);
+    if from > 0 {
+        from = text[from..open]
+            .iter()
+            .position(|&c| c == ' ')
+            .map_or(open, |space| from + space + 1);
+    }
+    let mut to = (from + MENTION_CONTEXT_CHARS)
+        .max(close + 1)
+        .min(text.len());
+    if to < text.len() {
+        to = text[close + 1..to]
             .iter()
-            .map(|(text, href)| FooterLink {
-                text: text.to_string(),
-                href: href.to_string(),
-            })
-            .collect(),
-    )
+            .rposition(|&c| c == ' ')
+            .map_or(to, |space| close + 1 + space);
+    }
+
+    let quote: String = text[from..to].iter().collect();
+    let mut html = String::new();
+    if from > 0 {
+        html.push('…');
+    }
+    html.push_str(
+        &escape_html(quote.trim())
+            .replace(MARK_OPEN, "<mark>")
+            .replace(MARK_CLOSE, "</mark>"),
+    );
+    if to < text.len() {
+        html.push('…');
+    }
+    Some(html)
 }
 
+/// Index keys a link to `slug` can be recorded under: the slug itself, the slug without
+/// `/index`, and `.` for the home page.
 fn backlink_targets(slug: &str) -> Vec<String> {
     let mut targets = Vec::new();
     let mut normalized = slug.trim_matches('/').to_string();
@@ -729,7 +2728,8 @@ fn backlink_targets(slug: &str) -> Vec<String> {
     targets
 }
 
-fn frontmatter_title(path: &Path) -> Option<String> {
+/// The raw frontmatter keys of the file at `path`, without running the plugin pipeline.
+fn frontmatter_map(path: &Path) -> Option<std::collections::HashMap<String, serde_yaml::Value>> {
     let content = fs::read_to_string(path).ok()?;
     let mut lines = content.lines();
     if lines.next()? != "---" {
@@ -747,9 +2747,11 @@ fn frontmatter_title(path: &Path) -> Option<String> {
         return None;
     }
 
-    let yaml = fm.join("\n");
-    serde_yaml::from_str::<serde_yaml::Value>(&yaml)
-        .ok()?
+    serde_yaml::from_str(&fm.join("\n")).ok()
+}
+
+fn frontmatter_title(path: &Path) -> Option<String> {
+    frontmatter_map(path)?
         .get("title")
         .and_then(|v| v.as_str())
         .map(|s| s.to_string())
@@ -759,24 +2761,28 @@ fn is_false(b: &bool) -> bool {
     !*b
 }
 
-fn build_nav_from_content(config: &SiteConfig, current_slug: &str) -> Vec<NavItem> {
-    let content_root = resolve_path(
-        Path::new(env!("CARGO_MANIFEST_DIR")),
-        &config.paths.content_root,
-    );
-    let latest = latest_mtime_recursive(&content_root, &config.configuration.ignore_patterns);
+/// The nav and the explorer tree. `latest` is the newest mtime under the content root;
+/// both are rebuilt when it moves.
+fn build_nav_from_content(
+    engine: &TrellisEngine,
+    current_slug: &str,
+    latest: SystemTime,
+) -> (Vec<NavItem>, Arc<Vec<ExplorerEntry>>) {
+    let content_root = engine.content_root();
+    let ignore = engine.ignore_matcher();
 
     static NAV_CACHE: OnceLock<RwLock<NavCache>> = OnceLock::new();
     let cache = NAV_CACHE.get_or_init(|| {
         RwLock::new(NavCache {
             mtime: SystemTime::UNIX_EPOCH,
             nav: Vec::new(),
+            tree: Arc::default(),
         })
     });
 
     let cached = if let Ok(guard) = cache.read() {
         if guard.mtime >= latest {
-            Some(guard.nav.clone())
+            Some((guard.nav.clone(), guard.tree.clone()))
         } else {
             None
         }
@@ -784,38 +2790,712 @@ fn build_nav_from_content(config: &SiteConfig, current_slug: &str) -> Vec<NavIte
         None
     };
 
-    let mut nav = cached.unwrap_or_else(|| {
-        let computed = compute_nav(&content_root, &config.configuration.ignore_patterns);
+    let (mut nav, tree) = cached.unwrap_or_else(|| {
+        let (nav, tree) = compute_nav(
+            content_root,
+            ignore,
+            engine.registry(),
+            &engine.config.content,
+            &engine.config.encryption,
+        );
+        let tree = Arc::new(tree);
 
         if let Ok(mut guard) = cache.write() {
             // Only replace if fresher; avoids races with concurrent builders
             if latest >= guard.mtime {
                 guard.mtime = latest;
-                guard.nav = computed.clone();
+                guard.nav = nav.clone();
+                guard.tree = tree.clone();
             }
         }
 
-        computed
+        (nav, tree)
     });
 
+    cap_nav_depth(&mut nav, engine.config.layout.explorer.max_depth, 1);
     mark_nav_open(&mut nav, current_slug);
 
-    nav
+    (nav, tree)
+}
+
+/// The first `Comments` component in the content layout, unless this is an index or list
+/// page or the page sets `comments: false`. Without the component, `comments: true` turns
+/// on native comments.
+fn comments_context(
+    engine: &TrellisEngine,
+    layout: &LayoutContext,
+    page: &RenderedPage,
+) -> Option<CommentsContext> {
+    let slug = page.slug.trim_matches('/');
+    if slug == "index" || slug.ends_with("/index") || page.frontmatter.comments == Some(false) {
+        return None;
+    }
+    let Some(cfg) = [
+        &layout.content.before_body,
+        &layout.content.left,
+        &layout.content.right,
+        &layout.content.after_body,
+        &layout.shared.after_body,
+    ]
+    .into_iter()
+    .find_map(|list| find_comments(list)) else {
+        return (page.frontmatter.comments == Some(true))
+            .then(|| native_comments_context(engine, page))
+            .flatten();
+    };
+
+    let lang = cfg.lang.clone().unwrap_or_else(|| {
+        let locale = &engine.config.configuration.locale;
+        locale
+            .split(['-', '_'])
+            .next()
+            .filter(|lang| !lang.is_empty())
+            .unwrap_or("en")
+            .to_string()
+    });
+    let flag = |on: bool| if on { "1" } else { "0" };
+    let (mut attrs, light, dark): (Vec<(&str, String)>, _, _) = match cfg.provider {
+        CommentsProvider::Giscus => (
+            vec![
+                ("data-repo", cfg.repo.clone()),
+                ("data-repo-id", cfg.repo_id.clone()),
+                ("data-category", cfg.category.clone()),
+                ("data-category-id", cfg.category_id.clone()),
+                ("data-mapping", cfg.mapping.clone()),
+                ("data-strict", flag(cfg.strict).into()),
+                ("data-reactions-enabled", flag(cfg.reactions_enabled).into()),
+                ("data-emit-metadata", "0".into()),
+                ("data-input-position", cfg.input_position.clone()),
+                ("data-lang", lang),
+                ("data-loading", "lazy".into()),
+            ],
+            "light",
+            "dark",
+        ),
+        CommentsProvider::Utterances => (
+            vec![
+                ("repo", cfg.repo.clone()),
+                ("issue-term", cfg.mapping.clone()),
+            ],
+            "github-light",
+            "github-dark",
+        ),
+        CommentsProvider::Native => return native_comments_context(engine, page),
+    };
+    if let (CommentsProvider::Utterances, Some(label)) = (cfg.provider, &cfg.label) {
+        attrs.push(("label", label.clone()));
+    }
+    let attrs: serde_json::Map<String, serde_json::Value> = attrs
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.into()))
+        .collect();
+
+    Some(CommentsContext {
+        provider: cfg.provider,
+        src: cfg.provider.script_src(),
+        attrs_json: serde_json::Value::Object(attrs).to_string(),
+        light_theme: cfg.light_theme.clone().unwrap_or_else(|| light.into()),
+        dark_theme: cfg.dark_theme.clone().unwrap_or_else(|| dark.into()),
+        native: None,
+    })
+}
+
+/// Native comments for `page`; none on encrypted notes, whose thread would be public.
+fn native_comments_context(engine: &TrellisEngine, page: &RenderedPage) -> Option<CommentsContext> {
+    if page.frontmatter.encrypted.unwrap_or(false) {
+        return None;
+    }
+    let cfg = &engine.config.native_comments;
+    Some(CommentsContext {
+        provider: CommentsProvider::Native,
+        src: None,
+        attrs_json: "{}".into(),
+        light_theme: String::new(),
+        dark_theme: String::new(),
+        native: Some(NativeCommentsContext {
+            slug: page.slug.trim_matches('/').to_string(),
+            comments: Vec::new(),
+            max_name_chars: cfg.max_name_chars,
+            max_body_chars: cfg.max_body_chars,
+            require_approval: cfg.require_approval,
+        }),
+    })
+}
+
+/// Whether `slug` is a page showing native comments, so its thread may be read and added to.
+fn native_comments_on(engine: &TrellisEngine, slug: &str) -> bool {
+    if !engine.page_exists(slug) {
+        return false;
+    }
+    let Ok(page) = engine.render_page(slug) else {
+        return false;
+    };
+    let layout = LayoutContext::for_page(engine, &page);
+    comments_context(engine, &layout, &page)
+        .is_some_and(|comments| comments.provider == CommentsProvider::Native)
+}
+
+fn find_comments(list: &[LayoutComponent]) -> Option<&CommentsConfig> {
+    components(list)
+        .into_iter()
+        .find_map(|component| match component {
+            LayoutComponent::Comments(cfg) => Some(cfg),
+            _ => None,
+        })
+}
+
+/// The notes before and after `current_slug` in its folder, ordered as the first
+/// `PrevNext` component in the content layout asks. `None` without the component, on
+/// folder index pages, and for a note with no siblings.
+fn prev_next_context(
+    engine: &TrellisEngine,
+    layout: &LayoutContext,
+    current_slug: &str,
+    content_mtime: SystemTime,
+) -> Option<PrevNextContext> {
+    let slug = current_slug.trim_matches('/');
+    if slug == "index" || slug.ends_with("/index") {
+        return None;
+    }
+    let cfg = [
+        &layout.content.before_body,
+        &layout.content.left,
+        &layout.content.right,
+        &layout.content.after_body,
+        &layout.shared.after_body,
+    ]
+    .into_iter()
+    .find_map(|list| find_prev_next(list))?;
+
+    let folder = |slug: &str| slug.rsplit_once('/').map_or("", |(folder, _)| folder);
+    let notes = note_summaries(engine, content_mtime);
+    let mut siblings: Vec<&NoteSummary> = notes
+        .iter()
+        .filter(|note| folder(&note.slug) == folder(slug))
+        .filter(|note| note.slug != "index" && !note.slug.ends_with("/index"))
+        .collect();
+    match cfg.order {
+        PrevNextOrder::Name => siblings.sort_by(|a, b| a.slug.cmp(&b.slug)),
+        PrevNextOrder::Date => siblings.sort_by(|a, b| {
+            (a.date.is_none(), a.date)
+                .cmp(&(b.date.is_none(), b.date))
+                .then_with(|| a.slug.cmp(&b.slug))
+        }),
+    }
+
+    let position = siblings.iter().position(|note| note.slug == slug)?;
+    let urls = engine.urls();
+    let link = |note: &NoteSummary| PageLink {
+        title: note.title.clone(),
+        href: urls.canonical(&note.slug),
+    };
+    let prev = position
+        .checked_sub(1)
+        .and_then(|i| siblings.get(i))
+        .map(|note| link(note));
+    let next = siblings.get(position + 1).map(|note| link(note));
+    (prev.is_some() || next.is_some()).then_some(PrevNextContext { prev, next })
+}
+
+fn find_prev_next(list: &[LayoutComponent]) -> Option<&PrevNextConfig> {
+    components(list)
+        .into_iter()
+        .find_map(|component| match component {
+            LayoutComponent::PrevNext(cfg) => Some(cfg),
+            _ => None,
+        })
+}
+
+/// Every non-empty `Banner` in `shared.header`, with its markdown rendered inline.
+fn banners_context(header: &[LayoutComponent]) -> Vec<BannerContext> {
+    collect_banners(header)
+        .into_iter()
+        .filter(|cfg| !cfg.markdown.trim().is_empty())
+        .map(|cfg| {
+            let html = render_md(cfg.markdown.trim());
+            // A single paragraph is unwrapped so the text sits inline with the dismiss button.
+            let html = match html
+                .trim()
+                .strip_prefix("<p>")
+                .and_then(|h| h.strip_suffix("</p>"))
+            {
+                Some(inner) if !inner.contains("<p>") => inner.to_string(),
+                _ => html,
+            };
+            BannerContext {
+                id: cfg.id.clone(),
+                html,
+                dismissible: cfg.dismissible,
+                style: cfg.style,
+            }
+        })
+        .collect()
+}
+
+fn collect_banners(list: &[LayoutComponent]) -> Vec<&BannerConfig> {
+    components(list)
+        .into_iter()
+        .filter_map(|component| match component {
+            LayoutComponent::Banner(cfg) => Some(cfg),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Built from the first `ContentMeta` component in the content layout, or the defaults.
+fn content_meta_context(
+    engine: &TrellisEngine,
+    layout: &PageLayout,
+    page: &RenderedPage,
+) -> ContentMetaContext {
+    let default_cfg = ContentMetaConfig::default();
+    let cfg = [&layout.before_body, &layout.left, &layout.right]
+        .into_iter()
+        .find_map(|list| find_content_meta(list))
+        .unwrap_or(&default_cfg);
+    let locale = &engine.config.configuration.locale;
+    let meta = &page.frontmatter;
+
+    let date_segment = |date: Option<DateTime<Utc>>| {
+        date.map(|date| MetaSegment {
+            text: format_date(date, &cfg.date_format, locale),
+            datetime: Some(date.to_rfc3339()),
+        })
+    };
+    let words = meta.word_count.unwrap_or_else(|| count_words(&page.html));
+    let mut segments = Vec::new();
+
+    if cfg.show_date {
+        segments.extend(date_segment(engine.page_date(&page.slug, meta)));
+    }
+    if cfg.show_created {
+        segments.extend(date_segment(meta.created));
+    }
+    if cfg.show_updated {
+        segments.extend(date_segment(meta.updated));
+    }
+    if cfg.show_reading_time && words > 0 {
+        segments.push(MetaSegment {
+            text: reading_time(words),
+            datetime: None,
+        });
+    }
+    if cfg.show_word_count && words > 0 {
+        segments.push(MetaSegment {
+            text: format!("{words} words"),
+            datetime: None,
+        });
+    }
+
+    ContentMetaContext {
+        separator: cfg.separator.clone(),
+        segments,
+    }
+}
+
+fn find_content_meta(list: &[LayoutComponent]) -> Option<&ContentMetaConfig> {
+    components(list)
+        .into_iter()
+        .find_map(|component| match component {
+            LayoutComponent::ContentMeta(cfg) => Some(cfg),
+            _ => None,
+        })
+}
+
+fn reading_time(words: u64) -> String {
+    format!("{} min read", reading_minutes(words))
+}
+
+/// The sidebars of `layout` and the stylesheet for their `Flex` components (empty when
+/// there are none).
+fn sidebars_context(
+    engine: &TrellisEngine,
+    layout: &PageLayout,
+    current_slug: &str,
+    latest: SystemTime,
+) -> (SidebarSlots, Option<String>) {
+    let mut wanted = collect_recent_notes(&layout.left);
+    wanted.extend(collect_recent_notes(&layout.right));
+    // Listing notes walks the whole content tree; skip it unless a sidebar shows them.
+    let notes = if wanted.is_empty() {
+        Arc::default()
+    } else {
+        note_summaries(engine, latest)
+    };
+
+    let mut slots = SlotBuilder {
+        recent_notes: |cfg: &RecentNotesConfig| recent_notes(engine, cfg, &notes, current_slug),
+        newsletter: engine.config.newsletter.enabled,
+        css: String::new(),
+        flexes: 0,
+    };
+    let sidebars = SidebarSlots {
+        left: slots.nodes(&layout.left),
+        right: slots.nodes(&layout.right),
+    };
+    (sidebars, Some(slots.css).filter(|css| !css.is_empty()))
+}
+
+/// Turns layout components into [`SlotNode`]s, numbering `Flex` components as it meets
+/// them and writing their rules to `css`.
+struct SlotBuilder<F> {
+    recent_notes: F,
+    /// Whether `Subscribe` components have an endpoint to post to.
+    newsletter: bool,
+    css: String,
+    flexes: usize,
+}
+
+impl<F: Fn(&RecentNotesConfig) -> RecentNotesContext> SlotBuilder<F> {
+    fn nodes(&mut self, list: &[LayoutComponent]) -> Vec<SlotNode> {
+        list.iter()
+            .filter_map(|component| self.node(component))
+            .collect()
+    }
+
+    fn node(&mut self, component: &LayoutComponent) -> Option<SlotNode> {
+        let node = match component {
+            LayoutComponent::PageTitle => SlotNode {
+                page_title: true,
+                ..SlotNode::default()
+            },
+            LayoutComponent::Spacer => SlotNode {
+                spacer: true,
+                ..SlotNode::default()
+            },
+            LayoutComponent::Search => SlotNode {
+                search: true,
+                ..SlotNode::default()
+            },
+            LayoutComponent::Explorer(_) => SlotNode {
+                explorer: true,
+                ..SlotNode::default()
+            },
+            LayoutComponent::Graph => SlotNode {
+                graph: true,
+                ..SlotNode::default()
+            },
+            LayoutComponent::TableOfContents => SlotNode {
+                toc: true,
+                ..SlotNode::default()
+            },
+            LayoutComponent::Backlinks(_) => SlotNode {
+                backlinks: true,
+                ..SlotNode::default()
+            },
+            LayoutComponent::RecentNotes(cfg) => SlotNode {
+                recent_notes: Some((self.recent_notes)(cfg)),
+                ..SlotNode::default()
+            },
+            LayoutComponent::Subscribe(cfg) if self.newsletter => SlotNode {
+                subscribe: Some(cfg.clone()),
+                ..SlotNode::default()
+            },
+            LayoutComponent::Flex(cfg) => self.flex(cfg)?,
+            LayoutComponent::MobileOnly(inner) => SlotNode {
+                breakpoint: Some("mobile-only"),
+                child: Some(Box::new(self.node(inner)?)),
+                ..SlotNode::default()
+            },
+            LayoutComponent::DesktopOnly(inner) => SlotNode {
+                breakpoint: Some("desktop-only"),
+                child: Some(Box::new(self.node(inner)?)),
+                ..SlotNode::default()
+            },
+            // Resolved by `LayoutContext::for_page` before the sidebars are built.
+            LayoutComponent::ConditionalRender(_) => return None,
+            // Darkmode and ReaderMode have no partial yet; the rest have a fixed place in
+            // the page rather than a sidebar slot.
+            _ => return None,
+        };
+        Some(node)
+    }
+
+    fn flex(&mut self, cfg: &FlexConfig) -> Option<SlotNode> {
+        self.flexes += 1;
+        let class = format!("flex-{}", self.flexes);
+        let mut items = Vec::new();
+        let mut rules = css_rule(
+            &format!(".{class}"),
+            &[
+                ("flex-direction", cfg.direction.as_deref()),
+                ("flex-wrap", cfg.wrap.as_deref()),
+                ("gap", cfg.gap.as_deref()),
+            ],
+        );
+        for (index, item) in cfg.components.iter().enumerate() {
+            let Some(node) = self.node(&item.component) else {
+                continue;
+            };
+            let item_class = format!("{class}-{index}");
+            let order = item.order.map(|order| order.to_string());
+            rules.push_str(&css_rule(
+                &format!(".{item_class}"),
+                &[
+                    ("flex-grow", Some(if item.grow { "1" } else { "0" })),
+                    ("flex-shrink", Some(if item.shrink { "1" } else { "0" })),
+                    ("flex-basis", item.basis.as_deref()),
+                    ("order", order.as_deref()),
+                    ("align-self", item.align.as_deref().map(flex_alignment)),
+                    ("justify-self", item.justify.as_deref().map(flex_alignment)),
+                ],
+            ));
+            items.push(FlexItemContext {
+                class: item_class,
+                node,
+            });
+        }
+        if items.is_empty() {
+            return None;
+        }
+
+        self.css.push_str(&rules);
+        Some(SlotNode {
+            flex: Some(FlexContext { class, items }),
+            ..SlotNode::default()
+        })
+    }
+}
+
+/// `start`/`end` as flexbox spells them; other values pass through.
+fn flex_alignment(value: &str) -> &str {
+    match value {
+        "start" => "flex-start",
+        "end" => "flex-end",
+        other => other,
+    }
+}
+
+/// One CSS rule from the declarations that are set. Values that could close the rule or
+/// the `<style>` element are dropped; validation reports them.
+fn css_rule(selector: &str, declarations: &[(&str, Option<&str>)]) -> String {
+    let body: String = declarations
+        .iter()
+        .filter_map(|(property, value)| Some((property, value.map(str::trim)?)))
+        .filter(|(_, value)| !value.is_empty() && is_safe_css_value(value))
+        .map(|(property, value)| format!("{property}:{value};"))
+        .collect();
+    if body.is_empty() {
+        String::new()
+    } else {
+        format!("{selector}{{{body}}}\n")
+    }
+}
+
+fn collect_recent_notes(list: &[LayoutComponent]) -> Vec<&RecentNotesConfig> {
+    components(list)
+        .into_iter()
+        .filter_map(|component| match component {
+            LayoutComponent::RecentNotes(cfg) => Some(cfg),
+            _ => None,
+        })
+        .collect()
+}
+
+fn recent_notes(
+    engine: &TrellisEngine,
+    cfg: &RecentNotesConfig,
+    notes: &[NoteSummary],
+    current_slug: &str,
+) -> RecentNotesContext {
+    let folder = cfg
+        .folder
+        .as_deref()
+        .map(|folder| format!("{}/", folder.trim_matches('/')));
+    let matching: Vec<&NoteSummary> = notes
+        .iter()
+        .filter(|note| note.slug != current_slug)
+        .filter(|note| {
+            folder
+                .as_deref()
+                .is_none_or(|prefix| note.slug.starts_with(prefix))
+        })
+        .filter(|note| {
+            cfg.tag
+                .as_deref()
+                .is_none_or(|tag| note.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+        })
+        .collect();
+
+    let urls = engine.urls();
+    let items = matching
+        .iter()
+        .take(cfg.limit)
+        .map(|note| RecentNote {
+            title: note.title.clone(),
+            href: urls.canonical(&note.slug),
+            date: note
+                .date
+                .map(|d| d.format("%Y-%m-%d").to_string())
+                .unwrap_or_default(),
+            description: note.description.clone(),
+        })
+        .collect();
+    let remaining = matching.len().saturating_sub(cfg.limit);
+
+    let see_more = (cfg.show_see_more && remaining > 0).then(|| {
+        if let Some(tag) = &cfg.tag {
+            urls.absolute(&format!("tags/{tag}"))
+        } else if let Some(folder) = &cfg.folder {
+            urls.canonical(&format!("{}/index", folder.trim_matches('/')))
+        } else {
+            urls.canonical("index")
+        }
+    });
+
+    RecentNotesContext {
+        title: cfg.title.clone(),
+        items,
+        see_more,
+        remaining,
+    }
+}
+
+/// Every published note, newest first, rebuilt when the content mtime moves (the same
+/// signal the nav cache uses).
+fn note_summaries(engine: &TrellisEngine, latest: SystemTime) -> Arc<Vec<NoteSummary>> {
+    static NOTES_CACHE: OnceLock<RwLock<NotesCache>> = OnceLock::new();
+    let cache = NOTES_CACHE.get_or_init(|| {
+        RwLock::new(NotesCache {
+            mtime: SystemTime::UNIX_EPOCH,
+            notes: None,
+        })
+    });
+
+    if let Ok(guard) = cache.read() {
+        if guard.mtime >= latest {
+            if let Some(notes) = &guard.notes {
+                return Arc::clone(notes);
+            }
+        }
+    }
+
+    let notes = Arc::new(compute_note_summaries(engine));
+    if let Ok(mut guard) = cache.write() {
+        if latest >= guard.mtime {
+            guard.mtime = latest;
+            guard.notes = Some(Arc::clone(&notes));
+        }
+    }
+    notes
+}
+
+struct NotesCache {
+    mtime: SystemTime,
+    notes: Option<Arc<Vec<NoteSummary>>>,
+}
+
+/// The search index, rebuilt when anything under the content root is newer than it.
+fn search_index(engine: &TrellisEngine) -> Arc<SearchIndex> {
+    static SEARCH_CACHE: OnceLock<RwLock<SearchCache>> = OnceLock::new();
+    let latest = latest_mtime_recursive(engine.content_root(), engine.ignore_matcher());
+    let cache = SEARCH_CACHE.get_or_init(|| {
+        RwLock::new(SearchCache {
+            mtime: SystemTime::UNIX_EPOCH,
+            index: None,
+        })
+    });
+
+    if let Ok(guard) = cache.read() {
+        if guard.mtime >= latest {
+            if let Some(index) = &guard.index {
+                return Arc::clone(index);
+            }
+        }
+    }
+
+    let index = Arc::new(SearchIndex::build(
+        engine.content_root(),
+        engine.ignore_matcher(),
+        engine.registry(),
+        &engine.config.content,
+        &engine.config.encryption,
+    ));
+    if let Ok(mut guard) = cache.write() {
+        if latest >= guard.mtime {
+            guard.mtime = latest;
+            guard.index = Some(Arc::clone(&index));
+        }
+    }
+    index
+}
+
+struct SearchCache {
+    mtime: SystemTime,
+    index: Option<Arc<SearchIndex>>,
+}
+
+fn compute_note_summaries(engine: &TrellisEngine) -> Vec<NoteSummary> {
+    let content_root = engine.content_root();
+    let date_type = engine.config.configuration.default_date_type;
+    let mut notes = Vec::new();
+
+    for entry in WalkDir::new(content_root)
+        .into_iter()
+        .filter_entry(|e| !engine.is_ignored_path(e.path()))
+        .filter_map(Result::ok)
+        .filter(|e| e.path().is_file())
+    {
+        if !engine.config.content.is_content_file(entry.path()) {
+            continue;
+        }
+        let slug = slug_from_path(entry.path(), content_root);
+        if slug == "index" || slug.starts_with("tags/") {
+            continue;
+        }
+        let Ok(content) = fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        let page = Page::new(slug.clone(), entry.path().to_path_buf(), content);
+        let Ok(Some(page)) = engine.registry().front_matter(page) else {
+            continue;
+        };
+
+        let meta = page.frontmatter;
+        let date = entry
+            .metadata()
+            .ok()
+            .and_then(|file| page_date(&meta, &file, date_type));
+        let title = display_name(
+            &engine.config.content,
+            content_root,
+            &slug,
+            meta.title.clone(),
+        );
+        // Listed like the content index lists it: a title, and no description or tags.
+        let encryption = &engine.config.encryption;
+        let protected = encryption.protects(&slug, &meta);
+        notes.push(NoteSummary {
+            title: if protected {
+                encryption.listed_title(title)
+            } else {
+                title
+            },
+            description: meta.description.clone().filter(|_| !protected),
+            date,
+            tags: meta.tags.clone().filter(|_| !protected).unwrap_or_default(),
+            slug,
+        });
+    }
+
+    notes.sort_by(|a, b| cmp_page_dates(a.date, b.date).then_with(|| a.slug.cmp(&b.slug)));
+    notes
 }
 
 struct NavCache {
     mtime: SystemTime,
     nav: Vec<NavItem>,
+    tree: Arc<Vec<ExplorerEntry>>,
 }
 
-fn latest_mtime_recursive(root: &Path, ignore_patterns: &[String]) -> SystemTime {
+fn latest_mtime_recursive(root: &Path, ignore: &IgnoreMatcher) -> SystemTime {
     let mut newest = fs::metadata(root)
         .and_then(|m| m.modified())
         .unwrap_or(SystemTime::UNIX_EPOCH);
 
     for entry in WalkDir::new(root)
         .into_iter()
-        .filter_entry(|e| !is_ignored(e.path(), root, ignore_patterns))
+        .filter_entry(|e| !ignore.is_ignored(e.path(), root))
         .filter_map(Result::ok)
     {
         if let Ok(meta) = entry.metadata() {
@@ -830,27 +3510,50 @@ fn latest_mtime_recursive(root: &Path, ignore_patterns: &[String]) -> SystemTime
     newest
 }
 
-fn compute_nav(content_root: &Path, ignore_patterns: &[String]) -> Vec<NavItem> {
-    let mut groups: std::collections::BTreeMap<String, Vec<String>> =
-        std::collections::BTreeMap::new();
+fn compute_nav(
+    content_root: &Path,
+    ignore: &IgnoreMatcher,
+    registry: &PluginRegistry,
+    content_cfg: &ContentConfig,
+    encryption: &EncryptionConfig,
+) -> (Vec<NavItem>, Vec<ExplorerEntry>) {
+    let mut trie = SlugTrie::default();
+    // Source of each slug; under `content.slugify` a slug is not a path to join.
+    let mut sources = std::collections::HashMap::new();
+    // Slugs of encrypted pages, listed under `encryption.placeholder_title`.
+    let mut protected = std::collections::HashSet::new();
 
     for entry in WalkDir::new(content_root)
         .into_iter()
-        .filter_entry(|e| !is_ignored(e.path(), content_root, ignore_patterns))
+        .filter_entry(|e| !ignore.is_ignored(e.path(), content_root))
         .filter_map(Result::ok)
         .filter(|e| e.path().is_file())
     {
-        if entry
-            .path()
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|ext| ext.eq_ignore_ascii_case("md"))
-            != Some(true)
-        {
+        let Some(format) = content_cfg.format_for(entry.path()) else {
             continue;
-        }
+        };
 
         let mut slug = slug_from_path(entry.path(), content_root);
+        sources
+            .entry(slug.clone())
+            .or_insert_with(|| entry.path().to_path_buf());
+        // Pages the registry's filters drop (drafts, for one) stay out, as in the content index.
+        let meta = if format == ContentFormat::Plain {
+            PageMetadata::default()
+        } else {
+            let Ok(content) = fs::read_to_string(entry.path()) else {
+                continue;
+            };
+            let page = Page::new(slug.clone(), entry.path().to_path_buf(), content);
+            match registry.front_matter(page) {
+                Ok(Some(page)) => page.frontmatter,
+                Ok(None) => continue,
+                Err(_) => PageMetadata::default(),
+            }
+        };
+        if encryption.protects(&slug, &meta) {
+            protected.insert(slug.clone());
+        }
         if slug.ends_with("/index") {
             slug.truncate(slug.len() - "/index".len());
         }
@@ -858,108 +3561,184 @@ fn compute_nav(content_root: &Path, ignore_patterns: &[String]) -> Vec<NavItem>
             // root home handled separately
             continue;
         }
-
-        let mut parts = slug.split('/').collect::<Vec<_>>();
-        if parts.is_empty() {
-            continue;
-        }
-
-        let group = parts.remove(0).to_string();
-        if parts.is_empty() {
-            groups.entry(group).or_default();
-        } else {
-            groups.entry(group).or_default().push(slug);
-        }
+        trie.insert(&slug);
     }
 
-    let mut nav = Vec::with_capacity(groups.len());
-
-    let humanize = |slug: &str| humanize_segment(slug.rsplit('/').next().unwrap_or(slug));
-
-    let title_for = |slug: &str, is_folder: bool| -> String {
-        let path = if is_folder {
-            content_root.join(slug).join("index.md")
+    let meta_for = |slug: &str, is_folder: bool| -> NavMeta {
+        let page = if is_folder {
+            format!("{slug}/index")
         } else {
-            content_root.join(slug).with_extension("md")
+            slug.to_string()
         };
 
-        frontmatter_title(&path).unwrap_or_else(|| humanize(slug))
+        let fm = sources
+            .get(&page)
+            .and_then(|path| frontmatter_map(path))
+            .unwrap_or_default();
+        let title = display_name(
+            content_cfg,
+            content_root,
+            slug,
+            fm.get("title")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string()),
+        );
+        NavMeta {
+            title: if protected.contains(&page) {
+                encryption.listed_title(title)
+            } else {
+                title
+            },
+            weight: fm.get("weight").and_then(|v| v.as_f64()),
+            hidden: nav_hidden(&fm).unwrap_or(false),
+        }
     };
 
-    for (group, mut children) in groups {
-        children.sort();
-
-        let children = if children.is_empty() {
-            None
-        } else {
-            Some(
-                children
-                    .iter()
-                    .map(|slug| NavLeaf {
-                        title: title_for(slug, false),
-                        path: slug.clone(),
-                    })
-                    .collect(),
-            )
-        };
+    let nav = trie.nav("", &meta_for);
+    let tree = trie.entries("", &meta_for);
+    (nav, tree)
+}
 
-        let title = title_for(&group, children.is_some());
+/// What the nav and explorer tree take from a page's frontmatter, or from a folder's
+/// `index` page for the whole folder.
+struct NavMeta {
+    title: String,
+    weight: Option<f64>,
+    hidden: bool,
+}
 
-        nav.push(NavItem {
-            title,
-            path: group.clone(),
-            open: false,
-            children,
-        });
+/// Weighted entries first, lowest weight first; unweighted ones keep their order after them.
+fn cmp_nav_weight(a: Option<f64>, b: Option<f64>) -> std::cmp::Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
     }
-
-    nav
 }
 
-fn mark_nav_open(nav: &mut [NavItem], current_slug: &str) {
-    let normalized = current_slug.trim_end_matches('/');
-    for item in nav {
-        let group_prefix = format!("{}/", item.path);
-        let mut is_open = normalized == item.path || normalized.starts_with(&group_prefix);
+/// Content slugs split into path segments, so every folder level becomes a node.
+#[derive(Default)]
+struct SlugTrie {
+    children: std::collections::BTreeMap<String, SlugTrie>,
+}
 
-        if !is_open {
-            if let Some(children) = &item.children {
-                is_open = children.iter().any(|child| child.path == normalized);
-            }
+impl SlugTrie {
+    fn insert(&mut self, slug: &str) {
+        let mut node = self;
+        for segment in slug.split('/') {
+            node = node.children.entry(segment.to_string()).or_default();
         }
+    }
 
-        item.open = is_open;
+    /// Nav items at every level, ordered by weight then title. A segment with anything
+    /// below it is a folder described by its `index` page; hidden pages and folders are
+    /// left out along with everything under them.
+    fn nav(&self, prefix: &str, meta_for: &dyn Fn(&str, bool) -> NavMeta) -> Vec<NavItem> {
+        let mut items: Vec<(Option<f64>, NavItem)> = self
+            .children
+            .iter()
+            .filter_map(|(segment, node)| {
+                let path = if prefix.is_empty() {
+                    segment.clone()
+                } else {
+                    format!("{prefix}/{segment}")
+                };
+                let is_folder = !node.children.is_empty();
+                let meta = meta_for(&path, is_folder);
+                if meta.hidden {
+                    return None;
+                }
+                let item = NavItem {
+                    title: meta.title,
+                    children: is_folder.then(|| node.nav(&path, meta_for)),
+                    open: false,
+                    active: false,
+                    path,
+                };
+                Some((meta.weight, item))
+            })
+            .collect();
+        items.sort_by(|(wa, a), (wb, b)| {
+            cmp_nav_weight(*wa, *wb)
+                .then_with(|| a.title.to_lowercase().cmp(&b.title.to_lowercase()))
+        });
+        items.into_iter().map(|(_, item)| item).collect()
     }
-}
 
-fn humanize_segment(segment: &str) -> String {
-    segment.replace('-', " ")
+    /// Weighted entries first, then folders before files, each ordered by title like the
+    /// default `foldersFirst` sort. Hidden entries are dropped as in [`SlugTrie::nav`].
+    fn entries(
+        &self,
+        prefix: &str,
+        meta_for: &dyn Fn(&str, bool) -> NavMeta,
+    ) -> Vec<ExplorerEntry> {
+        let mut entries: Vec<ExplorerEntry> = self
+            .children
+            .iter()
+            .filter_map(|(segment, node)| {
+                let slug = if prefix.is_empty() {
+                    segment.clone()
+                } else {
+                    format!("{prefix}/{segment}")
+                };
+                let is_folder = !node.children.is_empty();
+                let meta = meta_for(&slug, is_folder);
+                if meta.hidden {
+                    return None;
+                }
+                Some(ExplorerEntry {
+                    title: meta.title,
+                    children: node.entries(&slug, meta_for),
+                    slug,
+                    is_folder,
+                    weight: meta.weight,
+                })
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            cmp_nav_weight(a.weight, b.weight)
+                .then_with(|| b.is_folder.cmp(&a.is_folder))
+                .then_with(|| a.title.to_lowercase().cmp(&b.title.to_lowercase()))
+        });
+        entries
+    }
 }
 
-fn is_ignored(path: &Path, root: &Path, patterns: &[String]) -> bool {
-    let Ok(rel) = path.strip_prefix(root) else {
-        return false;
-    };
-
-    rel.components().any(|comp| {
-        comp.as_os_str()
-            .to_str()
-            .map(|s| patterns.iter().any(|p| p == s))
-            .unwrap_or(false)
-    })
+/// Folders below `layout.explorer.max_depth` become plain links to their index page, as in
+/// the explorer tree. 0 keeps every level.
+fn cap_nav_depth(nav: &mut [NavItem], max_depth: usize, depth: usize) {
+    for item in nav {
+        if let Some(children) = &mut item.children {
+            if max_depth != 0 && depth >= max_depth {
+                item.children = None;
+            } else {
+                cap_nav_depth(children, max_depth, depth + 1);
+            }
+        }
+    }
 }
 
-fn resolve_path(base: &Path, path: &str) -> PathBuf {
-    let candidate = PathBuf::from(path);
-    if candidate.is_absolute() {
-        candidate
-    } else {
-        base.join(path)
+/// Opens every folder on the way to `current_slug`, however deep it sits, and marks the
+/// entry for the page itself active.
+fn mark_nav_open(nav: &mut [NavItem], current_slug: &str) {
+    let current = current_slug.trim_matches('/');
+    let current = current.strip_suffix("/index").unwrap_or(current);
+    for item in nav {
+        item.active = current == item.path;
+        item.open = item.active || current.starts_with(&format!("{}/", item.path));
+        if let Some(children) = &mut item.children {
+            mark_nav_open(children, current);
+        }
     }
 }
 
-fn to_article(page: &RenderedPage) -> ArticleContext {
+fn to_article(engine: &TrellisEngine, page: &RenderedPage) -> ArticleContext {
     let page = page.to_owned();
+    let date = engine
+        .page_date(&page.slug, &page.frontmatter)
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .unwrap_or_default();
 
     let created = page
         .frontmatter
@@ -975,7 +3754,7 @@ fn to_article(page: &RenderedPage) -> ArticleContext {
     let words = page
         .frontmatter
         .word_count
-        .unwrap_or_else(|| page.html.split_whitespace().count().max(1) as u64);
+        .unwrap_or_else(|| count_words(&page.html));
 
     ArticleContext {
         slug: page.slug.clone(),
@@ -983,12 +3762,12 @@ fn to_article(page: &RenderedPage) -> ArticleContext {
         intro: page.frontmatter.description.unwrap_or(String::new()),
         created,
         updated,
-        read_time: format!(
-            "{} min read",
-            ((words as f64) / 200.0).ceil().max(1.0) as u32
-        ),
+        date,
+        read_time: reading_time(words),
         body: page.html.to_owned(),
         tags: page.frontmatter.tags.unwrap_or_default(),
+        frontmatter: page.extra,
         html: Some(page.html.clone()),
+        views: None,
     }
 }
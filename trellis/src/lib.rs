@@ -1,120 +1,66 @@
+#[cfg(feature = "server")]
+mod auth;
+pub mod commands;
+#[cfg(feature = "server")]
+mod comments;
+#[cfg(feature = "db")]
+pub mod db;
+#[cfg(feature = "server")]
+mod git_dates;
+#[cfg(feature = "server")]
 mod handlers;
-mod trellis;
-
-use log::info;
-use std::ffi::OsStr;
-use std::fs;
-use std::path::Path;
-use std::{env, io};
-
-use actix_cors::Cors;
-use actix_web::{App, HttpServer, http::header, web};
-use handlebars::Handlebars;
-use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
-use tokio::fs::File;
-use walkdir::WalkDir;
-
-use crate::trellis::config::SiteConfig;
-
-pub async fn run() -> io::Result<()> {
-    let config = SiteConfig::load();
-    let server_cfg = config.server;
-    let pool = get_db_pool()
-        .await
-        .expect("Unable to create or load existing sqlite database!");
-
-    // Configure max file upload size and CORS
-    let max_bytes = server_cfg.max_payload_bytes();
-    let cors_origins = server_cfg.cors_origins.clone();
-
-    HttpServer::new(move || {
-        App::new()
-            .app_data(web::PayloadConfig::new(max_bytes))
-            .app_data(web::Data::new(pool.clone()))
-            .app_data(web::Data::new(build_handlebars()))
-            .wrap(build_cors(&cors_origins))
-            .configure(handlers::config)
-    })
-    .bind((server_cfg.host, server_cfg.port))?
-    .run()
-    .await
+#[cfg(feature = "server")]
+mod newsletter;
+#[cfg(feature = "server")]
+mod notes;
+#[cfg(feature = "server")]
+mod page_views;
+#[cfg(feature = "server")]
+mod rate_limit;
+#[cfg(feature = "server")]
+mod render_cache;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "server")]
+mod shortlinks;
+#[cfg(all(unix, feature = "server"))]
+mod socket;
+#[cfg(feature = "server")]
+mod telemetry;
+#[cfg(feature = "server")]
+mod templates;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+#[cfg(feature = "server")]
+mod tls;
+/// The engine: configuration, content loading, the plugin pipeline, rendering and the
+/// cache. Available whichever features are enabled.
+pub mod trellis;
+
+use std::path::PathBuf;
+
+#[cfg(feature = "db")]
+pub use crate::db::get_db_pool;
+#[cfg(feature = "server")]
+pub use crate::server::{ServeOverrides, init_logging, run, serve};
+pub use crate::trellis::fragment::{RenderOptions, RenderedFragment, render_markdown};
+pub use crate::trellis::{EngineBuilder, SiteConfig, TrellisEngine, install_engine};
+
+/// Use `path` instead of the default `config.yml`. Must be called before the engine loads.
+pub fn set_config_path(path: PathBuf) {
+    trellis::SiteConfig::set_config_path(path);
 }
 
-fn build_cors(origins: &[String]) -> Cors {
-    let base = Cors::default()
-        .allowed_methods(vec!["GET", "POST", "PATCH", "DELETE"])
-        .allowed_headers(vec![header::CONTENT_TYPE, header::ACCEPT]);
-
-    if origins.iter().any(|o| o == "*") {
-        return base.allow_any_origin();
-    }
-
-    let cors = origins
-        .iter()
-        .fold(base, |c, origin| c.allowed_origin(origin));
-    cors.supports_credentials()
+/// Overlay `config.{profile}.yml` on the base config. Must be called before the engine loads.
+pub fn set_profile(profile: String) {
+    trellis::SiteConfig::set_profile(profile);
 }
 
-fn build_handlebars() -> Handlebars<'static> {
-    let mut handlebars = Handlebars::new();
-    // Register every .hbs file in `templates/`` so they are available
-    let templates_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("templates");
-
-    for entry in WalkDir::new(&templates_dir)
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| e.path().is_file() && e.path().extension() == Some(OsStr::new("hbs")))
-    {
-        let path = entry.path();
-        let rel = path
-            .strip_prefix(&templates_dir)
-            .expect("template path prefix");
-        let rel_no_ext = rel.with_extension("");
-        let name = rel_no_ext.to_string_lossy().replace('\\', "/");
-
-        if rel.parent().map(|p| p == Path::new("")).unwrap_or(true) {
-            // top-level templates. e.g. index, page
-            let stem = rel_no_ext
-                .file_name()
-                .and_then(|s| s.to_str())
-                .unwrap_or("template");
-            handlebars
-                .register_template_file(stem, &path)
-                .unwrap_or_else(|e| panic!("failed to register template {}: {}", stem, e));
-        } else {
-            // nested templates treated as partials (e.g., components/...)
-            let partial_src = fs::read_to_string(path)
-                .unwrap_or_else(|e| panic!("failed to read partial {}: {}", name, e));
-            handlebars
-                .register_partial(name.as_str(), partial_src)
-                .unwrap_or_else(|e| panic!("failed to register partial {}: {}", name, e));
-        }
-    }
-    handlebars
-}
-
-pub async fn get_db_pool() -> anyhow::Result<SqlitePool> {
-    // Override database path via .env
-    let url = env::var("DATABASE_URL").unwrap_or_else(|_| {
-        let mut path = env::current_dir().expect("cwd");
-        path.push("trellis.db");
-        path.display().to_string()
+/// Configure strict validation and default fallback. Must be called before the engine loads.
+pub fn set_config_options(strict: bool, allow_default_fallback: bool) {
+    let env = trellis::config::LoadOptions::from_env();
+    trellis::SiteConfig::set_load_options(trellis::config::LoadOptions {
+        strict: strict || env.strict,
+        allow_default_fallback: allow_default_fallback || env.allow_default_fallback,
     });
-
-    let uri = format!("sqlite://{}", &url);
-    let db_path = std::path::PathBuf::from(&url);
-
-    // Ensure the directories exist and create db if missing
-    if let Some(parent) = db_path.parent() {
-        if !parent.exists() {
-            tokio::fs::create_dir_all(parent).await?;
-        }
-    }
-    if tokio::fs::metadata(&db_path).await.is_err() {
-        File::create(&db_path).await?;
-    }
-
-    info!("Loading Trellis sqlite database: {}", &uri);
-    let pool = SqlitePoolOptions::new().connect(&uri).await?;
-    Ok(pool)
 }
@@ -1,10 +1,119 @@
-use env_logger::Env;
-use log::info;
 use std::io;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use log::{error, info};
+use trellis::ServeOverrides;
+
+#[derive(Parser)]
+#[command(
+    name = "trellis",
+    version,
+    about = "Create digital gardens with markdown"
+)]
+struct Cli {
+    /// Path to config.yml (also settable via TRELLIS_CONFIG).
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Treat config warnings such as unknown keys as errors.
+    #[arg(long, global = true)]
+    strict_config: bool,
+    /// Overlay config.{profile}.yml on the base config (also settable via TRELLIS_ENV).
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Start the web server (the default when no subcommand is given).
+    Serve {
+        #[arg(long)]
+        host: Option<String>,
+        #[arg(long)]
+        port: Option<u16>,
+        /// Start with built-in defaults if config.yml is invalid.
+        #[arg(long)]
+        allow_default_config: bool,
+    },
+    /// Render every page, the content index, and static files into the cache root.
+    Build,
+    /// Validate config and content; exits non-zero when problems are found. Also lists
+    /// orphaned pages and dead ends.
+    Check {
+        /// Count pages that share a tag as linked in the orphan report.
+        #[arg(long)]
+        tag_links: bool,
+    },
+    /// Scaffold a new markdown note with frontmatter.
+    New {
+        slug: String,
+        #[arg(long)]
+        title: Option<String>,
+    },
+}
 
 #[actix_web::main]
 async fn main() -> io::Result<()> {
-    info!("Growing a garden...");
-    env_logger::init_from_env(Env::default().default_filter_or("info"));
-    trellis::run().await
+    let cli = Cli::parse();
+    if let Some(path) = cli.config {
+        trellis::set_config_path(path);
+    }
+    if let Some(profile) = cli.profile {
+        trellis::set_profile(profile);
+    }
+
+    let command = cli.command.unwrap_or(Command::Serve {
+        host: None,
+        port: None,
+        allow_default_config: false,
+    });
+    let allow_default_config = matches!(
+        command,
+        Command::Serve {
+            allow_default_config: true,
+            ..
+        }
+    );
+    trellis::set_config_options(cli.strict_config, allow_default_config);
+    trellis::init_logging();
+
+    match command {
+        Command::Serve { host, port, .. } => {
+            info!("Growing a garden...");
+            trellis::serve(ServeOverrides { host, port }).await
+        }
+        Command::Build => match trellis::commands::build() {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("build failed: {err:#}");
+                std::process::exit(1);
+            }
+        },
+        Command::Check { tag_links } => {
+            let problems = trellis::commands::check(tag_links);
+            if problems.is_empty() {
+                info!("No problems found");
+                return Ok(());
+            }
+            for problem in &problems {
+                error!("{problem}");
+            }
+            error!("{} problem(s) found", problems.len());
+            std::process::exit(1);
+        }
+        Command::New { slug, title } => {
+            match trellis::commands::new_note(&slug, title.as_deref()) {
+                Ok(path) => {
+                    info!("Created {}", path.display());
+                    Ok(())
+                }
+                Err(err) => {
+                    error!("{err:#}");
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
 }
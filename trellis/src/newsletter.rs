@@ -0,0 +1,210 @@
+use lettre::message::Mailbox;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{
+    Address, AsyncSendmailTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+
+use crate::rate_limit::RateLimiter;
+use crate::trellis::config::NewsletterConfig;
+
+/// Longest email address accepted (RFC 5321).
+const MAX_EMAIL_CHARS: usize = 254;
+
+/// A sign-up as posted, by JSON or by the subscribe form.
+#[derive(Debug, Deserialize)]
+pub struct Subscription {
+    pub email: String,
+    /// Honeypot: the form hides this field from people, so anything in it came from a bot.
+    #[serde(default)]
+    pub website: Option<String>,
+}
+
+impl Subscription {
+    pub fn is_spam(&self) -> bool {
+        self.website
+            .as_deref()
+            .is_some_and(|site| !site.trim().is_empty())
+    }
+
+    /// The address to subscribe; the error is meant for the visitor.
+    pub fn validate(&self) -> Result<Address, String> {
+        let email = self.email.trim();
+        if email.is_empty() {
+            return Err("an email address is required".into());
+        }
+        let invalid = || "the email address is not valid".to_string();
+        if email.chars().count() > MAX_EMAIL_CHARS {
+            return Err(invalid());
+        }
+        let address: Address = email.parse().map_err(|_| invalid())?;
+        // A bare host such as `localhost` parses, but no one's mailbox lives there.
+        let domain = address.domain();
+        if !domain.contains('.') || domain.starts_with('.') || domain.ends_with('.') {
+            return Err(invalid());
+        }
+        Ok(address)
+    }
+}
+
+/// Whether `address` may try another sign-up, counting this attempt.
+pub fn allow(address: &str, per_hour: u32) -> bool {
+    static RECENT: RateLimiter = RateLimiter::new();
+    RECENT.allow(address, per_hour)
+}
+
+/// Whether confirmation mail can be sent; without it sign-ups are confirmed at once.
+pub fn can_mail(cfg: &NewsletterConfig) -> bool {
+    cfg.sendmail_command.is_some() || cfg.smtp.is_some()
+}
+
+fn generate_token() -> anyhow::Result<String> {
+    let mut bytes = [0u8; 24];
+    getrandom::fill(&mut bytes).map_err(|err| anyhow::anyhow!("no random token: {err}"))?;
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// Record `email` as a subscriber, confirmed straight away when `confirmed`. Returns the
+/// token to mail while the address is still pending, `None` once it is confirmed. Signing
+/// up again while pending keeps the first token, so an earlier mail stays good.
+pub async fn subscribe(
+    pool: &SqlitePool,
+    email: &Address,
+    confirmed: bool,
+) -> anyhow::Result<Option<String>> {
+    sqlx::query(
+        "INSERT INTO subscribers (email, token) VALUES (?, ?) ON CONFLICT (email) DO NOTHING",
+    )
+    .bind(email.as_ref())
+    .bind(generate_token()?)
+    .execute(pool)
+    .await?;
+    if confirmed {
+        sqlx::query(
+            "UPDATE subscribers SET status = 'confirmed', \
+             confirmed_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now') \
+             WHERE email = ? AND status = 'pending'",
+        )
+        .bind(email.as_ref())
+        .execute(pool)
+        .await?;
+        return Ok(None);
+    }
+    Ok(
+        sqlx::query_scalar("SELECT token FROM subscribers WHERE email = ? AND status = 'pending'")
+            .bind(email.as_ref())
+            .fetch_optional(pool)
+            .await?,
+    )
+}
+
+/// Confirm the subscriber `token` was mailed to; false when no one holds it. Confirming
+/// twice is not an error, so a link opened again still lands on the thank-you page.
+pub async fn confirm(pool: &SqlitePool, token: &str) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        "UPDATE subscribers SET status = 'confirmed', \
+         confirmed_at = coalesce(confirmed_at, strftime('%Y-%m-%dT%H:%M:%SZ', 'now')) \
+         WHERE token = ?",
+    )
+    .bind(token)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[derive(sqlx::FromRow)]
+struct Confirmed {
+    email: String,
+    created_at: String,
+    confirmed_at: Option<String>,
+}
+
+/// Confirmed subscribers as CSV with a header row, in the order they confirmed.
+pub async fn confirmed_csv(pool: &SqlitePool) -> anyhow::Result<String> {
+    let rows: Vec<Confirmed> = sqlx::query_as(
+        "SELECT email, created_at, confirmed_at FROM subscribers \
+         WHERE status = 'confirmed' ORDER BY confirmed_at, id",
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut csv = String::from("email,subscribed_at,confirmed_at\r\n");
+    for row in rows {
+        let fields = [
+            row.email.as_str(),
+            row.created_at.as_str(),
+            row.confirmed_at.as_deref().unwrap_or(""),
+        ];
+        let fields: Vec<String> = fields.into_iter().map(csv_field).collect();
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    Ok(csv)
+}
+
+/// Quote `value` for CSV when it needs it. Spreadsheets run cells starting with `=`, `+`,
+/// `-` or `@` as formulas, which an address may legally do, so those get a leading `'`.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{value}")
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+/// Mail `to` the link confirming their subscription to `site`, through the configured
+/// sendmail command or SMTP relay.
+pub async fn send_confirmation(
+    cfg: &NewsletterConfig,
+    site: &str,
+    to: &Address,
+    link: &str,
+) -> anyhow::Result<()> {
+    let from: Mailbox = cfg
+        .from
+        .parse()
+        .map_err(|err| anyhow::anyhow!("newsletter.from {:?}: {err}", cfg.from))?;
+    let body = format!(
+        "Someone, hopefully you, asked to get updates from {site} at this address.\n\
+         Open this link to confirm:\n\n{link}\n\n\
+         If it wasn't you, ignore this mail and nothing more will be sent.\n"
+    );
+    let message = Message::builder()
+        .from(from)
+        .to(Mailbox::new(None, to.clone()))
+        .subject(&cfg.subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(body)?;
+
+    if let Some(command) = &cfg.sendmail_command {
+        AsyncSendmailTransport::<Tokio1Executor>::new_with_command(command)
+            .send(message)
+            .await?;
+        return Ok(());
+    }
+    let Some(smtp) = &cfg.smtp else {
+        anyhow::bail!("no sendmail_command or smtp configured");
+    };
+    let mut transport = if smtp.starttls {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.host)?
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp.host)?
+    }
+    .port(smtp.port);
+    if let Some(username) = &smtp.username {
+        let password = match &smtp.password_env {
+            Some(var) => std::env::var(var)
+                .map_err(|_| anyhow::anyhow!("smtp password variable {var} is not set"))?,
+            None => String::new(),
+        };
+        transport = transport.credentials(Credentials::new(username.clone(), password));
+    }
+    transport.build().send(message).await?;
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use sha2::{Digest, Sha256};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// A lock per slug with a request holding or waiting for it.
+static LOCKS: Mutex<Option<HashMap<String, Arc<AsyncMutex<()>>>>> = Mutex::new(None);
+
+/// The hash `If-Match` is compared against and `ETag` reports: SHA-256 of the file, hex.
+pub fn content_hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Whether an `If-Match` header admits a write to a note whose content hashes to
+/// `current` (`None` when there is no note yet). `*` admits any existing note.
+pub fn if_match_allows(header: &str, current: Option<&str>) -> bool {
+    let Some(current) = current else {
+        return false;
+    };
+    header.split(',').any(|tag| {
+        let tag = tag.trim();
+        let tag = tag.strip_prefix("W/").unwrap_or(tag).trim_matches('"');
+        tag == "*" || tag == current
+    })
+}
+
+/// Held while a note is read, compared and written, so requests for one slug run one at
+/// a time. Dropping the last one for a slug forgets its lock.
+pub struct NoteLock {
+    slug: String,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+pub async fn lock(slug: &str) -> NoteLock {
+    let lock = {
+        let mut locks = LOCKS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        locks
+            .get_or_insert_with(HashMap::new)
+            .entry(slug.to_string())
+            .or_default()
+            .clone()
+    };
+    NoteLock {
+        slug: slug.to_string(),
+        guard: Some(lock.lock_owned().await),
+    }
+}
+
+impl Drop for NoteLock {
+    fn drop(&mut self) {
+        // Taking a lock clones it under `LOCKS`, so while that is held nobody else can
+        // start waiting for this one; only the map's reference left means no one is.
+        let mut locks = LOCKS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        drop(self.guard.take());
+        if let Some(locks) = locks.as_mut() {
+            if locks
+                .get(&self.slug)
+                .is_some_and(|lock| Arc::strong_count(lock) == 1)
+            {
+                locks.remove(&self.slug);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn held(slug: &str) -> bool {
+        let locks = LOCKS.lock().unwrap();
+        locks.as_ref().is_some_and(|locks| locks.contains_key(slug))
+    }
+
+    #[actix_web::test]
+    async fn the_last_guard_forgets_its_lock() {
+        let first = lock("notes/forgotten").await;
+        assert!(held("notes/forgotten"));
+        let waiter = actix_web::rt::spawn(async { lock("notes/forgotten").await });
+        actix_web::rt::task::yield_now().await;
+        drop(first);
+
+        let second = waiter.await.unwrap();
+        assert!(held("notes/forgotten"));
+        drop(second);
+        assert!(!held("notes/forgotten"));
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Mutex, OnceLock};
+
+use actix_web::HttpRequest;
+use actix_web::http::header;
+use chrono::{Days, NaiveDate, Utc};
+use log::{debug, warn};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+
+use crate::trellis::config::ServerConfig;
+use crate::trellis::trellis_engine;
+
+/// Views waiting to be written; when the writer falls this far behind, new views are
+/// dropped rather than slowing requests down.
+const QUEUE_CAPACITY: usize = 4096;
+/// Views written per transaction.
+const BATCH_SIZE: usize = 256;
+/// Days in the `/api/stats` series, today included.
+const SERIES_DAYS: u64 = 30;
+
+/// User agent fragments (lowercase) of crawlers, link previewers and scripted clients.
+const BOT_MARKERS: &[&str] = &[
+    "bot",
+    "crawl",
+    "spider",
+    "slurp",
+    "preview",
+    "headless",
+    "lighthouse",
+    "monitor",
+    "curl",
+    "wget",
+    "python-requests",
+    "httpclient",
+    "go-http-client",
+    "java/",
+];
+
+static QUEUE: OnceLock<mpsc::Sender<PageView>> = OnceLock::new();
+
+struct PageView {
+    slug: String,
+    day: String,
+    visitor: String,
+    referrer_host: Option<String>,
+}
+
+/// Start the task that writes queued views to `pool`. Views recorded before this, or
+/// after a second call, are ignored.
+pub fn start(pool: SqlitePool) {
+    let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+    if QUEUE.set(tx).is_ok() {
+        tokio::spawn(write_views(pool, rx));
+    }
+}
+
+/// Queue a view of `slug` without waiting on the database. Nothing is recorded while
+/// `page_views.enabled` is off or when the user agent looks like a bot.
+pub fn record(req: &HttpRequest, slug: &str) {
+    let Some(queue) = QUEUE.get() else {
+        return;
+    };
+    let engine = trellis_engine();
+    if !engine.config.page_views.enabled {
+        return;
+    }
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if is_bot(user_agent) {
+        return;
+    }
+
+    let day = Utc::now().date_naive();
+    let Some(visitor) = visitor_hash(day, &client_address(req, &engine.config.server), user_agent)
+    else {
+        return;
+    };
+    let view = PageView {
+        slug: slug.to_string(),
+        day: day.to_string(),
+        visitor,
+        referrer_host: referrer_host(req, req.connection_info().host()),
+    };
+    if queue.try_send(view).is_err() {
+        debug!("page view queue full; dropping a view of {slug}");
+    }
+}
+
+/// The client's address without its port. That is the connecting address, unless it is
+/// one of `server.trusted_proxies` (or the Unix socket): then it is the nearest address in
+/// `X-Forwarded-For` that is not a trusted proxy itself. Anything further left was written
+/// by the client and could name any address at all.
+pub fn client_address(req: &HttpRequest, server: &ServerConfig) -> String {
+    let trusted: Vec<IpAddr> = server
+        .trusted_proxies
+        .iter()
+        .filter_map(|proxy| proxy.trim().parse::<IpAddr>().ok())
+        .map(|ip| ip.to_canonical())
+        .collect();
+    let peer = req.peer_addr().map(|addr| addr.ip().to_canonical());
+    let proxied = match peer {
+        Some(ip) => trusted.contains(&ip),
+        None => server.socket_path.is_some(),
+    };
+    if proxied {
+        let forwarded: Vec<&str> = req
+            .headers()
+            .get_all("x-forwarded-for")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        for hop in forwarded.into_iter().rev() {
+            let Some(ip) = parse_hop(hop) else {
+                break;
+            };
+            if !trusted.contains(&ip) {
+                return ip.to_string();
+            }
+        }
+    }
+    peer.map(|ip| ip.to_string()).unwrap_or_default()
+}
+
+/// One `X-Forwarded-For` entry, with or without a port.
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    hop.parse::<IpAddr>()
+        .or_else(|_| hop.parse::<SocketAddr>().map(|socket| socket.ip()))
+        .ok()
+        .map(|ip| ip.to_canonical())
+}
+
+fn is_bot(user_agent: &str) -> bool {
+    let user_agent = user_agent.to_ascii_lowercase();
+    user_agent.is_empty() || BOT_MARKERS.iter().any(|marker| user_agent.contains(marker))
+}
+
+/// The referring site's host, unless it is this site.
+fn referrer_host(req: &HttpRequest, own_host: &str) -> Option<String> {
+    let referrer = req.headers().get(header::REFERER)?.to_str().ok()?;
+    let (_, rest) = referrer.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?.to_ascii_lowercase();
+    let own = own_host.to_ascii_lowercase();
+    (!host.is_empty() && host != own).then_some(host)
+}
+
+/// Identifies a visitor for one day. The salt lives only in memory and is replaced when
+/// the day changes, so yesterday's hashes can't be recomputed.
+fn visitor_hash(day: NaiveDate, address: &str, user_agent: &str) -> Option<String> {
+    static SALT: Mutex<Option<(NaiveDate, [u8; 16])>> = Mutex::new(None);
+    let mut guard = SALT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let salt = match *guard {
+        Some((salt_day, salt)) if salt_day == day => salt,
+        _ => {
+            let mut salt = [0u8; 16];
+            getrandom::fill(&mut salt)
+                .map_err(|err| warn!("no page view salt, not recording views: {err}"))
+                .ok()?;
+            *guard = Some((day, salt));
+            salt
+        }
+    };
+    drop(guard);
+
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(address.as_bytes());
+    hasher.update([0]);
+    hasher.update(user_agent.as_bytes());
+    Some(format!("{:x}", hasher.finalize())[..32].to_string())
+}
+
+async fn write_views(pool: SqlitePool, mut rx: mpsc::Receiver<PageView>) {
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while rx.recv_many(&mut batch, BATCH_SIZE).await > 0 {
+        if let Err(err) = insert(&pool, &batch).await {
+            warn!("dropping {} page views: {err:#}", batch.len());
+        }
+        batch.clear();
+    }
+}
+
+async fn insert(pool: &SqlitePool, views: &[PageView]) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    for view in views {
+        sqlx::query(
+            "INSERT OR IGNORE INTO page_views (slug, day, visitor, referrer_host) \
+             VALUES (?, ?, ?, ?)",
+        )
+        .bind(&view.slug)
+        .bind(&view.day)
+        .bind(&view.visitor)
+        .bind(&view.referrer_host)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// All recorded views of `slug`.
+pub async fn total(pool: &SqlitePool, slug: &str) -> anyhow::Result<i64> {
+    Ok(
+        sqlx::query_scalar("SELECT COUNT(*) FROM page_views WHERE slug = ?")
+            .bind(slug)
+            .fetch_one(pool)
+            .await?,
+    )
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    /// Every page with views, most viewed first.
+    pub totals: Vec<SlugViews>,
+    /// The last 30 days, oldest first, days without views included.
+    pub series: Vec<DayViews>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SlugViews {
+    pub slug: String,
+    pub views: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DayViews {
+    pub day: String,
+    pub views: i64,
+}
+
+/// Per-page totals and the daily series, for the whole site or only `slug`.
+pub async fn stats(pool: &SqlitePool, slug: Option<&str>) -> anyhow::Result<Stats> {
+    let totals = sqlx::query_as::<_, (String, i64)>(
+        "SELECT slug, COUNT(*) FROM page_views WHERE ?1 IS NULL OR slug = ?1 \
+         GROUP BY slug ORDER BY COUNT(*) DESC, slug",
+    )
+    .bind(slug)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|(slug, views)| SlugViews { slug, views })
+    .collect();
+
+    let today = Utc::now().date_naive();
+    let first = today - Days::new(SERIES_DAYS - 1);
+    let counted: BTreeMap<String, i64> = sqlx::query_as::<_, (String, i64)>(
+        "SELECT day, COUNT(*) FROM page_views WHERE day >= ?1 AND (?2 IS NULL OR slug = ?2) \
+         GROUP BY day",
+    )
+    .bind(first.to_string())
+    .bind(slug)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+    let series = first
+        .iter_days()
+        .take_while(|day| *day <= today)
+        .map(|day| {
+            let day = day.to_string();
+            let views = counted.get(&day).copied().unwrap_or(0);
+            DayViews { day, views }
+        })
+        .collect();
+
+    Ok(Stats { totals, series })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn server(trusted_proxies: &[&str]) -> ServerConfig {
+        ServerConfig {
+            trusted_proxies: trusted_proxies
+                .iter()
+                .map(|proxy| proxy.to_string())
+                .collect(),
+            ..ServerConfig::default()
+        }
+    }
+
+    fn request(peer: &str, forwarded_for: Option<&str>) -> HttpRequest {
+        let mut req = TestRequest::default().peer_addr(peer.parse().unwrap());
+        if let Some(forwarded_for) = forwarded_for {
+            req = req.insert_header(("x-forwarded-for", forwarded_for));
+        }
+        req.to_http_request()
+    }
+
+    #[test]
+    fn forwarded_headers_from_untrusted_peers_are_ignored() {
+        let req = request("203.0.113.7:51234", Some("198.51.100.1"));
+        assert_eq!(client_address(&req, &server(&[])), "203.0.113.7");
+        assert_eq!(client_address(&req, &server(&["127.0.0.1"])), "203.0.113.7");
+    }
+
+    #[test]
+    fn a_trusted_proxy_names_the_client() {
+        let req = request("127.0.0.1:40000", Some("198.51.100.1"));
+        assert_eq!(
+            client_address(&req, &server(&["127.0.0.1"])),
+            "198.51.100.1"
+        );
+
+        let req = request("127.0.0.1:40000", None);
+        assert_eq!(client_address(&req, &server(&["127.0.0.1"])), "127.0.0.1");
+    }
+
+    #[test]
+    fn addresses_the_client_prepended_are_skipped() {
+        let trusted = server(&["127.0.0.1", "10.0.0.2"]);
+        let req = request(
+            "127.0.0.1:40000",
+            Some("192.0.2.99, 198.51.100.1:6000, 10.0.0.2"),
+        );
+        assert_eq!(client_address(&req, &trusted), "198.51.100.1");
+
+        let req = request("127.0.0.1:40000", Some("192.0.2.99, not-an-address"));
+        assert_eq!(client_address(&req, &trusted), "127.0.0.1");
+    }
+
+    #[test]
+    fn ipv4_mapped_peers_match_ipv4_proxies() {
+        let req = request("[::ffff:127.0.0.1]:40000", Some("198.51.100.1"));
+        assert_eq!(
+            client_address(&req, &server(&["127.0.0.1"])),
+            "198.51.100.1"
+        );
+    }
+}
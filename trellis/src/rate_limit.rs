@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The window `per_hour` limits count attempts over.
+const WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Attempts per client address over the last hour, kept in memory: a restart forgets them.
+#[derive(Default)]
+pub struct RateLimiter {
+    recent: Mutex<Option<HashMap<String, Vec<Instant>>>>,
+}
+
+impl RateLimiter {
+    pub const fn new() -> Self {
+        Self {
+            recent: Mutex::new(None),
+        }
+    }
+
+    /// Whether `address` may make another attempt, counting this one; `per_hour` 0 admits
+    /// everything.
+    pub fn allow(&self, address: &str, per_hour: u32) -> bool {
+        if per_hour == 0 {
+            return true;
+        }
+        let now = Instant::now();
+        let mut guard = self
+            .recent
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let recent = guard.get_or_insert_with(HashMap::new);
+        recent.retain(|_, times| {
+            times.retain(|at| now.duration_since(*at) < WINDOW);
+            !times.is_empty()
+        });
+        let times = recent.entry(address.to_string()).or_default();
+        if times.len() >= per_hour as usize {
+            return false;
+        }
+        times.push(now);
+        true
+    }
+}
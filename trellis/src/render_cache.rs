@@ -0,0 +1,100 @@
+use std::path::Path;
+
+use log::{info, warn};
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+
+use crate::trellis::cache::{self, CacheEntry};
+
+/// A change to one slug's entry: the new entry, or `None` when it was dropped.
+type Change = (String, Option<CacheEntry>);
+
+#[derive(sqlx::FromRow)]
+struct Row {
+    slug: String,
+    source_hash: String,
+    source_mtime_ms: i64,
+    source_len: i64,
+    theme_hash: String,
+    config_hash: String,
+    pipeline: String,
+    render_ms: i64,
+    html_path: String,
+    rendered_at: String,
+}
+
+/// Load the render cache index of `cache_root` from `pool` and write every later change
+/// back to it. Must run before pages are prebuilt, or they all render afresh.
+pub async fn start(pool: SqlitePool, cache_root: &Path) -> anyhow::Result<()> {
+    let rows: Vec<Row> = sqlx::query_as(
+        "SELECT slug, source_hash, source_mtime_ms, source_len, theme_hash, config_hash, \
+         pipeline, render_ms, html_path, rendered_at FROM render_cache",
+    )
+    .fetch_all(&pool)
+    .await?;
+    info!("Render cache: {} pages recorded", rows.len());
+    let index = cache::index(cache_root);
+    index.load(rows.into_iter().map(|row| {
+        let entry = CacheEntry {
+            source_hash: row.source_hash,
+            source_mtime_ms: row.source_mtime_ms,
+            source_len: row.source_len,
+            theme_hash: row.theme_hash,
+            config_hash: row.config_hash,
+            pipeline: row.pipeline,
+            render_ms: row.render_ms,
+            html_path: row.html_path,
+            rendered_at: row.rendered_at,
+        };
+        (row.slug, entry)
+    }));
+
+    let (tx, rx) = mpsc::unbounded_channel::<Change>();
+    index.set_sink(Box::new(move |slug, entry| {
+        let _ = tx.send((slug.to_string(), entry.cloned()));
+    }));
+    tokio::spawn(write_changes(pool, rx));
+    Ok(())
+}
+
+async fn write_changes(pool: SqlitePool, mut rx: mpsc::UnboundedReceiver<Change>) {
+    let mut batch = Vec::new();
+    while rx.recv_many(&mut batch, 256).await > 0 {
+        if let Err(err) = apply(&pool, &batch).await {
+            warn!("render cache index not saved: {err:#}");
+        }
+        batch.clear();
+    }
+}
+
+async fn apply(pool: &SqlitePool, changes: &[Change]) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    for (slug, entry) in changes {
+        let Some(entry) = entry else {
+            sqlx::query("DELETE FROM render_cache WHERE slug = ?")
+                .bind(slug)
+                .execute(&mut *tx)
+                .await?;
+            continue;
+        };
+        sqlx::query(
+            "INSERT OR REPLACE INTO render_cache (slug, source_hash, source_mtime_ms, \
+             source_len, theme_hash, config_hash, pipeline, render_ms, html_path, rendered_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(slug)
+        .bind(&entry.source_hash)
+        .bind(entry.source_mtime_ms)
+        .bind(entry.source_len)
+        .bind(&entry.theme_hash)
+        .bind(&entry.config_hash)
+        .bind(&entry.pipeline)
+        .bind(entry.render_ms)
+        .bind(&entry.html_path)
+        .bind(&entry.rendered_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use std::io;
+use std::time::Duration;
+
+use actix_cors::Cors;
+use actix_web::{
+    App, HttpRequest, HttpServer,
+    http::{KeepAlive, header},
+    middleware::from_fn,
+    web,
+};
+use log::{info, warn};
+
+use crate::db::get_db_pool;
+#[cfg(unix)]
+use crate::socket;
+use crate::templates::Templates;
+use crate::trellis;
+use crate::{git_dates, handlers, page_views, render_cache, telemetry, tls};
+
+/// Start logging as `server.log` in the config file says, unless `RUST_LOG` overrides its
+/// filter. Call once, after `set_config_options` and before anything logs.
+pub fn init_logging() {
+    // Problems with the file are reported when the engine loads it for real.
+    let log = trellis::SiteConfig::try_load()
+        .map(|config| config.server.log)
+        .unwrap_or_default();
+    telemetry::init(&log);
+}
+
+/// Command-line overrides for the server bind address.
+#[derive(Debug, Clone, Default)]
+pub struct ServeOverrides {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+}
+
+pub async fn run() -> io::Result<()> {
+    serve(ServeOverrides::default()).await
+}
+
+pub async fn serve(overrides: ServeOverrides) -> io::Result<()> {
+    // Initialise the engine first: it configures the templates override used below.
+    let engine = trellis::trellis_engine();
+    match trellis::SiteConfig::profile() {
+        Some(profile) => info!("Config profile: {profile}"),
+        None => info!("Config profile: none (base config only)"),
+    }
+    let mut server_cfg = engine.config.server.clone();
+    if let Some(host) = overrides.host {
+        server_cfg.host = host;
+    }
+    if let Some(port) = overrides.port {
+        server_cfg.port = port;
+    }
+    // Kept alive for the lifetime of the server so config and static edits are picked up.
+    let _config_watcher = match trellis::watcher::watch_config() {
+        Ok(watcher) => Some(watcher),
+        Err(err) => {
+            warn!("config hot-reload disabled: {err}");
+            None
+        }
+    };
+    let _static_watcher = match trellis::watcher::watch_static_dirs() {
+        Ok(watcher) => Some(watcher),
+        Err(err) => {
+            warn!("static dir sync on change disabled: {err}");
+            None
+        }
+    };
+    let pool = get_db_pool()
+        .await
+        .map_err(|err| io::Error::other(format!("database unavailable: {err:#}")))?;
+    page_views::start(pool.clone());
+    if let Err(err) = render_cache::start(pool.clone(), engine.cache_root()).await {
+        warn!("render cache index unavailable, pages will render afresh: {err:#}");
+    }
+    if let Err(err) = git_dates::start(pool.clone()).await {
+        warn!("saved git dates unavailable, they will be read from the log: {err:#}");
+    }
+
+    // Request body limit for every extractor (comment posts are the main consumer), and CORS
+    let max_bytes = server_cfg.max_payload_bytes();
+    let cors_origins = server_cfg.cors_origins.clone();
+    let templates = web::Data::new(Templates::new(server_cfg.dev_mode));
+
+    let socket_path = server_cfg
+        .socket_path
+        .as_deref()
+        .map(trellis::SiteConfig::resolve_path);
+    if socket_path.is_some() {
+        info!("server.socket_path is set; ignoring host, port and tls settings");
+    }
+
+    // Load certificates before anything binds so a bad cert/key fails startup immediately.
+    let tls = match &server_cfg.tls {
+        Some(tls_cfg) if socket_path.is_none() => {
+            let cert = trellis::SiteConfig::resolve_path(&tls_cfg.cert_path);
+            let key = trellis::SiteConfig::resolve_path(&tls_cfg.key_path);
+            let rustls_cfg = tls::load_server_config(&cert, &key)
+                .map_err(|err| io::Error::other(format!("{err:#}")))?;
+            Some((rustls_cfg, tls_cfg.redirect_http_port))
+        }
+        Some(_) | None => None,
+    };
+    let hsts_max_age = match &server_cfg.tls {
+        Some(tls_cfg) if tls.is_some() => tls_cfg.hsts_max_age_secs,
+        Some(_) | None => 0,
+    };
+
+    let workers = server_cfg.worker_count();
+    let keep_alive = match server_cfg.keep_alive_secs {
+        0 => KeepAlive::Disabled,
+        secs => KeepAlive::Timeout(Duration::from_secs(secs)),
+    };
+    let request_timeout = Duration::from_secs(server_cfg.client_request_timeout_secs);
+    info!(
+        "Starting {workers} workers (keep-alive {}s, client request timeout {}s)",
+        server_cfg.keep_alive_secs, server_cfg.client_request_timeout_secs
+    );
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::PayloadConfig::new(max_bytes))
+            .app_data(web::JsonConfig::default().limit(max_bytes))
+            .app_data(web::FormConfig::default().limit(max_bytes))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(templates.clone())
+            .wrap(build_cors(&cors_origins))
+            .wrap(tls::strict_transport_security(hsts_max_age))
+            .wrap(from_fn(telemetry::request_span))
+            .configure(handlers::config)
+    })
+    .workers(workers)
+    .keep_alive(keep_alive)
+    .client_request_timeout(request_timeout);
+
+    if let Some(socket_path) = socket_path {
+        #[cfg(unix)]
+        {
+            let mode = server_cfg.socket_mode_bits().ok_or_else(|| {
+                io::Error::other(format!(
+                    "invalid server.socket_mode {:?}",
+                    server_cfg.socket_mode
+                ))
+            })?;
+            socket::remove_stale(&socket_path)?;
+            let server = server.bind_uds(&socket_path)?;
+            socket::set_mode(&socket_path, mode)?;
+            info!(
+                "Listening on unix:{} (mode {mode:o})",
+                socket_path.display()
+            );
+
+            let result = server.run().await;
+            socket::cleanup(&socket_path);
+            return result;
+        }
+        #[cfg(not(unix))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "server.socket_path ({}) requires a Unix platform",
+                socket_path.display()
+            ),
+        ));
+    }
+
+    let Some((rustls_cfg, redirect_port)) = tls else {
+        info!(
+            "Listening on http://{}:{}",
+            server_cfg.host, server_cfg.port
+        );
+        return server.bind((server_cfg.host, server_cfg.port))?.run().await;
+    };
+
+    info!(
+        "Listening on https://{}:{}",
+        server_cfg.host, server_cfg.port
+    );
+
+    let https = server
+        .bind_rustls_0_23((server_cfg.host.clone(), server_cfg.port), rustls_cfg)?
+        .run();
+    let Some(redirect_port) = redirect_port else {
+        return https.await;
+    };
+
+    let https_port = server_cfg.port;
+    info!(
+        "Redirecting http://{}:{redirect_port} to HTTPS",
+        server_cfg.host
+    );
+    let redirect = HttpServer::new(move || {
+        App::new().default_service(web::to(move |req: HttpRequest| async move {
+            tls::redirect_to_https(&req, https_port)
+        }))
+    })
+    .workers(1)
+    .bind((server_cfg.host, redirect_port))?
+    .run();
+
+    tokio::try_join!(https, redirect)?;
+    Ok(())
+}
+
+fn build_cors(origins: &[String]) -> Cors {
+    let base = Cors::default()
+        .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
+        .allowed_headers(vec![header::CONTENT_TYPE, header::ACCEPT]);
+
+    if origins.iter().any(|o| o == "*") {
+        return base.allow_any_origin();
+    }
+
+    let cors = origins
+        .iter()
+        .fold(base, |c, origin| c.allowed_origin(origin));
+    cors.supports_credentials()
+}
@@ -0,0 +1,115 @@
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+/// Characters of generated codes: base58, which leaves out 0, O, I and l.
+const ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+/// Length of generated codes; 58^7 is a little over two trillion.
+const GENERATED_LEN: usize = 7;
+/// Longest code accepted when one is chosen by hand.
+pub const MAX_CODE_LEN: usize = 64;
+/// Generated codes tried before giving up on finding a free one.
+const ATTEMPTS: usize = 5;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Shortlink {
+    pub code: String,
+    pub slug: String,
+    /// UTC, RFC 3339.
+    pub created_at: String,
+    pub hits: i64,
+}
+
+/// Codes are ASCII letters, digits, `-` and `_`, so they never need escaping in a URL.
+pub fn is_valid_code(code: &str) -> bool {
+    !code.is_empty()
+        && code.len() <= MAX_CODE_LEN
+        && code
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+fn generate_code() -> anyhow::Result<String> {
+    // Bytes past the last whole multiple of 58 are redrawn so every character is as likely.
+    let limit = (256 / ALPHABET.len() * ALPHABET.len()) as u8;
+    let mut code = String::with_capacity(GENERATED_LEN);
+    while code.len() < GENERATED_LEN {
+        let mut bytes = [0u8; 16];
+        getrandom::fill(&mut bytes).map_err(|err| anyhow::anyhow!("no random code: {err}"))?;
+        code.extend(
+            bytes
+                .into_iter()
+                .filter(|b| *b < limit)
+                .map(|b| ALPHABET[b as usize % ALPHABET.len()] as char)
+                .take(GENERATED_LEN - code.len()),
+        );
+    }
+    Ok(code)
+}
+
+/// Point a new code at `slug`: `code` when given, otherwise a random one. `None` when the
+/// chosen code is already taken.
+pub async fn create(
+    pool: &SqlitePool,
+    slug: &str,
+    code: Option<&str>,
+) -> anyhow::Result<Option<Shortlink>> {
+    if let Some(code) = code {
+        return insert(pool, code, slug).await;
+    }
+    for _ in 0..ATTEMPTS {
+        if let Some(link) = insert(pool, &generate_code()?, slug).await? {
+            return Ok(Some(link));
+        }
+    }
+    anyhow::bail!("no free short code after {ATTEMPTS} attempts")
+}
+
+async fn insert(pool: &SqlitePool, code: &str, slug: &str) -> anyhow::Result<Option<Shortlink>> {
+    let inserted = sqlx::query_as(
+        "INSERT INTO shortlinks (code, slug) VALUES (?, ?) \
+         RETURNING code, slug, created_at, hits",
+    )
+    .bind(code)
+    .bind(slug)
+    .fetch_one(pool)
+    .await;
+    match inserted {
+        Ok(link) => Ok(Some(link)),
+        Err(err)
+            if err
+                .as_database_error()
+                .is_some_and(|db| db.is_unique_violation()) =>
+        {
+            Ok(None)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// The slug `code` points at.
+pub async fn target(pool: &SqlitePool, code: &str) -> anyhow::Result<Option<String>> {
+    Ok(
+        sqlx::query_scalar("SELECT slug FROM shortlinks WHERE code = ?")
+            .bind(code)
+            .fetch_optional(pool)
+            .await?,
+    )
+}
+
+/// Count one use of `code`.
+pub async fn hit(pool: &SqlitePool, code: &str) -> anyhow::Result<()> {
+    sqlx::query("UPDATE shortlinks SET hits = hits + 1 WHERE code = ?")
+        .bind(code)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Every code, newest first.
+pub async fn all(pool: &SqlitePool) -> anyhow::Result<Vec<Shortlink>> {
+    Ok(sqlx::query_as(
+        "SELECT code, slug, created_at, hits FROM shortlinks ORDER BY created_at DESC, code",
+    )
+    .fetch_all(pool)
+    .await?)
+}
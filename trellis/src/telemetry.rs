@@ -0,0 +1,95 @@
+use std::time::Instant;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use tracing::{Instrument, Span, field, info_span, warn};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+use uuid::Uuid;
+
+use crate::trellis::config::{LogConfig, LogFormat};
+
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Install the process-wide subscriber, writing to stderr what `RUST_LOG` or else
+/// `server.log.filter` lets through, and a line as each span closes, with its fields and
+/// timings. `log` records from dependencies and older code are forwarded to it.
+pub fn init(config: &LogConfig) {
+    let (filter, bad_filter) = match EnvFilter::try_from_default_env() {
+        Ok(filter) => (filter, None),
+        Err(_) => match EnvFilter::try_new(&config.filter) {
+            Ok(filter) => (filter, None),
+            Err(err) => (EnvFilter::new("info"), Some(err)),
+        },
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_span_events(FmtSpan::CLOSE);
+    let installed = match config.format {
+        LogFormat::Pretty => builder.try_init(),
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .try_init(),
+    };
+    if let Err(err) = installed {
+        eprintln!("logging was already set up: {err}");
+    }
+    if let Some(err) = bad_filter {
+        warn!(
+            "invalid server.log.filter {:?}, using info: {err}",
+            config.filter
+        );
+    }
+}
+
+/// Run each request in a `request` span with its id, method and path, plus the canonical
+/// slug once a page handler has resolved it (see [`record_slug`]). The id, taken from the
+/// request's own `X-Request-Id` when a proxy set a plausible one, is echoed in the
+/// response's.
+pub async fn request_span(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let request_id = req
+        .headers()
+        .get(&REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_plausible_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().simple().to_string());
+    let span = info_span!(
+        "request",
+        id = %request_id,
+        method = %req.method(),
+        path = %req.path(),
+        slug = field::Empty,
+        status = field::Empty,
+        duration_ms = field::Empty,
+    );
+
+    let started = Instant::now();
+    let mut res = next.call(req).instrument(span.clone()).await?;
+    span.record("status", res.status().as_u16());
+    span.record("duration_ms", started.elapsed().as_millis() as u64);
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        res.headers_mut().insert(REQUEST_ID, value);
+    }
+    Ok(res)
+}
+
+/// Note on the current request's span which page it is for.
+pub fn record_slug(slug: &str) {
+    Span::current().record("slug", slug);
+}
+
+fn is_plausible_id(id: &str) -> bool {
+    (1..=128).contains(&id.len())
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
@@ -0,0 +1,126 @@
+use std::fs;
+use std::path::Path;
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+use anyhow::{Result, anyhow};
+use handlebars::{Handlebars, RenderError};
+use log::{error, info};
+use serde::Serialize;
+
+use crate::trellis::assets;
+
+mod helpers;
+
+/// Handlebars registry shared by all workers.
+///
+/// In production templates are registered once at startup. In dev mode the registry is
+/// rebuilt whenever an `.hbs` template is newer than the last build, so both top-level
+/// templates and nested partials pick up edits without a restart.
+pub struct Templates {
+    state: RwLock<TemplatesState>,
+    dev_mode: bool,
+}
+
+struct TemplatesState {
+    handlebars: Handlebars<'static>,
+    mtime: SystemTime,
+}
+
+impl Templates {
+    pub fn new(dev_mode: bool) -> Self {
+        let mtime = templates_mtime();
+        let handlebars = build_handlebars().unwrap_or_else(|e| panic!("{e}"));
+        Self {
+            state: RwLock::new(TemplatesState { handlebars, mtime }),
+            dev_mode,
+        }
+    }
+
+    pub fn render<T: Serialize>(&self, name: &str, data: &T) -> Result<String, RenderError> {
+        if self.dev_mode {
+            self.refresh_if_stale();
+        }
+
+        let state = match self.state.read() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        state.handlebars.render(name, data)
+    }
+
+    /// Whether `name` is a top-level template, `name.hbs` directly under `templates/`, as
+    /// opposed to a partial in a subdirectory.
+    pub fn has_template(&self, name: &str) -> bool {
+        if self.dev_mode {
+            self.refresh_if_stale();
+        }
+        let state = match self.state.read() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        !name.contains('/') && state.handlebars.has_template(name)
+    }
+
+    fn refresh_if_stale(&self) {
+        let newest = templates_mtime();
+        let stale = self
+            .state
+            .read()
+            .map(|state| state.mtime < newest)
+            .unwrap_or(true);
+        if !stale {
+            return;
+        }
+
+        let Ok(mut state) = self.state.write() else {
+            return;
+        };
+        if state.mtime >= newest {
+            return;
+        }
+        // Record the attempt either way so a broken template isn't rebuilt on every request.
+        state.mtime = newest;
+        match build_handlebars() {
+            Ok(handlebars) => {
+                state.handlebars = handlebars;
+                info!("Reloaded handlebars templates");
+            }
+            Err(err) => error!("Keeping previous templates: {err}"),
+        }
+    }
+}
+
+fn templates_mtime() -> SystemTime {
+    assets::newest_mtime("", "hbs")
+}
+
+fn build_handlebars() -> Result<Handlebars<'static>> {
+    let mut handlebars = Handlebars::new();
+    helpers::register(&mut handlebars);
+    // Register every .hbs file (override first, embedded fallback) so they are available
+    for (rel, path) in assets::list_files("", "hbs") {
+        let rel = Path::new(&rel);
+        let rel_no_ext = rel.with_extension("");
+        let name = rel_no_ext.to_string_lossy().replace('\\', "/");
+        let src = fs::read_to_string(&path)
+            .map_err(|e| anyhow!("failed to read template {}: {}", name, e))?;
+
+        if rel.parent().map(|p| p == Path::new("")).unwrap_or(true) {
+            // top-level templates. e.g. index, page
+            let stem = rel_no_ext
+                .file_name()
+                .and_then(|s| s.to_str())
+                .unwrap_or("template");
+            handlebars
+                .register_template_string(stem, src)
+                .map_err(|e| anyhow!("failed to register template {}: {}", stem, e))?;
+        } else {
+            // nested templates treated as partials (e.g., components/...)
+            handlebars
+                .register_partial(name.as_str(), src)
+                .map_err(|e| anyhow!("failed to register partial {}: {}", name, e))?;
+        }
+    }
+    Ok(handlebars)
+}
@@ -0,0 +1,294 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, NaiveDate, Utc};
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext, RenderError,
+    RenderErrorReason, ScopedJson,
+};
+use serde_json::Value;
+
+use crate::trellis::types::format_date;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+const DEFAULT_DATE_LOCALE: &str = "en-US";
+
+/// Register the helpers templates get on top of the handlebars built-ins (`if`, `each`,
+/// `with`, `lookup`, `eq`, `ne`, `gt`, `gte`, `lt`, `lte`, `and`, `or`, `not`, `len`):
+///
+/// - `{{format_date value "%e %B %Y" locale="de-DE"}}`: an RFC 3339 or `YYYY-MM-DD` date
+///   in a chrono format (default `%Y-%m-%d`), names in `locale` (default `en-US`).
+/// - `{{markdown text}}`: text rendered as GFM markdown, raw HTML in it escaped.
+/// - `{{json value}}`: any value, pretty-printed.
+/// - `{{default value "fallback"}}`: `value` unless missing, null or an empty string.
+/// - `{{truncate text 80 suffix="…"}}`: at most that many characters, cut at a space.
+///
+/// Like any value, what `format_date`, `json`, `default` and `truncate` return is escaped
+/// by `{{...}}` and left alone by `{{{...}}}`. `markdown` writes HTML, which is never
+/// escaped; it is safe because the markdown's own HTML is.
+pub fn register(handlebars: &mut Handlebars<'static>) {
+    handlebars.register_helper("format_date", Box::new(FormatDate));
+    handlebars.register_helper("markdown", Box::new(markdown_helper));
+    handlebars.register_helper("json", Box::new(Json));
+    handlebars.register_helper("default", Box::new(DefaultValue));
+    handlebars.register_helper("truncate", Box::new(Truncate));
+}
+
+struct FormatDate;
+
+impl HelperDef for FormatDate {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let value = h.param(0).map(|param| param.value());
+        let date = match value {
+            None | Some(Value::Null) => return Ok(string(String::new())),
+            Some(value) => parse_date(value).ok_or_else(|| {
+                RenderErrorReason::Other(format!("format_date: {value} is not a date"))
+            })?,
+        };
+        let format = str_param(h, 1).unwrap_or(DEFAULT_DATE_FORMAT);
+        // chrono panics on displaying a bad format, so check it first.
+        if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+            return Err(
+                RenderErrorReason::Other(format!("format_date: bad format {format:?}")).into(),
+            );
+        }
+        let locale = h
+            .hash_get("locale")
+            .and_then(|locale| locale.value().as_str())
+            .unwrap_or(DEFAULT_DATE_LOCALE);
+        Ok(string(format_date(date, format, locale)))
+    }
+}
+
+/// An RFC 3339 timestamp, a `YYYY-MM-DD` date (at midnight UTC) or Unix seconds.
+fn parse_date(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::String(text) => DateTime::parse_from_rfc3339(text.trim())
+            .map(|date| date.with_timezone(&Utc))
+            .ok()
+            .or_else(|| {
+                NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d")
+                    .ok()
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+                    .map(|date| date.and_utc())
+            }),
+        Value::Number(secs) => DateTime::from_timestamp(secs.as_i64()?, 0),
+        _ => None,
+    }
+}
+
+fn markdown_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let Some(src) = str_param(h, 0) else {
+        return Ok(());
+    };
+    // Options::gfm() leaves allow_dangerous_html off, so inline tags are escaped.
+    let html = markdown::to_html_with_options(src, &markdown::Options::gfm())
+        .map_err(|err| RenderErrorReason::Other(format!("markdown: {err}")))?;
+    out.write(&html)?;
+    Ok(())
+}
+
+struct Json;
+
+impl HelperDef for Json {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let value = h.param(0).map_or(&Value::Null, |param| param.value());
+        let text = serde_json::to_string_pretty(value)
+            .map_err(|err| RenderErrorReason::Other(format!("json: {err}")))?;
+        Ok(string(text))
+    }
+}
+
+struct DefaultValue;
+
+impl HelperDef for DefaultValue {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let value = h.param(0).map_or(&Value::Null, |param| param.value());
+        let unset = match value {
+            Value::Null => true,
+            Value::String(text) => text.is_empty(),
+            _ => false,
+        };
+        let chosen = if unset {
+            h.param(1).map_or(&Value::Null, |param| param.value())
+        } else {
+            value
+        };
+        Ok(ScopedJson::Derived(chosen.clone()))
+    }
+}
+
+struct Truncate;
+
+impl HelperDef for Truncate {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let text = str_param(h, 0).unwrap_or_default();
+        let limit = h
+            .param(1)
+            .and_then(|param| param.value().as_u64())
+            .ok_or(RenderErrorReason::ParamNotFoundForIndex("truncate", 1))?;
+        let suffix = h
+            .hash_get("suffix")
+            .and_then(|suffix| suffix.value().as_str())
+            .unwrap_or("…");
+        Ok(string(truncate(text, limit as usize, suffix)))
+    }
+}
+
+/// `text` cut to `limit` characters, back to the last space when there is one, with
+/// `suffix` added; unchanged when it already fits.
+fn truncate(text: &str, limit: usize, suffix: &str) -> String {
+    let Some((cut, _)) = text.char_indices().nth(limit) else {
+        return text.to_string();
+    };
+    let kept = &text[..cut];
+    let kept = kept
+        .rfind(char::is_whitespace)
+        .map_or(kept, |space| &kept[..space]);
+    format!("{}{suffix}", kept.trim_end())
+}
+
+fn str_param<'a>(h: &'a Helper, index: usize) -> Option<&'a str> {
+    h.param(index).and_then(|param| param.value().as_str())
+}
+
+fn string<'rc>(text: String) -> ScopedJson<'rc> {
+    ScopedJson::Derived(Value::String(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn render(template: &str, data: Value) -> Result<String, RenderError> {
+        let mut handlebars = Handlebars::new();
+        register(&mut handlebars);
+        handlebars.render_template(template, &data)
+    }
+
+    #[test]
+    fn format_date_formats_dates_and_timestamps() {
+        let data = json!({ "day": "2024-03-05", "at": "2024-03-05T18:30:00+02:00" });
+        assert_eq!(
+            render("{{format_date day}}", data.clone()).unwrap(),
+            "2024-03-05"
+        );
+        assert_eq!(
+            render("{{format_date at \"%d %B %Y, %H:%M\"}}", data.clone()).unwrap(),
+            "05 March 2024, 16:30"
+        );
+        assert_eq!(
+            render("{{format_date day \"%d. %B %Y\" locale=\"de-DE\"}}", data).unwrap(),
+            "05. März 2024"
+        );
+        assert_eq!(render("{{format_date missing}}", json!({})).unwrap(), "");
+    }
+
+    #[test]
+    fn format_date_rejects_bad_dates_and_formats() {
+        assert!(render("{{format_date day}}", json!({ "day": "soon" })).is_err());
+        assert!(
+            render(
+                "{{format_date day \"%Y %\"}}",
+                json!({ "day": "2024-03-05" })
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn markdown_renders_html_and_escapes_raw_html() {
+        let data = json!({ "text": "*hi* <b>there</b>" });
+        let html = render("{{markdown text}}", data.clone()).unwrap();
+        assert!(html.contains("<em>hi</em>"));
+        assert!(html.contains("&lt;b&gt;there&lt;/b&gt;"));
+        assert_eq!(render("{{{markdown text}}}", data).unwrap(), html);
+        assert_eq!(render("{{markdown missing}}", json!({})).unwrap(), "");
+    }
+
+    #[test]
+    fn json_pretty_prints_and_is_escaped_unless_triple_stashed() {
+        let data = json!({ "value": { "a": [1, "<x>"] } });
+        assert_eq!(
+            render("{{{json value}}}", data.clone()).unwrap(),
+            "{\n  \"a\": [\n    1,\n    \"<x>\"\n  ]\n}"
+        );
+        let escaped = render("{{json value}}", data).unwrap();
+        assert!(escaped.contains("&quot;a&quot;"));
+        assert!(escaped.contains("&lt;x&gt;"));
+    }
+
+    #[test]
+    fn comparisons_are_the_builtins() {
+        let data = json!({ "kind": "note", "n": 5 });
+        let template = "{{#if (eq kind \"note\")}}eq{{/if}} {{#if (ne kind \"page\")}}ne{{/if}} \
+                        {{#if (gt n 3)}}gt{{/if}}{{#if (gt n 7)}}!{{/if}}";
+        assert_eq!(render(template, data).unwrap(), "eq ne gt");
+    }
+
+    #[test]
+    fn default_replaces_missing_null_and_empty_values() {
+        let template = "{{default value \"none\"}}";
+        assert_eq!(render(template, json!({})).unwrap(), "none");
+        assert_eq!(render(template, json!({ "value": null })).unwrap(), "none");
+        assert_eq!(render(template, json!({ "value": "" })).unwrap(), "none");
+        assert_eq!(render(template, json!({ "value": "set" })).unwrap(), "set");
+        assert_eq!(render(template, json!({ "value": 0 })).unwrap(), "0");
+        assert_eq!(
+            render("{{default value \"<none>\"}}", json!({})).unwrap(),
+            "&lt;none&gt;"
+        );
+    }
+
+    #[test]
+    fn truncate_cuts_at_a_space() {
+        let data = json!({ "text": "The quick brown fox" });
+        assert_eq!(
+            render("{{truncate text 12}}", data.clone()).unwrap(),
+            "The quick…"
+        );
+        assert_eq!(
+            render("{{truncate text 12 suffix=\"...\"}}", data.clone()).unwrap(),
+            "The quick..."
+        );
+        assert_eq!(
+            render("{{truncate text 50}}", data.clone()).unwrap(),
+            "The quick brown fox"
+        );
+        assert!(render("{{truncate text}}", data).is_err());
+        assert_eq!(
+            render("{{truncate text 3}}", json!({ "text": "<b>bold</b>" })).unwrap(),
+            "&lt;b&gt;…"
+        );
+    }
+}
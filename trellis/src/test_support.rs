@@ -0,0 +1,137 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::middleware::from_fn;
+use actix_web::{App, web};
+use anyhow::{Context, Result};
+use sqlx::SqlitePool;
+use tempfile::TempDir;
+
+use crate::db;
+use crate::handlers;
+use crate::telemetry;
+use crate::templates::Templates;
+use crate::trellis::{self, SiteConfig, TrellisEngine};
+
+/// Handlers read the process-wide engine, so sites take turns: each holds this while alive.
+static TURN: Mutex<()> = Mutex::new(());
+
+/// A vault scaffolded in a temporary directory, with an engine over it installed as the
+/// one handlers serve and an in-memory database. Everything is removed when dropped.
+pub struct TestSite {
+    pub engine: Arc<TrellisEngine>,
+    pool: SqlitePool,
+    dir: TempDir,
+    // Last, so the next site waits until this one's directory is gone.
+    _turn: MutexGuard<'static, ()>,
+}
+
+impl TestSite {
+    /// A site with the default configuration whose content is `files`, each a note path
+    /// relative to the content root (`notes/hello.md`) and its markdown.
+    pub async fn new<'a>(files: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self> {
+        Self::with_config(files, SiteConfig::default()).await
+    }
+
+    /// As [`TestSite::new`], with `config`; its `paths` are replaced by the site's own.
+    pub async fn with_config<'a>(
+        files: impl IntoIterator<Item = (&'a str, &'a str)>,
+        config: SiteConfig,
+    ) -> Result<Self> {
+        let turn = TURN.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().context("creating the site directory")?;
+        let content_root = dir.path().join("content");
+        fs::create_dir_all(&content_root)
+            .with_context(|| format!("creating {}", content_root.display()))?;
+        for (path, markdown) in files {
+            write_file(&content_root.join(path), markdown)?;
+        }
+
+        let engine = TrellisEngine::builder()
+            .config(config)
+            .content_root(&content_root)
+            .cache_root(dir.path().join("cache"))
+            .build()?;
+        let engine = Arc::new(engine);
+        trellis::install_engine(Arc::clone(&engine));
+        let pool = db::memory_pool().await?;
+        Ok(Self {
+            engine,
+            pool,
+            dir,
+            _turn: turn,
+        })
+    }
+
+    pub fn content_root(&self) -> &Path {
+        self.engine.content_root()
+    }
+
+    /// Where the site's files live; the content root is `content/` inside it.
+    pub fn dir(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
+    /// Create or replace the note at `path`, relative to the content root.
+    pub fn write(&self, path: &str, markdown: &str) -> Result<PathBuf> {
+        let path = self.content_root().join(path);
+        write_file(&path, markdown)?;
+        Ok(path)
+    }
+
+    /// Create or replace `templates/<path>` (`resume.hbs`, `components/footer.hbs`) as an
+    /// override of the embedded templates, serving the site over it from now on.
+    pub fn write_template(&mut self, path: &str, source: &str) -> Result<()> {
+        let templates_root = self.dir.path().join("templates");
+        write_file(&templates_root.join(path), source)?;
+        if self.engine.config.templates_root().as_deref() != Some(templates_root.as_path()) {
+            let engine = TrellisEngine::builder()
+                .config(self.engine.config.clone())
+                .content_root(self.engine.content_root())
+                .cache_root(self.dir.path().join("cache"))
+                .templates_root(templates_root)
+                .build()?;
+            self.engine = Arc::new(engine);
+            trellis::install_engine(Arc::clone(&self.engine));
+        }
+        Ok(())
+    }
+
+    pub fn remove(&self, path: &str) -> Result<()> {
+        let path = self.content_root().join(path);
+        fs::remove_file(&path).with_context(|| format!("removing {}", path.display()))
+    }
+
+    /// The app as the server builds it, with every handler, for
+    /// `actix_web::test::init_service`. Building it prebuilds the content, as startup does.
+    pub fn app(
+        &self,
+    ) -> App<
+        impl ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse,
+            Error = actix_web::Error,
+            InitError = (),
+        >,
+    > {
+        App::new()
+            .app_data(web::Data::new(self.pool.clone()))
+            .app_data(web::Data::new(Templates::new(false)))
+            .wrap(from_fn(telemetry::request_span))
+            .configure(handlers::config)
+    }
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+    }
+    fs::write(path, contents).with_context(|| format!("writing {}", path.display()))
+}
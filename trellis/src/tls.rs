@@ -0,0 +1,126 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use actix_web::middleware::{Condition, DefaultHeaders};
+use actix_web::{HttpRequest, HttpResponse, http::header};
+use anyhow::{Context, Result, bail};
+use rustls::ServerConfig;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+/// Build a rustls server config from PEM files, failing with a readable error when either
+/// file is missing, empty, or the key doesn't belong to the certificate.
+pub fn load_server_config(cert_path: &Path, key_path: &Path) -> Result<ServerConfig> {
+    let certs = load_certs(cert_path)?;
+    let key = load_key(key_path)?;
+
+    ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .context("configuring TLS protocol versions")?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .with_context(|| {
+            format!(
+                "TLS key {} does not match certificate {}",
+                key_path.display(),
+                cert_path.display()
+            )
+        })
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let file =
+        File::open(path).with_context(|| format!("opening TLS certificate {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("parsing TLS certificate {}", path.display()))?;
+    if certs.is_empty() {
+        bail!("no certificates found in {}", path.display());
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).with_context(|| format!("opening TLS key {}", path.display()))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("parsing TLS key {}", path.display()))?
+        .with_context(|| format!("no private key found in {}", path.display()))
+}
+
+/// Permanent redirect from plain HTTP to the same path on the HTTPS listener.
+pub fn redirect_to_https(req: &HttpRequest, https_port: u16) -> HttpResponse {
+    let conn = req.connection_info();
+    let host = conn.host();
+    // Drop any port from the Host header; it names the plain HTTP listener.
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    };
+    let authority = if https_port == 443 {
+        host.to_string()
+    } else {
+        format!("{host}:{https_port}")
+    };
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+
+    HttpResponse::MovedPermanently()
+        .insert_header((header::LOCATION, format!("https://{authority}{path}")))
+        .finish()
+}
+
+/// Adds Strict-Transport-Security with `max_age_secs` to every response, or nothing when it
+/// is 0. Only for the HTTPS listener: the header is ignored over plain HTTP anyway.
+pub fn strict_transport_security(max_age_secs: u64) -> Condition<DefaultHeaders> {
+    Condition::new(
+        max_age_secs > 0,
+        DefaultHeaders::new().add((
+            header::STRICT_TRANSPORT_SECURITY,
+            format!("max-age={max_age_secs}"),
+        )),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, test, web};
+
+    use super::*;
+
+    async fn hsts(max_age_secs: u64) -> Option<String> {
+        let app = test::init_service(
+            App::new()
+                .wrap(strict_transport_security(max_age_secs))
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let res = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        res.headers()
+            .get(header::STRICT_TRANSPORT_SECURITY)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[actix_web::test]
+    async fn https_responses_carry_hsts() {
+        assert_eq!(hsts(31_536_000).await.as_deref(), Some("max-age=31536000"));
+    }
+
+    #[actix_web::test]
+    async fn a_zero_max_age_sends_no_header() {
+        assert_eq!(hsts(0).await, None);
+    }
+
+    #[actix_web::test]
+    async fn the_redirect_never_sends_hsts() {
+        let req = test::TestRequest::get().uri("/notes").to_http_request();
+        let res = redirect_to_https(&req, 443);
+        assert!(
+            !res.headers()
+                .contains_key(header::STRICT_TRANSPORT_SECURITY)
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use crate::trellis::config::{AnalyticsConfig, AnalyticsProvider};
+use crate::trellis::html::escape_html;
+
+/// The `<head>` markup for the configured analytics provider, or `None` when analytics
+/// are not configured.
+///
+/// `respect_dnt` defers the snippet behind a Do Not Track check, and with SPA navigation
+/// enabled every script is marked `spa-preserve` so the router doesn't re-run it per page.
+pub fn analytics_head(cfg: &AnalyticsConfig, enable_spa: bool) -> Option<String> {
+    let snippet = match &cfg.html {
+        Some(html) if !html.trim().is_empty() => html.clone(),
+        _ => provider_snippet(cfg)?,
+    };
+
+    let head = if cfg.respect_dnt {
+        format!(
+            "<template id=\"trellis-analytics\">{snippet}</template>\
+             <script>if(navigator.doNotTrack!==\"1\"&&window.doNotTrack!==\"1\"){{\
+             document.head.appendChild(document.importNode(\
+             document.getElementById(\"trellis-analytics\").content,true))}}</script>"
+        )
+    } else {
+        snippet
+    };
+
+    if enable_spa {
+        Some(head.replace("<script", "<script spa-preserve"))
+    } else {
+        Some(head)
+    }
+}
+
+fn provider_snippet(cfg: &AnalyticsConfig) -> Option<String> {
+    let provider = cfg.provider.as_ref()?;
+    let site_id = escape_html(cfg.site_id.as_deref()?);
+    let host = cfg
+        .host
+        .as_deref()
+        .map(|host| escape_html(host.trim_end_matches('/')));
+
+    let snippet = match provider {
+        AnalyticsProvider::Plausible => {
+            let host = host.unwrap_or_else(|| "https://plausible.io".into());
+            format!("<script defer data-domain=\"{site_id}\" src=\"{host}/js/script.js\"></script>")
+        }
+        AnalyticsProvider::Umami => {
+            let host = host.unwrap_or_else(|| "https://cloud.umami.is".into());
+            format!(
+                "<script defer src=\"{host}/script.js\" data-website-id=\"{site_id}\"></script>"
+            )
+        }
+        AnalyticsProvider::GoatCounter => match host {
+            Some(host) => format!(
+                "<script data-goatcounter=\"{host}/count\" async src=\"{host}/count.js\"></script>"
+            ),
+            None => format!(
+                "<script data-goatcounter=\"https://{site_id}.goatcounter.com/count\" async src=\"https://gc.zgo.at/count.js\"></script>"
+            ),
+        },
+    };
+    Some(snippet)
+}
@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+use include_dir::{Dir, include_dir};
+use log::info;
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use crate::trellis::cache;
+
+/// Default `templates/` tree compiled into the binary.
+static EMBEDDED: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/templates");
+
+/// Optional on-disk override (`paths.templates_root`), consulted before the embedded copy.
+static OVERRIDE_ROOT: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Where [`extract_defaults`] puts the embedded tree: a content-addressed temp directory,
+/// so path-based consumers (grass imports, the swc loader) can read the defaults like any
+/// other file.
+static EMBEDDED_ROOT: Lazy<PathBuf> = Lazy::new(|| {
+    let mut hasher = Sha256::new();
+    hash_dir(&EMBEDDED, &mut hasher);
+    let digest = format!("{:x}", hasher.finalize());
+    std::env::temp_dir().join(format!("trellis-templates-{}", &digest[..16]))
+});
+
+pub fn set_templates_root(root: Option<PathBuf>) {
+    if let Some(root) = &root {
+        info!("Using templates override at {}", root.display());
+    }
+    match OVERRIDE_ROOT.write() {
+        Ok(mut guard) => *guard = root,
+        Err(poisoned) => *poisoned.into_inner() = root,
+    }
+}
+
+pub fn templates_root() -> Option<PathBuf> {
+    match OVERRIDE_ROOT.read() {
+        Ok(guard) => guard.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    }
+}
+
+/// Roots to search in priority order: the override (if any), then the embedded defaults.
+pub fn search_roots() -> Vec<PathBuf> {
+    TemplateRoots::current().search_roots()
+}
+
+/// Resolve a path relative to the templates root, falling back per-file to the embedded copy.
+pub fn resolve(rel: impl AsRef<Path>) -> PathBuf {
+    TemplateRoots::current().resolve(rel)
+}
+
+/// Re-resolve an absolute path that may point into either root (e.g. a relative import
+/// joined onto an overridden file whose sibling only exists in the embedded tree).
+pub fn reresolve(path: &Path) -> PathBuf {
+    TemplateRoots::current().reresolve(path)
+}
+
+/// `path` relative to whichever templates root contains it.
+pub fn relative_path(path: &Path) -> Option<PathBuf> {
+    TemplateRoots::current().relative_path(path)
+}
+
+/// Template lookups against an explicit override root, for consumers that are handed the
+/// configured `paths.templates_root` rather than reading the process-wide setting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateRoots {
+    override_root: Option<PathBuf>,
+}
+
+impl TemplateRoots {
+    pub fn new(override_root: Option<PathBuf>) -> Self {
+        Self { override_root }
+    }
+
+    /// The roots set by [`set_templates_root`].
+    pub fn current() -> Self {
+        Self::new(templates_root())
+    }
+
+    pub fn search_roots(&self) -> Vec<PathBuf> {
+        let mut roots = Vec::with_capacity(2);
+        if let Some(root) = &self.override_root {
+            roots.push(root.clone());
+        }
+        roots.push(EMBEDDED_ROOT.clone());
+        roots
+    }
+
+    pub fn resolve(&self, rel: impl AsRef<Path>) -> PathBuf {
+        let rel = rel.as_ref();
+        if let Some(root) = &self.override_root {
+            let candidate = root.join(rel);
+            if candidate.is_file() {
+                return candidate;
+            }
+        }
+        EMBEDDED_ROOT.join(rel)
+    }
+
+    pub fn reresolve(&self, path: &Path) -> PathBuf {
+        if path.is_file() {
+            return path.to_path_buf();
+        }
+        self.search_roots()
+            .iter()
+            .find_map(|root| path.strip_prefix(root).ok())
+            .map(|rel| self.resolve(rel))
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+
+    pub fn relative_path(&self, path: &Path) -> Option<PathBuf> {
+        self.search_roots()
+            .iter()
+            .find_map(|root| path.strip_prefix(root).ok().map(Path::to_path_buf))
+    }
+}
+
+/// All files with `ext` under `dir`, keyed by their path relative to the templates root.
+/// Override files shadow embedded ones with the same relative path.
+pub fn list_files(dir: &str, ext: &str) -> BTreeMap<String, PathBuf> {
+    let mut files = BTreeMap::new();
+    for root in search_roots().into_iter().rev() {
+        for entry in WalkDir::new(root.join(dir))
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|e| e.path().is_file())
+        {
+            if entry.path().extension().map(|e| e == ext) != Some(true) {
+                continue;
+            }
+            if let Ok(rel) = entry.path().strip_prefix(&root) {
+                let key = rel.to_string_lossy().replace('\\', "/");
+                files.insert(key, entry.path().to_path_buf());
+            }
+        }
+    }
+    files
+}
+
+/// Newest modification time for files with `ext` under `dir` across all roots.
+pub fn newest_mtime(dir: &str, ext: &str) -> SystemTime {
+    search_roots()
+        .iter()
+        .filter_map(|root| cache::newest_mtime_with_extension(&root.join(dir), ext).ok())
+        .max()
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Extract the embedded templates unless an earlier run already has. Engines call this
+/// when created, so a failure stops startup instead of leaving lookups to point at a
+/// directory that isn't there.
+pub fn extract_defaults() -> io::Result<()> {
+    let target = &*EMBEDDED_ROOT;
+    if target.is_dir() {
+        return Ok(());
+    }
+
+    let staging = target.with_extension(format!("{}.tmp", std::process::id()));
+    let result = fs::create_dir_all(&staging)
+        .and_then(|_| EMBEDDED.extract(&staging))
+        .and_then(|_| fs::rename(&staging, target));
+
+    if let Err(err) = result {
+        let _ = fs::remove_dir_all(&staging);
+        // Another process may have won the race; only fail if nothing is there.
+        if !target.is_dir() {
+            return Err(io::Error::new(
+                err.kind(),
+                format!(
+                    "failed to extract embedded templates to {}: {err}",
+                    target.display()
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn hash_dir(dir: &Dir<'_>, hasher: &mut Sha256) {
+    for file in dir.files() {
+        hasher.update(file.path().to_string_lossy().as_bytes());
+        hasher.update(file.contents());
+    }
+    for sub in dir.dirs() {
+        hash_dir(sub, hasher);
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use log::warn;
+use walkdir::WalkDir;
+
+use crate::trellis::cache;
+
+/// Encrypted copies of attachments, under `cache_root/static/`.
+pub const ENCRYPTED_DIR: &str = "enc";
+
+/// Under the cache root: static paths only encrypted notes use, one per line. The static
+/// copy leaves them out.
+const PROTECTED_LIST: &str = ".protected-attachments";
+
+/// Every file the static dirs provide, keyed by its path under `/static/`. Later dirs win,
+/// as when they are copied.
+pub struct StaticFiles {
+    files: BTreeMap<String, PathBuf>,
+}
+
+impl StaticFiles {
+    pub fn scan(dirs: &[PathBuf]) -> Self {
+        let mut files = BTreeMap::new();
+        for dir in dirs.iter().filter(|dir| dir.is_dir()) {
+            for entry in WalkDir::new(dir)
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter(|e| e.path().is_file())
+            {
+                if let Ok(rel) = entry.path().strip_prefix(dir) {
+                    let rel = rel.to_string_lossy().replace('\\', "/");
+                    files.insert(rel, entry.path().to_path_buf());
+                }
+            }
+        }
+        Self { files }
+    }
+
+    /// Static paths the markdown in `content` refers to: `/static/...` links and image
+    /// sources, in markdown or raw HTML, and `![[name]]` embeds matched by file name.
+    pub fn referenced(&self, content: &str) -> BTreeSet<String> {
+        static EMBED: OnceLock<regex::Regex> = OnceLock::new();
+        static LINK: OnceLock<regex::Regex> = OnceLock::new();
+        let embed =
+            EMBED.get_or_init(|| regex::Regex::new(r"!\[\[([^\]\|#]+)").expect("valid pattern"));
+        let link = LINK.get_or_init(|| {
+            regex::Regex::new(r#"\]\(\s*<?([^)\s>]+)|(?:src|href)\s*=\s*["']([^"']+)["']"#)
+                .expect("valid pattern")
+        });
+
+        let mut found = BTreeSet::new();
+        for cap in embed.captures_iter(content) {
+            let name = cap[1].trim();
+            let by_path = static_path(name).filter(|rel| self.files.contains_key(*rel));
+            let by_name = || {
+                self.files
+                    .keys()
+                    .filter(|rel| rel.rsplit('/').next() == Some(name))
+                    .min_by_key(|rel| rel.len())
+                    .map(String::as_str)
+            };
+            if let Some(rel) = by_path.or_else(by_name) {
+                found.insert(rel.to_string());
+            }
+        }
+        for cap in link.captures_iter(content) {
+            let Some(target) = cap.get(1).or_else(|| cap.get(2)) else {
+                continue;
+            };
+            if let Some(rel) =
+                static_path(target.as_str()).filter(|rel| self.files.contains_key(*rel))
+            {
+                found.insert(rel.to_string());
+            }
+        }
+        found
+    }
+}
+
+/// The path under `/static/` a link or source points at, if it points there at all.
+/// Query strings and fragments are dropped; paths climbing out with `..` are refused.
+pub fn static_path(target: &str) -> Option<&str> {
+    if target.contains("://") {
+        return None;
+    }
+    let path = target.split(['?', '#']).next().unwrap_or(target);
+    let path = path.trim_start_matches("./").trim_start_matches('/');
+    let rel = path.strip_prefix("static/")?;
+    (!rel.is_empty() && !rel.split('/').any(|segment| segment == "..")).then_some(rel)
+}
+
+/// The file a static path comes from, checking the dirs latest first.
+pub fn static_source(dirs: &[PathBuf], rel: &str) -> Option<PathBuf> {
+    dirs.iter()
+        .rev()
+        .map(|dir| dir.join(rel))
+        .find(|path| path.is_file())
+}
+
+/// Record which attachments only encrypted notes use, so the static copy skips them.
+/// Attachments public pages use too stay published, with a warning.
+pub fn record_protected(
+    cache_root: &Path,
+    protected: &BTreeSet<String>,
+    public: &BTreeSet<String>,
+) -> io::Result<()> {
+    for shared in protected.intersection(public) {
+        warn!(
+            "static/{shared} is used by encrypted and public notes; it stays published unencrypted"
+        );
+    }
+    let list = protected
+        .difference(public)
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join("\n");
+    cache::write_atomic(&cache_root.join(PROTECTED_LIST), list.as_bytes(), false)
+}
+
+/// The static paths the last content index found only encrypted notes using.
+pub fn protected(cache_root: &Path) -> BTreeSet<String> {
+    fs::read_to_string(cache_root.join(PROTECTED_LIST))
+        .map(|list| list.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
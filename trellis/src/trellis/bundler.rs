@@ -1,42 +1,66 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::{OnceLock, RwLock};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Instant, SystemTime};
 
 use anyhow::{Context, Error, Result};
-use log::error;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as B64;
+use log::{debug, error, info, warn};
 use serde::Serialize;
+use sha2::{Digest, Sha256};
 use swc_atoms::Atom;
 use swc_bundler::{Bundle, Bundler, Config, Hook, Load, ModuleData, ModuleType, Resolve};
+use swc_common::comments::{Comments, SingleThreadedComments};
 use swc_common::errors::{EmitterWriter, Handler};
+use swc_common::source_map::SourceMapGenConfig;
 use swc_common::{FileName, GLOBALS, Globals, Mark, SourceMap, sync::Lrc};
 use swc_ecma_ast::{EsVersion, KeyValueProp, Module, Program};
 use swc_ecma_codegen::{Emitter, text_writer::JsWriter};
+use swc_ecma_minifier::optimize;
+use swc_ecma_minifier::option::{CompressOptions, ExtraOptions, MangleOptions, MinifyOptions};
 use swc_ecma_parser::lexer::Lexer;
-use swc_ecma_parser::{Parser, StringInput, Syntax, TsSyntax};
+use swc_ecma_parser::{EsSyntax, Parser, StringInput, Syntax, TsSyntax};
+use swc_ecma_transforms_base::fixer::fixer;
 use swc_ecma_transforms_base::helpers::Helpers;
 use swc_ecma_transforms_base::resolver;
+use swc_ecma_transforms_react::{Options as JsxOptions, Runtime, jsx};
 use swc_ecma_transforms_typescript::strip_type;
 use swc_ecma_visit::VisitMutWith;
+use tracing::{debug_span, field};
 
-#[derive(Debug, Serialize, Clone, Default)]
-pub struct InlineScripts {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub explorer: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub overlay_explorer: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub encrypted_note: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub mermaid: Option<String>,
+use crate::trellis::assets::TemplateRoots;
+use crate::trellis::cache;
+use crate::trellis::config::{BundleOutput, BundlerConfig, ScriptsConfig, SiteConfig};
+use crate::trellis::node_modules::{self, NodeModules};
+
+/// One component script as the page template emits it: inline source, or the URL of a
+/// content-hashed bundle under `static/js/`.
+#[derive(Debug, Serialize, Clone)]
+pub struct PageScript {
+    pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub callouts: Option<String>,
+    pub src: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub graph: Option<String>,
+    pub code: Option<String>,
+}
+
+/// A bundle that failed to build, shown on the pages that need it in dev mode.
+#[derive(Debug, Serialize, Clone)]
+pub struct BundleError {
+    pub name: String,
+    pub message: String,
+}
+
+/// Scripts for one page plus, in dev mode, the bundles it needed that failed to build.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct PageScripts {
+    pub scripts: Vec<PageScript>,
+    pub script_errors: Vec<BundleError>,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct ScriptNeeds {
     pub explorer: bool,
     pub overlay_explorer: bool,
@@ -44,9 +68,39 @@ pub struct ScriptNeeds {
     pub mermaid: bool,
     pub callouts: bool,
     pub graph: bool,
+    pub toc: bool,
+    pub comments: bool,
+    pub banner: bool,
+    pub search: bool,
+    pub subscribe: bool,
+    pub popover: bool,
+    /// On-demand custom scripts named in the page's `scripts` frontmatter.
+    pub custom: Vec<String>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+impl ScriptNeeds {
+    /// Needed kinds, in the order their scripts appear on the page.
+    fn kinds(&self) -> impl Iterator<Item = ScriptKind> {
+        [
+            (self.explorer, ScriptKind::Explorer),
+            (self.overlay_explorer, ScriptKind::OverlayExplorer),
+            (self.encrypted_note, ScriptKind::EncryptedNote),
+            (self.callouts, ScriptKind::Callouts),
+            (self.graph, ScriptKind::Graph),
+            (self.toc, ScriptKind::Toc),
+            (self.comments, ScriptKind::Comments),
+            (self.banner, ScriptKind::Banner),
+            (self.search, ScriptKind::Search),
+            (self.subscribe, ScriptKind::Subscribe),
+            (self.popover, ScriptKind::Popover),
+            (self.mermaid, ScriptKind::Mermaid),
+        ]
+        .into_iter()
+        .filter_map(|(needed, kind)| needed.then_some(kind))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ScriptKind {
     Explorer,
     OverlayExplorer,
@@ -54,183 +108,438 @@ pub enum ScriptKind {
     Mermaid,
     Callouts,
     Graph,
+    Toc,
+    Comments,
+    Banner,
+    Search,
+    Subscribe,
+    Popover,
+    /// A `*.inline.{ts,tsx,js,jsx}` entry from `scripts.custom_dir`, by name without the
+    /// `.inline.*` suffix.
+    Custom(String),
+}
+
+impl ScriptKind {
+    fn name(&self) -> &str {
+        match self {
+            Self::Explorer => "explorer",
+            Self::OverlayExplorer => "overlay-explorer",
+            Self::EncryptedNote => "encrypted-note",
+            Self::Mermaid => "mermaid",
+            Self::Callouts => "callouts",
+            Self::Graph => "graph",
+            Self::Toc => "toc",
+            Self::Comments => "comments",
+            Self::Banner => "banner",
+            Self::Search => "search",
+            Self::Subscribe => "subscribe",
+            Self::Popover => "popover",
+            Self::Custom(name) => name,
+        }
+    }
+}
+
+/// How page scripts are built and delivered, gathered from `bundler`, `scripts` and
+/// `server.dev_mode`.
+pub struct BundleSettings<'a> {
+    pub bundler: &'a BundlerConfig,
+    pub cache_root: &'a Path,
+    /// Unminified output with inline source maps.
+    pub dev_mode: bool,
+    pub scripts: &'a ScriptsConfig,
+    /// `paths.templates_root`; built-in scripts not found there come from the embedded copy.
+    pub templates_root: Option<&'a Path>,
+}
+
+/// Everything that shapes bundle output; a change to any of it forces a rebuild.
+#[derive(Debug, Clone, PartialEq)]
+struct BuildOptions {
+    dev_mode: bool,
+    templates: TemplateRoots,
+    output: BundleOutput,
+    custom_dir: Option<PathBuf>,
+    node_modules: Option<NodeModules>,
+    external_modules: Vec<String>,
+    script_externals: BTreeMap<String, Vec<String>>,
+    jsx: JsxPragma,
+}
+
+/// Classic-runtime JSX factory and fragment, e.g. preact's `h` and `Fragment`.
+#[derive(Debug, Clone, PartialEq)]
+struct JsxPragma {
+    factory: String,
+    fragment: String,
+}
+
+impl BuildOptions {
+    /// Specifiers left as imports in `kind`'s bundle: its own list from
+    /// `bundler.script_externals` if it has one, otherwise `bundler.external_modules`.
+    fn externals_for(&self, kind: &ScriptKind) -> &[String] {
+        self.script_externals
+            .get(kind.name())
+            .unwrap_or(&self.external_modules)
+    }
+
+    fn from_settings(settings: &BundleSettings) -> Self {
+        let bundler = settings.bundler;
+        Self {
+            dev_mode: settings.dev_mode,
+            templates: TemplateRoots::new(settings.templates_root.map(Path::to_path_buf)),
+            output: bundler.output,
+            custom_dir: settings
+                .scripts
+                .custom_dir
+                .as_deref()
+                .map(SiteConfig::resolve_path),
+            node_modules: bundler.node_modules.as_deref().map(|dir| {
+                NodeModules::new(
+                    SiteConfig::resolve_path(dir),
+                    bundler.allowed_packages.clone(),
+                    bundler.package_size_warning_kb,
+                )
+            }),
+            external_modules: bundler.external_modules.clone(),
+            script_externals: bundler.script_externals.clone(),
+            jsx: JsxPragma {
+                factory: bundler.jsx_pragma.clone(),
+                fragment: bundler.jsx_fragment.clone(),
+            },
+        }
+    }
 }
 
 static CACHE: OnceLock<RwLock<ScriptsCache>> = OnceLock::new();
+/// Dev bundles (unminified, with source maps) are cached apart from production ones so
+/// toggling `server.dev_mode` never serves the other variant.
+static DEV_CACHE: OnceLock<RwLock<ScriptsCache>> = OnceLock::new();
+
+/// The scripts a page needs, each bundled the first time a page needs it and rebuilt only
+/// when its entry or one of the modules it imports changes.
+///
+/// With [`BundleOutput::External`] each bundle is also written to
+/// `cache_root/static/js/{kind}.{hash}.js`; an unchanged bundle keeps its file name, so it
+/// can be served as immutable.
+///
+/// `dev_mode` skips minification and appends an inline source map pointing at the
+/// original `.ts` sources.
+///
+/// Custom scripts follow the built-in ones: every custom script is included unless it is
+/// listed in `scripts.on_demand`, in which case only pages naming it in their `scripts`
+/// frontmatter get it.
+///
+/// A needed bundle that failed to build is skipped. In dev mode it is replaced by a stub
+/// that logs the build error to the console, and the error is returned for the page to
+/// display.
+pub fn page_scripts(needs: &ScriptNeeds, settings: &BundleSettings) -> PageScripts {
+    let BundleSettings {
+        cache_root,
+        dev_mode,
+        scripts,
+        ..
+    } = *settings;
+    let options = BuildOptions::from_settings(settings);
+    let cache = if dev_mode { &DEV_CACHE } else { &CACHE };
+    let cache = cache.get_or_init(|| RwLock::new(ScriptsCache::default()));
 
-pub fn inline_scripts(needs: ScriptNeeds) -> InlineScripts {
-    let newest_mtime = newest_templates_mtime().unwrap_or(UNIX_EPOCH);
-    let cache = CACHE.get_or_init(|| {
-        RwLock::new(ScriptsCache {
-            bundles: HashMap::new(),
-            mtime: UNIX_EPOCH,
+    let mut wanted: Vec<(ScriptKind, PathBuf)> = needs
+        .kinds()
+        .map(|kind| {
+            let path = builtin_entry(&kind, &options.templates);
+            (kind, path)
         })
-    });
-    {
-        if let Ok(cache_guard) = cache.read() {
-            if cache_guard.mtime >= newest_mtime {
-                return InlineScripts {
-                    explorer: needs
-                        .explorer
-                        .then(|| cache_guard.bundles.get(&ScriptKind::Explorer).cloned())
-                        .flatten(),
-                    overlay_explorer: needs
-                        .overlay_explorer
-                        .then(|| {
-                            cache_guard
-                                .bundles
-                                .get(&ScriptKind::OverlayExplorer)
-                                .cloned()
-                        })
-                        .flatten(),
-                    encrypted_note: needs
-                        .encrypted_note
-                        .then(|| cache_guard.bundles.get(&ScriptKind::EncryptedNote).cloned())
-                        .flatten(),
-                    mermaid: needs
-                        .mermaid
-                        .then(|| cache_guard.bundles.get(&ScriptKind::Mermaid).cloned())
-                        .flatten(),
-                    callouts: needs
-                        .callouts
-                        .then(|| cache_guard.bundles.get(&ScriptKind::Callouts).cloned())
-                        .flatten(),
-                    graph: needs
-                        .graph
-                        .then(|| cache_guard.bundles.get(&ScriptKind::Graph).cloned())
-                        .flatten(),
-                };
+        .collect();
+    if let Some(dir) = options.custom_dir.as_deref() {
+        wanted.extend(custom_entries(dir).into_iter().filter(|(kind, _)| {
+            let name = kind.name();
+            !scripts.on_demand.iter().any(|n| n == name) || needs.custom.iter().any(|n| n == name)
+        }));
+    }
+
+    let stale: Vec<&(ScriptKind, PathBuf)> = match cache.read() {
+        Ok(guard) if guard.options.as_ref() == Some(&options) => wanted
+            .iter()
+            .filter(|(kind, _)| !guard.bundles.get(kind).is_some_and(CachedBundle::is_fresh))
+            .collect(),
+        _ => wanted.iter().collect(),
+    };
+
+    if !stale.is_empty() {
+        let built: Vec<(ScriptKind, CachedBundle)> = stale
+            .into_iter()
+            .map(|(kind, path)| (kind.clone(), build_bundle(kind, path, &options, cache_root)))
+            .collect();
+        if let Ok(mut guard) = cache.write() {
+            if guard.options.as_ref() != Some(&options) {
+                guard.bundles.clear();
+                guard.options = Some(options.clone());
             }
+            guard.bundles.extend(built);
         }
     }
 
-    match build_all_bundles() {
-        Ok((bundles, mtime)) => {
-            if let Ok(mut cache_guard) = cache.write() {
-                cache_guard.bundles = bundles.clone();
-                cache_guard.mtime = mtime;
-            }
-            InlineScripts {
-                explorer: needs
-                    .explorer
-                    .then(|| bundles.get(&ScriptKind::Explorer).cloned())
-                    .flatten(),
-                overlay_explorer: needs
-                    .overlay_explorer
-                    .then(|| bundles.get(&ScriptKind::OverlayExplorer).cloned())
-                    .flatten(),
-                encrypted_note: needs
-                    .encrypted_note
-                    .then(|| bundles.get(&ScriptKind::EncryptedNote).cloned())
-                    .flatten(),
-                mermaid: needs
-                    .mermaid
-                    .then(|| bundles.get(&ScriptKind::Mermaid).cloned())
-                    .flatten(),
-                callouts: needs
-                    .callouts
-                    .then(|| bundles.get(&ScriptKind::Callouts).cloned())
-                    .flatten(),
-                graph: needs
-                    .graph
-                    .then(|| bundles.get(&ScriptKind::Graph).cloned())
-                    .flatten(),
+    let Ok(guard) = cache.read() else {
+        return PageScripts::default();
+    };
+
+    let mut page = PageScripts::default();
+    for (kind, _) in &wanted {
+        let Some(bundle) = guard.bundles.get(kind) else {
+            continue;
+        };
+        let name = kind.name().to_string();
+        match &bundle.output {
+            Ok(code) => page.scripts.push(PageScript {
+                name,
+                src: bundle.url.clone(),
+                code: bundle.url.is_none().then(|| code.clone()),
+            }),
+            Err(message) if dev_mode => {
+                page.scripts.push(PageScript {
+                    name: name.clone(),
+                    src: None,
+                    code: Some(console_error_stub(&name, message)),
+                });
+                page.script_errors.push(BundleError {
+                    name,
+                    message: message.clone(),
+                });
             }
-        }
-        Err(err) => {
-            error!("failed to build inline scripts: {err:?}");
-            InlineScripts::default()
+            Err(_) => {}
         }
     }
+    page
 }
 
+/// Names of bundles whose last build failed, for the health endpoint.
+pub fn failed_bundles() -> Vec<String> {
+    let mut failed: Vec<String> = [&CACHE, &DEV_CACHE]
+        .iter()
+        .filter_map(|cache| cache.get()?.read().ok())
+        .flat_map(|guard| {
+            guard
+                .bundles
+                .iter()
+                .filter(|(_, bundle)| bundle.output.is_err())
+                .map(|(kind, _)| kind.name().to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    failed.sort();
+    failed.dedup();
+    failed
+}
+
+fn console_error_stub(name: &str, message: &str) -> String {
+    let text = format!("[trellis] {name} bundle failed to build:\n{message}");
+    let literal = serde_json::to_string(&text)
+        .unwrap_or_default()
+        .replace("</", "<\\/");
+    format!("console.error({literal});")
+}
+
+#[derive(Default)]
 struct ScriptsCache {
-    bundles: HashMap<ScriptKind, String>,
-    mtime: SystemTime,
-}
-
-fn newest_templates_mtime() -> Result<SystemTime> {
-    let templates = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("templates");
-    let util = templates.join("util");
-    let components = templates.join("components/scripts");
-    let util_mtime = newest_mtime_in(&util)?;
-    let comp_mtime = newest_mtime_in(&components)?;
-    Ok(util_mtime.max(comp_mtime))
-}
-
-fn newest_mtime_in(dir: &Path) -> Result<SystemTime> {
-    let mut newest = UNIX_EPOCH;
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
-        let metadata = entry.metadata()?;
-        if metadata.is_file() {
-            if let Ok(mtime) = metadata.modified() {
-                if mtime > newest {
-                    newest = mtime;
-                }
-            }
+    bundles: HashMap<ScriptKind, CachedBundle>,
+    /// Options the bundles were built with; a change drops every bundle.
+    options: Option<BuildOptions>,
+}
+
+struct CachedBundle {
+    /// Bundled code, or the build error with file and position when known.
+    output: Result<String, String>,
+    /// URL of the written bundle file in external mode.
+    url: Option<String>,
+    /// Every file the loader read for this bundle, with its mtime at the time.
+    sources: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl CachedBundle {
+    /// Whether every source is unchanged since the build. A file that was missing then
+    /// (a failed build) counts as unchanged while it stays missing.
+    fn is_fresh(&self) -> bool {
+        self.sources
+            .iter()
+            .all(|(path, mtime)| file_mtime(path) == *mtime)
+    }
+}
+
+fn file_mtime(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+fn build_bundle(
+    kind: &ScriptKind,
+    entry: &Path,
+    options: &BuildOptions,
+    cache_root: &Path,
+) -> CachedBundle {
+    let span = debug_span!(
+        "bundle",
+        script = kind.name(),
+        bytes = field::Empty,
+        duration_ms = field::Empty,
+    );
+    let _entered = span.enter();
+    let started = Instant::now();
+    let sources = SourceLog::default();
+    let output = bundle_entry(kind, entry, options, &sources).and_then(|code| {
+        let url = match options.output {
+            BundleOutput::Inline => None,
+            BundleOutput::External => Some(write_bundle_file(
+                kind,
+                &code,
+                cache_root,
+                options.dev_mode,
+            )?),
+        };
+        Ok((code, url))
+    });
+    span.record("duration_ms", started.elapsed().as_millis() as u64);
+    let (output, url) = match output {
+        Ok((code, url)) => {
+            span.record("bytes", code.len());
+            debug!("bundled {}", kind.name());
+            (Ok(code), url)
+        }
+        Err(err) => {
+            error!("failed to bundle {:?}: {:#}", kind, err);
+            (Err(format!("{err:#}")), None)
         }
+    };
+
+    let mut sources = sources.lock().map(|s| s.clone()).unwrap_or_default();
+    if !sources.iter().any(|(path, _)| path == entry) {
+        sources.push((entry.to_path_buf(), file_mtime(entry)));
     }
-    Ok(newest)
-}
-
-fn build_all_bundles() -> Result<(HashMap<ScriptKind, String>, SystemTime)> {
-    let templates_root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("templates");
-    let component_root = templates_root.join("components/scripts");
-
-    let entries = vec![
-        (
-            ScriptKind::Explorer,
-            component_root.join("explorer.inline.ts"),
-        ),
-        (
-            ScriptKind::OverlayExplorer,
-            component_root.join("overlay-explorer.inline.ts"),
-        ),
-        (
-            ScriptKind::EncryptedNote,
-            component_root.join("encrypted-note.inline.ts"),
-        ),
-        (
-            ScriptKind::Mermaid,
-            component_root.join("mermaid.inline.ts"),
-        ),
-        (
-            ScriptKind::Callouts,
-            component_root.join("callouts.inline.ts"),
-        ),
-        (ScriptKind::Graph, component_root.join("graph.inline.ts")),
-    ];
-
-    let mut bundles = HashMap::new();
-    let mut latest = newest_templates_mtime().unwrap_or(UNIX_EPOCH);
-
-    for (kind, path) in entries {
-        match bundle_entry(&path) {
-            Ok(code) => {
-                bundles.insert(kind, code);
-                if let Ok(meta) = fs::metadata(&path) {
-                    if let Ok(mtime) = meta.modified() {
-                        if mtime > latest {
-                            latest = mtime;
-                        }
-                    }
-                }
-            }
-            Err(err) => {
-                error!("failed to bundle {:?}: {}", kind, err);
+    CachedBundle {
+        output,
+        url,
+        sources,
+    }
+}
+
+/// Write a bundle under `static/js/` with a content hash in its name, and delete older
+/// files of the same script and variant (dev or production).
+fn write_bundle_file(
+    kind: &ScriptKind,
+    code: &str,
+    cache_root: &Path,
+    dev_mode: bool,
+) -> Result<String> {
+    let variant = if dev_mode { ".dev." } else { "." };
+    let dir = cache_root.join("static").join("js");
+    fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+
+    let hash = format!("{:x}", Sha256::digest(code.as_bytes()));
+    let prefix = format!("{}{variant}", kind.name());
+    let file_name = format!("{prefix}{}.js", &hash[..16]);
+    let path = dir.join(&file_name);
+    if !path.is_file() {
+        cache::write_atomic(&path, code.as_bytes(), false)
+            .with_context(|| format!("writing bundle {}", path.display()))?;
+    }
+
+    for entry in fs::read_dir(&dir)?.filter_map(Result::ok) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(hash) = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(".js"))
+        else {
+            continue;
+        };
+        // `explorer.` also prefixes `explorer.dev.`; only a bare hash is this variant.
+        if name != file_name && !hash.contains('.') {
+            debug!("removing stale bundle {name}");
+            if let Err(err) = fs::remove_file(entry.path()) {
+                warn!("failed to remove stale bundle {name}: {err}");
             }
         }
     }
 
-    Ok((bundles, latest))
+    Ok(format!("/static/js/{file_name}"))
 }
 
-fn bundle_entry(entry: &Path) -> Result<String> {
+fn builtin_entry(kind: &ScriptKind, templates: &TemplateRoots) -> PathBuf {
+    templates.resolve(Path::new("components/scripts").join(format!("{}.inline.ts", kind.name())))
+}
+
+/// `*.inline.{ts,tsx,js,jsx}` entries directly inside `dir`. Names that clash with a
+/// built-in script are skipped.
+fn custom_entries(dir: &Path) -> Vec<(ScriptKind, PathBuf)> {
+    let Ok(read) = fs::read_dir(dir) else {
+        warn!("scripts.custom_dir {} is not readable", dir.display());
+        return Vec::new();
+    };
+
+    let mut entries: Vec<(ScriptKind, PathBuf)> = read
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            let file_name = path.file_name()?.to_str()?;
+            let name = file_name
+                .rsplit_once('.')
+                .filter(|(_, ext)| ENTRY_EXTENSIONS.contains(ext))?
+                .0
+                .strip_suffix(".inline")?
+                .to_string();
+            Some((ScriptKind::Custom(name), path))
+        })
+        .filter(|(kind, path)| {
+            let builtin = BUILTIN_NAMES.contains(&kind.name());
+            if builtin {
+                warn!(
+                    "custom script {} shadows a built-in script name; skipping",
+                    path.display()
+                );
+            }
+            !builtin
+        })
+        .collect();
+    entries.sort_by(|a, b| a.1.cmp(&b.1));
+    entries
+}
+
+/// Source extensions the loader understands, in the order extensionless imports try them.
+const ENTRY_EXTENSIONS: &[&str] = &["tsx", "ts", "js", "jsx"];
+
+const BUILTIN_NAMES: &[&str] = &[
+    "explorer",
+    "overlay-explorer",
+    "encrypted-note",
+    "mermaid",
+    "callouts",
+    "graph",
+    "toc",
+    "comments",
+    "banner",
+    "search",
+    "subscribe",
+    "popover",
+];
+
+/// Files read while bundling one entry, with their mtimes; the entry's import graph.
+type SourceLog = Arc<Mutex<Vec<(PathBuf, Option<SystemTime>)>>>;
+
+fn bundle_entry(
+    kind: &ScriptKind,
+    entry: &Path,
+    options: &BuildOptions,
+    sources: &SourceLog,
+) -> Result<String> {
+    let dev_mode = options.dev_mode;
     let cm: Lrc<SourceMap> = Default::default();
     let globals = Globals::new();
-    let loader = FsLoader { cm: cm.clone() };
-    let resolver = ScriptResolver::new();
+    // Only collected in dev mode, where the output keeps comments.
+    let comments = dev_mode.then(SingleThreadedComments::default);
+    let loader = FsLoader {
+        cm: cm.clone(),
+        comments: comments.clone(),
+        sources: sources.clone(),
+        jsx: options.jsx.clone(),
+        templates: options.templates.clone(),
+    };
+    let resolver = ScriptResolver::new(options.node_modules.clone(), options.templates.clone());
     let hook = Box::new(NoopHook);
 
     let mut bundler = Bundler::new(
@@ -242,25 +551,27 @@ fn bundle_entry(entry: &Path) -> Result<String> {
             require: false,
             disable_hygiene: false,
             disable_fixer: false,
+            // Inlining is left to the minifier's compress pass, which is better at it; dev
+            // bundles keep every module intact so source maps line up.
             disable_inliner: true,
-            disable_dce: true,
-            external_modules: vec![Atom::from(
-                "https://cdnjs.cloudflare.com/ajax/libs/mermaid/11.4.0/mermaid.esm.min.mjs",
-            )],
+            disable_dce: dev_mode,
+            external_modules: options
+                .externals_for(kind)
+                .iter()
+                .map(|spec| Atom::from(spec.as_str()))
+                .collect(),
             module: ModuleType::Es,
         },
         hook,
     );
 
     let mut entries = HashMap::new();
-    entries.insert(
-        entry
-            .file_name()
-            .and_then(|s| s.to_str())
-            .unwrap_or("entry")
-            .to_string(),
-        FileName::Real(entry.to_path_buf()),
-    );
+    let entry_name = entry
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("entry")
+        .to_string();
+    entries.insert(entry_name.clone(), FileName::Real(entry.to_path_buf()));
 
     let bundles: Vec<Bundle> = bundler.bundle(entries)?;
 
@@ -270,7 +581,17 @@ fn bundle_entry(entry: &Path) -> Result<String> {
         .find(|b| matches!(b.kind, swc_bundler::BundleKind::Named { .. }))
         .ok_or_else(|| Error::msg("bundle not produced"))?;
 
-    emit_minified(&bundled.module, cm, &globals)
+    if dev_mode {
+        emit_dev(
+            &bundled.module,
+            cm,
+            &globals,
+            comments.as_ref(),
+            &options.templates,
+        )
+    } else {
+        emit_minified(&entry_name, &bundled.module, cm, &globals)
+    }
 }
 
 struct NoopHook;
@@ -284,17 +605,36 @@ impl Hook for NoopHook {
     }
 }
 
-struct ScriptResolver;
+struct ScriptResolver {
+    node_modules: Option<NodeModules>,
+    templates: TemplateRoots,
+}
 
 impl ScriptResolver {
-    fn new() -> Self {
-        Self
+    fn new(node_modules: Option<NodeModules>, templates: TemplateRoots) -> Self {
+        Self {
+            node_modules,
+            templates,
+        }
+    }
+
+    fn resolve_bare(&self, base: &FileName, spec: &str) -> Result<FileName> {
+        let importer = match base {
+            FileName::Real(path) => path.clone(),
+            other => PathBuf::from(other.to_string()),
+        };
+        let Some(node_modules) = &self.node_modules else {
+            return Err(Error::msg(format!(
+                "{} imports {spec:?}, but bundler.node_modules is not configured",
+                importer.display()
+            )));
+        };
+        Ok(FileName::Real(node_modules.resolve(&importer, spec)?))
     }
 
     fn resolve_spec(&self, spec: &str) -> Result<FileName> {
-        let templates_root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("templates");
-        let util_root = templates_root.join("util");
-        let scripts_root = templates_root.join("components/scripts");
+        let util_root = Path::new("util");
+        let scripts_root = Path::new("components/scripts");
 
         let (root, mut rel) = if let Some(rest) = spec.strip_prefix("/js/util/") {
             (util_root, rest.to_string())
@@ -310,7 +650,7 @@ impl ScriptResolver {
             rel = rel.trim_end_matches(".js").to_string() + ".ts";
         }
 
-        Ok(FileName::Real(root.join(rel)))
+        Ok(FileName::Real(self.templates.resolve(root.join(rel))))
     }
 }
 
@@ -326,6 +666,8 @@ impl Resolve for ScriptResolver {
             FileName::Custom(module_specifier.to_string())
         } else if module_specifier.starts_with("/js/") {
             self.resolve_spec(module_specifier)?
+        } else if node_modules::is_bare(module_specifier) {
+            self.resolve_bare(base, module_specifier)?
         } else {
             match base {
                 FileName::Real(path) => {
@@ -335,12 +677,20 @@ impl Resolve for ScriptResolver {
                         .unwrap_or_else(|| PathBuf::from("."));
                     let mut joined = parent.join(module_specifier);
                     if joined.extension().is_none() {
-                        // Try TypeScript first; these sources live alongside.
-                        joined.set_extension("ts");
-                    } else if joined.extension().map(|e| e == "js").unwrap_or(false) {
+                        // Try TypeScript first, then plain JavaScript for custom scripts;
+                        // fall back to `.ts` so the loader's error names a sensible file.
+                        let ext = ENTRY_EXTENSIONS
+                            .iter()
+                            .find(|ext| joined.with_extension(ext).is_file())
+                            .unwrap_or(&"ts");
+                        joined.set_extension(ext);
+                    } else if joined.extension().map(|e| e == "js").unwrap_or(false)
+                        && !joined.is_file()
+                    {
                         joined.set_extension("ts");
                     }
-                    FileName::Real(joined)
+                    // Siblings of an overridden file may only exist in the embedded tree.
+                    FileName::Real(self.templates.reresolve(&joined))
                 }
                 _ => return Err(Error::msg("unsupported base filename")),
             }
@@ -355,6 +705,10 @@ impl Resolve for ScriptResolver {
 
 struct FsLoader {
     cm: Lrc<SourceMap>,
+    comments: Option<SingleThreadedComments>,
+    sources: SourceLog,
+    jsx: JsxPragma,
+    templates: TemplateRoots,
 }
 
 impl Load for FsLoader {
@@ -363,6 +717,11 @@ impl Load for FsLoader {
             FileName::Real(p) => p.clone(),
             _ => return Err(Error::msg("unsupported filename kind for loader")),
         };
+        // Recorded before loading, so a missing or broken import still invalidates the
+        // bundle once it is fixed.
+        if let Ok(mut sources) = self.sources.lock() {
+            sources.push((path.clone(), file_mtime(&path)));
+        }
 
         let emitter = EmitterWriter::new(
             Box::new(std::io::stderr()),
@@ -377,24 +736,69 @@ impl Load for FsLoader {
             .load_file(&path)
             .with_context(|| format!("loading script {}", path.display()))?;
 
-        let syntax = Syntax::Typescript(TsSyntax {
-            tsx: false,
-            decorators: true,
-            dts: false,
-            ..Default::default()
-        });
+        // npm packages and custom `.js` entries are plain JavaScript.
+        let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        let is_jsx = matches!(ext, "tsx" | "jsx");
+        let syntax = if matches!(ext, "ts" | "tsx") {
+            Syntax::Typescript(TsSyntax {
+                tsx: is_jsx,
+                decorators: true,
+                dts: false,
+                ..Default::default()
+            })
+        } else {
+            Syntax::Es(EsSyntax {
+                jsx: is_jsx,
+                ..Default::default()
+            })
+        };
 
-        let lexer = Lexer::new(syntax, EsVersion::Es2022, StringInput::from(&*fm), None);
+        let lexer = Lexer::new(
+            syntax,
+            EsVersion::Es2022,
+            StringInput::from(&*fm),
+            self.comments.as_ref().map(|c| c as &dyn Comments),
+        );
         let mut parser = Parser::new_from(lexer);
         let mut module = parser.parse_module().map_err(|e| {
+            let loc = self.cm.lookup_char_pos(e.span().lo);
+            let shown = self
+                .templates
+                .relative_path(&path)
+                .unwrap_or_else(|| path.clone());
+            let message = format!(
+                "{}:{}:{}: {}",
+                shown.display(),
+                loc.line,
+                loc.col.0 + 1,
+                e.kind().msg()
+            );
             let mut diag = e.into_diagnostic(&handler);
             diag.emit();
-            Error::msg("failed to parse module")
+            Error::msg(message)
         })?;
 
         // Strip TypeScript types so downstream bundler passes don't see TS nodes.
         module.visit_mut_with(&mut strip_type());
 
+        // JSX becomes factory calls (`h("div", null, ...)`) for the configured pragma.
+        if is_jsx {
+            let mut program = Program::Module(module);
+            program.mutate(jsx(
+                self.cm.clone(),
+                self.comments.clone(),
+                JsxOptions {
+                    runtime: Some(Runtime::Classic),
+                    pragma: Some(self.jsx.factory.clone().into()),
+                    pragma_frag: Some(self.jsx.fragment.clone().into()),
+                    ..Default::default()
+                },
+                Mark::new(),
+                Mark::new(),
+            ));
+            module = program.expect_module();
+        }
+
         Ok(ModuleData {
             fm,
             module,
@@ -403,7 +807,16 @@ impl Load for FsLoader {
     }
 }
 
-fn emit_minified(module: &Module, cm: Lrc<SourceMap>, globals: &Globals) -> Result<String> {
+/// Production output: swc_ecma_minifier (compress + mangle) followed by minified codegen.
+///
+/// The result is re-parsed before it is used; if the minifier ever produces something that
+/// doesn't parse, the whitespace-only minification is served instead.
+fn emit_minified(
+    name: &str,
+    module: &Module,
+    cm: Lrc<SourceMap>,
+    globals: &Globals,
+) -> Result<String> {
     GLOBALS.set(globals, || {
         let unresolved_mark = Mark::new();
         let top_level_mark = Mark::new();
@@ -413,25 +826,288 @@ fn emit_minified(module: &Module, cm: Lrc<SourceMap>, globals: &Globals) -> Resu
         // typescript = true so TS syntax contexts are handled correctly
         resolved.visit_mut_with(&mut resolver(unresolved_mark, top_level_mark, true));
 
+        let program = Program::Module(resolved);
+        let baseline = codegen_minified(&program, &cm)?;
+
+        let options = MinifyOptions {
+            compress: Some(CompressOptions {
+                module: true,
+                ecma: EsVersion::Es2016,
+                ..Default::default()
+            }),
+            mangle: Some(MangleOptions {
+                top_level: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut optimized = optimize(
+            program,
+            cm.clone(),
+            None,
+            None,
+            &options,
+            &ExtraOptions {
+                unresolved_mark,
+                top_level_mark,
+                mangle_name_cache: None,
+            },
+        );
+        optimized.visit_mut_with(&mut fixer(None));
+
+        let out = codegen_minified(&optimized, &cm)?;
+        if let Err(err) = reparse(&out) {
+            warn!("minified {name} failed to re-parse ({err}); serving unminified output");
+            return Ok(baseline);
+        }
+
+        info!(
+            "bundled {name}: {} bytes minified ({} before compress/mangle)",
+            out.len(),
+            baseline.len()
+        );
+        Ok(out)
+    })
+}
+
+fn codegen_minified(program: &Program, cm: &Lrc<SourceMap>) -> Result<String> {
+    let mut buf = Vec::new();
+    {
+        let mut cfg = swc_ecma_codegen::Config::default();
+        cfg.minify = true;
+        cfg.target = EsVersion::Es2016;
+
+        let mut emitter = Emitter {
+            cfg,
+            comments: None,
+            cm: cm.clone(),
+            wr: JsWriter::new(cm.clone(), "\n", &mut buf, None),
+        };
+
+        emitter.emit_program(program)?;
+    }
+
+    Ok(String::from_utf8(buf)?)
+}
+
+/// Check that `code` is still a valid ES2016 module.
+fn reparse(code: &str) -> Result<()> {
+    let cm: Lrc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(Lrc::new(FileName::Anon), code.to_string());
+    let lexer = Lexer::new(
+        Syntax::Es(EsSyntax::default()),
+        EsVersion::Es2016,
+        StringInput::from(&*fm),
+        None,
+    );
+    Parser::new_from(lexer)
+        .parse_module()
+        .map(|_| ())
+        .map_err(|err| Error::msg(err.kind().msg().into_owned()))
+}
+
+/// Readable output for dev mode: no minification, comments kept, and an inline source map
+/// so stack traces point at the original `.ts` files.
+fn emit_dev(
+    module: &Module,
+    cm: Lrc<SourceMap>,
+    globals: &Globals,
+    comments: Option<&SingleThreadedComments>,
+    templates: &TemplateRoots,
+) -> Result<String> {
+    GLOBALS.set(globals, || {
+        let unresolved_mark = Mark::new();
+        let top_level_mark = Mark::new();
+
+        let mut resolved = module.clone();
+        resolved.visit_mut_with(&mut resolver(unresolved_mark, top_level_mark, true));
+
         let program = Program::Module(resolved);
 
         let mut buf = Vec::new();
+        let mut mappings = Vec::new();
         {
             let mut cfg = swc_ecma_codegen::Config::default();
-            cfg.minify = true;
             cfg.target = EsVersion::Es2016;
 
             let mut emitter = Emitter {
                 cfg,
-                comments: None,
+                comments: comments.map(|c| c as &dyn Comments),
                 cm: cm.clone(),
-                wr: JsWriter::new(cm.clone(), "\n", &mut buf, None),
+                wr: JsWriter::new(cm.clone(), "\n", &mut buf, Some(&mut mappings)),
             };
 
             emitter.emit_program(&program)?;
         }
 
-        let out = String::from_utf8(buf)?;
+        let mut map = Vec::new();
+        cm.build_source_map(&mappings, None, TemplateSourceMapConfig(templates))
+            .to_writer(&mut map)
+            .context("writing source map")?;
+
+        let mut out = String::from_utf8(buf)?;
+        out.push_str("\n//# sourceMappingURL=data:application/json;base64,");
+        out.push_str(&B64.encode(map));
         Ok(out)
     })
 }
+
+/// Names sources by their path under the templates root (`components/scripts/graph.inline.ts`)
+/// rather than the extraction directory, and embeds their contents.
+struct TemplateSourceMapConfig<'a>(&'a TemplateRoots);
+
+impl SourceMapGenConfig for TemplateSourceMapConfig<'_> {
+    fn file_name_to_source(&self, f: &FileName) -> String {
+        match f {
+            FileName::Real(path) => self
+                .0
+                .relative_path(path)
+                .map(|rel| format!("templates/{}", rel.to_string_lossy().replace('\\', "/")))
+                .unwrap_or_else(|| path.display().to_string()),
+            other => other.to_string(),
+        }
+    }
+
+    fn inline_sources_content(&self, _f: &FileName) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(dev_mode: bool) -> BuildOptions {
+        BuildOptions {
+            dev_mode,
+            templates: TemplateRoots::new(None),
+            output: BundleOutput::Inline,
+            custom_dir: None,
+            node_modules: None,
+            external_modules: Vec::new(),
+            script_externals: BTreeMap::new(),
+            jsx: JsxPragma {
+                factory: "h".into(),
+                fragment: "Fragment".into(),
+            },
+        }
+    }
+
+    /// Bundle `source`, written to a file called `file_name`, as the custom script `name`.
+    fn bundle_source(
+        name: &str,
+        file_name: &str,
+        source: &str,
+        options: &BuildOptions,
+    ) -> Result<String> {
+        let dir = tempfile::tempdir().unwrap();
+        let entry = dir.path().join(file_name);
+        fs::write(&entry, source).unwrap();
+        bundle_entry(
+            &ScriptKind::Custom(name.into()),
+            &entry,
+            options,
+            &SourceLog::default(),
+        )
+    }
+
+    #[test]
+    fn production_bundles_are_minified_and_reparse() {
+        let source = "function unusedHelper() {\n  return 1;\n}\n\n\
+                      export function greet(longParameterName: string) {\n  \
+                      const message = \"hello \" + longParameterName;\n  return message;\n}\n";
+        let code = bundle_source("greet", "greet.inline.ts", source, &options(false)).unwrap();
+        reparse(&code).unwrap();
+        assert!(!code.contains("unusedHelper"), "{code}");
+        assert!(!code.contains("longParameterName"), "{code}");
+        assert!(!code.contains('\n'), "{code}");
+        assert!(code.contains("greet"), "{code}");
+    }
+
+    #[test]
+    fn builtin_scripts_minify_to_valid_javascript() {
+        crate::trellis::assets::extract_defaults().unwrap();
+        let options = options(false);
+        for kind in [ScriptKind::Callouts, ScriptKind::Explorer] {
+            let entry = builtin_entry(&kind, &options.templates);
+            let code = bundle_entry(&kind, &entry, &options, &SourceLog::default()).unwrap();
+            reparse(&code).unwrap_or_else(|err| panic!("{}: {err}", kind.name()));
+        }
+    }
+
+    #[test]
+    fn external_imports_survive_bundling() {
+        let source = "import { select } from \"d3\";\n\
+                      import mermaid from \"https://cdn.example/mermaid.esm.min.mjs\";\n\n\
+                      export function draw() {\n  mermaid.initialize({});\n  return select(\"body\");\n}\n";
+        let mut options = options(false);
+        options.external_modules = vec!["https://cdn.example/mermaid.esm.min.mjs".into()];
+        options.script_externals.insert(
+            "chart".into(),
+            vec![
+                "d3".into(),
+                "https://cdn.example/mermaid.esm.min.mjs".into(),
+            ],
+        );
+
+        let code = bundle_source("chart", "chart.inline.ts", source, &options).unwrap();
+        assert!(code.contains("\"d3\""), "{code}");
+        assert!(
+            code.contains("\"https://cdn.example/mermaid.esm.min.mjs\""),
+            "{code}"
+        );
+
+        // Without its own list a script falls back to `external_modules`, which leaves
+        // `d3` to be resolved, and there is no node_modules to resolve it from.
+        assert!(bundle_source("other", "other.inline.ts", source, &options).is_err());
+    }
+
+    #[test]
+    fn tsx_entries_compile_to_factory_calls() {
+        let source = "import { h, Fragment, render } from \"preact\";\n\n\
+                      const Badge = ({ label }: { label: string }) => <span class=\"badge\">{label}</span>;\n\n\
+                      render(<><Badge label=\"new\" /></>, document.body);\n";
+        let mut options = options(true);
+        options
+            .script_externals
+            .insert("badge".into(), vec!["preact".into()]);
+
+        let code = bundle_source("badge", "badge.inline.tsx", source, &options).unwrap();
+        assert!(code.contains("h(\"span\""), "{code}");
+        assert!(code.contains("h(Fragment"), "{code}");
+        assert!(code.contains("h(Badge"), "{code}");
+        assert!(!code.contains("<span"), "{code}");
+    }
+
+    #[test]
+    fn builtin_scripts_come_from_the_templates_root() {
+        crate::trellis::assets::extract_defaults().unwrap();
+        let root = tempfile::tempdir().unwrap();
+        let scripts = root.path().join("components/scripts");
+        fs::create_dir_all(&scripts).unwrap();
+        fs::write(
+            scripts.join("callouts.inline.ts"),
+            "document.body.dataset.callouts = \"overridden callouts\";\n",
+        )
+        .unwrap();
+
+        let mut options = options(false);
+        options.templates = TemplateRoots::new(Some(root.path().to_path_buf()));
+        let entry = builtin_entry(&ScriptKind::Callouts, &options.templates);
+        assert_eq!(entry, scripts.join("callouts.inline.ts"));
+        let code = bundle_entry(
+            &ScriptKind::Callouts,
+            &entry,
+            &options,
+            &SourceLog::default(),
+        )
+        .unwrap();
+        assert!(code.contains("overridden callouts"), "{code}");
+        assert!(!code.contains("is-collapsed"), "{code}");
+
+        // Scripts the override root lacks still come from the embedded templates.
+        let entry = builtin_entry(&ScriptKind::Toc, &options.templates);
+        assert!(!entry.starts_with(root.path()));
+        assert!(entry.is_file());
+    }
+}
@@ -1,10 +1,27 @@
+use std::collections::HashMap;
 use std::fs;
-use std::io;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 use std::time::SystemTime;
 
+use chrono::{SecondsFormat, Utc};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use walkdir::WalkDir;
 
+use crate::trellis::config::{SiteConfig, theme_hash};
+use crate::trellis::types::PageOutline;
+
+/// Per-path write locks so concurrent writers of the same file don't interleave.
+static WRITE_LOCKS: Lazy<Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Version of the rendered HTML markup. Bump whenever the pipeline output changes
+/// (callout markup, heading ids, ...) so pages cached in an older format are discarded.
+pub const CACHE_FORMAT_VERSION: &str = "2";
+
 pub fn cache_path(cache_root: &Path, slug: &str) -> PathBuf {
     let mut path = cache_root.to_path_buf();
     let slug_path = Path::new(slug);
@@ -22,32 +39,70 @@ pub fn ensure_cache_root(cache_root: &Path) -> io::Result<()> {
     fs::create_dir_all(cache_root)
 }
 
-pub fn cache_is_fresh(src: &Path, cached: &Path, deps: &[SystemTime]) -> io::Result<bool> {
-    let src_meta = fs::metadata(src)?;
-    let cache_meta = fs::metadata(cached)?;
+pub fn write_cache(path: &Path, html: &str) -> io::Result<()> {
+    write_atomic(path, html.as_bytes(), false)
+}
 
-    let src_time = src_meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
-    let cache_time = cache_meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+/// The sidecar next to the cached HTML at `html_path` holding the page's [`PageOutline`].
+pub fn outline_path(html_path: &Path) -> PathBuf {
+    html_path.with_extension("outline.json")
+}
 
-    let newest_dep = deps.iter().copied().max().unwrap_or(SystemTime::UNIX_EPOCH);
+pub fn write_outline(html_path: &Path, outline: &PageOutline) -> io::Result<()> {
+    let json = serde_json::to_vec(outline).map_err(io::Error::other)?;
+    write_atomic(&outline_path(html_path), &json, false)
+}
 
-    Ok(cache_time >= src_time && cache_time >= newest_dep)
+/// The outline cached with `html_path`, or `None` when it is missing or unreadable, as it
+/// is for pages cached before outlines were.
+pub fn read_outline(html_path: &Path) -> Option<PageOutline> {
+    let json = fs::read(outline_path(html_path)).ok()?;
+    serde_json::from_slice(&json).ok()
 }
 
-pub fn write_cache(path: &Path, html: &str) -> io::Result<()> {
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)?;
+/// Write `contents` to a temp file next to `path` and rename it into place, so readers
+/// only ever observe the previous file or the complete new one. Set `sync` to fsync the
+/// temp file before the rename when durability across power loss matters.
+pub fn write_atomic(path: &Path, contents: &[u8], sync: bool) -> io::Result<()> {
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    fs::create_dir_all(parent)?;
+
+    let lock = path_lock(path);
+    let _guard = lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    let file_name = path
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "cache".into());
+    let tmp_path = parent.join(format!(".{}.{}.tmp", file_name, std::process::id()));
+
+    let result = (|| {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(contents)?;
+        if sync {
+            file.sync_all()?;
+        }
+        drop(file);
+        fs::rename(&tmp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
     }
-    fs::write(path, html)
+    result
 }
 
-/// Modified time of the current executable (used to bust caches on new builds).
-pub fn binary_mtime() -> SystemTime {
-    std::env::current_exe()
-        .ok()
-        .and_then(|p| fs::metadata(p).ok())
-        .and_then(|m| m.modified().ok())
-        .unwrap_or(SystemTime::UNIX_EPOCH)
+fn path_lock(path: &Path) -> Arc<Mutex<()>> {
+    let mut locks = WRITE_LOCKS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    locks
+        .entry(path.to_path_buf())
+        .or_insert_with(|| Arc::new(Mutex::new(())))
+        .clone()
 }
 
 /// Find the most recent modification time for files with the given extension under `dir`.
@@ -75,26 +130,343 @@ pub fn newest_mtime_with_extension(dir: &Path, ext: &str) -> io::Result<SystemTi
     Ok(newest)
 }
 
-/// Write or refresh a marker file containing the provided hash and return its mtime.
-/// This lets callers include semantic hashes as cache dependencies without parsing HTML.
-pub fn update_hash_marker(cache_root: &Path, name: &str, hash: &str) -> io::Result<SystemTime> {
-    let marker = cache_root.join(format!(".{name}_hash"));
-    let mut needs_write = true;
+/// Render cache indexes by cache root; see [`index`].
+static INDEXES: Lazy<Mutex<HashMap<PathBuf, Arc<RenderIndex>>>> = Lazy::new(Default::default);
+
+/// Receives every change to an index: the new entry of a slug, or `None` when it was dropped.
+pub type IndexSink = Box<dyn Fn(&str, Option<&CacheEntry>) + Send + Sync>;
+
+/// What a page's cached HTML was rendered from. Freshness is decided by comparing these
+/// hashes with the current ones rather than by file times.
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheEntry {
+    pub source_hash: String,
+    /// Source modified time (ms since the epoch) and length when hashed. While both still
+    /// match, the source is taken as unchanged without reading it again.
+    pub source_mtime_ms: i64,
+    pub source_len: i64,
+    pub theme_hash: String,
+    pub config_hash: String,
+    /// [`CACHE_FORMAT_VERSION`] and the version of trellis that rendered the page.
+    pub pipeline: String,
+    pub render_ms: i64,
+    /// The cached HTML, relative to the cache root.
+    pub html_path: String,
+    /// UTC, RFC 3339.
+    pub rendered_at: String,
+}
+
+/// The inputs every page is rendered with; computed once per engine.
+#[derive(Debug, Clone)]
+pub struct RenderKey {
+    pub theme_hash: String,
+    pub config_hash: String,
+    pub pipeline: String,
+}
+
+impl RenderKey {
+    pub fn new(config: &SiteConfig) -> Self {
+        let config_json = serde_json::to_string(config).unwrap_or_default();
+        Self {
+            theme_hash: theme_hash(&config.configuration.theme),
+            config_hash: format!("{:x}", Sha256::digest(config_json.as_bytes())),
+            pipeline: format!("v{CACHE_FORMAT_VERSION}+{}", env!("CARGO_PKG_VERSION")),
+        }
+    }
+}
+
+/// Why a page's cached HTML can or can't be served, checked in this order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Staleness {
+    Fresh,
+    NoHtml,
+    Unrecorded,
+    Pipeline,
+    Theme,
+    Config,
+    Source,
+}
 
-    if let Ok(existing) = fs::read_to_string(&marker) {
-        if existing.trim() == hash {
-            needs_write = false;
+impl Staleness {
+    pub fn reason(self) -> &'static str {
+        match self {
+            Self::Fresh => "cached HTML matches the source, theme, config and pipeline",
+            Self::NoHtml => "no cached HTML",
+            Self::Unrecorded => "not rendered since the render cache was recorded",
+            Self::Pipeline => "rendered by another version of trellis or cache format",
+            Self::Theme => "the theme changed",
+            Self::Config => "the config changed",
+            Self::Source => "the source changed",
         }
     }
+}
+
+/// The outcome of [`check`]: the verdict, plus what is needed to record a new render.
+#[derive(Debug, Clone)]
+pub struct Freshness {
+    pub staleness: Staleness,
+    pub recorded: Option<CacheEntry>,
+    source_hash: String,
+    source_mtime_ms: i64,
+    source_len: i64,
+}
+
+impl Freshness {
+    pub fn is_fresh(&self) -> bool {
+        self.staleness == Staleness::Fresh
+    }
 
-    if needs_write {
-        if let Some(parent) = marker.parent() {
-            fs::create_dir_all(parent)?;
+    /// Record that `slug` was just rendered with `key`, taking `render_ms`.
+    pub fn record_render(&self, cache_root: &Path, slug: &str, key: &RenderKey, render_ms: i64) {
+        let html_path = cache_path(cache_root, slug);
+        let html_path = html_path.strip_prefix(cache_root).unwrap_or(&html_path);
+        index(cache_root).record(
+            slug,
+            CacheEntry {
+                source_hash: self.source_hash.clone(),
+                source_mtime_ms: self.source_mtime_ms,
+                source_len: self.source_len,
+                theme_hash: key.theme_hash.clone(),
+                config_hash: key.config_hash.clone(),
+                pipeline: key.pipeline.clone(),
+                render_ms,
+                html_path: html_path.to_string_lossy().replace('\\', "/"),
+                rendered_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            },
+        );
+    }
+
+    /// After serving fresh HTML, note a source that was touched but not changed so the
+    /// next check skips hashing it.
+    pub fn refresh_stamp(&self, cache_root: &Path, slug: &str) {
+        let Some(entry) = &self.recorded else {
+            return;
+        };
+        if entry.source_mtime_ms != self.source_mtime_ms || entry.source_len != self.source_len {
+            let mut entry = entry.clone();
+            entry.source_mtime_ms = self.source_mtime_ms;
+            entry.source_len = self.source_len;
+            index(cache_root).record(slug, entry);
         }
-        fs::write(&marker, hash)?;
     }
+}
+
+/// Whether the cached HTML of `slug` can be served for `source`, rendered with `key`.
+pub fn check(
+    cache_root: &Path,
+    slug: &str,
+    source: &Path,
+    key: &RenderKey,
+) -> io::Result<Freshness> {
+    let meta = fs::metadata(source)?;
+    let source_mtime_ms = meta
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|age| age.as_millis() as i64)
+        .unwrap_or_default();
+    let source_len = meta.len() as i64;
+    let recorded = index(cache_root).recorded(slug);
 
-    fs::metadata(&marker)
-        .and_then(|m| m.modified())
-        .or(Ok(SystemTime::UNIX_EPOCH))
+    // mtime and length are only a shortcut: when either moved, the content decides.
+    let source_hash = match &recorded {
+        Some(entry)
+            if entry.source_mtime_ms == source_mtime_ms && entry.source_len == source_len =>
+        {
+            entry.source_hash.clone()
+        }
+        _ => format!("{:x}", Sha256::digest(fs::read(source)?)),
+    };
+
+    let staleness = match &recorded {
+        _ if !cache_path(cache_root, slug).is_file() => Staleness::NoHtml,
+        None => Staleness::Unrecorded,
+        Some(entry) if entry.pipeline != key.pipeline => Staleness::Pipeline,
+        Some(entry) if entry.theme_hash != key.theme_hash => Staleness::Theme,
+        Some(entry) if entry.config_hash != key.config_hash => Staleness::Config,
+        Some(entry) if entry.source_hash != source_hash => Staleness::Source,
+        Some(_) => Staleness::Fresh,
+    };
+    Ok(Freshness {
+        staleness,
+        recorded,
+        source_hash,
+        source_mtime_ms,
+        source_len,
+    })
+}
+
+/// The index of the pages cached under `cache_root`, shared by every engine using it.
+/// It starts empty, so pages render afresh, until entries are [loaded](RenderIndex::load).
+pub fn index(cache_root: &Path) -> Arc<RenderIndex> {
+    let mut indexes = INDEXES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    Arc::clone(indexes.entry(cache_root.to_path_buf()).or_default())
+}
+
+/// What each page cached under one root was rendered from, by slug.
+#[derive(Default)]
+pub struct RenderIndex {
+    entries: RwLock<HashMap<String, CacheEntry>>,
+    /// Where changes are persisted, once the server has a database.
+    sink: OnceLock<IndexSink>,
+}
+
+impl RenderIndex {
+    /// Replace the entries with those read back from the database at startup.
+    pub fn load(&self, entries: impl IntoIterator<Item = (String, CacheEntry)>) {
+        let mut index = self
+            .entries
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        *index = entries.into_iter().collect();
+    }
+
+    /// Persist every later change through `sink`; only the first call counts. Without one
+    /// the index lives in memory only, and pages render afresh on each start.
+    pub fn set_sink(&self, sink: IndexSink) {
+        let _ = self.sink.set(sink);
+    }
+
+    pub fn recorded(&self, slug: &str) -> Option<CacheEntry> {
+        let index = self
+            .entries
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        index.get(slug).cloned()
+    }
+
+    /// Every slug with an entry, for finding cache files whose page is gone.
+    pub fn slugs(&self) -> Vec<String> {
+        let index = self
+            .entries
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        index.keys().cloned().collect()
+    }
+
+    fn record(&self, slug: &str, entry: CacheEntry) {
+        if let Some(sink) = self.sink.get() {
+            sink(slug, Some(&entry));
+        }
+        let mut index = self
+            .entries
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        index.insert(slug.to_string(), entry);
+    }
+
+    /// Drop the entry for `slug`.
+    pub fn forget(&self, slug: &str) {
+        if let Some(sink) = self.sink.get() {
+            sink(slug, None);
+        }
+        let mut index = self
+            .entries
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        index.remove(slug);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    /// A content index of `pages` entries, as JSON.
+    fn index_json(pages: usize) -> String {
+        let entries: HashMap<String, String> = (0..pages)
+            .map(|i| (format!("notes/{i}"), "lorem ipsum ".repeat(8)))
+            .collect();
+        serde_json::to_string(&entries).unwrap()
+    }
+
+    fn read_index(path: &Path) -> HashMap<String, String> {
+        serde_json::from_slice(&fs::read(path).unwrap()).expect("a complete index")
+    }
+
+    #[test]
+    fn a_write_cut_short_leaves_the_previous_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("content-index.json");
+        write_atomic(&path, index_json(10).as_bytes(), false).unwrap();
+
+        // A writer that died before its rename leaves a truncated temp file behind.
+        let full = index_json(500);
+        let tmp = dir
+            .path()
+            .join(format!(".content-index.json.{}.tmp", std::process::id()));
+        fs::write(&tmp, &full.as_bytes()[..full.len() / 2]).unwrap();
+        assert_eq!(read_index(&path).len(), 10);
+
+        write_atomic(&path, full.as_bytes(), false).unwrap();
+        assert_eq!(read_index(&path).len(), 500);
+        assert!(!tmp.exists());
+    }
+
+    #[test]
+    fn readers_never_see_a_truncated_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("content-index.json");
+        let (small, large) = (index_json(10), index_json(2000));
+        write_atomic(&path, small.as_bytes(), true).unwrap();
+
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || {
+                for round in 0..200 {
+                    let json = if round % 2 == 0 { &large } else { &small };
+                    write_atomic(&path, json.as_bytes(), false).unwrap();
+                }
+            })
+        };
+        while !writer.is_finished() {
+            let len = read_index(&path).len();
+            assert!(len == 10 || len == 2000, "read {len} entries");
+        }
+        writer.join().unwrap();
+    }
+
+    /// A cache root holding the rendered HTML of `slug`, whose source is `source`.
+    fn rendered(dir: &Path, slug: &str, source: &Path) -> (PathBuf, Freshness) {
+        let root = dir.join("cache");
+        write_cache(&cache_path(&root, slug), "<p>hi</p>").unwrap();
+        let key = RenderKey::new(&SiteConfig::default());
+        let freshness = check(&root, slug, source, &key).unwrap();
+        (root, freshness)
+    }
+
+    #[test]
+    fn each_cache_root_has_its_own_index() {
+        let (one, two) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let source = one.path().join("hello.md");
+        fs::write(&source, "# Hello").unwrap();
+        let key = RenderKey::new(&SiteConfig::default());
+
+        let (first, freshness) = rendered(one.path(), "hello", &source);
+        assert_eq!(freshness.staleness, Staleness::Unrecorded);
+        freshness.record_render(&first, "hello", &key, 1);
+        let again = check(&first, "hello", &source, &key).unwrap();
+        assert_eq!(again.staleness, Staleness::Fresh);
+
+        let (second, freshness) = rendered(two.path(), "hello", &source);
+        assert_eq!(freshness.staleness, Staleness::Unrecorded);
+        assert!(index(&second).slugs().is_empty());
+
+        index(&first).forget("hello");
+        assert!(index(&first).recorded("hello").is_none());
+    }
+
+    #[test]
+    fn the_pipeline_names_the_format_and_crate_version() {
+        let key = RenderKey::new(&SiteConfig::default());
+        assert_eq!(
+            key.pipeline,
+            format!("v{CACHE_FORMAT_VERSION}+{}", env!("CARGO_PKG_VERSION"))
+        );
+    }
 }
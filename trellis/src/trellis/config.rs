@@ -1,12 +1,44 @@
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 use confik::{Configuration, EnvSource};
 use serde::{Deserialize, Serialize};
 use serde_json;
 use sha2::{Digest, Sha256};
 
-use self::yaml::YamlFileSource;
-use crate::trellis::layout::LayoutConfig;
+use self::yaml::YamlSource;
+use crate::trellis::layout::{LayoutConfig, PageLayout};
+use crate::trellis::types::PageMetadata;
+use crate::trellis::validation::{self, ConfigIssue};
+
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+static PROFILE: OnceLock<String> = OnceLock::new();
+static LOAD_OPTIONS: OnceLock<LoadOptions> = OnceLock::new();
+
+/// How config problems are treated at load time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoadOptions {
+    /// Treat warnings (e.g. unknown keys) as errors.
+    pub strict: bool,
+    /// Start with the compiled-in defaults when the config is invalid.
+    pub allow_default_fallback: bool,
+}
+
+impl LoadOptions {
+    /// `TRELLIS_STRICT_CONFIG` and `TRELLIS_ALLOW_DEFAULT_CONFIG` (`1`/`true`).
+    pub fn from_env() -> Self {
+        let flag = |name: &str| {
+            std::env::var(name)
+                .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+                .unwrap_or(false)
+        };
+        Self {
+            strict: flag("TRELLIS_STRICT_CONFIG"),
+            allow_default_fallback: flag("TRELLIS_ALLOW_DEFAULT_CONFIG"),
+        }
+    }
+}
 
 fn default_host() -> String {
     "0.0.0.0".into()
@@ -20,6 +52,39 @@ fn default_max_payload_mb() -> usize {
     100
 }
 
+fn default_static_dirs() -> Vec<String> {
+    vec!["../content/static".into()]
+}
+
+fn default_fonts_dir() -> String {
+    "../content/fonts".into()
+}
+
+fn default_log_filter() -> String {
+    "info".into()
+}
+
+fn default_socket_mode() -> String {
+    "0660".into()
+}
+
+fn default_hsts_max_age_secs() -> u64 {
+    // One year, the minimum browsers' preload lists accept.
+    31_536_000
+}
+
+fn default_keep_alive_secs() -> u64 {
+    5
+}
+
+fn default_client_request_timeout_secs() -> u64 {
+    5
+}
+
+fn default_dev_mode() -> bool {
+    cfg!(debug_assertions)
+}
+
 fn default_content_root() -> String {
     "../content".into()
 }
@@ -56,13 +121,388 @@ pub struct ThemeMode {
 
 #[derive(Debug, Clone, Serialize, Deserialize, Configuration)]
 pub struct ThemeConfig {
+    /// Name of a built-in palette (see [`THEME_PRESETS`]). Colors set explicitly under
+    /// `colors` override the preset's values key by key.
+    #[serde(default)]
+    pub preset: Option<String>,
+    /// `googleFonts` links the families from Google Fonts; `local` serves them from
+    /// `paths.fonts_dir` instead.
     pub font_origin: String,
+    /// Emit preconnect hints for the Google Fonts CDN.
     pub cdn_caching: bool,
+    /// `font-display` for Google Fonts and the generated `@font-face` rules.
+    #[serde(default)]
+    pub font_display: FontDisplay,
     pub typography: ThemeFonts,
     pub colors: ThemeMode,
+    /// SCSS appended to the built-in styles. Defaults to `custom.scss` in the content root
+    /// when that file exists.
+    #[serde(default)]
+    pub custom_scss: Option<String>,
+    #[serde(default)]
+    pub css_delivery: CssDelivery,
+    /// Palette shown before (or without) the client's saved preference.
+    #[serde(default)]
+    pub default_mode: ThemeDefaultMode,
+    /// Browserslist query the compiled CSS is prefixed and lowered for.
+    #[serde(default = "default_css_targets")]
+    pub css_targets: String,
+    /// Code token colors for each mode (see [`SYNTAX_THEMES`]).
+    #[serde(default)]
+    pub syntax: SyntaxThemes,
+    /// Screen widths the layout switches at; `mobile-only` and `desktop-only` components
+    /// flip at `mobile`.
+    #[serde(default)]
+    pub breakpoints: Breakpoints,
+}
+
+/// CSS lengths handed to `variables.scss` as `$breakpoints`.
+#[derive(Debug, Clone, Serialize, Deserialize, Configuration)]
+pub struct Breakpoints {
+    /// At or below this width pages use the single-column mobile grid.
+    #[serde(default = "default_breakpoint_mobile")]
+    pub mobile: String,
+    /// At or above this width both sidebars are shown.
+    #[serde(default = "default_breakpoint_desktop")]
+    pub desktop: String,
+}
+
+fn default_breakpoint_mobile() -> String {
+    "800px".into()
+}
+
+fn default_breakpoint_desktop() -> String {
+    "1200px".into()
+}
+
+impl Default for Breakpoints {
+    fn default() -> Self {
+        Self {
+            mobile: default_breakpoint_mobile(),
+            desktop: default_breakpoint_desktop(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Configuration)]
+pub struct SyntaxThemes {
+    #[serde(default = "default_syntax_light")]
+    pub light: String,
+    #[serde(default = "default_syntax_dark")]
+    pub dark: String,
+}
+
+fn default_css_targets() -> String {
+    "defaults".into()
+}
+
+fn default_syntax_light() -> String {
+    "github-light".into()
+}
+
+fn default_syntax_dark() -> String {
+    "github-dark".into()
+}
+
+impl Default for SyntaxThemes {
+    fn default() -> Self {
+        Self {
+            light: default_syntax_light(),
+            dark: default_syntax_dark(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Configuration)]
+#[serde(rename_all = "lowercase")]
+#[confik(forward(serde(rename_all = "lowercase")))]
+pub enum ThemeDefaultMode {
+    #[default]
+    Light,
+    Dark,
+    /// Follow the visitor's `prefers-color-scheme`.
+    Auto,
+}
+
+/// How the compiled site CSS reaches the page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Configuration)]
+#[serde(rename_all = "lowercase")]
+#[confik(forward(serde(rename_all = "lowercase")))]
+pub enum CssDelivery {
+    /// A `<style>` block in every page.
+    #[default]
+    Inline,
+    /// A `<link>` to `static/index.{hash}.css`, cacheable across pages.
+    External,
+}
+
+/// How text renders while a web font loads (the CSS `font-display` descriptor).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Configuration)]
+#[serde(rename_all = "lowercase")]
+#[confik(forward(serde(rename_all = "lowercase")))]
+pub enum FontDisplay {
+    Auto,
+    Block,
+    /// Fallback text straight away, swapped for the web font whenever it arrives.
+    #[default]
+    Swap,
+    Fallback,
+    Optional,
+}
+
+impl FontDisplay {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Block => "block",
+            Self::Swap => "swap",
+            Self::Fallback => "fallback",
+            Self::Optional => "optional",
+        }
+    }
+}
+
+/// Palette keys in the order preset colors are listed.
+const PALETTE_KEYS: [&str; 9] = [
+    "light",
+    "lightgray",
+    "gray",
+    "darkgray",
+    "dark",
+    "secondary",
+    "tertiary",
+    "highlight",
+    "text_highlight",
+];
+
+pub struct ThemePreset {
+    pub name: &'static str,
+    pub light_mode: [&'static str; 9],
+    pub dark_mode: [&'static str; 9],
+}
+
+/// Built-in palettes selectable with `theme.preset`. Each lists colors in
+/// [`PALETTE_KEYS`] order; the light mode uses the scheme's matching light variant.
+pub const THEME_PRESETS: &[ThemePreset] = &[
+    ThemePreset {
+        name: "catppuccin-mocha",
+        light_mode: [
+            "#eff1f5",
+            "#ccd0da",
+            "#9ca0b0",
+            "#5c5f77",
+            "#4c4f69",
+            "#1e66f5",
+            "#179299",
+            "rgba(30, 102, 245, 0.15)",
+            "#df8e1d88",
+        ],
+        dark_mode: [
+            "#1e1e2e",
+            "#313244",
+            "#6c7086",
+            "#bac2de",
+            "#cdd6f4",
+            "#89b4fa",
+            "#94e2d5",
+            "rgba(137, 180, 250, 0.15)",
+            "#f9e2af44",
+        ],
+    },
+    ThemePreset {
+        name: "gruvbox",
+        light_mode: [
+            "#fbf1c7",
+            "#ebdbb2",
+            "#a89984",
+            "#504945",
+            "#3c3836",
+            "#076678",
+            "#427b58",
+            "rgba(7, 102, 120, 0.15)",
+            "#d7992188",
+        ],
+        dark_mode: [
+            "#282828",
+            "#3c3836",
+            "#7c6f64",
+            "#d5c4a1",
+            "#ebdbb2",
+            "#83a598",
+            "#8ec07c",
+            "rgba(131, 165, 152, 0.15)",
+            "#fabd2f44",
+        ],
+    },
+    ThemePreset {
+        name: "rose-pine",
+        light_mode: [
+            "#faf4ed",
+            "#f2e9e1",
+            "#9893a5",
+            "#797593",
+            "#575279",
+            "#286983",
+            "#56949f",
+            "rgba(40, 105, 131, 0.12)",
+            "#ea9d3488",
+        ],
+        dark_mode: [
+            "#191724",
+            "#26233a",
+            "#6e6a86",
+            "#908caa",
+            "#e0def4",
+            "#c4a7e7",
+            "#9ccfd8",
+            "rgba(196, 167, 231, 0.15)",
+            "#f6c17744",
+        ],
+    },
+    ThemePreset {
+        name: "solarized",
+        light_mode: [
+            "#fdf6e3",
+            "#eee8d5",
+            "#93a1a1",
+            "#586e75",
+            "#073642",
+            "#268bd2",
+            "#2aa198",
+            "rgba(38, 139, 210, 0.15)",
+            "#b5890088",
+        ],
+        dark_mode: [
+            "#002b36",
+            "#073642",
+            "#586e75",
+            "#93a1a1",
+            "#eee8d5",
+            "#268bd2",
+            "#2aa198",
+            "rgba(38, 139, 210, 0.15)",
+            "#b5890044",
+        ],
+    },
+];
+
+pub fn theme_preset(name: &str) -> Option<&'static ThemePreset> {
+    THEME_PRESETS.iter().find(|preset| preset.name == name)
+}
+
+/// Code token keys in the order syntax theme colors are listed; each becomes a
+/// `--code-{key}` CSS variable.
+pub const SYNTAX_TOKEN_KEYS: [&str; 9] = [
+    "fg",
+    "bg",
+    "comment",
+    "keyword",
+    "string",
+    "number",
+    "function",
+    "type",
+    "punctuation",
+];
+
+pub struct SyntaxTheme {
+    pub name: &'static str,
+    pub colors: [&'static str; 9],
+}
+
+/// Built-in code token palettes selectable with `theme.syntax.light` / `.dark`, colors in
+/// [`SYNTAX_TOKEN_KEYS`] order.
+pub const SYNTAX_THEMES: &[SyntaxTheme] = &[
+    SyntaxTheme {
+        name: "github-light",
+        colors: [
+            "#24292f", "#f6f8fa", "#6e7781", "#cf222e", "#0a3069", "#0550ae", "#8250df", "#953800",
+            "#24292f",
+        ],
+    },
+    SyntaxTheme {
+        name: "github-dark",
+        colors: [
+            "#c9d1d9", "#161b22", "#8b949e", "#ff7b72", "#a5d6ff", "#79c0ff", "#d2a8ff", "#ffa657",
+            "#c9d1d9",
+        ],
+    },
+    SyntaxTheme {
+        name: "one-light",
+        colors: [
+            "#383a42", "#fafafa", "#a0a1a7", "#a626a4", "#50a14f", "#986801", "#4078f2", "#c18401",
+            "#383a42",
+        ],
+    },
+    SyntaxTheme {
+        name: "one-dark",
+        colors: [
+            "#abb2bf", "#282c34", "#5c6370", "#c678dd", "#98c379", "#d19a66", "#61afef", "#e5c07b",
+            "#abb2bf",
+        ],
+    },
+];
+
+pub fn syntax_theme(name: &str) -> Option<&'static SyntaxTheme> {
+    SYNTAX_THEMES.iter().find(|theme| theme.name == name)
+}
+
+/// Expand `configuration.theme.preset` in a raw config document, filling in every palette
+/// key the document doesn't set itself. Runs before deserialization so explicit colors
+/// can be partial.
+pub fn apply_theme_preset(doc: &mut serde_yaml::Value) -> Result<(), String> {
+    use serde_yaml::{Mapping, Value};
+
+    let Some(Value::Mapping(theme)) = doc
+        .get_mut("configuration")
+        .and_then(|cfg| cfg.get_mut("theme"))
+    else {
+        return Ok(());
+    };
+    let Some(name) = theme.get("preset").and_then(Value::as_str) else {
+        return Ok(());
+    };
+    let Some(preset) = theme_preset(name) else {
+        let available: Vec<&str> = THEME_PRESETS.iter().map(|p| p.name).collect();
+        return Err(format!(
+            "unknown preset {name:?}; available presets: {}",
+            available.join(", ")
+        ));
+    };
+
+    let colors = theme
+        .entry("colors".into())
+        .or_insert_with(|| Value::Mapping(Mapping::new()));
+    if colors.is_null() {
+        *colors = Value::Mapping(Mapping::new());
+    }
+    let Value::Mapping(colors) = colors else {
+        return Ok(());
+    };
+
+    for (mode, palette) in [
+        ("light_mode", &preset.light_mode),
+        ("dark_mode", &preset.dark_mode),
+    ] {
+        let entry = colors
+            .entry(mode.into())
+            .or_insert_with(|| Value::Mapping(Mapping::new()));
+        if entry.is_null() {
+            *entry = Value::Mapping(Mapping::new());
+        }
+        if let Value::Mapping(entry) = entry {
+            for (key, color) in PALETTE_KEYS.iter().zip(palette) {
+                entry
+                    .entry((*key).into())
+                    .or_insert_with(|| (*color).into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Which date is displayed for pages and used to order listings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Configuration)]
 #[serde(rename_all = "lowercase")]
 #[confik(forward(serde(rename_all = "lowercase")))]
 pub enum DefaultDateType {
@@ -75,21 +515,37 @@ pub enum DefaultDateType {
 pub struct GlobalConfiguration {
     pub page_title: String,
     pub tagline: Option<String>,
+    /// Appended to page titles in `<title>`. When empty, the separator and site title are.
     #[serde(default)]
     pub page_title_suffix: String,
+    /// Placed between a page's title and the site title in `<title>`.
+    #[serde(default = "default_title_separator")]
+    pub title_separator: String,
     pub enable_spa: bool,
     pub enable_popovers: bool,
     #[serde(default)]
     pub locale: String,
+    /// Public origin of the site (e.g. `https://notes.example.com`), used for canonical and
+    /// other absolute URLs. Links stay root-relative when unset.
     #[serde(default)]
     pub base_url: Option<String>,
+    /// Globs matched against paths relative to the content root. Plain names such as
+    /// `private` match that directory or file anywhere in the tree.
     #[serde(default)]
     pub ignore_patterns: Vec<String>,
     #[serde(default = "default_date_type_modified")]
     pub default_date_type: DefaultDateType,
+    /// Date pages without `created`/`updated` frontmatter by their first and last commit
+    /// when the content root is in a git repository.
+    #[serde(default)]
+    pub dates_from_git: bool,
     pub theme: ThemeConfig,
 }
 
+fn default_title_separator() -> String {
+    " — ".into()
+}
+
 fn default_date_type_modified() -> DefaultDateType {
     DefaultDateType::Modified
 }
@@ -102,8 +558,117 @@ pub struct ServerConfig {
     pub port: u16,
     #[serde(default)]
     pub cors_origins: Vec<String>,
+    /// Largest request body accepted, such as a posted comment.
     #[serde(default = "default_max_payload_mb")]
     pub max_payload_mb: usize,
+    /// Development conveniences such as template hot-reload. Defaults to on for debug builds.
+    #[serde(default = "default_dev_mode")]
+    pub dev_mode: bool,
+    /// HTTP worker threads; 0 means one per available CPU.
+    #[serde(default)]
+    pub workers: usize,
+    /// Idle keep-alive for client connections; 0 disables keep-alive.
+    #[serde(default = "default_keep_alive_secs")]
+    pub keep_alive_secs: u64,
+    /// Time allowed for a client to send request headers; 0 disables the timeout.
+    #[serde(default = "default_client_request_timeout_secs")]
+    pub client_request_timeout_secs: u64,
+    /// Serve HTTPS directly instead of plain HTTP.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Listen on this Unix domain socket instead of `host`/`port` (and `tls`).
+    #[serde(default)]
+    pub socket_path: Option<String>,
+    /// Octal permissions applied to `socket_path` after binding.
+    #[serde(default = "default_socket_mode")]
+    pub socket_mode: String,
+    /// Addresses of reverse proxies whose `X-Forwarded-For` names the client. Requests from
+    /// anywhere else are keyed on the connecting address, whatever headers they carry.
+    /// Connections over `socket_path` count as coming through a trusted proxy.
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    /// Content-Security-Policy sent with rendered pages.
+    #[serde(default)]
+    pub csp: CspConfig,
+    /// Environment variable holding the bearer token for the protected `/api` endpoints.
+    /// Those endpoints answer 403 while it is unset or empty.
+    #[serde(default)]
+    pub admin_token_env: Option<String>,
+    /// Log output format and filter.
+    #[serde(default)]
+    pub log: LogConfig,
+}
+
+/// How log lines are written to stderr.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Configuration)]
+#[serde(rename_all = "lowercase")]
+#[confik(forward(serde(rename_all = "lowercase")))]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Pretty,
+    /// One JSON object per line, carrying the fields of the spans it happened in.
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Configuration)]
+pub struct LogConfig {
+    #[serde(default)]
+    pub format: LogFormat,
+    /// Which events are written, in `RUST_LOG` syntax such as `info,trellis=debug`.
+    /// `RUST_LOG` takes precedence when set.
+    #[serde(default = "default_log_filter")]
+    pub filter: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            filter: default_log_filter(),
+        }
+    }
+}
+
+/// How inline `<script>`/`<style>` tags are allowed by the page's Content-Security-Policy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Configuration)]
+#[serde(rename_all = "lowercase")]
+#[confik(forward(serde(rename_all = "lowercase")))]
+pub enum CspMode {
+    /// No policy header.
+    #[default]
+    Off,
+    /// A fresh nonce per response, attached to every script and style tag.
+    Nonce,
+    /// SHA-256 hashes of the inline contents; responses stay cacheable.
+    Hash,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, Configuration)]
+pub struct CspConfig {
+    #[serde(default)]
+    pub mode: CspMode,
+    /// Extra `script-src` sources, such as an analytics host.
+    #[serde(default)]
+    pub script_src: Vec<String>,
+    /// Extra `style-src` sources.
+    #[serde(default)]
+    pub style_src: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Configuration)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first.
+    pub cert_path: String,
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1).
+    pub key_path: String,
+    /// Also listen for plain HTTP on this port and redirect it to HTTPS.
+    #[serde(default)]
+    pub redirect_http_port: Option<u16>,
+    /// `max-age` of the Strict-Transport-Security header on HTTPS responses; 0 leaves it
+    /// out. The redirect listener never sends it.
+    #[serde(default = "default_hsts_max_age_secs")]
+    pub hsts_max_age_secs: u64,
 }
 
 impl Default for ServerConfig {
@@ -113,6 +678,17 @@ impl Default for ServerConfig {
             port: default_port(),
             cors_origins: vec!["0.0.0.0:40075".into()],
             max_payload_mb: default_max_payload_mb(),
+            dev_mode: default_dev_mode(),
+            workers: 0,
+            keep_alive_secs: default_keep_alive_secs(),
+            client_request_timeout_secs: default_client_request_timeout_secs(),
+            tls: None,
+            socket_path: None,
+            socket_mode: default_socket_mode(),
+            trusted_proxies: Vec::new(),
+            csp: CspConfig::default(),
+            admin_token_env: None,
+            log: LogConfig::default(),
         }
     }
 }
@@ -121,6 +697,23 @@ impl ServerConfig {
     pub fn max_payload_bytes(&self) -> usize {
         self.max_payload_mb.saturating_mul(1024 * 1024)
     }
+
+    /// `socket_mode` parsed as octal permission bits (`"0660"`, `"660"` or `"0o660"`).
+    pub fn socket_mode_bits(&self) -> Option<u32> {
+        u32::from_str_radix(self.socket_mode.trim().trim_start_matches("0o"), 8)
+            .ok()
+            .filter(|mode| *mode <= 0o7777)
+    }
+
+    /// Configured worker count, resolving 0 to the number of available CPUs.
+    pub fn worker_count(&self) -> usize {
+        if self.workers > 0 {
+            return self.workers;
+        }
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Configuration)]
@@ -129,6 +722,19 @@ pub struct PathsConfig {
     pub content_root: String,
     #[serde(default = "default_cache_root")]
     pub cache_root: String,
+    /// Directory whose files override the embedded `templates/` tree, file by file.
+    #[serde(default)]
+    pub templates_root: Option<String>,
+    /// Directories copied verbatim into `cache_root/static/` (served at `/static/`).
+    #[serde(default = "default_static_dirs")]
+    pub static_dirs: Vec<String>,
+    /// Font files served at `/static/fonts/` when `theme.font_origin` is `local`.
+    #[serde(default = "default_fonts_dir")]
+    pub fonts_dir: String,
+    /// `name: password` mapping for notes using `password_ref`. Must live outside the
+    /// content root.
+    #[serde(default)]
+    pub secrets_file: Option<String>,
 }
 
 impl Default for PathsConfig {
@@ -136,8 +742,277 @@ impl Default for PathsConfig {
         Self {
             content_root: default_content_root(),
             cache_root: default_cache_root(),
+            templates_root: None,
+            static_dirs: default_static_dirs(),
+            fonts_dir: default_fonts_dir(),
+            secrets_file: None,
+        }
+    }
+}
+
+/// How a content file is turned into HTML.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Configuration)]
+#[serde(rename_all = "lowercase")]
+#[confik(forward(serde(rename_all = "lowercase")))]
+pub enum ContentFormat {
+    Markdown,
+    /// Rendered verbatim inside `<pre>`, titled after the filename.
+    Plain,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Configuration)]
+pub struct ContentConfig {
+    /// File extensions (without the dot) treated as markdown, in lookup priority order.
+    #[serde(default = "default_content_extensions")]
+    pub extensions: Vec<String>,
+    /// Extra extension -> format mappings, e.g. `txt: plain`.
+    #[serde(default)]
+    pub mappings: BTreeMap<String, ContentFormat>,
+    /// Leave out pages carrying any of these tags, like drafts.
+    #[serde(default)]
+    pub exclude_tags: Vec<String>,
+    /// Leave out pages whose `published` date is still to come.
+    #[serde(default)]
+    pub hide_scheduled: bool,
+    /// How file paths become URLs; see [`SlugMode`].
+    #[serde(default = "default_slugify")]
+    pub slugify: SlugMode,
+    /// Spell accented and other non-ASCII letters in ASCII (`Über` becomes `Uber`) when
+    /// `slugify` is not `passthrough`.
+    #[serde(default)]
+    pub transliterate: bool,
+    /// Names shown for notes and folders, by slug (`apis-sdks: "APIs & SDKs"`), over
+    /// frontmatter titles and folder `_meta.yml` files.
+    #[serde(default)]
+    pub display_names: BTreeMap<String, String>,
+}
+
+/// How a note's path becomes its slug. Every mode but `passthrough` also NFC-normalizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Configuration)]
+#[serde(rename_all = "kebab-case")]
+#[confik(forward(serde(rename_all = "kebab-case")))]
+pub enum SlugMode {
+    /// The path as it is: `My Note.md` is served at `/My Note`.
+    Passthrough,
+    /// As Obsidian Publish and Quartz do: `/My-Note`, case kept.
+    Obsidian,
+    /// `/my-note`: lowercase, spaces and underscores as dashes, punctuation dropped.
+    LowerDash,
+}
+
+fn default_slugify() -> SlugMode {
+    SlugMode::Passthrough
+}
+
+fn default_content_extensions() -> Vec<String> {
+    vec!["md".into()]
+}
+
+impl Default for ContentConfig {
+    fn default() -> Self {
+        Self {
+            extensions: default_content_extensions(),
+            mappings: BTreeMap::new(),
+            exclude_tags: Vec::new(),
+            hide_scheduled: false,
+            slugify: default_slugify(),
+            transliterate: false,
+            display_names: BTreeMap::new(),
+        }
+    }
+}
+
+/// Passwords applied by path to pages whose frontmatter names none. A page takes the rule
+/// with the longest matching `prefix`, and `site_password_env` when no rule matches.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, Configuration)]
+pub struct EncryptionConfig {
+    /// Environment variable holding the password for every page no rule covers.
+    #[serde(default)]
+    pub site_password_env: Option<String>,
+    #[serde(default)]
+    pub rules: Vec<EncryptionRule>,
+    /// Use a locked note's `password_hint` as its description and `og:description`.
+    #[serde(default)]
+    pub hint_in_description: bool,
+    /// Title encrypted notes are listed under in the content index, graph.json and search.
+    /// Their own title when unset.
+    #[serde(default)]
+    pub placeholder_title: Option<String>,
+}
+
+/// Pages under `prefix` (whole path segments, so `work/` does not cover `workshop`) use
+/// the password from `password_env` or from `password_ref` in the secrets file. A rule with
+/// neither leaves its pages unencrypted, exempting them from `site_password_env`.
+#[derive(Debug, Clone, Serialize, Deserialize, Configuration)]
+pub struct EncryptionRule {
+    pub prefix: String,
+    #[serde(default)]
+    pub password_env: Option<String>,
+    #[serde(default)]
+    pub password_ref: Option<String>,
+}
+
+/// Where a page's password comes from when its frontmatter names none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordSource<'a> {
+    Env(&'a str),
+    Ref(&'a str),
+}
+
+impl EncryptionConfig {
+    /// The password source the rules give `slug`, if any.
+    pub fn source_for(&self, slug: &str) -> Option<PasswordSource<'_>> {
+        let rule = self
+            .rules
+            .iter()
+            .filter(|rule| covers(&rule.prefix, slug))
+            .max_by_key(|rule| rule.prefix.trim_matches('/').len());
+        match rule {
+            Some(rule) => rule
+                .password_ref
+                .as_deref()
+                .map(PasswordSource::Ref)
+                .or_else(|| rule.password_env.as_deref().map(PasswordSource::Env)),
+            None => self.site_password_env.as_deref().map(PasswordSource::Env),
         }
     }
+
+    /// Whether the page at `slug` renders encrypted, from its frontmatter or the rules. Its
+    /// text must then stay out of the content index, search and every other listing.
+    pub fn protects(&self, slug: &str, meta: &PageMetadata) -> bool {
+        meta.password.is_some()
+            || meta.passwords.is_some()
+            || meta.password_env.is_some()
+            || meta.password_ref.is_some()
+            || self.source_for(slug).is_some()
+    }
+
+    /// The title an encrypted note with `title` is listed under.
+    pub fn listed_title(&self, title: String) -> String {
+        self.placeholder_title.clone().unwrap_or(title)
+    }
+}
+
+fn covers(prefix: &str, slug: &str) -> bool {
+    let prefix = prefix.trim_matches('/');
+    prefix.is_empty()
+        || slug == prefix
+        || slug
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// What `static/content-index.json` carries per page, on top of `slug` and `filePath`.
+#[derive(Debug, Clone, Serialize, Deserialize, Configuration)]
+pub struct ContentIndexConfig {
+    #[serde(default = "default_content_index_fields")]
+    pub fields: Vec<ContentIndexField>,
+    /// Add `richContent`, the opening of each page's rendered HTML, so popovers need no
+    /// request per hover. Encrypted pages never get one.
+    #[serde(default)]
+    pub rich_content: bool,
+    /// Characters of text kept in `richContent`, rounded up to the end of an element.
+    #[serde(default = "default_rich_content_chars")]
+    pub rich_content_chars: usize,
+}
+
+/// An optional content index field, named as it appears in the JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Configuration)]
+#[serde(rename_all = "camelCase")]
+#[confik(forward(serde(rename_all = "camelCase")))]
+pub enum ContentIndexField {
+    Title,
+    Links,
+    Tags,
+    /// The page date chosen by `default_date_type`, RFC 3339.
+    Date,
+    /// The `description` frontmatter, or the opening of the text.
+    Description,
+    /// Minutes at 200 words a minute, at least 1.
+    ReadingTime,
+    WordCount,
+    /// The full plain text; off by default as it dominates the file size.
+    Content,
+}
+
+fn default_content_index_fields() -> Vec<ContentIndexField> {
+    vec![
+        ContentIndexField::Title,
+        ContentIndexField::Links,
+        ContentIndexField::Tags,
+        ContentIndexField::Date,
+        ContentIndexField::Description,
+        ContentIndexField::ReadingTime,
+        ContentIndexField::WordCount,
+    ]
+}
+
+fn default_rich_content_chars() -> usize {
+    1000
+}
+
+impl Default for ContentIndexConfig {
+    fn default() -> Self {
+        Self {
+            fields: default_content_index_fields(),
+            rich_content: false,
+            rich_content_chars: default_rich_content_chars(),
+        }
+    }
+}
+
+impl ContentIndexConfig {
+    pub fn includes(&self, field: ContentIndexField) -> bool {
+        self.fields.contains(&field)
+    }
+}
+
+impl ContentConfig {
+    /// Format for a content file, or `None` when its extension isn't configured.
+    pub fn format_for(&self, path: &Path) -> Option<ContentFormat> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        if let Some(format) = self.mappings.get(&ext) {
+            return Some(*format);
+        }
+        self.extensions
+            .iter()
+            .any(|e| e.eq_ignore_ascii_case(&ext))
+            .then_some(ContentFormat::Markdown)
+    }
+
+    pub fn is_content_file(&self, path: &Path) -> bool {
+        self.format_for(path).is_some()
+    }
+
+    /// All recognised extensions: markdown extensions first, then mapped ones.
+    pub fn all_extensions(&self) -> Vec<String> {
+        let mut all: Vec<String> = Vec::new();
+        for ext in self.extensions.iter().chain(self.mappings.keys()) {
+            let ext = ext.trim_start_matches('.').to_ascii_lowercase();
+            if !all.contains(&ext) {
+                all.push(ext);
+            }
+        }
+        all
+    }
+
+    /// Candidate source files for an extension-less path, in lookup order.
+    pub fn candidates(&self, base: &Path) -> Vec<PathBuf> {
+        self.all_extensions()
+            .iter()
+            .map(|ext| {
+                let mut path = base.as_os_str().to_os_string();
+                path.push(".");
+                path.push(ext);
+                PathBuf::from(path)
+            })
+            .collect()
+    }
+
+    /// First existing source file for an extension-less path.
+    pub fn find_source(&self, base: &Path) -> Option<PathBuf> {
+        self.candidates(base).into_iter().find(|p| p.is_file())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Configuration)]
@@ -229,15 +1104,273 @@ impl PluginConfig {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Configuration)]
+#[serde(rename_all = "lowercase")]
+#[confik(forward(serde(rename_all = "lowercase")))]
+pub enum AnalyticsProvider {
+    Plausible,
+    Umami,
+    GoatCounter,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Configuration)]
+#[serde(rename_all = "lowercase")]
+#[confik(forward(serde(rename_all = "lowercase")))]
+pub enum BundleOutput {
+    /// Every bundle a page needs is embedded in its HTML.
+    #[default]
+    Inline,
+    /// Bundles are written to `static/js/{kind}.{hash}.js` and referenced by URL, so
+    /// browsers cache them across pages.
+    External,
+}
+
+/// How component scripts are delivered to the browser.
+#[derive(Debug, Clone, Serialize, Deserialize, Configuration)]
+pub struct BundlerConfig {
+    #[serde(default)]
+    pub output: BundleOutput,
+    /// `node_modules` directory searched for bare imports such as `"motion"`.
+    #[serde(default)]
+    pub node_modules: Option<String>,
+    /// Packages bare imports may resolve to; importing anything else fails the bundle.
+    #[serde(default)]
+    pub allowed_packages: Vec<String>,
+    /// Warn when a module pulled from `node_modules` is larger than this; 0 disables.
+    #[serde(default = "default_package_size_warning_kb")]
+    pub package_size_warning_kb: u64,
+    /// Import specifiers left untouched in every bundle and loaded by the browser.
+    #[serde(default = "default_external_modules")]
+    pub external_modules: Vec<String>,
+    /// Per-script replacements for `external_modules`, keyed by script name (`graph`,
+    /// `mermaid`, or a custom script).
+    #[serde(default)]
+    pub script_externals: BTreeMap<String, Vec<String>>,
+    /// JSX factory for `.tsx`/`.jsx` sources; the default suits preact.
+    #[serde(default = "default_jsx_pragma")]
+    pub jsx_pragma: String,
+    #[serde(default = "default_jsx_fragment")]
+    pub jsx_fragment: String,
+}
+
+fn default_external_modules() -> Vec<String> {
+    vec!["https://cdnjs.cloudflare.com/ajax/libs/mermaid/11.4.0/mermaid.esm.min.mjs".into()]
+}
+
+fn default_package_size_warning_kb() -> u64 {
+    64
+}
+
+fn default_jsx_pragma() -> String {
+    "h".into()
+}
+
+fn default_jsx_fragment() -> String {
+    "Fragment".into()
+}
+
+impl Default for BundlerConfig {
+    fn default() -> Self {
+        Self {
+            output: BundleOutput::default(),
+            node_modules: None,
+            allowed_packages: Vec::new(),
+            package_size_warning_kb: default_package_size_warning_kb(),
+            external_modules: default_external_modules(),
+            script_externals: BTreeMap::new(),
+            jsx_pragma: default_jsx_pragma(),
+            jsx_fragment: default_jsx_fragment(),
+        }
+    }
+}
+
+/// Site-specific scripts bundled alongside the built-in components.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, Configuration)]
+pub struct ScriptsConfig {
+    /// Directory of `*.inline.{ts,tsx,js,jsx}` entries, each bundled on its own.
+    #[serde(default)]
+    pub custom_dir: Option<String>,
+    /// Custom scripts included only on pages that list them under `scripts` in
+    /// frontmatter; all others are included on every page.
+    #[serde(default)]
+    pub on_demand: Vec<String>,
+}
+
+/// Third-party analytics injected into every page's `<head>`. Nothing is emitted unless a
+/// provider or raw `html` is set.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, Configuration)]
+pub struct AnalyticsConfig {
+    #[serde(default)]
+    pub provider: Option<AnalyticsProvider>,
+    /// Plausible domain, Umami website id, or GoatCounter site code.
+    #[serde(default)]
+    pub site_id: Option<String>,
+    /// Base URL of a self-hosted script host; defaults to the provider's cloud.
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Raw markup used instead of a provider snippet.
+    #[serde(default)]
+    pub html: Option<String>,
+    /// Only load the snippet for visitors without Do Not Track enabled.
+    #[serde(default)]
+    pub respect_dnt: bool,
+}
+
+/// Self-hosted view counts kept in the sqlite database: one view per visitor, page and
+/// day. Visitors are identified by a hash of address and user agent under a salt that is
+/// never stored and changes daily.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, Configuration)]
+pub struct PageViewsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Add the page's total as `article.views` for templates.
+    #[serde(default)]
+    pub show_in_article: bool,
+}
+
+/// Limits for comments posted with the `native` comments provider. They are stored in the
+/// sqlite database and shown once approved through the moderation API.
+#[derive(Debug, Clone, Serialize, Deserialize, Configuration)]
+pub struct NativeCommentsConfig {
+    /// Hold new comments as pending until a moderator approves them.
+    #[serde(default = "default_require_approval")]
+    pub require_approval: bool,
+    #[serde(default = "default_comment_name_chars")]
+    pub max_name_chars: usize,
+    #[serde(default = "default_comment_body_chars")]
+    pub max_body_chars: usize,
+    /// Comments one address may post per hour; 0 lifts the limit.
+    #[serde(default = "default_comments_per_hour")]
+    pub per_hour: u32,
+}
+
+impl Default for NativeCommentsConfig {
+    fn default() -> Self {
+        Self {
+            require_approval: default_require_approval(),
+            max_name_chars: default_comment_name_chars(),
+            max_body_chars: default_comment_body_chars(),
+            per_hour: default_comments_per_hour(),
+        }
+    }
+}
+
+fn default_require_approval() -> bool {
+    true
+}
+
+fn default_comment_name_chars() -> usize {
+    80
+}
+
+fn default_comment_body_chars() -> usize {
+    4000
+}
+
+fn default_comments_per_hour() -> u32 {
+    5
+}
+
+/// Newsletter sign-ups kept in the sqlite database, confirmed by a mailed link. Mail goes
+/// through `sendmail_command` when set, otherwise `smtp`; with neither, addresses are
+/// confirmed on sign-up.
+#[derive(Debug, Clone, Serialize, Deserialize, Configuration)]
+pub struct NewsletterConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Sender of confirmation mail, as `Name <address>` or a bare address.
+    #[serde(default = "default_newsletter_from")]
+    pub from: String,
+    #[serde(default = "default_newsletter_subject")]
+    pub subject: String,
+    /// A sendmail-compatible program; the message is written to its standard input.
+    #[serde(default)]
+    pub sendmail_command: Option<String>,
+    #[serde(default)]
+    pub smtp: Option<SmtpConfig>,
+    /// Sign-ups one address may attempt per hour; 0 lifts the limit.
+    #[serde(default = "default_subscribes_per_hour")]
+    pub per_hour: u32,
+}
+
+impl Default for NewsletterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            from: default_newsletter_from(),
+            subject: default_newsletter_subject(),
+            sendmail_command: None,
+            smtp: None,
+            per_hour: default_subscribes_per_hour(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Configuration)]
+pub struct SmtpConfig {
+    pub host: String,
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Environment variable holding the password, so it stays out of `config.yml`.
+    #[serde(default)]
+    pub password_env: Option<String>,
+    /// Upgrade a plain connection with STARTTLS; `false` uses TLS from the start.
+    #[serde(default = "default_starttls")]
+    pub starttls: bool,
+}
+
+fn default_newsletter_from() -> String {
+    "Moss <newsletter@example.com>".into()
+}
+
+fn default_newsletter_subject() -> String {
+    "Confirm your subscription".into()
+}
+
+fn default_subscribes_per_hour() -> u32 {
+    3
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_starttls() -> bool {
+    true
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Configuration)]
 pub struct SiteConfig {
     pub configuration: GlobalConfiguration,
     pub layout: LayoutConfig,
+    /// Named page layouts a page selects with `layout:` in its frontmatter.
+    #[serde(default)]
+    pub layouts: BTreeMap<String, PageLayout>,
     pub plugins: PluginConfig,
     #[serde(default)]
     pub server: ServerConfig,
     #[serde(default)]
     pub paths: PathsConfig,
+    #[serde(default)]
+    pub content: ContentConfig,
+    #[serde(default)]
+    pub content_index: ContentIndexConfig,
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+    #[serde(default)]
+    pub analytics: AnalyticsConfig,
+    #[serde(default)]
+    pub page_views: PageViewsConfig,
+    #[serde(default)]
+    pub native_comments: NativeCommentsConfig,
+    #[serde(default)]
+    pub newsletter: NewsletterConfig,
+    #[serde(default)]
+    pub bundler: BundlerConfig,
+    #[serde(default)]
+    pub scripts: ScriptsConfig,
 }
 
 impl Default for SiteConfig {
@@ -247,6 +1380,7 @@ impl Default for SiteConfig {
                 page_title: "Moss".into(),
                 tagline: None,
                 page_title_suffix: String::new(),
+                title_separator: default_title_separator(),
                 enable_spa: true,
                 enable_popovers: true,
                 locale: "en-US".into(),
@@ -254,8 +1388,16 @@ impl Default for SiteConfig {
                 ignore_patterns: vec!["private".into(), "templates".into(), ".obsidian".into()],
                 default_date_type: DefaultDateType::Modified,
                 theme: ThemeConfig {
+                    preset: None,
+                    custom_scss: None,
+                    css_delivery: CssDelivery::Inline,
+                    default_mode: ThemeDefaultMode::Light,
+                    css_targets: default_css_targets(),
+                    syntax: SyntaxThemes::default(),
+                    breakpoints: Breakpoints::default(),
                     font_origin: "googleFonts".into(),
                     cdn_caching: true,
+                    font_display: FontDisplay::Swap,
                     typography: ThemeFonts {
                         header: "Schibsted Grotesk".into(),
                         body: "Source Sans Pro".into(),
@@ -288,47 +1430,351 @@ impl Default for SiteConfig {
                 },
             },
             layout: LayoutConfig::default(),
+            layouts: BTreeMap::new(),
             plugins: PluginConfig::default(),
             server: ServerConfig::default(),
             paths: PathsConfig::default(),
+            content: ContentConfig::default(),
+            content_index: ContentIndexConfig::default(),
+            encryption: EncryptionConfig::default(),
+            analytics: AnalyticsConfig::default(),
+            page_views: PageViewsConfig::default(),
+            native_comments: NativeCommentsConfig::default(),
+            newsletter: NewsletterConfig::default(),
+            bundler: BundlerConfig::default(),
+            scripts: ScriptsConfig::default(),
         }
     }
 }
 
 impl SiteConfig {
     /// Load configuration from `config.yml` (if present) and environment variables.
-    /// Falls back to the compiled-in defaults when parsing fails.
+    /// Invalid configuration aborts the process unless default fallback was explicitly
+    /// allowed (`--allow-default-config` / `TRELLIS_ALLOW_DEFAULT_CONFIG=1`).
     pub fn load() -> Self {
-        let config_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("config.yml");
-        let mut builder = SiteConfig::builder();
+        Self::or_fallback(Self::try_load())
+    }
+
+    /// Like [`SiteConfig::load`] for an explicit config file.
+    pub fn load_from(path: &Path) -> Self {
+        Self::or_fallback(Self::try_load_from(path))
+    }
 
-        if config_path.exists() {
-            builder.override_with(YamlFileSource::new(config_path));
+    fn or_fallback(result: anyhow::Result<Self>) -> Self {
+        match result {
+            Ok(cfg) => cfg,
+            Err(err) if Self::load_options().allow_default_fallback => {
+                log::warn!("{err:#}. Using defaults.");
+                SiteConfig::default()
+            }
+            Err(err) => {
+                log::error!("{err:#}");
+                log::error!(
+                    "Fix the configuration, or set TRELLIS_ALLOW_DEFAULT_CONFIG=1 to start with defaults"
+                );
+                std::process::exit(1);
+            }
         }
+    }
 
+    /// Like [`SiteConfig::load`], but surfaces parse errors instead of falling back.
+    /// A missing default `config.yml` is fine; a missing explicitly chosen file is not.
+    pub fn try_load() -> anyhow::Result<Self> {
+        let config_path = Self::config_path();
+        if !config_path.exists() && Self::explicit_config_path().is_none() {
+            if let Some(profile) = Self::profile() {
+                anyhow::bail!(
+                    "profile {profile:?} selected but {} does not exist",
+                    config_path.display()
+                );
+            }
+            log::warn!(
+                "{} not found; starting with the default configuration",
+                config_path.display()
+            );
+            let mut builder = SiteConfig::builder();
+            builder.override_with(EnvSource::new());
+            return builder
+                .try_build()
+                .map_err(|err| anyhow::anyhow!("Failed to load env overrides: {err}"));
+        }
+        Self::try_load_from(&config_path)
+    }
+
+    pub fn try_load_from(path: &Path) -> anyhow::Result<Self> {
+        let (config, issues) = Self::diagnose(path);
+        let strict = Self::load_options().strict;
+
+        let mut errors = Vec::new();
+        for issue in issues {
+            if issue.is_error() || strict {
+                errors.push(issue.to_string());
+            } else {
+                log::warn!("{}: {issue}", path.display());
+            }
+        }
+        if !errors.is_empty() {
+            anyhow::bail!(
+                "Invalid config {}:\n  {}",
+                path.display(),
+                errors.join("\n  ")
+            );
+        }
+
+        config.ok_or_else(|| anyhow::anyhow!("Failed to load {}", path.display()))
+    }
+
+    /// Validate `path` and build the final config (file plus env overrides), returning
+    /// every issue found. Shared by startup and the `check` subcommand.
+    pub fn diagnose(path: &Path) -> (Option<Self>, Vec<ConfigIssue>) {
+        if !path.is_file() {
+            let issue = ConfigIssue::error("", format!("{} does not exist", path.display()));
+            return (None, vec![issue]);
+        }
+
+        let doc = match Self::read_document(path) {
+            Ok(doc) => doc,
+            Err(issue) => return (None, vec![issue]),
+        };
+        let (parsed, mut issues) = validation::validate_document(doc.clone());
+        if parsed.is_none() {
+            return (None, issues);
+        }
+
+        let mut builder = SiteConfig::builder();
+        builder.override_with(YamlSource::new(doc));
         builder.override_with(EnvSource::new());
 
         match builder.try_build() {
-            Ok(cfg) => cfg,
+            Ok(cfg) => {
+                // Env overrides may have changed values the file pass already checked.
+                for issue in validation::validate(&cfg) {
+                    if !issues
+                        .iter()
+                        .any(|i| i.path == issue.path && i.message == issue.message)
+                    {
+                        issues.push(issue);
+                    }
+                }
+                (Some(cfg), issues)
+            }
             Err(err) => {
-                log::warn!("Failed to load config.yml or env overrides: {err}. Using defaults.");
-                SiteConfig::default()
+                issues.push(ConfigIssue::error(
+                    "",
+                    format!("failed to apply env overrides: {err}"),
+                ));
+                (None, issues)
+            }
+        }
+    }
+
+    /// The base config with the active profile's overlay deep-merged over it and the theme
+    /// preset expanded, ready for validation and deserialization.
+    fn read_document(path: &Path) -> Result<serde_yaml::Value, ConfigIssue> {
+        let mut doc = read_yaml(path)?;
+
+        if let Some(profile) = Self::profile() {
+            if !profile
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(ConfigIssue::error(
+                    "",
+                    format!("invalid profile name {profile:?}"),
+                ));
+            }
+            let overlay_path = Self::profile_path(path, &profile);
+            if !overlay_path.is_file() {
+                return Err(ConfigIssue::error(
+                    "",
+                    format!(
+                        "unknown profile {profile:?}: {} does not exist",
+                        overlay_path.display()
+                    ),
+                ));
+            }
+            let overlay = read_yaml(&overlay_path)?;
+            if !overlay.is_null() {
+                merge_yaml(&mut doc, overlay);
             }
         }
+
+        apply_theme_preset(&mut doc)
+            .map_err(|message| ConfigIssue::error("configuration.theme.preset", message))?;
+        Ok(doc)
+    }
+
+    /// Select the config profile for this process (e.g. from `--profile`). First call wins.
+    pub fn set_profile(profile: String) {
+        let _ = PROFILE.set(profile);
+    }
+
+    /// Active profile: `--profile`, then `TRELLIS_ENV`. `None` means the base config only.
+    pub fn profile() -> Option<String> {
+        PROFILE
+            .get()
+            .cloned()
+            .or_else(|| std::env::var("TRELLIS_ENV").ok())
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+    }
+
+    /// Overlay file for `profile` next to the base config: `config.yml` → `config.prod.yml`.
+    pub fn profile_path(base: &Path, profile: &str) -> PathBuf {
+        let stem = base
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "config".into());
+        let name = match base.extension() {
+            Some(ext) => format!("{stem}.{profile}.{}", ext.to_string_lossy()),
+            None => format!("{stem}.{profile}"),
+        };
+        base.with_file_name(name)
+    }
+
+    /// Set how strictly configuration is loaded for this process. First call wins.
+    pub fn set_load_options(options: LoadOptions) {
+        let _ = LOAD_OPTIONS.set(options);
+    }
+
+    pub fn load_options() -> LoadOptions {
+        LOAD_OPTIONS
+            .get()
+            .copied()
+            .unwrap_or_else(LoadOptions::from_env)
+    }
+
+    /// Choose the config file for this process (e.g. from `--config`). First call wins.
+    pub fn set_config_path(path: PathBuf) {
+        let _ = CONFIG_PATH.set(path);
+    }
+
+    /// Location of the site's `config.yml`: `--config`, then `TRELLIS_CONFIG`, then
+    /// `config.yml` in the working directory. Debug builds without one there, such as
+    /// `cargo run -p trellis` from the workspace root, use the checkout's own.
+    pub fn config_path() -> PathBuf {
+        Self::explicit_config_path().unwrap_or_else(|| {
+            let name = PathBuf::from("config.yml");
+            let local = std::path::absolute(&name).unwrap_or(name);
+            let checkout = Path::new(env!("CARGO_MANIFEST_DIR")).join("config.yml");
+            if cfg!(debug_assertions) && !local.exists() && checkout.exists() {
+                return checkout;
+            }
+            local
+        })
+    }
+
+    fn explicit_config_path() -> Option<PathBuf> {
+        CONFIG_PATH
+            .get()
+            .cloned()
+            .or_else(|| std::env::var_os("TRELLIS_CONFIG").map(PathBuf::from))
+    }
+
+    /// Directory that relative `paths.*` entries are resolved against: `TRELLIS_BASE_DIR` if
+    /// set, otherwise the directory containing `config.yml`, otherwise the working directory.
+    pub fn base_dir() -> PathBuf {
+        let base = std::env::var_os("TRELLIS_BASE_DIR")
+            .map(PathBuf::from)
+            .or_else(|| {
+                let config_path = Self::config_path();
+                if config_path.is_file() {
+                    config_path.parent().map(Path::to_path_buf)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or_else(|| PathBuf::from("."));
+        std::path::absolute(&base).unwrap_or(base)
+    }
+
+    /// Resolve a configured path against [`SiteConfig::base_dir`] unless it is absolute.
+    pub fn resolve_path(path: &str) -> PathBuf {
+        let candidate = PathBuf::from(path);
+        if candidate.is_absolute() {
+            candidate
+        } else {
+            Self::base_dir().join(candidate)
+        }
+    }
+
+    pub fn content_root(&self) -> PathBuf {
+        Self::resolve_path(&self.paths.content_root)
+    }
+
+    pub fn cache_root(&self) -> PathBuf {
+        Self::resolve_path(&self.paths.cache_root)
+    }
+
+    pub fn templates_root(&self) -> Option<PathBuf> {
+        self.paths.templates_root.as_deref().map(Self::resolve_path)
+    }
+
+    pub fn static_dirs(&self) -> Vec<PathBuf> {
+        self.paths
+            .static_dirs
+            .iter()
+            .map(|dir| Self::resolve_path(dir))
+            .collect()
+    }
+
+    pub fn fonts_dir(&self) -> PathBuf {
+        Self::resolve_path(&self.paths.fonts_dir)
+    }
+
+    /// The user stylesheet: `theme.custom_scss` if set, else `custom.scss` in the content
+    /// root if present.
+    pub fn custom_scss(&self) -> Option<PathBuf> {
+        match &self.configuration.theme.custom_scss {
+            Some(path) => Some(Self::resolve_path(path)),
+            None => Some(self.content_root().join("custom.scss")).filter(|path| path.is_file()),
+        }
+    }
+
+    pub fn secrets_file(&self) -> Option<PathBuf> {
+        self.paths.secrets_file.as_deref().map(Self::resolve_path)
+    }
+}
+
+pub const FONT_ORIGINS: &[&str] = &["googleFonts", "local"];
+
+impl GlobalConfiguration {
+    /// Text for a page's `<title>`: the bare site title for the home page, otherwise the
+    /// page title followed by `page_title_suffix` (or the separator and site title).
+    pub fn document_title(&self, slug: &str, page_title: &str) -> String {
+        if slug == "index" || page_title.is_empty() {
+            return self.page_title.clone();
+        }
+        if self.page_title_suffix.is_empty() {
+            format!("{page_title}{}{}", self.title_separator, self.page_title)
+        } else {
+            format!("{page_title}{}", self.page_title_suffix)
+        }
+    }
+}
+
+impl ThemeConfig {
+    pub fn uses_local_fonts(&self) -> bool {
+        self.font_origin == "local"
     }
 }
 
-pub fn google_font_href(theme: &ThemeConfig) -> String {
+/// Google Fonts stylesheet for the theme's families, or `None` unless `font_origin` is
+/// `googleFonts`.
+pub fn google_font_href(theme: &ThemeConfig, display: FontDisplay) -> Option<String> {
+    if theme.font_origin != "googleFonts" {
+        return None;
+    }
     let typography = &theme.typography;
-    let code = &typography.code;
-    let header = &typography.header;
-    let body = &typography.body;
+    let family = |name: &str| name.trim().replace(' ', "+");
 
-    format!(
-        "https://fonts.googleapis.com/css2?family={}
-  &family={}:wght@400;700&family={}:ital,wght@0,400;0,600;1,400;1,600&display=swap",
-        code, header, body
-    )
+    Some(format!(
+        "https://fonts.googleapis.com/css2?family={}&family={}:wght@400;700&family={}:ital,wght@0,400;0,600;1,400;1,600&display={}",
+        family(&typography.code),
+        family(&typography.header),
+        family(&typography.body),
+        display.as_str()
+    ))
 }
 
 /// Stable hash of the active theme configuration, used for cache busting.
@@ -337,6 +1783,106 @@ pub fn theme_hash(theme: &ThemeConfig) -> String {
     format!("{:x}", Sha256::digest(json.as_bytes()))
 }
 
+/// Force-directed graph settings for one graph view (`layout.graph.local` or `.global`).
+/// Written snake_case in `config.yml`; serialized camelCase for `graph.inline.ts`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct D3Config {
+    pub drag: bool,
+    pub zoom: bool,
+    /// Link hops shown around the current page; -1 shows the whole graph.
+    pub depth: i32,
+    pub scale: f32,
+    pub repel_force: f32,
+    pub center_force: f32,
+    pub link_distance: i32,
+    pub font_size: f32,
+    pub opacity_scale: f32,
+    pub remove_tags: Vec<String>,
+    pub show_tags: bool,
+    pub focus_on_hover: bool,
+    pub enable_radial: bool,
+}
+
+impl D3Config {
+    /// The per-page graph in the sidebar.
+    pub fn local() -> Self {
+        Self {
+            drag: true,
+            zoom: true,
+            depth: 1,
+            scale: 1.1,
+            repel_force: 0.5,
+            center_force: 0.3,
+            link_distance: 30,
+            font_size: 0.6,
+            opacity_scale: 1.0,
+            remove_tags: Vec::new(),
+            show_tags: true,
+            focus_on_hover: false,
+            enable_radial: false,
+        }
+    }
+
+    /// The whole-site graph opened from the sidebar graph.
+    pub fn global() -> Self {
+        Self {
+            depth: -1,
+            scale: 0.9,
+            center_force: 0.2,
+            focus_on_hover: true,
+            enable_radial: true,
+            ..Self::local()
+        }
+    }
+
+    pub fn deserialize_local<'de, D: serde::Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        D3Overrides::deserialize(de).map(|overrides| overrides.apply(Self::local()))
+    }
+
+    pub fn deserialize_global<'de, D: serde::Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        D3Overrides::deserialize(de).map(|overrides| overrides.apply(Self::global()))
+    }
+}
+
+/// A partial [`D3Config`], so setting one key keeps the view's other defaults.
+#[derive(Deserialize)]
+struct D3Overrides {
+    drag: Option<bool>,
+    zoom: Option<bool>,
+    depth: Option<i32>,
+    scale: Option<f32>,
+    repel_force: Option<f32>,
+    center_force: Option<f32>,
+    link_distance: Option<i32>,
+    font_size: Option<f32>,
+    opacity_scale: Option<f32>,
+    remove_tags: Option<Vec<String>>,
+    show_tags: Option<bool>,
+    focus_on_hover: Option<bool>,
+    enable_radial: Option<bool>,
+}
+
+impl D3Overrides {
+    fn apply(self, base: D3Config) -> D3Config {
+        D3Config {
+            drag: self.drag.unwrap_or(base.drag),
+            zoom: self.zoom.unwrap_or(base.zoom),
+            depth: self.depth.unwrap_or(base.depth),
+            scale: self.scale.unwrap_or(base.scale),
+            repel_force: self.repel_force.unwrap_or(base.repel_force),
+            center_force: self.center_force.unwrap_or(base.center_force),
+            link_distance: self.link_distance.unwrap_or(base.link_distance),
+            font_size: self.font_size.unwrap_or(base.font_size),
+            opacity_scale: self.opacity_scale.unwrap_or(base.opacity_scale),
+            remove_tags: self.remove_tags.unwrap_or(base.remove_tags),
+            show_tags: self.show_tags.unwrap_or(base.show_tags),
+            focus_on_hover: self.focus_on_hover.unwrap_or(base.focus_on_hover),
+            enable_radial: self.enable_radial.unwrap_or(base.enable_radial),
+        }
+    }
+}
+
 fn join_segments(base: &str, tail: &str) -> String {
     if base.is_empty() || base == "." {
         return tail.to_string();
@@ -352,48 +1898,51 @@ fn join_segments(base: &str, tail: &str) -> String {
     }
 }
 
+fn read_yaml(path: &Path) -> Result<serde_yaml::Value, ConfigIssue> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| ConfigIssue::error("", format!("reading {}: {err}", path.display())))?;
+    serde_yaml::from_str(&contents)
+        .map_err(|err| ConfigIssue::error("", format!("invalid YAML in {}: {err}", path.display())))
+}
+
+/// Deep-merge `overlay` into `base`: mappings merge key by key, anything else is replaced.
+fn merge_yaml(base: &mut serde_yaml::Value, overlay: serde_yaml::Value) {
+    match (base, overlay) {
+        (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_yaml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
 /// Build the per-page resource list, mirroring Quartz's `pageResources` helper.
 /// `base_dir` should be the relative path from the current page to the site root (e.g., ".", "..", "../../").
+///
+/// Unlike Quartz there is no `prescript.js`/`postscript.js` bundle, and the site stylesheet
+/// (inline or `static/index.{hash}.css`) is emitted by the page templates, so the only core
+/// resource is the content index fetch that the component scripts await.
 pub fn page_resources(base_dir: &str, static_resources: &ComponentResources) -> ComponentResources {
     let content_index_path = join_segments(base_dir, "static/content-index.json");
     let content_index_script = format!(
-        "const fetchData = fetch(\"{}\").then(data => data.json())",
+        "const fetchData = fetch(\"{}\").then(data => data.json()).catch(() => undefined)",
         content_index_path
     );
 
-    let mut css = vec![CssResource {
-        content: join_segments(base_dir, "index.css"),
-        inline: false,
-        spa_preserve: false,
-    }];
-    css.extend(static_resources.css.clone());
-
-    let mut js = vec![
-        JsResource::external(
-            JsLoadTime::BeforeDomReady,
-            join_segments(base_dir, "prescript.js"),
-        ),
-        JsResource {
-            load_time: JsLoadTime::BeforeDomReady,
-            content_type: JsContentType::Inline,
-            module_type: None,
-            spa_preserve: true,
-            src: None,
-            script: Some(content_index_script),
-        },
-    ];
+    let mut fetch_index = JsResource::inline(JsLoadTime::BeforeDomReady, content_index_script);
+    fetch_index.spa_preserve = true;
 
+    let mut js = vec![fetch_index];
     js.extend(static_resources.js.clone());
 
-    let mut postscript = JsResource::external(
-        JsLoadTime::AfterDomReady,
-        join_segments(base_dir, "postscript.js"),
-    );
-    postscript.module_type = Some("module".into());
-    js.push(postscript);
-
     ComponentResources {
-        css,
+        css: static_resources.css.clone(),
         js,
         additional_head: static_resources.additional_head.clone(),
     }
@@ -401,24 +1950,24 @@ pub fn page_resources(base_dir: &str, static_resources: &ComponentResources) ->
 
 mod yaml {
     use std::error::Error;
-    use std::path::PathBuf;
 
     use confik::Source;
     use serde::de::DeserializeOwned;
     use serde_yaml;
 
+    /// An already merged config document (base file plus profile overlay).
     #[derive(Debug)]
-    pub struct YamlFileSource {
-        path: PathBuf,
+    pub struct YamlSource {
+        doc: serde_yaml::Value,
     }
 
-    impl YamlFileSource {
-        pub fn new(path: impl Into<PathBuf>) -> Self {
-            Self { path: path.into() }
+    impl YamlSource {
+        pub fn new(doc: serde_yaml::Value) -> Self {
+            Self { doc }
         }
     }
 
-    impl<T> Source<T> for YamlFileSource
+    impl<T> Source<T> for YamlSource
     where
         T: DeserializeOwned + confik::ConfigurationBuilder,
     {
@@ -427,9 +1976,34 @@ mod yaml {
         }
 
         fn provide(&self) -> Result<T, Box<dyn Error + Sync + Send>> {
-            let contents = std::fs::read_to_string(&self.path)?;
-            let parsed = serde_yaml::from_str(&contents)?;
-            Ok(parsed)
+            Ok(serde_yaml::from_value(self.doc.clone())?)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn google_font_href_carries_the_display_parameter() {
+        let mut theme = SiteConfig::default().configuration.theme;
+        theme.font_origin = "googleFonts".into();
+        for display in [
+            FontDisplay::Auto,
+            FontDisplay::Block,
+            FontDisplay::Swap,
+            FontDisplay::Fallback,
+            FontDisplay::Optional,
+        ] {
+            let href = google_font_href(&theme, display).unwrap();
+            assert!(
+                href.ends_with(&format!("&display={}", display.as_str())),
+                "{href}"
+            );
+        }
+
+        theme.font_origin = "local".into();
+        assert_eq!(google_font_href(&theme, FontDisplay::Swap), None);
+    }
+}
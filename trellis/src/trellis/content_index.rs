@@ -1,17 +1,33 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
+use std::ops::Range;
 use std::path::Path;
+use std::sync::OnceLock;
 
 use anyhow::{Context, Result};
-use log::debug;
+use log::{debug, info, warn};
 use serde::Serialize;
 use walkdir::WalkDir;
 
-use crate::trellis::plugins::frontmatter::FrontMatter;
-use crate::trellis::plugins::traits::Transformer;
-use crate::trellis::types::{Page, slug_from_path};
+use crate::trellis::attachments::{self, StaticFiles};
+use crate::trellis::cache::{self, RenderKey};
+use crate::trellis::config::{
+    ContentConfig, ContentFormat, ContentIndexField, EncryptionConfig, SiteConfig,
+};
+use crate::trellis::error::RenderError;
+use crate::trellis::git_history;
+use crate::trellis::graph_data::{GraphPage, link_report, write_graph_json};
+use crate::trellis::ignore::IgnoreMatcher;
+use crate::trellis::plugins::PluginRegistry;
+use crate::trellis::plugins::structure::excerpt;
+use crate::trellis::search::markdown_text;
+use crate::trellis::slugs::{SlugStyle, slugify, slugify_with, style as slug_style};
+use crate::trellis::types::{
+    LinkKind, Page, PageMetadata, ResolvedLink, count_words, page_date, reading_minutes,
+    slug_from_path,
+};
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Default, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct ContentIndexEntry {
     slug: String,
@@ -22,32 +38,81 @@ struct ContentIndexEntry {
     links: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tags: Option<Vec<String>>,
+    /// RFC 3339, so the explorer's `byDate` preset can compare dates as strings.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    /// Minutes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reading_time: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    word_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    /// Sanitized opening of the rendered HTML, for popovers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rich_content: Option<String>,
+    /// Nav ordering and visibility from frontmatter; always included so the explorer
+    /// matches the server-rendered nav.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    weight: Option<f64>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    hide_nav: bool,
 }
 
+/// Write `static/content-index.json` with the fields `config.content_index` asks for.
+/// Pages the registry's filters exclude (drafts, for one) are left out entirely, so the
+/// explorer and graph never see them. `static/graph.json` is written from the same pages.
 pub fn generate_content_index(
     content_root: &Path,
     cache_root: &Path,
-    ignore_patterns: &[String],
+    ignore: &IgnoreMatcher,
+    registry: &PluginRegistry,
+    config: &SiteConfig,
 ) -> Result<()> {
+    if let Err(err) = git_history::refresh() {
+        warn!("dates from git unavailable: {err:#}");
+    }
+    let fields = &config.content_index;
+    let date_type = config.configuration.default_date_type;
+    let render_key = RenderKey::new(config);
+    let wants_text = [
+        ContentIndexField::Description,
+        ContentIndexField::ReadingTime,
+        ContentIndexField::WordCount,
+        ContentIndexField::Content,
+    ]
+    .into_iter()
+    .any(|field| fields.includes(field));
+    let slugs = content_slugs(content_root, ignore, &config.content);
     let mut entries: BTreeMap<String, ContentIndexEntry> = BTreeMap::new();
+    let mut graph_pages = Vec::new();
+    // Static files each kind of page refers to; the encrypted-only ones are kept out of
+    // the static copy.
+    let static_files = StaticFiles::scan(&config.static_dirs());
+    let mut protected_attachments = BTreeSet::new();
+    let mut public_attachments = BTreeSet::new();
 
     for entry in WalkDir::new(content_root)
         .into_iter()
-        .filter_entry(|e| !is_ignored(e.path(), content_root, ignore_patterns))
+        .filter_entry(|e| !ignore.is_ignored(e.path(), content_root))
         .filter_map(Result::ok)
         .filter(|e| e.path().is_file())
     {
-        if entry
-            .path()
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|ext| ext.eq_ignore_ascii_case("md"))
-            != Some(true)
-        {
+        let Some(format) = config.content.format_for(entry.path()) else {
             continue;
-        }
+        };
 
         let slug = slug_from_path(entry.path(), content_root);
+        if let Some(existing) = entries.get(&slug) {
+            warn!(
+                "slug collision for {slug}: {} and {}",
+                existing.file_path,
+                entry.path().display()
+            );
+            continue;
+        }
         let file_path = entry
             .path()
             .strip_prefix(content_root)
@@ -61,33 +126,131 @@ pub fn generate_content_index(
                 entry.path().display()
             )
         })?;
+        let file_meta = entry.metadata().ok();
+        let mut rich_content = None;
 
-        let mut page = Page::new(slug.clone(), entry.path().to_path_buf(), content);
-        // Reuse frontmatter parsing to extract title/tags.
-        page = FrontMatter
-            .transform(page)
-            .context("parsing frontmatter for content index")?;
+        let (mut meta, text, links, summary, protected) = if format == ContentFormat::Plain {
+            let mut meta = PageMetadata::default();
+            meta.title = entry
+                .path()
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().replace('-', " "));
+            let protected = config.encryption.protects(&slug, &meta);
+            (
+                meta,
+                (wants_text && !protected).then_some(content),
+                Vec::new(),
+                None,
+                protected,
+            )
+        } else {
+            let source = fields.rich_content.then(|| content.clone());
+            let page = Page::new(slug.clone(), entry.path().to_path_buf(), content);
+            // Reuse frontmatter parsing to extract title/tags.
+            let Some(page) = registry
+                .front_matter(page)
+                .context("parsing frontmatter for content index")?
+            else {
+                debug!("content index skips filtered page {slug}");
+                continue;
+            };
+            // An encrypted note's text and links stay out of the index, like its rendered
+            // body. The pipeline has not run yet, so ask the frontmatter and the rules.
+            let encrypted = config.encryption.protects(&slug, &page.frontmatter);
+            let attachments = static_files.referenced(&page.content);
+            if encrypted {
+                protected_attachments.extend(attachments);
+            } else {
+                public_attachments.extend(attachments);
+            }
+            // Wikilinks and markdown links, resolved to slugs against the whole tree.
+            let links = if encrypted {
+                Vec::new()
+            } else {
+                link_targets(&page.links, &slugs)
+            };
+            let text = (wants_text && !encrypted).then(|| markdown_text(&page.content));
+            rich_content = source
+                .filter(|_| !encrypted)
+                .and_then(|source| {
+                    rendered_html(
+                        registry,
+                        cache_root,
+                        &render_key,
+                        &slug,
+                        entry.path(),
+                        source,
+                    )
+                })
+                .map(|html| rich_excerpt(&html, fields.rich_content_chars));
+            let mut meta = page.frontmatter;
+            if meta.title.is_none() {
+                // fallback: the file name, which `content.slugify` may have lowercased in the slug
+                meta.title = entry
+                    .path()
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().replace('-', " "));
+            }
+            let summary = page.excerpt.filter(|_| !encrypted);
+            (meta, text, links, summary, encrypted)
+        };
+        if protected {
+            // Listed by title alone: no tags, dates or description to go on.
+            meta = PageMetadata {
+                title: meta
+                    .title
+                    .map(|title| config.encryption.listed_title(title)),
+                weight: meta.weight,
+                hide_nav: meta.hide_nav,
+                ..PageMetadata::default()
+            };
+        }
+        let text = text.map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "));
+        let words = text.as_deref().map(count_words);
 
-        let title = page.frontmatter.title.clone().or_else(|| {
-            // fallback: use last segment of slug
-            slug.rsplit('/').next().map(|s| s.replace('-', " "))
+        let mut index_entry = ContentIndexEntry {
+            slug: slug.clone(),
+            file_path,
+            rich_content,
+            weight: meta.weight,
+            hide_nav: meta.hide_nav.unwrap_or(false),
+            ..ContentIndexEntry::default()
+        };
+        for field in &fields.fields {
+            match field {
+                ContentIndexField::Title => index_entry.title = meta.title.clone(),
+                ContentIndexField::Links => {
+                    index_entry.links = (!links.is_empty()).then(|| links.clone())
+                }
+                ContentIndexField::Tags => index_entry.tags = meta.tags.clone(),
+                ContentIndexField::Date => {
+                    index_entry.date = file_meta
+                        .as_ref()
+                        .filter(|_| !protected)
+                        .and_then(|file| page_date(&meta, file, date_type))
+                        .map(|date| date.to_rfc3339())
+                }
+                ContentIndexField::Description => {
+                    index_entry.description = meta
+                        .description
+                        .clone()
+                        .or_else(|| summary.clone())
+                        .or_else(|| text.as_deref().and_then(excerpt))
+                }
+                ContentIndexField::ReadingTime => {
+                    index_entry.reading_time = words.map(reading_minutes)
+                }
+                ContentIndexField::WordCount => index_entry.word_count = words,
+                ContentIndexField::Content => index_entry.content = text.clone(),
+            }
+        }
+        entries.insert(slug.clone(), index_entry);
+        graph_pages.push(GraphPage {
+            title: meta.title.unwrap_or_else(|| slug.clone()),
+            tags: meta.tags.unwrap_or_default(),
+            slug,
+            links,
         });
-
-        let tags = page.frontmatter.tags.clone();
-
-        // Minimal link extraction (wikilinks + markdown links) – best-effort.
-        let links = extract_links(&page.content);
-
-        entries.insert(
-            slug.clone(),
-            ContentIndexEntry {
-                slug,
-                file_path,
-                title,
-                links: if links.is_empty() { None } else { Some(links) },
-                tags,
-            },
-        );
     }
 
     let static_dir = cache_root.join("static");
@@ -96,61 +259,432 @@ pub fn generate_content_index(
 
     let json_path = static_dir.join("content-index.json");
     let json = serde_json::to_string(&entries)?;
-    fs::write(&json_path, json)
+    cache::write_atomic(&json_path, json.as_bytes(), false)
         .with_context(|| format!("writing content index to {}", json_path.display()))?;
 
     debug!("content-index.json written to {}", json_path.display());
+    attachments::record_protected(cache_root, &protected_attachments, &public_attachments)
+        .context("recording attachments of encrypted notes")?;
+    write_graph_json(&static_dir, &graph_pages, &config.layout.graph)?;
+    let report = link_report(&graph_pages, false);
+    info!(
+        "{} orphaned page(s), {} dead end(s); `trellis check` lists them",
+        report.orphans.len(),
+        report.dead_ends.len()
+    );
+    if fields.rich_content {
+        let rich_bytes: usize = entries
+            .values()
+            .filter_map(|entry| entry.rich_content.as_ref())
+            .map(String::len)
+            .sum();
+        info!(
+            "content index is {} KiB, {} KiB of it richContent",
+            json.len().div_ceil(1024),
+            rich_bytes.div_ceil(1024)
+        );
+    }
     Ok(())
 }
 
-fn is_ignored(path: &Path, root: &Path, patterns: &[String]) -> bool {
-    let Ok(rel) = path.strip_prefix(root) else {
-        return false;
-    };
+/// A page's body HTML: the cached render when the render cache says it is current,
+/// otherwise a fresh run through the registry. `None` when rendering fails.
+fn rendered_html(
+    registry: &PluginRegistry,
+    cache_root: &Path,
+    key: &RenderKey,
+    slug: &str,
+    source_path: &Path,
+    source: String,
+) -> Option<String> {
+    let fresh = cache::check(cache_root, slug, source_path, key)
+        .is_ok_and(|freshness| freshness.is_fresh());
+    if fresh {
+        if let Ok(html) = fs::read_to_string(cache::cache_path(cache_root, slug)) {
+            return Some(html);
+        }
+    }
+    let page = Page::new(slug.to_string(), source_path.to_path_buf(), source);
+    match registry.transform(page) {
+        Ok(page) => page.html,
+        Err(RenderError::Filtered { .. }) => None,
+        Err(err) => {
+            warn!("richContent for {slug} skipped: {err:#}");
+            None
+        }
+    }
+}
+
+/// Elements dropped from `richContent` along with everything inside them, besides the
+/// `script` and `style` ammonia already drops.
+const UNSAFE_ELEMENTS: &[&str] = &["iframe", "object", "embed", "template"];
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
 
-    rel.components().any(|comp| {
-        comp.as_os_str()
-            .to_str()
-            .map(|s| patterns.iter().any(|p| p == s))
-            .unwrap_or(false)
-    })
+/// `html` through ammonia's allow-list, which drops scripts, frames, comments, inline event
+/// handlers and `javascript:` URLs however they are written. Classes, ids and `data-`
+/// attributes survive for the site's styles and scripts, as do task-list checkboxes.
+fn sanitize_html(html: &str) -> String {
+    static SANITIZER: OnceLock<ammonia::Builder<'static>> = OnceLock::new();
+    SANITIZER
+        .get_or_init(|| {
+            let mut builder = ammonia::Builder::default();
+            builder
+                .add_tags(["input"])
+                .add_tag_attributes("input", ["type", "checked", "disabled"])
+                .add_generic_attributes(["class", "id"])
+                .add_generic_attribute_prefixes(["data-"])
+                .add_clean_content_tags(UNSAFE_ELEMENTS.iter().copied());
+            builder
+        })
+        .clean(html)
+        .to_string()
 }
 
-pub fn extract_links(content: &str) -> Vec<String> {
+/// The opening top-level elements of sanitized `html` holding at least `limit` characters
+/// of text. Cutting only where every element has closed keeps the markup balanced.
+fn rich_excerpt(html: &str, limit: usize) -> String {
+    let html = sanitize_html(html);
+    let mut depth = 0usize;
+    let mut text_chars = 0;
+    let mut cut = 0;
+    let mut at = 0;
+    while at < html.len() {
+        let rest = &html[at..];
+        if rest.starts_with('<') {
+            let end = tag_end(rest);
+            let tag = &rest[..end];
+            at += end;
+            let name = tag
+                .trim_start_matches(['<', '/'])
+                .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            if tag.starts_with("</") {
+                depth = depth.saturating_sub(1);
+            } else if !VOID_ELEMENTS.contains(&name.as_str()) {
+                depth += 1;
+            }
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            text_chars += rest[..end].chars().count();
+            at += end;
+        }
+        if depth == 0 {
+            cut = at;
+            if text_chars >= limit {
+                break;
+            }
+        }
+    }
+    html[..cut].to_string()
+}
+
+/// Length of the tag `html` opens with, through its closing `>`. A `>` inside a quoted
+/// attribute value does not end the tag.
+fn tag_end(html: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in html.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '>') => return i + 1,
+            _ => {}
+        }
+    }
+    html.len()
+}
+
+/// Every content slug under `content_root`, for resolving links. Reads no files.
+pub fn content_slugs(
+    content_root: &Path,
+    ignore: &IgnoreMatcher,
+    content_cfg: &ContentConfig,
+) -> BTreeSet<String> {
+    WalkDir::new(content_root)
+        .into_iter()
+        .filter_entry(|e| !ignore.is_ignored(e.path(), content_root))
+        .filter_map(Result::ok)
+        .filter(|e| e.path().is_file() && content_cfg.is_content_file(e.path()))
+        .map(|e| slug_from_path(e.path(), content_root))
+        .collect()
+}
+
+/// The link graph `graph.json` is written from, without the rest of the content index:
+/// every published page with its resolved links and tags. Encrypted notes appear by title
+/// alone, as in the content index.
+pub fn link_graph(
+    content_root: &Path,
+    ignore: &IgnoreMatcher,
+    registry: &PluginRegistry,
+    content_cfg: &ContentConfig,
+    encryption: &EncryptionConfig,
+) -> Result<Vec<GraphPage>> {
+    let slugs = content_slugs(content_root, ignore, content_cfg);
+    let mut seen = BTreeSet::new();
+    let mut pages = Vec::new();
+    for entry in WalkDir::new(content_root)
+        .into_iter()
+        .filter_entry(|e| !ignore.is_ignored(e.path(), content_root))
+        .filter_map(Result::ok)
+        .filter(|e| e.path().is_file())
+    {
+        let Some(format) = content_cfg.format_for(entry.path()) else {
+            continue;
+        };
+        let slug = slug_from_path(entry.path(), content_root);
+        if !seen.insert(slug.clone()) {
+            continue;
+        }
+        if format == ContentFormat::Plain {
+            pages.push(GraphPage {
+                title: slug.clone(),
+                slug,
+                links: Vec::new(),
+                tags: Vec::new(),
+            });
+            continue;
+        }
+
+        let content = fs::read_to_string(entry.path())
+            .with_context(|| format!("reading {} for the link graph", entry.path().display()))?;
+        let page = Page::new(slug.clone(), entry.path().to_path_buf(), content);
+        let Some(page) = registry
+            .front_matter(page)
+            .context("parsing frontmatter for the link graph")?
+        else {
+            continue;
+        };
+        let title = page
+            .frontmatter
+            .title
+            .clone()
+            .unwrap_or_else(|| slug.clone());
+        if encryption.protects(&slug, &page.frontmatter) {
+            pages.push(GraphPage {
+                title: encryption.listed_title(title),
+                slug,
+                links: Vec::new(),
+                tags: Vec::new(),
+            });
+            continue;
+        }
+        pages.push(GraphPage {
+            links: link_targets(&page.links, &slugs),
+            title,
+            tags: page.frontmatter.tags.unwrap_or_default(),
+            slug,
+        });
+    }
+    Ok(pages)
+}
+
+/// Pages linked from the page at `source_slug`, as slugs without a trailing `/index`
+/// (`.` for the home page). See [`link_mentions`] for how targets resolve.
+pub fn extract_links(content: &str, source_slug: &str, slugs: &BTreeSet<String>) -> Vec<String> {
+    link_targets(&page_links(content, source_slug), slugs)
+}
+
+/// The pages `links` point at, sorted and deduplicated, with wikilinks resolved against
+/// `slugs`. External and anchor links are left out.
+pub fn link_targets(links: &[ResolvedLink], slugs: &BTreeSet<String>) -> Vec<String> {
+    let mut targets: Vec<String> = links
+        .iter()
+        .filter_map(|link| resolve_link(link, slugs))
+        .collect();
+    targets.sort();
+    targets.dedup();
+    targets
+}
+
+/// One link in a page's markdown.
+#[derive(Debug, Clone)]
+pub struct LinkMention {
+    /// Resolved slug, as [`extract_links`] reports it.
+    pub target: String,
+    /// Byte range of the whole link syntax in the source.
+    pub span: Range<usize>,
+    /// The text a reader sees: the label, a wikilink's alias, or its target.
+    pub label: String,
+}
+
+/// Every link into the content tree in `content`, in source order. Markdown links resolve
+/// against the source's folder unless they start with `/`. Wikilinks name a page by its
+/// path or, as in Obsidian, by the shortest path among `slugs` ending in that name,
+/// falling back to the literal name. External URLs and `#anchor` links are skipped.
+pub fn link_mentions(
+    content: &str,
+    source_slug: &str,
+    slugs: &BTreeSet<String>,
+) -> Vec<LinkMention> {
+    scan_links(content, source_slug, slug_style())
+        .into_iter()
+        .filter_map(|(link, span, label)| {
+            Some(LinkMention {
+                target: resolve_link(&link, slugs)?,
+                span,
+                label,
+            })
+        })
+        .collect()
+}
+
+/// Every link in `content`, in source order, as the pipeline records it on
+/// [`Page::links`]. Wikilinks keep the name as written; [`link_targets`] resolves them.
+pub fn page_links(content: &str, source_slug: &str) -> Vec<ResolvedLink> {
+    page_links_with(content, source_slug, slug_style())
+}
+
+/// As [`page_links`], with internal links slugified in `style` rather than the engine's.
+pub fn page_links_with(content: &str, source_slug: &str, style: SlugStyle) -> Vec<ResolvedLink> {
+    scan_links(content, source_slug, style)
+        .into_iter()
+        .map(|(link, _, _)| link)
+        .collect()
+}
+
+/// The slug an internal link or wikilink points at; `None` for other kinds.
+fn resolve_link(link: &ResolvedLink, slugs: &BTreeSet<String>) -> Option<String> {
+    match link.kind {
+        LinkKind::Internal => Some(link.target.clone()),
+        LinkKind::Wikilink => Some(clean_link_target(&resolve_wikilink(&link.target, slugs))),
+        LinkKind::External | LinkKind::Anchor => None,
+    }
+}
+
+/// Each link with the byte range of its syntax and the text a reader sees.
+fn scan_links(
+    content: &str,
+    source_slug: &str,
+    style: SlugStyle,
+) -> Vec<(ResolvedLink, Range<usize>, String)> {
+    static WIKILINK: OnceLock<regex::Regex> = OnceLock::new();
+    static MARKDOWN_LINK: OnceLock<regex::Regex> = OnceLock::new();
+    let wikilink = WIKILINK.get_or_init(|| {
+        regex::Regex::new(r"\[\[([^\]\|#]+)(?:#[^\]\|]*)?(?:\|([^\]]*))?(?:\]\])?")
+            .expect("valid pattern")
+    });
+    let markdown_link = MARKDOWN_LINK
+        .get_or_init(|| regex::Regex::new(r"\[([^\]]*)\]\(([^)]+)\)").expect("valid pattern"));
     let mut links = Vec::new();
 
-    // [[wikilink]]
-    let wiki = regex::Regex::new(r"\[\[([^\]\|#]+)").ok();
-    if let Some(re) = wiki {
-        for cap in re.captures_iter(content) {
-            if let Some(m) = cap.get(1) {
-                links.push(clean_link_target(m.as_str()));
-            }
+    for cap in wikilink.captures_iter(content) {
+        let target = cap[1].trim();
+        if target.is_empty() {
+            continue;
         }
+        let label = cap
+            .get(2)
+            .map(|alias| alias.as_str().trim())
+            .filter(|alias| !alias.is_empty())
+            .unwrap_or(target);
+        let link = ResolvedLink {
+            target: target.to_string(),
+            kind: LinkKind::Wikilink,
+        };
+        let span = cap.get(0).map_or(0..0, |m| m.range());
+        links.push((link, span, label.to_string()));
     }
 
-    // markdown links [text](target)
-    let md = regex::Regex::new(r"\[[^\]]*\]\(([^)]+)\)").ok();
-    if let Some(re) = md {
-        for cap in re.captures_iter(content) {
-            if let Some(m) = cap.get(1) {
-                let target = m.as_str();
-                if !(target.starts_with("http://") || target.starts_with("https://")) {
-                    links.push(clean_link_target(target));
-                }
-            }
+    for cap in markdown_link.captures_iter(content) {
+        let raw = cap[2].trim();
+        // `[text](<path with spaces> "title")` or `[text](path "title")`.
+        let target = match raw.strip_prefix('<') {
+            Some(inner) => inner.split('>').next().unwrap_or_default(),
+            None => raw.split_whitespace().next().unwrap_or_default(),
+        };
+        if target.is_empty() {
+            continue;
         }
+        let link = if target.starts_with('#') {
+            ResolvedLink {
+                target: target.to_string(),
+                kind: LinkKind::Anchor,
+            }
+        } else if has_scheme(target) {
+            ResolvedLink {
+                target: target.to_string(),
+                kind: LinkKind::External,
+            }
+        } else {
+            let path = target
+                .split(['#', '?'])
+                .next()
+                .unwrap_or_default()
+                .replace("%20", " ");
+            let resolved = match path.strip_prefix('/') {
+                Some(from_root) => from_root.to_string(),
+                None => join_relative(source_dir(source_slug), &path),
+            };
+            ResolvedLink {
+                target: clean_link_target(&slugify_with(&resolved, style)),
+                kind: LinkKind::Internal,
+            }
+        };
+        let span = cap.get(0).map_or(0..0, |m| m.range());
+        links.push((link, span, cap[1].trim().to_string()));
     }
 
-    links.sort();
-    links.dedup();
+    links.sort_by_key(|(_, span, _)| span.start);
     links
 }
 
-fn clean_link_target(raw: &str) -> String {
-    let no_anchor = raw.split('#').next().unwrap_or(raw);
-    let trimmed = no_anchor.trim().trim_matches('.');
-    let mut cleaned = trimmed.trim_matches('/').to_string();
+/// `mailto:`, `https:` and the like; such links never point into the content tree.
+fn has_scheme(target: &str) -> bool {
+    target.split_once(':').is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// The folder holding the page at `slug`; `""` at the content root.
+fn source_dir(slug: &str) -> &str {
+    slug.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// `path` taken relative to `dir`, with `.` and `..` segments applied. `..` stops at the
+/// content root.
+fn join_relative(dir: &str, path: &str) -> String {
+    let mut segments: Vec<&str> = dir.split('/').filter(|s| !s.is_empty()).collect();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+/// The slug a wikilink target names, once `content.slugify` has shaped it: an exact path if
+/// one exists, else the shortest slug ending in `/<target>` (alphabetically first on a
+/// tie), else the shaped target.
+fn resolve_wikilink(target: &str, slugs: &BTreeSet<String>) -> String {
+    let name = target.trim_start_matches('/');
+    let name = slugify(name.strip_suffix(".md").unwrap_or(name));
+    let name = name.as_str();
+    if slugs.contains(name) || slugs.contains(&format!("{name}/index")) {
+        return name.to_string();
+    }
+    let page = format!("/{name}");
+    let folder = format!("/{name}/index");
+    slugs
+        .iter()
+        .filter(|slug| slug.ends_with(&page) || slug.ends_with(&folder))
+        .min_by_key(|slug| (slug.matches('/').count(), slug.as_str()))
+        .map(|slug| slug.strip_suffix("/index").unwrap_or(slug).to_string())
+        .unwrap_or_else(|| name.to_string())
+}
+
+fn clean_link_target(path: &str) -> String {
+    let mut cleaned = path.trim().trim_matches('/').to_string();
 
     if cleaned.ends_with(".md") {
         cleaned.truncate(cleaned.len() - 3);
@@ -162,9 +696,68 @@ fn clean_link_target(raw: &str) -> String {
         cleaned.truncate(cleaned.len() - "/index".len());
     }
 
-    if cleaned.is_empty() {
+    if cleaned.is_empty() || cleaned == "index" {
         ".".into()
     } else {
         cleaned
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_quoted_angle_bracket_does_not_end_the_tag() {
+        let html = sanitize_html(r#"<p><a title=">" onclick="alert(1)">hi</a></p>"#);
+        assert!(!html.contains("onclick"), "{html}");
+        assert!(!html.contains("alert"), "{html}");
+        assert!(html.contains("hi</a></p>"), "{html}");
+    }
+
+    #[test]
+    fn entity_encoded_javascript_urls_are_dropped() {
+        for href in [
+            "&#106;avascript:alert(1)",
+            "jav&#x61;script&colon;alert(1)",
+            "&#x20;javascript:alert(1)",
+        ] {
+            let html = sanitize_html(&format!(r#"<p><a href="{href}">hi</a></p>"#));
+            assert!(!html.contains("href"), "{html}");
+            assert!(html.contains("hi</a>"), "{html}");
+        }
+    }
+
+    #[test]
+    fn scripts_and_frames_are_dropped_with_their_contents() {
+        let html = sanitize_html(
+            "<p>kept</p><script>alert(1)</script><iframe src=\"/x\">framed</iframe><!-- note -->",
+        );
+        assert_eq!(html, "<p>kept</p>");
+    }
+
+    #[test]
+    fn classes_ids_and_data_attributes_survive() {
+        let html = sanitize_html(
+            r#"<blockquote class="callout" data-callout="note"><h2 id="intro">Intro</h2></blockquote>"#,
+        );
+        assert!(html.contains(r#"class="callout""#), "{html}");
+        assert!(html.contains(r#"data-callout="note""#), "{html}");
+        assert!(html.contains(r#"id="intro""#), "{html}");
+    }
+
+    #[test]
+    fn rich_excerpt_cuts_between_top_level_elements() {
+        let html = "<p>one two</p><p>three</p><p>four</p>";
+        assert_eq!(rich_excerpt(html, 3), "<p>one two</p>");
+        assert_eq!(rich_excerpt(html, 8), "<p>one two</p><p>three</p>");
+    }
+
+    #[test]
+    fn tags_end_at_the_first_unquoted_angle_bracket() {
+        assert_eq!(tag_end(r#"<a title="a > b">x"#), 17);
+        assert_eq!(tag_end("<a title='>'>x"), 13);
+        assert_eq!(tag_end("<p>x"), 3);
+        assert_eq!(tag_end(r#"<a title=">"#), 11);
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as B64;
+use getrandom::fill;
+use sha2::{Digest, Sha256};
+
+use crate::trellis::config::CspConfig;
+
+/// Sources one page needs on top of `'self'` and the configured extras: hosts of external
+/// modules and stylesheets, plus the nonce or the hashes of its inline tags.
+#[derive(Debug, Default)]
+pub struct PageSources {
+    pub scripts: Vec<String>,
+    pub styles: Vec<String>,
+    /// The explorer builds its sort/filter/map functions with `new Function`.
+    pub eval: bool,
+}
+
+/// A fresh base64 nonce for one response.
+pub fn nonce() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    fill(&mut bytes).map_err(|e| anyhow!("random CSP nonce failed: {e}"))?;
+    Ok(B64.encode(bytes))
+}
+
+/// `'sha256-…'` source matching an inline tag whose text is exactly `content`.
+pub fn hash_source(content: &str) -> String {
+    format!(
+        "'sha256-{}'",
+        B64.encode(Sha256::digest(content.as_bytes()))
+    )
+}
+
+/// `scheme://host[:port]` of an absolute http(s) URL; `None` for relative URLs, which
+/// `'self'` already covers.
+pub fn origin(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    if scheme != "http" && scheme != "https" {
+        return None;
+    }
+    let host = rest
+        .split(['/', '?', '#'])
+        .next()
+        .filter(|h| !h.is_empty())?;
+    Some(format!("{scheme}://{host}"))
+}
+
+/// Add `nonce` to every `<script>` and `<style>` tag in raw markup such as the analytics
+/// snippet.
+pub fn add_nonce(html: &str, nonce: &str) -> String {
+    html.replace("<script", &format!("<script nonce=\"{nonce}\""))
+        .replace("<style", &format!("<style nonce=\"{nonce}\""))
+}
+
+/// Text of each non-empty inline `<tag>` element in raw markup, for hashing.
+pub fn inline_contents<'a>(html: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{tag}");
+    let close_tag = format!("</{tag}>");
+    let mut found = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find(&open) {
+        let Some(open_end) = rest[start..].find('>') else {
+            break;
+        };
+        let body_start = start + open_end + 1;
+        let Some(close) = rest[body_start..].find(&close_tag) else {
+            break;
+        };
+        let body = &rest[body_start..body_start + close];
+        if !body.is_empty() {
+            found.push(body);
+        }
+        rest = &rest[body_start + close..];
+    }
+    found
+}
+
+/// The `Content-Security-Policy` header value for one page. No `unsafe-inline`: every
+/// inline tag must be covered by the nonce or a hash in `page`.
+pub fn header_value(cfg: &CspConfig, page: &PageSources) -> String {
+    let join = |page_sources: &[String], extra: &[String], eval: bool| {
+        let mut sources = vec!["'self'".to_string()];
+        if eval {
+            sources.push("'unsafe-eval'".into());
+        }
+        for source in extra.iter().chain(page_sources) {
+            if !sources.contains(source) {
+                sources.push(source.clone());
+            }
+        }
+        sources.join(" ")
+    };
+
+    format!(
+        "script-src {}; style-src {}; object-src 'none'; base-uri 'self'",
+        join(&page.scripts, &cfg.script_src, page.eval),
+        join(&page.styles, &cfg.style_src, false),
+    )
+}
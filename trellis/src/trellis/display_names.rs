@@ -0,0 +1,117 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use serde::Deserialize;
+
+use crate::trellis::config::ContentConfig;
+use crate::trellis::slugs;
+
+/// Name of the file giving a folder without an `index` page its title.
+pub const FOLDER_META: &str = "_meta.yml";
+
+/// Small words left lowercase inside a title.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "but", "by", "for", "from", "in", "into", "nor", "of", "on",
+    "or", "the", "to", "via", "vs", "with",
+];
+
+#[derive(Deserialize, Default)]
+struct FolderMeta {
+    #[serde(default)]
+    title: Option<String>,
+}
+
+/// What nav, the explorer tree, breadcrumbs and backlinks call the note or folder at
+/// `slug` (a folder's `/index` suffix is ignored): its `content.display_names` entry, else
+/// `title` (the page's, or the folder index's, frontmatter title), else the `title` in the
+/// folder's `_meta.yml`, else its last path segment, humanized.
+pub fn display_name(
+    content: &ContentConfig,
+    content_root: &Path,
+    slug: &str,
+    title: Option<String>,
+) -> String {
+    let slug = slug.strip_suffix("/index").unwrap_or(slug);
+    if let Some(name) = content.display_names.get(slug) {
+        return name.clone();
+    }
+    title
+        .filter(|title| !title.trim().is_empty())
+        .or_else(|| folder_dir(content_root, slug).and_then(|dir| meta_title(&dir)))
+        .unwrap_or_else(|| humanize(slug.rsplit('/').next().unwrap_or(slug)))
+}
+
+/// A slug segment as a title: dashes and underscores become spaces and each word is
+/// capitalized, except small words inside the title. Words already holding a capital or
+/// a digit (`iOS`, `APIs`, `v2`) are kept as written.
+pub fn humanize(segment: &str) -> String {
+    let words: Vec<&str> = segment
+        .split(|c: char| c == '-' || c == '_' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .collect();
+    let last = words.len().saturating_sub(1);
+    words
+        .iter()
+        .enumerate()
+        .map(|(i, word)| {
+            if word.chars().any(|c| c.is_uppercase() || c.is_numeric()) {
+                word.to_string()
+            } else if i != 0 && i != last && STOP_WORDS.contains(word) {
+                word.to_string()
+            } else {
+                capitalize(word)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        // `to_uppercase` may yield more than one char (`ß` is `SS`); all are kept.
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// The directory of the folder at `slug`, matched a segment at a time against the
+/// slugified names on disk, since under `content.slugify` a slug need not be the path.
+fn folder_dir(content_root: &Path, slug: &str) -> Option<PathBuf> {
+    let mut dir = content_root.to_path_buf();
+    for segment in slug.split('/') {
+        let joined = dir.join(segment);
+        if joined.is_dir() {
+            dir = joined;
+            continue;
+        }
+        dir = fs::read_dir(&dir)
+            .ok()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .find(|path| {
+                path.is_dir()
+                    && path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| slugs::slugify(name) == segment)
+            })?;
+    }
+    Some(dir)
+}
+
+fn meta_title(dir: &Path) -> Option<String> {
+    let path = dir.join(FOLDER_META);
+    let text = fs::read_to_string(&path).ok()?;
+    if text.trim().is_empty() {
+        return None;
+    }
+    match serde_yaml::from_str::<FolderMeta>(&text) {
+        Ok(meta) => meta.title.filter(|title| !title.trim().is_empty()),
+        Err(err) => {
+            warn!("ignoring {}: {err}", path.display());
+            None
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::fmt;
+use std::io;
+
+/// Why the engine produced no page for a slug. Handlers answer 404 for the first three
+/// variants and 500 for the rest.
+#[derive(Debug)]
+pub enum RenderError {
+    /// No source file for the slug, or an empty one.
+    NotFound(String),
+    /// A filter left the page out, such as a draft.
+    Filtered {
+        reason: String,
+    },
+    /// The slug matches `configuration.ignore_patterns`.
+    Ignored,
+    Io(io::Error),
+    /// A transformer in the plugin pipeline failed.
+    Transform {
+        plugin: &'static str,
+        source: anyhow::Error,
+    },
+    /// The page rendered, but its template did not.
+    Template(String),
+}
+
+impl RenderError {
+    /// Whether the page is simply not there to serve, as opposed to broken.
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            Self::NotFound(_) | Self::Filtered { .. } | Self::Ignored
+        )
+    }
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(what) => write!(f, "{what}"),
+            Self::Filtered { reason } => write!(f, "left out by a filter: {reason}"),
+            Self::Ignored => write!(f, "ignored by configuration.ignore_patterns"),
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Transform { plugin, source } => write!(f, "{plugin} failed: {source:#}"),
+            Self::Template(err) => write!(f, "template error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+impl From<io::Error> for RenderError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
@@ -0,0 +1,129 @@
+#[cfg(feature = "bundler")]
+use swc_common::{FileName, SourceMap, sync::Lrc};
+#[cfg(feature = "bundler")]
+use swc_ecma_ast::{EsVersion, Expr, Stmt};
+#[cfg(feature = "bundler")]
+use swc_ecma_parser::lexer::Lexer;
+#[cfg(feature = "bundler")]
+use swc_ecma_parser::{EsSyntax, Parser, StringInput, Syntax};
+
+use crate::trellis::layout::{ExplorerConfig, default_filter_fn, default_map_fn, default_sort_fn};
+
+/// Named `sort_fn` presets, so most sites never need to write JavaScript. Each puts pages
+/// with a frontmatter `weight` first, lowest first, like the server-rendered nav.
+pub const SORT_PRESETS: &[(&str, &str)] = &[
+    (
+        "alphabetical",
+        "(a,b)=>{const wa=a.data?.weight??Infinity,wb=b.data?.weight??Infinity;if(wa!==wb){return wa<wb?-1:1;}return a.displayName.localeCompare(b.displayName,undefined,{numeric:true,sensitivity:'base'});}",
+    ),
+    (
+        "byDate",
+        "(a,b)=>{const wa=a.data?.weight??Infinity,wb=b.data?.weight??Infinity;if(wa!==wb){return wa<wb?-1:1;}if(a.isFolder!==b.isFolder){return a.isFolder?-1:1;}const da=a.data?.date??'',db=b.data?.date??'';if(da!==db){return da<db?1:-1;}return a.displayName.localeCompare(b.displayName,undefined,{numeric:true,sensitivity:'base'});}",
+    ),
+    ("foldersFirst", DEFAULT_SORT),
+];
+
+pub(crate) const DEFAULT_SORT: &str = "(a,b)=>{const wa=a.data?.weight??Infinity,wb=b.data?.weight??Infinity;if(wa!==wb){return wa<wb?-1:1;}if((!a.isFolder&&!b.isFolder)||(a.isFolder&&b.isFolder)){return a.displayName.localeCompare(b.displayName,undefined,{numeric:true,sensitivity:'base'});}if(!a.isFolder&&b.isFolder){return 1;}else{return -1;}}";
+
+/// The explorer's sort/filter/map functions as they are sent to the browser: presets
+/// expanded, and anything that fails [`check_fn`] replaced by the built-in default.
+#[derive(Debug, Clone)]
+pub struct ExplorerFns {
+    pub sort: String,
+    pub filter: String,
+    pub map: String,
+}
+
+impl ExplorerFns {
+    pub fn from_config(cfg: &ExplorerConfig) -> Self {
+        Self {
+            sort: resolve(&expand_sort_preset(&cfg.sort_fn), default_sort_fn),
+            filter: resolve(&cfg.filter_fn, default_filter_fn),
+            map: resolve(&cfg.map_fn, default_map_fn),
+        }
+    }
+
+    /// Whether any function reads a node's `data` beyond `weight` and `hideNav`, the only
+    /// fields `GET /api/explorer` carries; the explorer then loads the full content index.
+    pub fn reads_index_fields(&self) -> bool {
+        [&self.sort, &self.filter, &self.map]
+            .into_iter()
+            .any(|source| {
+                source
+                    .replace("data?.weight", "")
+                    .replace("data?.hideNav", "")
+                    .contains("data")
+            })
+    }
+}
+
+fn resolve(source: &str, default: fn() -> String) -> String {
+    match check_fn(source) {
+        Ok(()) => source.to_string(),
+        Err(_) => default(),
+    }
+}
+
+/// The preset's JavaScript if `value` names one, otherwise `value` unchanged.
+pub fn expand_sort_preset(value: &str) -> String {
+    SORT_PRESETS
+        .iter()
+        .find(|(name, _)| *name == value.trim())
+        .map(|(_, js)| js.to_string())
+        .unwrap_or_else(|| value.to_string())
+}
+
+/// Check that `source` is a single arrow function or function expression.
+///
+/// The snippet ends up in `new Function("return " + source)` on the client, so anything
+/// else (a statement list, a call, trailing code) is rejected here rather than evaluated
+/// in every visitor's browser. Errors carry the 1-based line and column within `source`.
+#[cfg(feature = "bundler")]
+pub fn check_fn(source: &str) -> Result<(), String> {
+    let cm: Lrc<SourceMap> = Default::default();
+    // Parenthesized so `function (a, b) {}` parses as an expression, not a declaration.
+    let fm = cm.new_source_file(Lrc::new(FileName::Anon), format!("({source}\n)"));
+    let lexer = Lexer::new(
+        Syntax::Es(EsSyntax::default()),
+        EsVersion::Es2022,
+        StringInput::from(&*fm),
+        None,
+    );
+    let mut parser = Parser::new_from(lexer);
+
+    let position = |span: swc_common::Span| {
+        let loc = cm.lookup_char_pos(span.lo);
+        // Account for the opening parenthesis added above.
+        let col = if loc.line == 1 {
+            loc.col.0
+        } else {
+            loc.col.0 + 1
+        };
+        format!("line {}, column {}", loc.line, col)
+    };
+
+    let script = parser
+        .parse_script()
+        .map_err(|err| format!("{} at {}", err.kind().msg(), position(err.span())))?;
+    if let Some(err) = parser.take_errors().into_iter().next() {
+        return Err(format!("{} at {}", err.kind().msg(), position(err.span())));
+    }
+
+    let [Stmt::Expr(stmt)] = script.body.as_slice() else {
+        return Err("expected a single function expression".into());
+    };
+    let Expr::Paren(paren) = &*stmt.expr else {
+        return Err("expected a single function expression".into());
+    };
+    match &*paren.expr {
+        Expr::Arrow(_) | Expr::Fn(_) => Ok(()),
+        _ => Err("expected an arrow function or function expression".into()),
+    }
+}
+
+/// Without the `bundler` feature there is no JavaScript parser, and nothing is served to
+/// run the functions either, so every source is accepted as written.
+#[cfg(not(feature = "bundler"))]
+pub fn check_fn(_source: &str) -> Result<(), String> {
+    Ok(())
+}
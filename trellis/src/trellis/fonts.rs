@@ -0,0 +1,160 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use log::warn;
+
+use crate::trellis::config::ThemeConfig;
+
+/// URL prefix the files from `paths.fonts_dir` are served under.
+pub const FONTS_URL: &str = "/static/fonts";
+
+const FONT_EXTENSIONS: &[(&str, &str)] = &[
+    ("woff2", "woff2"),
+    ("woff", "woff"),
+    ("ttf", "truetype"),
+    ("otf", "opentype"),
+];
+
+/// `@font-face` rules for the families in `theme.typography`, built from the files in
+/// `fonts_dir`.
+///
+/// A file belongs to a family when its name starts with the family name (case, spaces and
+/// punctuation ignored), e.g. `SourceSansPro-SemiboldItalic.woff2` or
+/// `ibm-plex-mono-400.woff2`; weight and style are read from the rest of the name.
+/// Families without files are skipped, leaving the system stacks from
+/// `theme_css_variables` to take over.
+pub fn font_face_css(theme: &ThemeConfig, fonts_dir: &Path) -> String {
+    let display = theme.font_display.as_str();
+    let mut css = String::new();
+    for (family, faces) in family_files(theme, fonts_dir) {
+        if faces.is_empty() {
+            warn!(
+                "No font files for {family:?} in {}; falling back to system fonts",
+                fonts_dir.display()
+            );
+        }
+        for face in faces {
+            css.push_str(&format!(
+                "@font-face{{font-family:\"{family}\";src:url(\"{}\") format(\"{}\");font-weight:{};font-style:{};font-display:{display};}}\n",
+                face.url,
+                face.format,
+                face.weight,
+                if face.italic { "italic" } else { "normal" },
+            ));
+        }
+    }
+    css
+}
+
+/// URLs of the woff2 files the `@font-face` rules reference, for `<link rel="preload">`.
+pub fn preload_hrefs(theme: &ThemeConfig, fonts_dir: &Path) -> Vec<String> {
+    family_files(theme, fonts_dir)
+        .into_iter()
+        .flat_map(|(_, faces)| faces)
+        .filter(|face| face.format == "woff2")
+        .map(|face| face.url)
+        .collect()
+}
+
+struct FontFace {
+    url: String,
+    format: &'static str,
+    weight: u16,
+    italic: bool,
+}
+
+/// Each distinct family in `theme.typography` with the files in `fonts_dir` that belong to it.
+fn family_files<'a>(theme: &'a ThemeConfig, fonts_dir: &Path) -> Vec<(&'a str, Vec<FontFace>)> {
+    let typography = &theme.typography;
+    let families: BTreeSet<&str> = [&typography.header, &typography.body, &typography.code]
+        .into_iter()
+        .map(String::as_str)
+        .collect();
+    let files = font_files(fonts_dir);
+
+    families
+        .into_iter()
+        .map(|family| {
+            let key = normalize(family);
+            let faces = files
+                .iter()
+                .filter_map(|(file_name, stem, format)| {
+                    let stem = normalize(stem);
+                    let variant = stem.strip_prefix(&key)?;
+                    let (weight, italic) = parse_variant(variant);
+                    Some(FontFace {
+                        url: format!("{FONTS_URL}/{}", file_name.replace(' ', "%20")),
+                        format,
+                        weight,
+                        italic,
+                    })
+                })
+                .collect();
+            (family, faces)
+        })
+        .collect()
+}
+
+/// `(file name, stem, CSS format)` for each font file directly inside `dir`, sorted by name.
+fn font_files(dir: &Path) -> Vec<(String, String, &'static str)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut files: Vec<_> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+            let (_, format) = FONT_EXTENSIONS.iter().find(|(e, _)| *e == ext)?;
+            let file_name = path.file_name()?.to_str()?.to_string();
+            let stem = path.file_stem()?.to_str()?.to_string();
+            Some((file_name, stem, *format))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Weight and italic flag from the normalized remainder of a font file name.
+fn parse_variant(variant: &str) -> (u16, bool) {
+    let italic = variant.contains("italic") || variant.ends_with("oblique");
+    let rest = variant.replace("italic", "");
+
+    let digits: String = rest.chars().filter(char::is_ascii_digit).collect();
+    if let Ok(weight @ 100..=900) = digits.parse::<u16>() {
+        return (weight, italic);
+    }
+
+    // Compound names first so "semibold" isn't read as "bold".
+    const NAMED: &[(&str, u16)] = &[
+        ("extralight", 200),
+        ("ultralight", 200),
+        ("semibold", 600),
+        ("demibold", 600),
+        ("extrabold", 800),
+        ("ultrabold", 800),
+        ("thin", 100),
+        ("hairline", 100),
+        ("light", 300),
+        ("medium", 500),
+        ("bold", 700),
+        ("black", 900),
+        ("heavy", 900),
+    ];
+    let weight = NAMED
+        .iter()
+        .find(|(name, _)| rest.contains(name))
+        .map(|(_, weight)| *weight)
+        .unwrap_or(400);
+    (weight, italic)
+}
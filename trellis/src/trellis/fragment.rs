@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::trellis::config::SiteConfig;
+use crate::trellis::plugins::frontmatter::parse_frontmatter;
+use crate::trellis::plugins::headings::collect_headings;
+use crate::trellis::plugins::markdown::MarkdownRenderer;
+use crate::trellis::plugins::structure::outline_source_with;
+use crate::trellis::plugins::traits::Transformer;
+use crate::trellis::slugs::SlugStyle;
+use crate::trellis::types::{Heading, Page, PageMetadata, ResolvedLink};
+
+/// How [`render_markdown`] reads a snippet.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// The slug the snippet would have as a note (`notes/hello`); relative links resolve
+    /// against its folder. Empty, the default, is a note at the content root.
+    pub slug: String,
+    /// How internal link targets are slugified, as `content.slugify` and
+    /// `content.transliterate` do for a site. Left as written by default.
+    pub slug_style: SlugStyle,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            slug: String::new(),
+            slug_style: SlugStyle::PASSTHROUGH,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Options matching how the site `config` renders the note at `slug`.
+    pub fn for_site(config: &SiteConfig, slug: impl Into<String>) -> Self {
+        Self {
+            slug: slug.into(),
+            slug_style: SlugStyle::from_config(&config.content),
+        }
+    }
+}
+
+/// A snippet rendered by [`render_markdown`]: what [`RenderedPage`] holds for a note.
+///
+/// [`RenderedPage`]: crate::trellis::types::RenderedPage
+#[derive(Clone, Debug, Serialize)]
+pub struct RenderedFragment {
+    pub html: String,
+    pub frontmatter: PageMetadata,
+    /// Frontmatter keys with no field in [`PageMetadata`].
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub headings: Vec<Heading>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<ResolvedLink>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub excerpt: Option<String>,
+}
+
+/// Render `source`, a note's markdown with or without frontmatter, to the HTML a site
+/// serves for it: frontmatter parsed into metadata, then callouts, emoji shortcodes,
+/// mermaid fences and heading ids, with the headings, links and excerpt pulled out.
+///
+/// Nothing is read from disk, cached or looked up in the process-wide engine, so it is
+/// safe to call from any thread without a site. What needs a site is left out: dates
+/// from git, `style:` files next to the note and secrets from `password_env` or
+/// `password_ref`. Nothing is encrypted either; a note with a `password` comes back in
+/// the clear, its password still in `frontmatter`.
+///
+/// ```
+/// use trellis::{RenderOptions, render_markdown};
+///
+/// let source = "---\ntitle: Hello\n---\n\n# Greeting\n\nSee [[other note]].\n";
+/// let fragment = render_markdown(source, &RenderOptions::default()).unwrap();
+/// assert_eq!(fragment.frontmatter.title.as_deref(), Some("Hello"));
+/// assert_eq!(fragment.headings[0].text, "Greeting");
+/// assert_eq!(fragment.links[0].target, "other note");
+/// assert!(fragment.html.contains("<h1 id=\"greeting\">"));
+/// ```
+pub fn render_markdown(source: &str, opts: &RenderOptions) -> Result<RenderedFragment> {
+    let page = Page::new(opts.slug.clone(), PathBuf::new(), source.to_string());
+    let page = parse_frontmatter(page)?;
+    let mut page = MarkdownRenderer.transform(page)?;
+    outline_source_with(&mut page, opts.slug_style);
+    page.headings = page
+        .html
+        .as_deref()
+        .map(collect_headings)
+        .unwrap_or_default();
+    Ok(RenderedFragment {
+        html: page.html.unwrap_or_default(),
+        frontmatter: page.frontmatter,
+        extra: page.extra,
+        headings: page.headings,
+        links: page.links,
+        excerpt: page.excerpt,
+    })
+}
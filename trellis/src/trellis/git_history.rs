@@ -0,0 +1,228 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{OnceLock, RwLock};
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use log::{debug, info};
+use once_cell::sync::Lazy;
+
+/// When a content file was first and last committed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileDates {
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+}
+
+/// Dates of every committed content file, keyed by path relative to the content root, as
+/// of commit `head`.
+#[derive(Default)]
+struct History {
+    root: Option<PathBuf>,
+    enabled: bool,
+    head: Option<String>,
+    files: HashMap<String, FileDates>,
+}
+
+static HISTORY: Lazy<RwLock<History>> = Lazy::new(Default::default);
+
+/// Told the new head and dates after each [`refresh`] that read the log, to persist them.
+static SINK: OnceLock<Box<dyn Fn(&str, &HashMap<String, FileDates>) + Send + Sync>> =
+    OnceLock::new();
+
+/// Take dates from the git history of `content_root` when `enabled`
+/// (`configuration.dates_from_git`). Set when the engine starts.
+pub fn configure(content_root: &Path, enabled: bool) {
+    let mut history = HISTORY
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if history
+        .root
+        .as_deref()
+        .is_some_and(|root| root != content_root)
+    {
+        history.head = None;
+        history.files.clear();
+    }
+    history.root = Some(content_root.to_path_buf());
+    history.enabled = enabled;
+}
+
+pub fn enabled() -> bool {
+    HISTORY
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .enabled
+}
+
+/// Seed the dates read at `head`, as persisted from an earlier run.
+pub fn load(head: String, files: impl IntoIterator<Item = (String, FileDates)>) {
+    let mut history = HISTORY
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    history.head = Some(head);
+    history.files = files.into_iter().collect();
+}
+
+pub fn set_sink(sink: Box<dyn Fn(&str, &HashMap<String, FileDates>) + Send + Sync>) {
+    let _ = SINK.set(sink);
+}
+
+/// Read the dates again if HEAD has moved since they were last read: one `git log` over
+/// the whole content root, not one per page. Outside a git repository every page keeps
+/// its modification time.
+pub fn refresh() -> Result<()> {
+    let root = {
+        let history = HISTORY
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match (&history.root, history.enabled) {
+            (Some(root), true) => root.clone(),
+            _ => return Ok(()),
+        }
+    };
+    let head = match git(&root, &["rev-parse", "HEAD"]) {
+        Ok(head) => head.trim().to_string(),
+        Err(err) => {
+            debug!("no git dates for {}: {err:#}", root.display());
+            return Ok(());
+        }
+    };
+    let current = HISTORY
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .head
+        .clone();
+    if current.as_deref() == Some(head.as_str()) {
+        return Ok(());
+    }
+
+    let log = git(
+        &root,
+        &[
+            "-c",
+            "core.quotepath=off",
+            "log",
+            "-M",
+            "--relative",
+            "--name-status",
+            "--format=%x1e%aI",
+            "--",
+            ".",
+        ],
+    )?;
+    let files = parse_log(&log);
+    info!(
+        "git dates: {} files as of {}",
+        files.len(),
+        &head[..head.len().min(12)]
+    );
+    if let Some(sink) = SINK.get() {
+        sink(&head, &files);
+    }
+    load(head, files);
+    Ok(())
+}
+
+/// Created and updated dates of the file at `source_path`: from git when it has been
+/// committed, otherwise its modification time. `None` unless `dates_from_git` is on.
+pub fn dates_for(source_path: &Path) -> Option<FileDates> {
+    let history = HISTORY
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if !history.enabled {
+        return None;
+    }
+    let committed = history
+        .root
+        .as_deref()
+        .and_then(|root| source_path.strip_prefix(root).ok())
+        .and_then(|path| {
+            history
+                .files
+                .get(&path.to_string_lossy().replace('\\', "/"))
+        })
+        .copied();
+    committed.or_else(|| {
+        let modified = fs::metadata(source_path)
+            .and_then(|meta| meta.modified())
+            .ok()?;
+        let modified = DateTime::<Utc>::from(modified);
+        Some(FileDates {
+            created: modified,
+            updated: modified,
+        })
+    })
+}
+
+fn git(root: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()
+        .context("running git")?;
+    if !output.status.success() {
+        bail!(
+            "git {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Dates per file from `git log --name-status`, newest commit first. Renames are followed
+/// back to the file's first name, as `git log --follow` would for a single file; history
+/// from before a file was added belongs to an earlier file of the same name and is skipped.
+fn parse_log(log: &str) -> HashMap<String, FileDates> {
+    let mut files: HashMap<String, FileDates> = HashMap::new();
+    // Earlier names of renamed files, mapped to their current name.
+    let mut renamed: HashMap<String, String> = HashMap::new();
+    let mut added: HashSet<String> = HashSet::new();
+
+    for commit in log.split('\x1e') {
+        let mut lines = commit.lines();
+        let Some(date) = lines
+            .next()
+            .and_then(|line| DateTime::parse_from_rfc3339(line.trim()).ok())
+        else {
+            continue;
+        };
+        let date = date.with_timezone(&Utc);
+        for line in lines.filter(|line| !line.is_empty()) {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let (status, old, path) = match fields.as_slice() {
+                [status, path] => (*status, None, *path),
+                [status, old, path] => (*status, Some(*old), *path),
+                _ => continue,
+            };
+            let name = renamed
+                .get(path)
+                .cloned()
+                .unwrap_or_else(|| path.to_string());
+            if added.contains(&name) {
+                continue;
+            }
+            files
+                .entry(name.clone())
+                .and_modify(|dates| dates.created = date)
+                .or_insert(FileDates {
+                    created: date,
+                    updated: date,
+                });
+            match (status.chars().next(), old) {
+                (Some('A' | 'C'), _) => {
+                    added.insert(name);
+                }
+                (Some('R'), Some(old)) => {
+                    renamed.insert(old.to_string(), name);
+                }
+                _ => {}
+            }
+        }
+    }
+    files
+}
@@ -0,0 +1,190 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use log::info;
+use serde::Serialize;
+
+use crate::trellis::cache;
+use crate::trellis::layout::GraphConfig;
+
+/// What the graph needs from one published page.
+#[derive(Debug)]
+pub struct GraphPage {
+    pub slug: String,
+    pub title: String,
+    /// Link targets as `extract_links` reports them.
+    pub links: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct GraphData {
+    nodes: Vec<GraphNode>,
+    links: Vec<GraphLink>,
+}
+
+/// A page or tag. `id` is the simplified slug the client uses (`/` for the home page,
+/// `folder/` for a folder's index, `tags/<tag>` for a tag).
+#[derive(Serialize)]
+struct GraphNode {
+    id: String,
+    title: String,
+    /// Top-level folder, `""` for pages at the root and `tags` for tags.
+    group: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    tag: bool,
+}
+
+#[derive(Serialize)]
+struct GraphLink {
+    source: String,
+    target: String,
+}
+
+/// Write `static/graph.json` next to the content index. Tag nodes are included when either
+/// graph shows tags, minus tags both graphs remove; links to unknown pages are dropped.
+pub fn write_graph_json(static_dir: &Path, pages: &[GraphPage], cfg: &GraphConfig) -> Result<()> {
+    let ids: HashSet<String> = pages.iter().map(|page| node_id(&page.slug)).collect();
+    let views = [&cfg.local, &cfg.global];
+    let shows_tag = |tag: &str| {
+        views
+            .iter()
+            .any(|view| view.show_tags && !view.remove_tags.iter().any(|removed| removed == tag))
+    };
+
+    let mut nodes = Vec::with_capacity(pages.len());
+    let mut tags: BTreeMap<String, String> = BTreeMap::new();
+    let mut links: BTreeSet<(String, String)> = BTreeSet::new();
+    for page in pages {
+        let id = node_id(&page.slug);
+        for link in &page.links {
+            if let Some(target) = link_target(link, &ids) {
+                links.insert((id.clone(), target));
+            }
+        }
+        for tag in page.tags.iter().filter(|tag| shows_tag(tag)) {
+            let tag_id = format!("tags/{tag}");
+            links.insert((id.clone(), tag_id.clone()));
+            tags.entry(tag_id).or_insert_with(|| tag.clone());
+        }
+        nodes.push(GraphNode {
+            group: page
+                .slug
+                .split_once('/')
+                .map_or("", |(folder, _)| folder)
+                .to_string(),
+            id,
+            title: page.title.clone(),
+            tag: false,
+        });
+    }
+    nodes.extend(tags.into_iter().map(|(id, tag)| GraphNode {
+        id,
+        title: tag,
+        group: "tags".into(),
+        tag: true,
+    }));
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let data = GraphData {
+        links: links
+            .into_iter()
+            .map(|(source, target)| GraphLink { source, target })
+            .collect(),
+        nodes,
+    };
+    let json = serde_json::to_string(&data)?;
+    let json_path = static_dir.join("graph.json");
+    cache::write_atomic(&json_path, json.as_bytes(), false)
+        .with_context(|| format!("writing graph data to {}", json_path.display()))?;
+    info!(
+        "graph.json: {} nodes, {} edges, {} KiB",
+        data.nodes.len(),
+        data.links.len(),
+        json.len().div_ceil(1024)
+    );
+    Ok(())
+}
+
+/// Pages nothing links to and pages that link nowhere, each sorted by slug so reports can
+/// be diffed between runs.
+#[derive(Debug, Default, Serialize)]
+pub struct LinkReport {
+    /// No inbound links from another page. The home page and folder indexes are reached
+    /// through navigation and never count.
+    pub orphans: Vec<String>,
+    /// No outbound links to another page.
+    pub dead_ends: Vec<String>,
+}
+
+/// Orphans and dead ends in `pages`. Links to the page itself or to unknown pages are
+/// ignored; with `tag_links`, pages sharing a tag count as linking each other.
+pub fn link_report(pages: &[GraphPage], tag_links: bool) -> LinkReport {
+    let ids: HashSet<String> = pages.iter().map(|page| node_id(&page.slug)).collect();
+    let mut inbound: HashSet<String> = HashSet::new();
+    let mut outbound: HashSet<String> = HashSet::new();
+    for page in pages {
+        let id = node_id(&page.slug);
+        for link in &page.links {
+            if let Some(target) = link_target(link, &ids).filter(|target| *target != id) {
+                outbound.insert(id.clone());
+                inbound.insert(target);
+            }
+        }
+    }
+    if tag_links {
+        let mut tagged: HashMap<&str, BTreeSet<String>> = HashMap::new();
+        for page in pages {
+            for tag in &page.tags {
+                tagged
+                    .entry(tag.as_str())
+                    .or_default()
+                    .insert(node_id(&page.slug));
+            }
+        }
+        for ids in tagged.into_values().filter(|ids| ids.len() > 1) {
+            inbound.extend(ids.iter().cloned());
+            outbound.extend(ids);
+        }
+    }
+
+    let mut report = LinkReport::default();
+    for page in pages {
+        let id = node_id(&page.slug);
+        let is_index = page.slug == "index" || page.slug.ends_with("/index");
+        if !is_index && !inbound.contains(&id) {
+            report.orphans.push(page.slug.clone());
+        }
+        if !outbound.contains(&id) {
+            report.dead_ends.push(page.slug.clone());
+        }
+    }
+    report.orphans.sort();
+    report.dead_ends.sort();
+    report
+}
+
+/// The client's simplified slug: `index` becomes `/` and `folder/index` becomes `folder/`.
+fn node_id(slug: &str) -> String {
+    if slug == "index" {
+        return "/".into();
+    }
+    match slug.strip_suffix("/index") {
+        Some(folder) => format!("{folder}/"),
+        None => slug.to_string(),
+    }
+}
+
+/// The node a link points at, if it is a page in the graph. Links carry no `/index`, so a
+/// folder link matches the folder's index node.
+fn link_target(link: &str, ids: &HashSet<String>) -> Option<String> {
+    if link == "." || link == "index" {
+        return ids.contains("/").then(|| "/".into());
+    }
+    if ids.contains(link) {
+        return Some(link.to_string());
+    }
+    let folder = format!("{link}/");
+    ids.contains(&folder).then_some(folder)
+}
@@ -0,0 +1,9 @@
+/// `text` with the characters HTML gives meaning to escaped, so it is safe both as element
+/// content and inside a quoted attribute value.
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
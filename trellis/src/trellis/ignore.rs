@@ -0,0 +1,155 @@
+use std::path::Path;
+
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Compiled `ignore_patterns`, matched against paths relative to the content root.
+///
+/// Patterns containing a `/` are full-path globs (`**/drafts/**`). Patterns without one
+/// match any path component, gitignore-style: `_*`, `*.excalidraw.md`, and legacy plain
+/// names like `private` (equivalent to `**/private/**`) all keep working. A trailing `/`
+/// names a directory and everything beneath it. A leading `!` re-includes what earlier
+/// patterns ignored; as in gitignore the last matching pattern wins, and nothing inside
+/// an ignored directory comes back, since walks never enter it.
+#[derive(Debug, Clone)]
+pub struct IgnoreMatcher {
+    set: GlobSet,
+    /// Per glob in `set`, in pattern order: whether its pattern starts with `!`.
+    negated: Vec<bool>,
+}
+
+impl IgnoreMatcher {
+    pub fn new(patterns: &[String]) -> Result<Self, globset::Error> {
+        let mut builder = GlobSetBuilder::new();
+        let mut negated = Vec::new();
+        for pattern in patterns {
+            let (negate, globs) = expand(pattern)?;
+            for glob in globs {
+                builder.add(glob);
+                negated.push(negate);
+            }
+        }
+        Ok(Self {
+            set: builder.build()?,
+            negated,
+        })
+    }
+
+    /// Whether `path` (absolute, under `root`) is ignored. Paths outside `root` never are.
+    pub fn is_ignored(&self, path: &Path, root: &Path) -> bool {
+        let Ok(rel) = path.strip_prefix(root) else {
+            return false;
+        };
+        if rel.as_os_str().is_empty() {
+            return false;
+        }
+        self.set
+            .matches(rel)
+            .into_iter()
+            .max()
+            .is_some_and(|last| !self.negated[last])
+    }
+}
+
+impl Default for IgnoreMatcher {
+    fn default() -> Self {
+        Self {
+            set: GlobSet::empty(),
+            negated: Vec::new(),
+        }
+    }
+}
+
+/// Validate a single pattern without building a full matcher.
+pub fn check_pattern(pattern: &str) -> Result<(), globset::Error> {
+    expand(pattern).map(|_| ())
+}
+
+/// The globs for one pattern, and whether it is a `!` negation.
+fn expand(pattern: &str) -> Result<(bool, Vec<Glob>), globset::Error> {
+    let pattern = pattern.trim();
+    let (negated, pattern) = match pattern.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+    let pattern = pattern.trim_start_matches("./");
+    let (dir, pattern) = match pattern.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+    let sources = if pattern.contains('/') {
+        let pattern = pattern.trim_start_matches('/');
+        if dir {
+            vec![pattern.to_string(), format!("{pattern}/**")]
+        } else {
+            vec![pattern.to_string()]
+        }
+    } else {
+        // Match the entry itself (so walks prune it) and anything beneath it.
+        vec![format!("**/{pattern}"), format!("**/{pattern}/**")]
+    };
+
+    let globs = sources
+        .iter()
+        .map(|src| GlobBuilder::new(src).literal_separator(true).build())
+        .collect::<Result<_, _>>()?;
+    Ok((negated, globs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(patterns: &[&str]) -> IgnoreMatcher {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        IgnoreMatcher::new(&patterns).unwrap()
+    }
+
+    fn ignored(matcher: &IgnoreMatcher, rel: &str) -> bool {
+        let root = Path::new("/vault");
+        matcher.is_ignored(&root.join(rel), root)
+    }
+
+    #[test]
+    fn double_star_globs_match_at_any_depth() {
+        let drafts = matcher(&["**/drafts/**"]);
+        assert!(ignored(&drafts, "drafts/idea.md"));
+        assert!(ignored(&drafts, "notes/drafts/idea.md"));
+        assert!(!ignored(&drafts, "notes/drafts.md"));
+
+        let sketches = matcher(&["*.excalidraw.md"]);
+        assert!(ignored(&sketches, "boards/plan.excalidraw.md"));
+        assert!(!ignored(&sketches, "boards/plan.md"));
+    }
+
+    #[test]
+    fn plain_names_match_any_component() {
+        let private = matcher(&["private"]);
+        assert!(ignored(&private, "private"));
+        assert!(ignored(&private, "private/diary.md"));
+        assert!(ignored(&private, "notes/private/diary.md"));
+        assert!(!ignored(&private, "privateer.md"));
+    }
+
+    #[test]
+    fn a_trailing_slash_names_a_directory() {
+        let drafts = matcher(&["drafts/"]);
+        assert!(ignored(&drafts, "drafts"));
+        assert!(ignored(&drafts, "drafts/idea.md"));
+        assert!(ignored(&drafts, "notes/drafts/idea.md"));
+        assert!(!ignored(&drafts, "drafts.md"));
+
+        let archive = matcher(&["/archive/"]);
+        assert!(ignored(&archive, "archive/old.md"));
+        assert!(!ignored(&archive, "notes/archive/old.md"));
+    }
+
+    #[test]
+    fn negation_re_includes_and_the_last_match_wins() {
+        let keep = matcher(&["*.excalidraw.md", "!keep.excalidraw.md"]);
+        assert!(ignored(&keep, "boards/plan.excalidraw.md"));
+        assert!(!ignored(&keep, "boards/keep.excalidraw.md"));
+
+        let overridden = matcher(&["!keep.excalidraw.md", "*.excalidraw.md"]);
+        assert!(ignored(&overridden, "boards/keep.excalidraw.md"));
+    }
+}
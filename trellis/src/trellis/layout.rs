@@ -1,11 +1,18 @@
 use std::collections::BTreeMap;
+use std::fmt;
 
 use confik::Configuration;
-use serde::{Deserialize, Serialize};
+use globset::{GlobBuilder, GlobMatcher};
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::trellis::config::SiteConfig;
+use crate::trellis::config::{D3Config, SiteConfig};
+use crate::trellis::explorer_fns;
+use crate::trellis::types::PageMetadata;
 
-#[derive(Clone, Debug, Serialize)]
+/// A component slot in a page layout. In `config.yml` each entry is written as
+/// `{ type: table-of-contents }`, with a `config:` mapping for variants that take one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case", tag = "type", content = "config")]
 pub enum LayoutComponent {
     Head,
@@ -13,7 +20,7 @@ pub enum LayoutComponent {
     PageTitle,
     Breadcrumbs,
     ArticleTitle,
-    ContentMeta,
+    ContentMeta(ContentMetaConfig),
     TagList,
     Search,
     Darkmode,
@@ -22,13 +29,51 @@ pub enum LayoutComponent {
     Graph,
     TableOfContents,
     Backlinks(BacklinksConfig),
+    RecentNotes(RecentNotesConfig),
+    Subscribe(SubscribeConfig),
+    Comments(CommentsConfig),
+    PrevNext(PrevNextConfig),
+    ConditionalRender(ConditionalRenderConfig),
+    Banner(BannerConfig),
     Spacer,
     Flex(FlexConfig),
     MobileOnly(Box<LayoutComponent>),
     DesktopOnly(Box<LayoutComponent>),
 }
 
-#[derive(Clone, Debug, Serialize)]
+impl LayoutComponent {
+    /// The `type:` this component is written with in `config.yml`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Head => "head",
+            Self::Footer(_) => "footer",
+            Self::PageTitle => "page-title",
+            Self::Breadcrumbs => "breadcrumbs",
+            Self::ArticleTitle => "article-title",
+            Self::ContentMeta(_) => "content-meta",
+            Self::TagList => "tag-list",
+            Self::Search => "search",
+            Self::Darkmode => "darkmode",
+            Self::ReaderMode => "reader-mode",
+            Self::Explorer(_) => "explorer",
+            Self::Graph => "graph",
+            Self::TableOfContents => "table-of-contents",
+            Self::Backlinks(_) => "backlinks",
+            Self::RecentNotes(_) => "recent-notes",
+            Self::Subscribe(_) => "subscribe",
+            Self::Comments(_) => "comments",
+            Self::PrevNext(_) => "prev-next",
+            Self::ConditionalRender(_) => "conditional-render",
+            Self::Banner(_) => "banner",
+            Self::Spacer => "spacer",
+            Self::Flex(_) => "flex",
+            Self::MobileOnly(_) => "mobile-only",
+            Self::DesktopOnly(_) => "desktop-only",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FlexItem {
     pub component: LayoutComponent,
     #[serde(default)]
@@ -45,7 +90,13 @@ pub struct FlexItem {
     pub justify: Option<String>,
 }
 
-#[derive(Clone, Debug, Serialize)]
+/// Whether a `Flex` option can be written into a stylesheet as is: nothing that would end
+/// the declaration, the rule or the `<style>` element.
+pub fn is_safe_css_value(value: &str) -> bool {
+    !value.contains([';', '{', '}', '<', '>', '\\'])
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FlexConfig {
     pub components: Vec<FlexItem>,
     #[serde(default)]
@@ -64,6 +115,23 @@ pub struct LayoutConfig {
     pub explorer: ExplorerConfig,
     #[serde(default)]
     pub backlinks: BacklinksConfig,
+    #[serde(default)]
+    pub toc: TocConfig,
+    #[serde(default)]
+    pub graph: GraphConfig,
+    #[serde(default)]
+    pub breadcrumbs: BreadcrumbsConfig,
+    #[serde(default)]
+    pub search: SearchConfig,
+    /// Replaces the built-in head/header/footer slots when set.
+    #[serde(default)]
+    pub shared: Option<SharedLayout>,
+    /// Replaces the built-in layout for content pages when set.
+    #[serde(default)]
+    pub content: Option<PageLayout>,
+    /// Replaces the built-in layout for list pages (folders, tags) when set.
+    #[serde(default)]
+    pub list: Option<PageLayout>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Configuration, Default)]
@@ -84,9 +152,17 @@ pub struct ExplorerConfig {
     pub map_fn: String,
     #[serde(default = "default_order")]
     pub order: Vec<String>,
+    /// Folder levels in the server-rendered tree that works without JavaScript and in the
+    /// overlay nav; deeper folders link to their index page instead. 0 renders every level.
+    #[serde(default = "default_explorer_max_depth")]
+    pub max_depth: usize,
+    /// Files and folders in the server-rendered tree before the rest are left out; 0
+    /// renders them all.
+    #[serde(default = "default_explorer_max_entries")]
+    pub max_entries: usize,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, Configuration, Default)]
+#[derive(Clone, Debug, Serialize, Deserialize, Configuration)]
 pub struct BacklinksConfig {
     #[serde(default = "default_backlinks_title")]
     pub title: String,
@@ -94,6 +170,417 @@ pub struct BacklinksConfig {
     pub empty_text: String,
     #[serde(default = "default_hide_when_empty")]
     pub hide_when_empty: bool,
+    /// Text around the mentions shown under each linking page's title.
+    #[serde(default)]
+    pub context: BacklinkContext,
+}
+
+impl Default for BacklinksConfig {
+    fn default() -> Self {
+        Self {
+            title: default_backlinks_title(),
+            empty_text: default_no_backlinks_text(),
+            hide_when_empty: default_hide_when_empty(),
+            context: BacklinkContext::default(),
+        }
+    }
+}
+
+/// How many mentions of the current page a backlink quotes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Configuration)]
+#[serde(rename_all = "lowercase")]
+#[confik(forward(serde(rename_all = "lowercase")))]
+pub enum BacklinkContext {
+    /// Titles only.
+    None,
+    /// The first mention, followed by a count of the others.
+    #[default]
+    First,
+    /// Every mention.
+    All,
+}
+
+/// Which facts the line under the article title shows, in this order: date, created,
+/// updated, reading time, word count. Missing facts are skipped with their separator.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ContentMetaConfig {
+    /// The date chosen by `configuration.default_date_type`.
+    #[serde(default = "default_true")]
+    pub show_date: bool,
+    #[serde(default)]
+    pub show_created: bool,
+    #[serde(default)]
+    pub show_updated: bool,
+    #[serde(default = "default_true")]
+    pub show_reading_time: bool,
+    #[serde(default)]
+    pub show_word_count: bool,
+    #[serde(default = "default_content_meta_separator")]
+    pub separator: String,
+    /// chrono format string; month and day names follow `configuration.locale`.
+    #[serde(default = "default_content_meta_date_format")]
+    pub date_format: String,
+}
+
+impl Default for ContentMetaConfig {
+    fn default() -> Self {
+        Self {
+            show_date: true,
+            show_created: false,
+            show_updated: false,
+            show_reading_time: true,
+            show_word_count: false,
+            separator: default_content_meta_separator(),
+            date_format: default_content_meta_date_format(),
+        }
+    }
+}
+
+/// A list of the newest notes, ordered by `configuration.default_date_type`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecentNotesConfig {
+    #[serde(default = "default_recent_notes_title")]
+    pub title: String,
+    #[serde(default = "default_recent_notes_limit")]
+    pub limit: usize,
+    /// Only notes under this folder (a slug prefix such as `blog`).
+    #[serde(default)]
+    pub folder: Option<String>,
+    /// Only notes with this tag.
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Link to the tag page, the folder, or the site root when notes are left out.
+    #[serde(default)]
+    pub show_see_more: bool,
+}
+
+impl Default for RecentNotesConfig {
+    fn default() -> Self {
+        Self {
+            title: default_recent_notes_title(),
+            limit: default_recent_notes_limit(),
+            folder: None,
+            tag: None,
+            show_see_more: false,
+        }
+    }
+}
+
+/// A newsletter sign-up form posting to `/api/subscribe`; shown only while
+/// `newsletter.enabled` is set.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SubscribeConfig {
+    #[serde(default = "default_subscribe_title")]
+    pub title: String,
+    /// Plain text under the title.
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default = "default_subscribe_button")]
+    pub button: String,
+}
+
+impl Default for SubscribeConfig {
+    fn default() -> Self {
+        Self {
+            title: default_subscribe_title(),
+            description: None,
+            button: default_subscribe_button(),
+        }
+    }
+}
+
+/// Order of the notes [`LayoutComponent::PrevNext`] steps through.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrevNextOrder {
+    /// By file name, like the explorer.
+    #[default]
+    Name,
+    /// Oldest first, by `configuration.default_date_type`; undated notes come last.
+    Date,
+}
+
+/// Links to the neighbouring notes in the current note's folder, shown below the article.
+/// Folder index pages are not part of the sequence and show no links.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PrevNextConfig {
+    #[serde(default)]
+    pub order: PrevNextOrder,
+}
+
+/// Where a page's discussion lives: GitHub-backed comment widgets, or this server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentsProvider {
+    /// GitHub Discussions, <https://giscus.app>.
+    #[default]
+    Giscus,
+    /// GitHub issues, <https://utteranc.es>.
+    Utterances,
+    /// Stored in the sqlite database and moderated through `/api/comments`; limits are set
+    /// under `native_comments`.
+    Native,
+}
+
+impl CommentsProvider {
+    /// The client script the widget is loaded from; none for native comments.
+    pub fn script_src(self) -> Option<&'static str> {
+        match self {
+            Self::Giscus => Some("https://giscus.app/client.js"),
+            Self::Utterances => Some("https://utteranc.es/client.js"),
+            Self::Native => None,
+        }
+    }
+}
+
+/// Options for [`LayoutComponent::Comments`]. Shown on content pages only; a page opts out
+/// with `comments: false` in its frontmatter. Layouts without the component still show
+/// native comments on pages with `comments: true`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommentsConfig {
+    #[serde(default)]
+    pub provider: CommentsProvider,
+    /// `owner/name` of the GitHub repository holding the threads; unused by native comments.
+    #[serde(default)]
+    pub repo: String,
+    /// giscus only, from the snippet giscus.app generates.
+    #[serde(default)]
+    pub repo_id: String,
+    /// giscus only: the discussion category, and its id.
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub category_id: String,
+    /// How a page maps to a thread: giscus `data-mapping` or utterances `issue-term`.
+    #[serde(default = "default_comments_mapping")]
+    pub mapping: String,
+    /// giscus only: match threads by exact title hash.
+    #[serde(default)]
+    pub strict: bool,
+    /// giscus only.
+    #[serde(default = "default_true")]
+    pub reactions_enabled: bool,
+    /// giscus only: `top` or `bottom`.
+    #[serde(default = "default_comments_input_position")]
+    pub input_position: String,
+    /// utterances only: label added to created issues.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Widget language; defaults to the language of `configuration.locale`.
+    #[serde(default)]
+    pub lang: Option<String>,
+    /// Widget themes for the light and dark palettes; the provider's own defaults otherwise.
+    #[serde(default)]
+    pub light_theme: Option<String>,
+    #[serde(default)]
+    pub dark_theme: Option<String>,
+}
+
+/// Colour scheme of a [`BannerConfig`], matching the callout palette of the same name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BannerStyle {
+    #[default]
+    Info,
+    Success,
+    Warning,
+    Danger,
+}
+
+/// A site-wide announcement bar, placed in `shared.header`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BannerConfig {
+    /// Dismissals are remembered per id, so changing it shows the banner again.
+    pub id: String,
+    /// Inline markdown; an empty body renders nothing.
+    #[serde(default)]
+    pub markdown: String,
+    #[serde(default = "default_true")]
+    pub dismissible: bool,
+    #[serde(default)]
+    pub style: BannerStyle,
+}
+
+/// Options for [`LayoutComponent::ConditionalRender`]: `component` is rendered only on
+/// pages that match `condition`. Conditions are checked per request, and the component is
+/// removed from the page's layout before it reaches the template.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConditionalRenderConfig {
+    pub condition: RenderCondition,
+    pub component: Box<LayoutComponent>,
+}
+
+/// Which pages a [`ConditionalRenderConfig`] applies to. Every key that is set must match;
+/// an empty condition matches every page.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RenderCondition {
+    /// Glob over the page slug, such as `notes/**`. `*` stays within one path segment.
+    #[serde(default)]
+    pub slug: Option<String>,
+    /// The page carries this tag (compared case-insensitively).
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Frontmatter keys and the values they must equal, such as `{ type: post }`. Custom
+    /// keys count as well as the ones trellis knows.
+    #[serde(default)]
+    pub frontmatter: BTreeMap<String, serde_json::Value>,
+}
+
+impl RenderCondition {
+    pub fn matches(
+        &self,
+        slug: &str,
+        meta: &PageMetadata,
+        extra: &serde_json::Map<String, serde_json::Value>,
+    ) -> bool {
+        let slug_ok = self.slug.as_deref().is_none_or(|pattern| {
+            slug_glob(pattern).is_ok_and(|glob| glob.is_match(slug.trim_matches('/')))
+        });
+        let tag_ok = self.tag.as_deref().is_none_or(|tag| {
+            meta.tags
+                .iter()
+                .flatten()
+                .any(|t| t.eq_ignore_ascii_case(tag))
+        });
+        let frontmatter_ok = self.frontmatter.is_empty() || {
+            let fields = serde_json::to_value(meta).unwrap_or_default();
+            self.frontmatter
+                .iter()
+                .all(|(key, expected)| fields.get(key).or(extra.get(key)) == Some(expected))
+        };
+        slug_ok && tag_ok && frontmatter_ok
+    }
+}
+
+/// The matcher for a `condition.slug` pattern.
+pub fn slug_glob(pattern: &str) -> Result<GlobMatcher, globset::Error> {
+    let pattern = pattern.trim().trim_matches('/');
+    Ok(GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()?
+        .compile_matcher())
+}
+
+/// Options for the [`LayoutComponent::Graph`] slot: the sidebar (local) graph and the
+/// global graph it opens. Keys left out of `local`/`global` keep that view's defaults.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GraphConfig {
+    #[serde(default = "default_graph_title")]
+    pub title: String,
+    #[serde(
+        default = "D3Config::local",
+        deserialize_with = "D3Config::deserialize_local"
+    )]
+    pub local: D3Config,
+    #[serde(
+        default = "D3Config::global",
+        deserialize_with = "D3Config::deserialize_global"
+    )]
+    pub global: D3Config,
+}
+
+impl Default for GraphConfig {
+    fn default() -> Self {
+        Self {
+            title: default_graph_title(),
+            local: D3Config::local(),
+            global: D3Config::global(),
+        }
+    }
+}
+
+// Each view is merged over its own defaults, which confik's per-key merging can't express.
+impl Configuration for GraphConfig {
+    type Builder = Option<Self>;
+}
+
+/// Options for the [`LayoutComponent::TableOfContents`] slot.
+#[derive(Clone, Debug, Serialize, Deserialize, Configuration)]
+pub struct TocConfig {
+    #[serde(default = "default_toc_title")]
+    pub title: String,
+    /// Pages with fewer headings than this render no table of contents.
+    #[serde(default = "default_toc_min_headings")]
+    pub min_headings: usize,
+    /// Heading levels shown, counted from the page's highest one.
+    #[serde(default = "default_toc_max_depth")]
+    pub max_depth: u8,
+    /// Top-level entries shown before the rest go behind "show more"; 0 shows them all.
+    #[serde(default = "default_toc_fold_after")]
+    pub fold_after: usize,
+    #[serde(default)]
+    pub collapse_by_default: bool,
+}
+
+impl Default for TocConfig {
+    fn default() -> Self {
+        Self {
+            title: default_toc_title(),
+            min_headings: default_toc_min_headings(),
+            max_depth: default_toc_max_depth(),
+            fold_after: default_toc_fold_after(),
+            collapse_by_default: false,
+        }
+    }
+}
+
+/// Options for the [`LayoutComponent::Search`] box.
+#[derive(Clone, Debug, Serialize, Deserialize, Configuration)]
+pub struct SearchConfig {
+    #[serde(default = "default_search_placeholder")]
+    pub placeholder: String,
+    /// Keys that open full-screen search, e.g. `/` or `ctrl+k`. `ctrl` also matches ⌘ on
+    /// macOS; modifiers are `ctrl`, `alt` and `shift`.
+    #[serde(default = "default_search_hotkeys")]
+    pub hotkeys: Vec<String>,
+    /// Results shown in the dropdown; the `/search` page shows up to four times as many.
+    #[serde(default = "default_search_limit")]
+    pub limit: usize,
+}
+
+fn default_search_placeholder() -> String {
+    "Search".into()
+}
+
+fn default_search_hotkeys() -> Vec<String> {
+    vec!["/".into(), "ctrl+k".into()]
+}
+
+fn default_search_limit() -> usize {
+    8
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            placeholder: default_search_placeholder(),
+            hotkeys: default_search_hotkeys(),
+            limit: default_search_limit(),
+        }
+    }
+}
+
+/// Options for the [`LayoutComponent::Breadcrumbs`] trail.
+#[derive(Clone, Debug, Serialize, Deserialize, Configuration)]
+pub struct BreadcrumbsConfig {
+    /// Label of the first crumb, linking to the site root.
+    #[serde(default = "default_breadcrumbs_root_label")]
+    pub root_label: String,
+    #[serde(default = "default_breadcrumbs_spacer")]
+    pub spacer: String,
+    /// End the trail with the (unlinked) current page.
+    #[serde(default = "default_true")]
+    pub show_current_page: bool,
+}
+
+impl Default for BreadcrumbsConfig {
+    fn default() -> Self {
+        Self {
+            root_label: default_breadcrumbs_root_label(),
+            spacer: default_breadcrumbs_spacer(),
+            show_current_page: true,
+        }
+    }
 }
 
 fn default_explorer_title() -> String {
@@ -112,15 +599,15 @@ fn default_use_saved_state() -> bool {
     true
 }
 
-fn default_sort_fn() -> String {
-    "(a,b)=>{if((!a.isFolder&&!b.isFolder)||(a.isFolder&&b.isFolder)){return a.displayName.localeCompare(b.displayName,undefined,{numeric:true,sensitivity:'base'});}if(!a.isFolder&&b.isFolder){return 1;}else{return -1;}}".into()
+pub(crate) fn default_sort_fn() -> String {
+    explorer_fns::DEFAULT_SORT.into()
 }
 
-fn default_filter_fn() -> String {
+pub(crate) fn default_filter_fn() -> String {
     "(node)=>node.slugSegment!=='tags'".into()
 }
 
-fn default_map_fn() -> String {
+pub(crate) fn default_map_fn() -> String {
     "(node)=>node".into()
 }
 
@@ -128,6 +615,18 @@ fn default_order() -> Vec<String> {
     vec!["filter".into(), "map".into(), "sort".into()]
 }
 
+fn default_explorer_max_depth() -> usize {
+    4
+}
+
+fn default_explorer_max_entries() -> usize {
+    500
+}
+
+fn default_graph_title() -> String {
+    "Graph".into()
+}
+
 fn default_backlinks_title() -> String {
     "Backlinks".into()
 }
@@ -140,49 +639,229 @@ fn default_hide_when_empty() -> bool {
     true
 }
 
+fn default_comments_mapping() -> String {
+    "pathname".into()
+}
+
+fn default_comments_input_position() -> String {
+    "bottom".into()
+}
+
+fn default_content_meta_separator() -> String {
+    "·".into()
+}
+
+fn default_content_meta_date_format() -> String {
+    "%Y-%m-%d".into()
+}
+
+fn default_recent_notes_title() -> String {
+    "Recent Notes".into()
+}
+
+fn default_subscribe_title() -> String {
+    "Subscribe".into()
+}
+
+fn default_subscribe_button() -> String {
+    "Subscribe".into()
+}
+
+fn default_recent_notes_limit() -> usize {
+    3
+}
+
+fn default_breadcrumbs_root_label() -> String {
+    "Home".into()
+}
+
+fn default_breadcrumbs_spacer() -> String {
+    "❯".into()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_toc_title() -> String {
+    "Table of Contents".into()
+}
+
+fn default_toc_min_headings() -> usize {
+    2
+}
+
+fn default_toc_max_depth() -> u8 {
+    3
+}
+
+fn default_toc_fold_after() -> usize {
+    12
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, Configuration)]
 pub struct FooterConfig {
     #[serde(default = "default_footer_links")]
-    pub links: BTreeMap<String, String>,
+    pub links: FooterLinks,
+    /// Markdown shown under the links (license text, webring badges). Raw HTML inside it
+    /// is escaped; use `html` for markup that must pass through.
+    #[serde(default)]
+    pub markdown: Option<String>,
+    /// Raw HTML inserted verbatim after `markdown`. Opt-in: it is not sanitized.
+    #[serde(default)]
+    pub html: Option<String>,
+    /// Profile links rendered as icons, in the order written.
+    #[serde(default)]
+    pub socials: Vec<SocialLink>,
+    /// Shows the "Created with Trellis vX © year" line.
+    #[serde(default = "default_true")]
+    pub show_credit: bool,
 }
 
-pub fn default_footer_links() -> BTreeMap<String, String> {
-    let mut links = BTreeMap::new();
-    links.insert(
-        "GitHub".into(),
-        "https://github.com/jackyzha0/quartz".into(),
-    );
-    links.insert(
-        "Discord Community".into(),
-        "https://discord.gg/cRFFHYye7t".into(),
-    );
-    links
+/// A footer profile link. `platform` picks the icon (`github`, `mastodon`, `rss`, ...);
+/// unknown platforms get a generic globe.
+#[derive(Clone, Debug, Serialize, Deserialize, Configuration, PartialEq, Eq)]
+pub struct SocialLink {
+    pub platform: String,
+    pub url: String,
+    /// Accessible name; defaults to the platform's display name.
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+/// A footer link as written in `config.yml`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FooterLink {
+    pub text: String,
+    pub href: String,
+}
+
+/// Footer links in display order.
+///
+/// Accepts either a list of `{ text, href }` entries or the older `text: href` mapping;
+/// with the mapping form links appear in the order they are written in the YAML.
+#[derive(Clone, Debug, Default, Serialize, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct FooterLinks(pub Vec<FooterLink>);
+
+impl FooterLinks {
+    pub fn iter(&self) -> std::slice::Iter<'_, FooterLink> {
+        self.0.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<'de> Deserialize<'de> for FooterLinks {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct LinksVisitor;
+
+        impl<'de> Visitor<'de> for LinksVisitor {
+            type Value = FooterLinks;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a list of { text, href } links or a text: href mapping")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut links = Vec::new();
+                while let Some(link) = seq.next_element::<FooterLink>()? {
+                    links.push(link);
+                }
+                Ok(FooterLinks(links))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut links = Vec::new();
+                while let Some((text, href)) = map.next_entry::<String, String>()? {
+                    links.push(FooterLink { text, href });
+                }
+                Ok(FooterLinks(links))
+            }
+
+            fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+                Ok(FooterLinks::default())
+            }
+        }
+
+        deserializer.deserialize_any(LinksVisitor)
+    }
+}
+
+// Replaced wholesale, like layouts: merging two link lists has no sensible order.
+impl Configuration for FooterLinks {
+    type Builder = Option<Self>;
+}
+
+pub fn default_footer_links() -> FooterLinks {
+    FooterLinks(vec![
+        FooterLink {
+            text: "GitHub".into(),
+            href: "https://github.com/jackyzha0/quartz".into(),
+        },
+        FooterLink {
+            text: "Discord Community".into(),
+            href: "https://discord.gg/cRFFHYye7t".into(),
+        },
+    ])
 }
 
 impl Default for FooterConfig {
     fn default() -> Self {
         Self {
             links: default_footer_links(),
+            markdown: None,
+            html: None,
+            socials: Vec::new(),
+            show_credit: true,
         }
     }
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PageLayout {
+    #[serde(default)]
     pub before_body: Vec<LayoutComponent>,
+    #[serde(default)]
     pub left: Vec<LayoutComponent>,
+    #[serde(default)]
     pub right: Vec<LayoutComponent>,
+    /// Below the article, before comments and the footer.
+    #[serde(default)]
+    pub after_body: Vec<LayoutComponent>,
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SharedLayout {
+    #[serde(default = "default_head")]
     pub head: LayoutComponent,
+    #[serde(default)]
     pub header: Vec<LayoutComponent>,
+    #[serde(default = "default_footer")]
     pub footer: LayoutComponent,
     #[serde(default)]
     pub after_body: Vec<LayoutComponent>,
 }
 
+// Layouts are replaced wholesale rather than merged key-by-key across config sources.
+impl Configuration for PageLayout {
+    type Builder = Option<Self>;
+}
+
+impl Configuration for SharedLayout {
+    type Builder = Option<Self>;
+}
+
+fn default_head() -> LayoutComponent {
+    LayoutComponent::Head
+}
+
+fn default_footer() -> LayoutComponent {
+    LayoutComponent::Footer(FooterConfig::default())
+}
+
 fn flex_header_stack(include_reader: bool) -> LayoutComponent {
     let mut components = vec![
         FlexItem {
@@ -226,6 +905,10 @@ fn flex_header_stack(include_reader: bool) -> LayoutComponent {
 }
 
 pub fn shared_layout(cfg: &SiteConfig) -> SharedLayout {
+    if let Some(shared) = &cfg.layout.shared {
+        return shared.clone();
+    }
+
     let footer_cfg = cfg.layout.footer.clone();
     SharedLayout {
         head: LayoutComponent::Head,
@@ -240,7 +923,7 @@ pub fn default_content_page_layout() -> PageLayout {
         before_body: vec![
             LayoutComponent::Breadcrumbs,
             LayoutComponent::ArticleTitle,
-            LayoutComponent::ContentMeta,
+            LayoutComponent::ContentMeta(ContentMetaConfig::default()),
             LayoutComponent::TagList,
         ],
         left: vec![
@@ -254,6 +937,7 @@ pub fn default_content_page_layout() -> PageLayout {
             LayoutComponent::DesktopOnly(Box::new(LayoutComponent::TableOfContents)),
             LayoutComponent::Backlinks(LayoutConfig::default().backlinks.clone()),
         ],
+        after_body: vec![LayoutComponent::PrevNext(PrevNextConfig::default())],
     }
 }
 
@@ -262,7 +946,7 @@ pub fn default_list_page_layout() -> PageLayout {
         before_body: vec![
             LayoutComponent::Breadcrumbs,
             LayoutComponent::ArticleTitle,
-            LayoutComponent::ContentMeta,
+            LayoutComponent::ContentMeta(ContentMetaConfig::default()),
             LayoutComponent::TagList,
         ],
         left: vec![
@@ -272,5 +956,6 @@ pub fn default_list_page_layout() -> PageLayout {
             LayoutComponent::Explorer(LayoutConfig::default().explorer.clone()),
         ],
         right: vec![],
+        after_body: vec![],
     }
 }
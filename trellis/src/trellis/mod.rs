@@ -1,19 +1,85 @@
+pub mod analytics;
+pub mod assets;
+pub mod attachments;
+#[cfg(feature = "bundler")]
 pub mod bundler;
 pub mod cache;
 pub mod config;
 pub mod content_index;
+pub mod csp;
+pub mod display_names;
+pub mod error;
+pub mod explorer_fns;
+pub mod fonts;
+pub mod fragment;
+pub mod git_history;
+pub mod graph_data;
+pub mod html;
+pub mod ignore;
 pub mod layout;
+#[cfg(feature = "bundler")]
+pub mod node_modules;
 pub mod plugins;
 pub mod renderer;
+pub mod search;
+pub mod secrets;
+pub mod slugs;
+pub mod socials;
+pub mod static_files;
 pub mod styles;
+pub mod toc;
 pub mod types;
+pub mod urls;
+pub mod validation;
+#[cfg(feature = "server")]
+pub mod watcher;
 
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use anyhow::Result;
 
 pub use config::SiteConfig;
-pub use renderer::TrellisEngine;
+pub use renderer::{EngineBuilder, TrellisEngine};
+
+static ENGINE: OnceLock<RwLock<Arc<TrellisEngine>>> = OnceLock::new();
+
+fn engine_slot() -> &'static RwLock<Arc<TrellisEngine>> {
+    ENGINE.get_or_init(|| {
+        let engine = TrellisEngine::new(SiteConfig::load()).expect("init quartz engine");
+        RwLock::new(Arc::new(engine))
+    })
+}
+
+/// Snapshot of the active engine. Callers keep a consistent view for the whole request
+/// even if the configuration is reloaded underneath them.
+pub fn trellis_engine() -> Arc<TrellisEngine> {
+    match engine_slot().read() {
+        Ok(guard) => Arc::clone(&guard),
+        Err(poisoned) => Arc::clone(&poisoned.into_inner()),
+    }
+}
+
+/// Rebuild the engine from the current `config.yml` and swap it in.
+/// On error the previous engine keeps serving.
+pub fn reload_engine() -> Result<()> {
+    let config = SiteConfig::try_load()?;
+    let engine = Arc::new(TrellisEngine::new(config)?);
+
+    let mut slot = match engine_slot().write() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    *slot = engine;
+    Ok(())
+}
 
-pub fn trellis_engine() -> &'static TrellisEngine {
-    static ENGINE: OnceLock<TrellisEngine> = OnceLock::new();
-    ENGINE.get_or_init(|| TrellisEngine::new(SiteConfig::load()).expect("init quartz engine"))
+/// Serve `engine` instead of one built from `config.yml`, e.g. one from
+/// [`TrellisEngine::builder`]. The configuration file is not read.
+pub fn install_engine(engine: Arc<TrellisEngine>) {
+    let slot = ENGINE.get_or_init(|| RwLock::new(Arc::clone(&engine)));
+    let mut slot = match slot.write() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    *slot = engine;
 }
@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result, anyhow, bail};
+use log::warn;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+
+/// Export conditions tried, in order, when a package's `exports` map branches.
+const CONDITIONS: &[&str] = &["browser", "import", "module", "default"];
+
+/// Resolves bare imports (`"motion"`, `"@scope/pkg/sub"`) against a `node_modules` tree.
+///
+/// Only ES module entry points are understood: `exports` (string, subpath map, or
+/// condition map), then `module`, then `main`. Packages must be allow-listed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeModules {
+    root: PathBuf,
+    allowed: Vec<String>,
+    warn_bytes: u64,
+}
+
+/// Files already reported as oversized, so each is warned about once per process.
+static WARNED: Lazy<Mutex<HashSet<PathBuf>>> = Lazy::new(Default::default);
+
+impl NodeModules {
+    pub fn new(root: PathBuf, allowed: Vec<String>, warn_kb: u64) -> Self {
+        Self {
+            root,
+            allowed,
+            warn_bytes: warn_kb * 1024,
+        }
+    }
+
+    /// Resolve `spec` as imported from `importer`.
+    pub fn resolve(&self, importer: &Path, spec: &str) -> Result<PathBuf> {
+        let (package, subpath) = split_specifier(spec)
+            .ok_or_else(|| anyhow!("invalid bare import {spec:?} in {}", importer.display()))?;
+
+        if !self.allowed.iter().any(|allowed| allowed == package) {
+            bail!(
+                "{} imports {spec:?}, but package {package:?} is not listed in bundler.allowed_packages",
+                importer.display()
+            );
+        }
+
+        let dir = self.root.join(package);
+        if !dir.is_dir() {
+            bail!(
+                "{} imports {spec:?}, but {} does not exist",
+                importer.display(),
+                dir.display()
+            );
+        }
+
+        let resolved = resolve_in_package(&dir, subpath)
+            .with_context(|| format!("resolving {spec:?} imported by {}", importer.display()))?;
+        self.check_size(&resolved);
+        Ok(resolved)
+    }
+
+    fn check_size(&self, path: &Path) {
+        let Ok(meta) = fs::metadata(path) else {
+            return;
+        };
+        if self.warn_bytes == 0 || meta.len() <= self.warn_bytes {
+            return;
+        }
+        let Ok(mut warned) = WARNED.lock() else {
+            return;
+        };
+        if warned.insert(path.to_path_buf()) {
+            warn!(
+                "bundling {} ({} KiB) from node_modules; consider loading it as an external module",
+                path.display(),
+                meta.len() / 1024
+            );
+        }
+    }
+}
+
+/// A bare specifier: not relative, not absolute, not a URL.
+pub fn is_bare(spec: &str) -> bool {
+    !spec.starts_with('.')
+        && !spec.starts_with('/')
+        && !spec.starts_with("http://")
+        && !spec.starts_with("https://")
+}
+
+/// Split `@scope/pkg/sub/path` into (`@scope/pkg`, `sub/path`).
+fn split_specifier(spec: &str) -> Option<(&str, &str)> {
+    let name_len = if spec.starts_with('@') {
+        let scope_end = spec.find('/')?;
+        spec[scope_end + 1..]
+            .find('/')
+            .map(|i| scope_end + 1 + i)
+            .unwrap_or(spec.len())
+    } else {
+        spec.find('/').unwrap_or(spec.len())
+    };
+    let (package, rest) = spec.split_at(name_len);
+    if package.is_empty() {
+        return None;
+    }
+    Some((package, rest.trim_start_matches('/')))
+}
+
+fn resolve_in_package(dir: &Path, subpath: &str) -> Result<PathBuf> {
+    let manifest_path = dir.join("package.json");
+    let manifest: Value = match fs::read_to_string(&manifest_path) {
+        Ok(text) => serde_json::from_str(&text)
+            .with_context(|| format!("parsing {}", manifest_path.display()))?,
+        Err(_) => Value::Null,
+    };
+
+    let key = if subpath.is_empty() {
+        ".".to_string()
+    } else {
+        format!("./{subpath}")
+    };
+
+    if let Some(exports) = manifest.get("exports") {
+        let target = match exports {
+            Value::Object(map) if map.keys().any(|k| k.starts_with('.')) => {
+                map.get(&key).and_then(pick_condition)
+            }
+            // A bare string or condition map describes the package root only.
+            other if subpath.is_empty() => pick_condition(other),
+            _ => None,
+        };
+        return match target {
+            Some(target) => existing_file(&dir.join(target)),
+            None => bail!("{} does not export {key:?}", manifest_path.display()),
+        };
+    }
+
+    if !subpath.is_empty() {
+        return existing_file(&dir.join(subpath));
+    }
+
+    let entry = ["module", "main"]
+        .iter()
+        .find_map(|field| manifest.get(*field).and_then(Value::as_str))
+        .unwrap_or("index.js");
+    existing_file(&dir.join(entry))
+}
+
+fn pick_condition(value: &Value) -> Option<&str> {
+    match value {
+        Value::String(target) => Some(target),
+        Value::Object(map) => CONDITIONS
+            .iter()
+            .find_map(|condition| map.get(*condition).and_then(pick_condition)),
+        Value::Array(options) => options.iter().find_map(pick_condition),
+        _ => None,
+    }
+}
+
+/// `path` itself, or with `.js`/`.mjs` appended, or its `index.js`.
+fn existing_file(path: &Path) -> Result<PathBuf> {
+    if path.is_file() {
+        return Ok(path.to_path_buf());
+    }
+    let candidates = [
+        path.with_extension("js"),
+        path.with_extension("mjs"),
+        path.join("index.js"),
+        path.join("index.mjs"),
+    ];
+    candidates
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+        .ok_or_else(|| anyhow!("{} not found", path.display()))
+}
@@ -3,6 +3,8 @@ use std::collections::HashMap;
 use once_cell::sync::Lazy;
 use regex::Regex;
 
+use crate::trellis::html::escape_html;
+
 static CALLOUT_RE: Lazy<Regex> = Lazy::new(|| {
     // Allow leading whitespace before the blockquote marker so indented callouts match.
     Regex::new(r"(?i)^\s*>\s*\[\!(?P<kind>[\w-]+)(?:\|(?P<meta>[^\]]+))?\](?P<collapse>[+-]?)(?:\s+(?P<title>.*))?$")
@@ -113,7 +115,7 @@ pub fn rewrite_callouts(input: &str) -> String {
                 classes = class_list.join(" "),
                 kind = kind,
                 fold = data_fold,
-                meta = html_escape(meta),
+                meta = escape_html(meta),
             ));
             output.push_str(r#"<div class="callout-title">"#);
             output.push_str(r#"<div class="callout-icon"></div>"#);
@@ -162,15 +164,8 @@ fn capitalize(s: &str) -> String {
     "Note".to_string()
 }
 
-fn html_escape(s: &str) -> String {
-    s.replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
-        .replace('"', "&quot;")
-        .replace('\'', "&#39;")
-}
-
-fn render_md(src: &str) -> String {
+/// Render a markdown fragment with GFM and raw HTML allowed, as callout titles are.
+pub fn render_md(src: &str) -> String {
     markdown::to_html_with_options(
         src,
         &markdown::Options {
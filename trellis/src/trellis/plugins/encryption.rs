@@ -1,5 +1,9 @@
 use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
+use std::time::SystemTime;
 
 use aes_gcm::{
     Aes256Gcm, KeyInit,
@@ -8,15 +12,20 @@ use aes_gcm::{
         generic_array::{GenericArray, typenum::U12},
     },
 };
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow, bail};
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD as B64;
 use getrandom::fill;
 use once_cell::sync::Lazy;
 use pbkdf2::pbkdf2_hmac_array;
+use regex::Regex;
+use serde::Serialize;
 use sha2::{Digest, Sha256};
 
-use crate::trellis::types::Page;
+use crate::trellis::config::EncryptionConfig;
+use crate::trellis::html::escape_html;
+use crate::trellis::types::{Page, PasswordSpec, count_words};
+use crate::trellis::{attachments, cache};
 
 use super::traits::Transformer;
 
@@ -27,37 +36,113 @@ const NONCE_LEN: usize = 12;
 #[derive(Clone)]
 struct CachedCipher {
     ciphertext_b64: String,
-    salt_b64: String,
     nonce_b64: String,
+    /// `data-salt` (version 1) or `data-keys` (version 2), and `data-version`.
+    key_attrs: String,
+}
+
+/// The content key of a version 2 note, wrapped under one recipient's password.
+#[derive(Serialize)]
+struct WrappedKey {
+    salt: String,
+    nonce: String,
+    key: String,
 }
 
 static ENCRYPT_CACHE: Lazy<Mutex<HashMap<String, CachedCipher>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
 /// Encrypt note bodies when a `password` frontmatter key is present.
-/// The password itself is stripped from the rendered page; only cipher data
-/// is emitted to be decrypted client-side via WebCrypto.
-pub struct EncryptContent;
+/// The password is removed from the page's metadata; only cipher data is
+/// emitted to be decrypted client-side via WebCrypto.
+///
+/// With one password the key is derived from it (`data-version="1"`). With several
+/// (`passwords:`), a random content key encrypts the note and is wrapped once per
+/// password in `data-keys` (`data-version="2"`).
+#[derive(Default)]
+pub struct EncryptContent {
+    attachments: Option<EncryptedAttachments>,
+    rules: EncryptionConfig,
+}
+
+/// Where an encrypted note's `/static/` attachments are read from, and where their
+/// encrypted copies go.
+pub struct EncryptedAttachments {
+    pub static_dirs: Vec<PathBuf>,
+    pub output: PathBuf,
+}
+
+impl EncryptContent {
+    /// Also encrypt the static files an encrypted note links to or embeds, with its key.
+    pub fn with_attachments(attachments: EncryptedAttachments) -> Self {
+        Self {
+            attachments: Some(attachments),
+            ..Self::default()
+        }
+    }
+
+    /// Refuse to publish a page `rules` protect when no password reached it.
+    pub fn with_rules(mut self, rules: EncryptionConfig) -> Self {
+        self.rules = rules;
+        self
+    }
+}
 
 impl Transformer for EncryptContent {
     fn transform(&self, mut page: Page) -> Result<Page> {
-        let Some(password_val) = &page.frontmatter.password else {
+        let protected = self.rules.protects(&page.slug, &page.frontmatter);
+        // Take the passwords out first so no return path leaves them in the metadata.
+        let mut passwords: Vec<String> = page.frontmatter.password.take().into_iter().collect();
+        for spec in page.frontmatter.passwords.take().unwrap_or_default() {
+            match spec {
+                PasswordSpec::Literal(password) => passwords.push(password),
+                // ResolveSecrets runs first; anything left over must not publish the note.
+                _ => bail!("{}: a `passwords` entry was never resolved", page.slug),
+            }
+        }
+        if passwords.is_empty() {
+            if protected {
+                bail!(
+                    "{}: the note is password-protected, but no password reached it",
+                    page.slug
+                );
+            }
             return Ok(page);
-        };
-
-        let password = password_val.as_str();
+        }
+        // Headings, links and the excerpt would give away what the note says.
+        page.headings.clear();
+        page.links.clear();
+        page.excerpt = None;
 
         let Some(plaintext_html) = &page.html else {
             // We expect MarkdownRenderer to run before this transformer.
             return Ok(page);
         };
+        let refs = self
+            .attachments
+            .as_ref()
+            .map(|attachments| attachments.find(plaintext_html))
+            .unwrap_or_default();
 
-        // Backend cache so repeated renders don't re-encrypt unchanged notes.
+        // Backend cache so repeated renders don't re-encrypt unchanged notes. A changed
+        // password list misses it, so a removed recipient's wrapped key is gone for good.
         let mut hasher = Sha256::new();
-        hasher.update(password.as_bytes());
+        for password in &passwords {
+            hasher.update((password.len() as u64).to_le_bytes());
+            hasher.update(password.as_bytes());
+        }
         hasher.update(plaintext_html.as_bytes());
         hasher.update(PBKDF2_ITERATIONS.to_le_bytes());
         hasher.update(b"AES-256-GCM:v1");
+        // An edited attachment needs a fresh encrypted copy even if the note is unchanged.
+        for attachment in &refs {
+            hasher.update(attachment.source.as_os_str().as_encoded_bytes());
+            if let Ok(meta) = fs::metadata(&attachment.source) {
+                hasher.update(meta.len().to_le_bytes());
+                let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                hasher.update(format!("{modified:?}").as_bytes());
+            }
+        }
         let cache_key = format!("{:x}", hasher.finalize());
 
         let cached = ENCRYPT_CACHE
@@ -65,61 +150,87 @@ impl Transformer for EncryptContent {
             .ok()
             .and_then(|map| map.get(&cache_key).cloned());
 
-        let (ciphertext_b64, salt_b64, nonce_b64) = if let Some(hit) = cached {
-            (hit.ciphertext_b64, hit.salt_b64, hit.nonce_b64)
+        let encrypted = if let Some(hit) = cached {
+            hit
         } else {
-            let mut salt = [0u8; SALT_LEN];
-            fill(&mut salt).map_err(|e| anyhow!("random salt failed: {e}"))?;
-
-            let key =
-                pbkdf2_hmac_array::<Sha256, 32>(password.as_bytes(), &salt, PBKDF2_ITERATIONS);
-
-            let mut nonce = [0u8; NONCE_LEN];
-            fill(&mut nonce).map_err(|e| anyhow!("random nonce failed: {e}"))?;
-
-            let cipher = Aes256Gcm::new_from_slice(&key)
-                .map_err(|e| anyhow!("failed to create cipher: {e}"))?;
-
-            let nonce_ga = GenericArray::<u8, U12>::from_slice(&nonce);
-
-            let ciphertext = cipher
-                .encrypt(nonce_ga, plaintext_html.as_bytes())
-                .map_err(|e| anyhow!("encrypting protected note: {e}"))?;
+            let (key, key_attrs) = if let [password] = passwords.as_slice() {
+                let salt = random::<SALT_LEN>("salt")?;
+                let key =
+                    pbkdf2_hmac_array::<Sha256, 32>(password.as_bytes(), &salt, PBKDF2_ITERATIONS);
+                let attrs = format!(r#"data-salt="{}" data-version="1""#, B64.encode(salt));
+                (key, attrs)
+            } else {
+                // Every password unwraps the same random content key.
+                let key = random::<32>("content key")?;
+                let wrapped = passwords
+                    .iter()
+                    .map(|password| {
+                        let salt = random::<SALT_LEN>("salt")?;
+                        let wrap_key = pbkdf2_hmac_array::<Sha256, 32>(
+                            password.as_bytes(),
+                            &salt,
+                            PBKDF2_ITERATIONS,
+                        );
+                        let (nonce, wrapped) = seal(&wrap_key, &key, "content key")?;
+                        Ok(WrappedKey {
+                            salt: B64.encode(salt),
+                            nonce: B64.encode(nonce),
+                            key: B64.encode(wrapped),
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let attrs = format!(
+                    r#"data-keys="{}" data-version="2""#,
+                    escape_html(&serde_json::to_string(&wrapped)?)
+                );
+                (key, attrs)
+            };
 
-            let ciphertext_b64 = B64.encode(ciphertext);
-            let salt_b64 = B64.encode(salt);
-            let nonce_b64 = B64.encode(nonce_ga);
+            let html = match &self.attachments {
+                Some(attachments) if !refs.is_empty() => {
+                    attachments.rewrite(plaintext_html, &refs, &key)?
+                }
+                _ => plaintext_html.clone(),
+            };
+            let (nonce, ciphertext) = seal(&key, html.as_bytes(), "protected note")?;
 
+            let encrypted = CachedCipher {
+                ciphertext_b64: B64.encode(ciphertext),
+                nonce_b64: B64.encode(nonce),
+                key_attrs,
+            };
             if let Ok(mut map) = ENCRYPT_CACHE.lock() {
-                map.insert(
-                    cache_key,
-                    CachedCipher {
-                        ciphertext_b64: ciphertext_b64.clone(),
-                        salt_b64: salt_b64.clone(),
-                        nonce_b64: nonce_b64.clone(),
-                    },
-                );
+                map.insert(cache_key, encrypted.clone());
             }
-
-            (ciphertext_b64, salt_b64, nonce_b64)
+            encrypted
         };
 
-        // Prevent leaking the password into the rendered page.
-        page.frontmatter.password = Some(String::new());
-
         // Preserve an approximate word count for read-time calculations.
-        let word_count = plaintext_html.split_whitespace().count() as u64;
+        let word_count = count_words(&plaintext_html);
         page.frontmatter.word_count = Some(word_count);
         page.frontmatter.encrypted = Some(true);
 
+        // The hint is meant to be read, so it goes out as escaped text next to the field.
+        let hint = page
+            .frontmatter
+            .password_hint
+            .as_deref()
+            .map(|hint| {
+                format!(
+                    "\n        <p class=\"encrypted-note__hint\">Hint: {}</p>",
+                    escape_html(hint)
+                )
+            })
+            .unwrap_or_default();
+
         page.html = Some(format!(
-            r#"<div class="encrypted-note" data-ciphertext="{ciphertext}" data-salt="{salt}" data-nonce="{nonce}" data-iterations="{iterations}" data-algo="AES-256-GCM" data-kdf="PBKDF2-SHA256" data-version="1">
+            r#"<div class="encrypted-note" data-ciphertext="{ciphertext}" {key_attrs} data-nonce="{nonce}" data-iterations="{iterations}" data-algo="AES-256-GCM" data-kdf="PBKDF2-SHA256">
   <div class="encrypted-note__chrome">
     <div class="encrypted-note__status">Protected (AES-256-GCM) · Enter the password to decrypt locally.</div>
     <form class="encrypted-note__form" novalidate>
       <div class="encrypted-note__field">
         <input class="encrypted-note__input" type="password" name="password" autocomplete="current-password" placeholder=" " required />
-        <label class="encrypted-note__label">Password</label>
+        <label class="encrypted-note__label">Password</label>{hint}
       </div>
       <div class="encrypted-note__actions">
         <button type="submit">Decrypt</button>
@@ -129,12 +240,121 @@ impl Transformer for EncryptContent {
   <div class="encrypted-note__decode" aria-live="polite"></div>
   <div class="encrypted-note__body" hidden></div>
 </div>"#,
-            ciphertext = ciphertext_b64,
-            salt = salt_b64,
-            nonce = nonce_b64,
+            ciphertext = encrypted.ciphertext_b64,
+            key_attrs = encrypted.key_attrs,
+            nonce = encrypted.nonce_b64,
             iterations = PBKDF2_ITERATIONS,
         ));
 
         Ok(page)
     }
 }
+
+/// A `src` or `href` in a note's HTML that points at a static file.
+struct AttachmentRef {
+    /// The whole `attr="value"`.
+    span: Range<usize>,
+    attr: String,
+    source: PathBuf,
+}
+
+impl EncryptedAttachments {
+    fn find(&self, html: &str) -> Vec<AttachmentRef> {
+        static ATTR: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r#"\b(src|href)="([^"]*)""#).expect("valid pattern"));
+        ATTR.captures_iter(html)
+            .filter_map(|cap| {
+                let rel = attachments::static_path(&cap[2])?;
+                Some(AttachmentRef {
+                    span: cap.get(0)?.range(),
+                    attr: cap[1].to_string(),
+                    source: attachments::static_source(&self.static_dirs, rel)?,
+                })
+            })
+            .collect()
+    }
+
+    /// `html` with each reference swapped for `data-enc-*` attributes naming the encrypted
+    /// copy, which the client script fetches and decrypts once the note is unlocked.
+    fn rewrite(&self, html: &str, refs: &[AttachmentRef], key: &[u8; 32]) -> Result<String> {
+        let mut out = String::with_capacity(html.len());
+        let mut last = 0;
+        for attachment in refs {
+            let name = self.encrypt_file(&attachment.source, key)?;
+            out.push_str(&html[last..attachment.span.start]);
+            out.push_str(&format!(
+                r#"data-enc-src="/static/{dir}/{name}" data-enc-attr="{attr}" data-enc-type="{mime}""#,
+                dir = attachments::ENCRYPTED_DIR,
+                attr = attachment.attr,
+                mime = mime_type(&attachment.source),
+            ));
+            last = attachment.span.end;
+        }
+        out.push_str(&html[last..]);
+        Ok(out)
+    }
+
+    /// Write `source` encrypted with `key` (nonce, then ciphertext) and return its file
+    /// name. The name hashes key and contents, so an unchanged file is encrypted once.
+    fn encrypt_file(&self, source: &Path, key: &[u8; 32]) -> Result<String> {
+        let bytes =
+            fs::read(source).with_context(|| format!("reading attachment {}", source.display()))?;
+        let mut hasher = Sha256::new();
+        hasher.update(key);
+        hasher.update(&bytes);
+        let name = format!("{:x}.bin", hasher.finalize());
+        let path = self.output.join(&name);
+        if path.is_file() {
+            return Ok(name);
+        }
+
+        let (nonce, ciphertext) = seal(key, &bytes, "attachment")?;
+        let mut data = nonce.to_vec();
+        data.extend_from_slice(&ciphertext);
+        cache::write_atomic(&path, &data, false)
+            .with_context(|| format!("writing encrypted attachment {}", path.display()))?;
+        Ok(name)
+    }
+}
+
+fn random<const N: usize>(what: &str) -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    fill(&mut bytes).map_err(|e| anyhow!("random {what} failed: {e}"))?;
+    Ok(bytes)
+}
+
+/// AES-256-GCM under `key` with a fresh nonce, returned alongside the ciphertext.
+fn seal(key: &[u8; 32], plaintext: &[u8], what: &str) -> Result<([u8; NONCE_LEN], Vec<u8>)> {
+    let nonce = random::<NONCE_LEN>("nonce")?;
+    let cipher =
+        Aes256Gcm::new_from_slice(key).map_err(|e| anyhow!("failed to create cipher: {e}"))?;
+    let ciphertext = cipher
+        .encrypt(GenericArray::<u8, U12>::from_slice(&nonce), plaintext)
+        .map_err(|e| anyhow!("encrypting {what}: {e}"))?;
+    Ok((nonce, ciphertext))
+}
+
+/// Content type for the decrypted blob, so images and media display.
+fn mime_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "txt" | "md" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
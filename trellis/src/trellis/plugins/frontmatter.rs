@@ -1,69 +1,223 @@
 use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path};
 
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
+use log::warn;
 
-use crate::trellis::types::{Page, PageMetadata};
+use crate::trellis::git_history;
+use crate::trellis::types::{Page, PageMetadata, PasswordSpec};
 
-use super::traits::{Filter, Transformer};
+use super::traits::{Decision, Filter, Transformer};
+
+/// Keys parsed into [`PageMetadata`]; everything else goes to [`Page::extra`]. The
+/// password keys must stay listed, or they would reach templates.
+const TYPED_KEYS: &[&str] = &[
+    "title",
+    "description",
+    "created",
+    "updated",
+    "published",
+    "tags",
+    "word_count",
+    "encrypted",
+    "password",
+    "password_env",
+    "password_ref",
+    "passwords",
+    "password_hint",
+    "scripts",
+    "style",
+    "comments",
+    "layout",
+    "template",
+    "draft",
+    "publish",
+    "weight",
+    "hide_nav",
+    "nav",
+];
 
 pub struct FrontMatter;
 
 impl Transformer for FrontMatter {
-    fn transform(&self, mut page: Page) -> Result<Page> {
-        let content = page.content.clone();
-        let mut lines = content.lines();
-
-        let first_line = lines.next();
-        if first_line != Some("---") {
-            return Ok(page);
+    fn transform(&self, page: Page) -> Result<Page> {
+        let mut page = parse_frontmatter(page)?;
+        // `dates_from_git`: missing dates come from the file's commits, or its mtime.
+        if let Some(dates) = git_history::dates_for(&page.source_path) {
+            page.frontmatter.created.get_or_insert(dates.created);
+            page.frontmatter.updated.get_or_insert(dates.updated);
         }
+        Ok(page)
+    }
+}
 
-        let mut fm_lines = vec![];
-        for line in lines.by_ref() {
-            if line.trim() == "---" {
-                break;
-            }
-            fm_lines.push(line);
+/// `page` with its frontmatter parsed into metadata and removed from the content.
+pub(crate) fn parse_frontmatter(mut page: Page) -> Result<Page> {
+    let content = page.content.clone();
+    let mut lines = content.lines();
+
+    let first_line = lines.next();
+    if first_line != Some("---") {
+        return Ok(page);
+    }
+
+    let mut fm_lines = vec![];
+    for line in lines.by_ref() {
+        if line.trim() == "---" {
+            break;
         }
+        fm_lines.push(line);
+    }
 
-        let remainder: String = lines.collect::<Vec<&str>>().join("\n");
-        let yaml_str = fm_lines.join("\n");
+    let remainder: String = lines.collect::<Vec<&str>>().join("\n");
+    let yaml_str = fm_lines.join("\n");
 
-        if !yaml_str.is_empty() {
-            let parsed: HashMap<String, serde_yaml::Value> =
-                serde_yaml::from_str(&yaml_str).context("parsing frontmatter")?;
+    if !yaml_str.is_empty() {
+        let parsed: HashMap<String, serde_yaml::Value> =
+            serde_yaml::from_str(&yaml_str).context("parsing frontmatter")?;
 
-            let mut meta = PageMetadata::default();
+        let mut meta = PageMetadata::default();
 
-            if let Some(serde_yaml::Value::String(title)) = parsed.get("title") {
-                meta.title = Some(title.clone());
-            }
-            if let Some(serde_yaml::Value::String(desc)) = parsed.get("description") {
-                meta.description = Some(desc.clone());
-            }
-            if let Some(created) = parsed.get("created").and_then(as_datetime) {
-                meta.created = Some(created);
-            }
-            if let Some(updated) = parsed.get("updated").and_then(as_datetime) {
-                meta.updated = Some(updated);
-            }
-            if let Some(tags) = parsed.get("tags").and_then(as_string_list) {
-                meta.tags = Some(tags);
-            }
-            if let Some(password) = parsed.get("password").and_then(|v| v.as_str()) {
-                meta.password = Some(password.to_owned());
+        if let Some(serde_yaml::Value::String(title)) = parsed.get("title") {
+            meta.title = Some(title.clone());
+        }
+        if let Some(serde_yaml::Value::String(desc)) = parsed.get("description") {
+            meta.description = Some(desc.clone());
+        }
+        if let Some(created) = parsed.get("created").and_then(as_datetime) {
+            meta.created = Some(created);
+        }
+        if let Some(updated) = parsed.get("updated").and_then(as_datetime) {
+            meta.updated = Some(updated);
+        }
+        if let Some(published) = parsed.get("published").and_then(as_datetime) {
+            meta.published = Some(published);
+        }
+        if let Some(tags) = parsed.get("tags").and_then(as_string_list) {
+            meta.tags = Some(tags);
+        }
+        if let Some(scripts) = parsed.get("scripts").and_then(as_string_list) {
+            meta.scripts = Some(scripts);
+        }
+        if let Some(style) = parsed.get("style").and_then(|v| v.as_str()) {
+            meta.style = page_style(style, &page.source_path);
+        }
+        if let Some(password) = parsed.get("password").and_then(|v| v.as_str()) {
+            meta.password = Some(password.to_owned());
+        }
+        if let Some(name) = parsed.get("password_env").and_then(|v| v.as_str()) {
+            meta.password_env = Some(name.to_owned());
+        }
+        if let Some(name) = parsed.get("password_ref").and_then(|v| v.as_str()) {
+            meta.password_ref = Some(name.to_owned());
+        }
+        if let Some(serde_yaml::Value::Sequence(entries)) = parsed.get("passwords") {
+            meta.passwords = Some(
+                entries
+                    .iter()
+                    .filter_map(|entry| {
+                        let spec = password_spec(entry);
+                        if spec.is_none() {
+                            warn!(
+                                "{}: ignoring a `passwords` entry that is not a string, \
+                                     {{env: NAME}} or {{ref: NAME}}",
+                                page.slug
+                            );
+                        }
+                        spec
+                    })
+                    .collect(),
+            );
+        }
+        if let Some(hint) = parsed.get("password_hint").and_then(|v| v.as_str()) {
+            let hint = hint.trim();
+            if !hint.is_empty() {
+                meta.password_hint = Some(hint.to_owned());
             }
-            if let Some(draft) = parsed.get("draft").and_then(|v| v.as_bool()) {
-                meta.draft = Some(draft);
+        }
+        if let Some(comments) = parsed.get("comments").and_then(|v| v.as_bool()) {
+            meta.comments = Some(comments);
+        }
+        if let Some(layout) = parsed.get("layout").and_then(|v| v.as_str()) {
+            meta.layout = Some(layout.trim().to_owned());
+        }
+        if let Some(template) = parsed.get("template").and_then(|v| v.as_str()) {
+            let template = template.trim();
+            if !template.is_empty() {
+                meta.template = Some(template.to_owned());
             }
-            if let Some(publish) = parsed.get("publish").and_then(|v| v.as_bool()) {
-                meta.publish = Some(publish);
+        }
+        if let Some(draft) = parsed.get("draft").and_then(|v| v.as_bool()) {
+            meta.draft = Some(draft);
+        }
+        if let Some(publish) = parsed.get("publish").and_then(|v| v.as_bool()) {
+            meta.publish = Some(publish);
+        }
+        if let Some(weight) = parsed.get("weight").and_then(|v| v.as_f64()) {
+            meta.weight = Some(weight);
+        }
+        if let Some(hide) = nav_hidden(&parsed) {
+            meta.hide_nav = Some(hide);
+        }
+        page.frontmatter = meta;
+        page.extra = parsed
+            .iter()
+            .filter(|(key, _)| !TYPED_KEYS.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), yaml_to_json(value)))
+            .collect();
+    }
+    page.content = remainder;
+    Ok(page)
+}
+
+/// `hide_nav: true` or `nav: false`; `hide_nav` wins when both are set.
+pub(crate) fn nav_hidden(parsed: &HashMap<String, serde_yaml::Value>) -> Option<bool> {
+    parsed
+        .get("hide_nav")
+        .and_then(|v| v.as_bool())
+        .or_else(|| {
+            parsed
+                .get("nav")
+                .and_then(|v| v.as_bool())
+                .map(|shown| !shown)
+        })
+}
+
+/// `value` as JSON for templates. Mapping keys that are not strings are written out as
+/// YAML scalars, and tags are dropped in favour of the tagged value.
+fn yaml_to_json(value: &serde_yaml::Value) -> serde_json::Value {
+    use serde_yaml::Value;
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Bool(b) => (*b).into(),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                i.into()
+            } else if let Some(u) = n.as_u64() {
+                u.into()
+            } else {
+                n.as_f64()
+                    .and_then(serde_json::Number::from_f64)
+                    .map_or(serde_json::Value::Null, serde_json::Value::Number)
             }
-            page.frontmatter = meta;
         }
-        page.content = remainder;
-        Ok(page)
+        Value::String(s) => s.clone().into(),
+        Value::Sequence(seq) => seq.iter().map(yaml_to_json).collect(),
+        Value::Mapping(map) => map
+            .iter()
+            .map(|(key, value)| {
+                let key = match key {
+                    Value::String(s) => s.clone(),
+                    other => serde_yaml::to_string(other)
+                        .map(|s| s.trim_end().to_string())
+                        .unwrap_or_default(),
+                };
+                (key, yaml_to_json(value))
+            })
+            .collect(),
+        Value::Tagged(tagged) => yaml_to_json(&tagged.value),
     }
 }
 
@@ -76,6 +230,51 @@ fn as_datetime(value: &serde_yaml::Value) -> Option<DateTime<Utc>> {
     }
 }
 
+/// The `style` value as CSS: raw CSS as-is, or the contents of a `.css` file named relative
+/// to the note. Paths may not leave the note's directory, and a snippet without a source
+/// file has none to read from.
+fn page_style(value: &str, source_path: &Path) -> Option<String> {
+    let value = value.trim();
+    let is_path = value.ends_with(".css") && !value.contains('{');
+    if !is_path {
+        return Some(value.to_string()).filter(|css| !css.is_empty());
+    }
+    if source_path.as_os_str().is_empty() {
+        return None;
+    }
+
+    let rel = Path::new(value);
+    if !rel
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        warn!(
+            "ignoring style {value:?} in {}: must be a path next to the note",
+            source_path.display()
+        );
+        return None;
+    }
+    let path = source_path.parent().unwrap_or(Path::new(".")).join(rel);
+    match fs::read_to_string(&path) {
+        Ok(css) => Some(css),
+        Err(err) => {
+            warn!("reading style {}: {err}", path.display());
+            None
+        }
+    }
+}
+
+/// A `passwords:` entry: a password, or `{env: NAME}` / `{ref: NAME}` naming where it is.
+fn password_spec(value: &serde_yaml::Value) -> Option<PasswordSpec> {
+    if let Some(password) = value.as_str() {
+        return Some(PasswordSpec::Literal(password.to_owned()));
+    }
+    let name = |key: &str| value.get(key).and_then(|v| v.as_str()).map(str::to_owned);
+    name("env")
+        .map(PasswordSpec::Env)
+        .or_else(|| name("ref").map(PasswordSpec::Ref))
+}
+
 fn as_string_list(value: &serde_yaml::Value) -> Option<Vec<String>> {
     match value {
         serde_yaml::Value::Sequence(seq) => Some(
@@ -87,10 +286,92 @@ fn as_string_list(value: &serde_yaml::Value) -> Option<Vec<String>> {
     }
 }
 
+/// Drops pages whose frontmatter sets `draft: true`.
 pub struct DraftFilter;
 
 impl Filter for DraftFilter {
-    fn include(&self, page: &Page) -> bool {
-        page.frontmatter.draft.unwrap_or(true)
+    fn decide(&self, page: &Page) -> Decision {
+        exclude_if(page.frontmatter.draft.unwrap_or(false), || "draft".into())
+    }
+}
+
+/// Drops pages whose frontmatter sets `publish: false`.
+pub struct PublishFilter;
+
+impl Filter for PublishFilter {
+    fn decide(&self, page: &Page) -> Decision {
+        exclude_if(page.frontmatter.publish == Some(false), || {
+            "unpublished (publish: false)".into()
+        })
+    }
+}
+
+/// Drops pages whose `published` date is still to come (`content.hide_scheduled`).
+/// Pages are filtered when requested, so one appears by itself once its date passes;
+/// listings catch up when the content index is next rebuilt.
+pub struct ScheduleFilter;
+
+impl Filter for ScheduleFilter {
+    fn decide(&self, page: &Page) -> Decision {
+        match page.frontmatter.published {
+            Some(at) if at > Utc::now() => Decision::Exclude {
+                reason: format!("scheduled for {}", at.to_rfc3339()),
+            },
+            _ => Decision::Include,
+        }
+    }
+}
+
+/// Drops pages carrying any of `content.exclude_tags` (compared case-insensitively).
+pub struct TagFilter {
+    pub tags: Vec<String>,
+}
+
+impl Filter for TagFilter {
+    fn decide(&self, page: &Page) -> Decision {
+        let excluded = page.frontmatter.tags.iter().flatten().find(|tag| {
+            self.tags
+                .iter()
+                .any(|excluded| excluded.eq_ignore_ascii_case(tag))
+        });
+        match excluded {
+            Some(tag) => Decision::Exclude {
+                reason: format!("tagged {tag}"),
+            },
+            None => Decision::Include,
+        }
+    }
+}
+
+fn exclude_if(excluded: bool, reason: impl FnOnce() -> String) -> Decision {
+    if excluded {
+        Decision::Exclude { reason: reason() }
+    } else {
+        Decision::Include
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn page(draft: Option<bool>) -> Page {
+        let mut page = Page::new("note".into(), PathBuf::from("note.md"), String::new());
+        page.frontmatter.draft = draft;
+        page
+    }
+
+    #[test]
+    fn draft_filter_drops_only_drafts() {
+        assert_eq!(DraftFilter.decide(&page(None)), Decision::Include);
+        assert_eq!(
+            DraftFilter.decide(&page(Some(true))),
+            Decision::Exclude {
+                reason: "draft".into()
+            }
+        );
+        assert_eq!(DraftFilter.decide(&page(Some(false))), Decision::Include);
     }
 }
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use crate::trellis::types::Heading;
+
+/// Give every `<h1>`–`<h6>` in rendered markdown an `id` derived from its text, so the
+/// table of contents and `#fragment` links have something to point at.
+///
+/// Ids follow GitHub's scheme: lowercase, spaces become hyphens, other punctuation is
+/// dropped; repeats get `-1`, `-2`, ... Headings that already carry attributes (raw HTML
+/// in the note) are left alone.
+pub fn add_heading_ids(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut rest = html;
+
+    while let Some((start, level)) = find_heading_open(rest) {
+        let open_end = start + "<h1>".len();
+        let close_tag = format!("</h{level}>");
+        let Some(close) = rest[open_end..].find(&close_tag) else {
+            break;
+        };
+        let inner = &rest[open_end..open_end + close];
+
+        let base = slugify(&heading_text(inner));
+        let base = if base.is_empty() {
+            "section".to_string()
+        } else {
+            base
+        };
+        let count = seen.entry(base.clone()).or_insert(0);
+        let id = if *count == 0 {
+            base
+        } else {
+            format!("{base}-{count}")
+        };
+        *count += 1;
+
+        out.push_str(&rest[..start]);
+        out.push_str(&format!("<h{level} id=\"{id}\">{inner}{close_tag}"));
+        rest = &rest[open_end + close + close_tag.len()..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Headings with ids in document order, as [`add_heading_ids`] writes them.
+pub fn collect_headings(html: &str) -> Vec<Heading> {
+    let mut found = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find("<h") {
+        let after = &rest[start + 2..];
+        let depth = match after.as_bytes().first() {
+            Some(b @ b'1'..=b'6') => *b - b'0',
+            _ => {
+                rest = after;
+                continue;
+            }
+        };
+        let Some(id) = after[1..]
+            .strip_prefix(" id=\"")
+            .and_then(|s| s.split_once("\">"))
+            .map(|(id, _)| id)
+        else {
+            rest = after;
+            continue;
+        };
+        let open_len = 1 + " id=\"".len() + id.len() + "\">".len();
+        let close_tag = format!("</h{depth}>");
+        let Some(close) = after[open_len..].find(&close_tag) else {
+            break;
+        };
+        found.push(Heading {
+            depth,
+            text: heading_text(&after[open_len..open_len + close]),
+            id: id.to_string(),
+        });
+        rest = &after[open_len + close + close_tag.len()..];
+    }
+    found
+}
+
+/// Byte offset and level of the next attribute-less `<hN>` tag.
+fn find_heading_open(html: &str) -> Option<(usize, u8)> {
+    let bytes = html.as_bytes();
+    let mut offset = 0;
+    while let Some(pos) = html[offset..].find("<h") {
+        let start = offset + pos;
+        if let [b'<', b'h', level @ b'1'..=b'6', b'>', ..] = &bytes[start..] {
+            return Some((start, *level - b'0'));
+        }
+        offset = start + 2;
+    }
+    None
+}
+
+/// Plain text of a heading's inner HTML, with tags removed and basic entities decoded.
+pub fn heading_text(inner: &str) -> String {
+    let mut text = String::with_capacity(inner.len());
+    let mut in_tag = false;
+    for c in inner.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+fn slugify(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| match c {
+            ' ' | '-' => Some('-'),
+            '_' => Some('_'),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
@@ -4,6 +4,7 @@ use crate::trellis::types::Page;
 
 use super::callouts;
 use super::emojicode;
+use super::headings;
 use super::mermaid;
 use super::traits::Transformer;
 
@@ -29,7 +30,8 @@ impl Transformer for MarkdownRenderer {
             },
         )
         .map_err(|e| anyhow!(e.to_string()))?;
-        page.html = Some(rendered);
+        //  heading ids give the table of contents and fragment links their targets.
+        page.html = Some(headings::add_heading_ids(&rendered));
         Ok(page)
     }
 }
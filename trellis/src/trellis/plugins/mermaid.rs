@@ -1,6 +1,8 @@
 use once_cell::sync::Lazy;
 use regex::Regex;
 
+use crate::trellis::html::escape_html;
+
 /// Rewrite fenced ```mermaid code blocks into the HTML structure Quartz expects so that
 /// the client-side mermaid script can render and expand them.
 pub fn rewrite_mermaid(input: &str) -> String {
@@ -25,7 +27,7 @@ pub fn rewrite_mermaid(input: &str) -> String {
                 body.push('\n');
             }
 
-            let escaped = html_escape(&body);
+            let escaped = escape_html(&body);
             out.push_str(r#"<pre class="mermaid-block">"#);
             out.push_str(
                 r#"<button class="expand-button" aria-label="Expand mermaid diagram" data-view-component="true">"#,
@@ -50,11 +52,3 @@ pub fn rewrite_mermaid(input: &str) -> String {
 
     out
 }
-
-fn html_escape(s: &str) -> String {
-    s.replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
-        .replace('"', "&quot;")
-        .replace('\'', "&#39;")
-}
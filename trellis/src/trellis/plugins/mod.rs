@@ -1,19 +1,28 @@
 pub mod callouts;
+pub mod emojicode;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+#[cfg(not(feature = "encryption"))]
+#[path = "no_encryption.rs"]
 pub mod encryption;
 pub mod frontmatter;
+pub mod headings;
 pub mod markdown;
 pub mod mermaid;
-pub mod emojicode;
+pub mod structure;
 pub mod traits;
 
 use anyhow::Result;
+use tracing::{debug_span, trace_span};
 
+use crate::trellis::error::RenderError;
 use crate::trellis::types::Page;
 
 use self::encryption::EncryptContent;
 use self::frontmatter::FrontMatter;
 use self::markdown::MarkdownRenderer;
-use self::traits::{Filter, Transformer};
+use self::structure::{Structure, outline_source};
+use self::traits::{Decision, Filter, Transformer};
 
 pub struct PluginRegistry {
     transformers: Vec<Box<dyn Transformer>>,
@@ -27,7 +36,8 @@ impl PluginRegistry {
                 // Order matters: FrontMatter must run first so filters can see metadata
                 Box::new(FrontMatter),
                 Box::new(MarkdownRenderer),
-                Box::new(EncryptContent),
+                Box::new(Structure),
+                Box::new(EncryptContent::default()),
             ],
             filters: vec![],
         }
@@ -38,31 +48,87 @@ impl PluginRegistry {
         self
     }
 
+    /// Insert `transformer` right after FrontMatter, ahead of rendering and encryption.
+    pub fn with_early_transformer(mut self, transformer: Box<dyn Transformer>) -> Self {
+        let at = self.transformers.len().min(1);
+        self.transformers.insert(at, transformer);
+        self
+    }
+
+    /// Replace the final EncryptContent step, e.g. with one that also encrypts attachments.
+    pub fn with_encryption(mut self, encrypt: EncryptContent) -> Self {
+        self.transformers.pop();
+        self.transformers.push(Box::new(encrypt));
+        self
+    }
+
     /// Run transformers in order while honoring filters.
     ///
     /// Filters are evaluated after the first transformer (FrontMatter) has
     /// populated metadata/frontmatter so they can read flags like `draft`.
-    /// Returns `Ok(None)` when a filter excludes the page.
-    pub fn transform(&self, mut page: Page) -> Result<Option<Page>> {
+    /// Fails with [`RenderError::Filtered`] when a filter excludes the page.
+    pub fn transform(&self, mut page: Page) -> Result<Page, RenderError> {
+        let _span = debug_span!("transform", slug = %page.slug).entered();
+        let run = |transformer: &dyn Transformer, page: Page| {
+            let _span = trace_span!("plugin", name = transformer.name()).entered();
+            transformer
+                .transform(page)
+                .map_err(|source| RenderError::Transform {
+                    plugin: transformer.name(),
+                    source,
+                })
+        };
         // Run the first transformer (expected to be FrontMatter) before filters
         if let Some((first, rest)) = self.transformers.split_first() {
-            page = first.transform(page)?;
+            page = run(first.as_ref(), page)?;
 
-            if !self.allow(&page) {
-                return Ok(None);
+            let reasons = self.exclusions(&page);
+            if !reasons.is_empty() {
+                return Err(RenderError::Filtered {
+                    reason: reasons.join("; "),
+                });
             }
 
             for transformer in rest {
-                page = transformer.transform(page)?;
+                page = run(transformer.as_ref(), page)?;
             }
         }
 
+        Ok(page)
+    }
+
+    /// Run only the first transformer (FrontMatter) and the filters: the page with its
+    /// metadata, links and excerpt, or `Ok(None)` when a filter excludes it. For indexes
+    /// and listings that need a page's metadata but not its HTML. Encrypted notes keep
+    /// their links and excerpt here; callers check `encryption.protects` themselves.
+    pub fn front_matter(&self, page: Page) -> Result<Option<Page>> {
+        let mut page = match self.transformers.first() {
+            Some(first) => first.transform(page)?,
+            None => page,
+        };
+        if !self.allow(&page) {
+            return Ok(None);
+        }
+        outline_source(&mut page);
         Ok(Some(page))
     }
 
     pub fn allow(&self, page: &Page) -> bool {
-        self.filters.iter().all(|f| f.include(page))
+        self.filters
+            .iter()
+            .all(|f| f.decide(page) == Decision::Include)
+    }
+
+    /// Why each filter that leaves `page` out does so; empty when all include it.
+    pub fn exclusions(&self, page: &Page) -> Vec<String> {
+        self.filters
+            .iter()
+            .filter_map(|f| match f.decide(page) {
+                Decision::Include => None,
+                Decision::Exclude { reason } => Some(reason),
+            })
+            .collect()
     }
 }
 
-pub use frontmatter::DraftFilter;
+pub use frontmatter::{DraftFilter, PublishFilter, ScheduleFilter, TagFilter};
@@ -0,0 +1,93 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+
+use crate::trellis::config::EncryptionConfig;
+use crate::trellis::types::Page;
+
+use super::traits::Transformer;
+
+/// Stands in for the encrypting transformer when the `encryption` feature is off: pages
+/// without a password pass through untouched, and protected ones, by their frontmatter or
+/// the rules, fail to render instead of being published in the clear.
+#[derive(Default)]
+pub struct EncryptContent {
+    rules: EncryptionConfig,
+}
+
+/// Where an encrypted note's `/static/` attachments are read from, and where their
+/// encrypted copies go. Unused without the `encryption` feature.
+pub struct EncryptedAttachments {
+    pub static_dirs: Vec<PathBuf>,
+    pub output: PathBuf,
+}
+
+impl EncryptContent {
+    pub fn with_attachments(_attachments: EncryptedAttachments) -> Self {
+        Self::default()
+    }
+
+    /// Also refuse the pages `rules` protect.
+    pub fn with_rules(mut self, rules: EncryptionConfig) -> Self {
+        self.rules = rules;
+        self
+    }
+}
+
+impl Transformer for EncryptContent {
+    fn transform(&self, page: Page) -> Result<Page> {
+        if self.rules.protects(&page.slug, &page.frontmatter) {
+            bail!(
+                "{}: the note is password-protected, but trellis was built without the \
+                 `encryption` feature",
+                page.slug
+            );
+        }
+        Ok(page)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::trellis::config::EncryptionRule;
+
+    use super::*;
+
+    fn page(slug: &str) -> Page {
+        Page::new(
+            slug.to_string(),
+            PathBuf::from(format!("{slug}.md")),
+            "Hi".into(),
+        )
+    }
+
+    #[test]
+    fn rule_protected_pages_do_not_render() {
+        let encrypt = EncryptContent::default().with_rules(EncryptionConfig {
+            rules: vec![EncryptionRule {
+                prefix: "work/".into(),
+                password_env: Some("WORK_KEY".into()),
+                password_ref: None,
+            }],
+            ..EncryptionConfig::default()
+        });
+        assert!(encrypt.transform(page("work/plans")).is_err());
+        assert!(encrypt.transform(page("notes/hello")).is_ok());
+    }
+
+    #[test]
+    fn a_site_password_protects_every_page() {
+        let encrypt = EncryptContent::default().with_rules(EncryptionConfig {
+            site_password_env: Some("SITE_KEY".into()),
+            ..EncryptionConfig::default()
+        });
+        assert!(encrypt.transform(page("notes/hello")).is_err());
+    }
+
+    #[test]
+    fn frontmatter_passwords_fail_without_rules() {
+        let mut protected = page("diary");
+        protected.frontmatter.password = Some("hunter2".into());
+        assert!(EncryptContent::default().transform(protected).is_err());
+    }
+}
@@ -0,0 +1,55 @@
+use anyhow::Result;
+
+use crate::trellis::content_index::page_links_with;
+use crate::trellis::search::markdown_text;
+use crate::trellis::slugs::{self, SlugStyle};
+use crate::trellis::types::Page;
+
+use super::headings::collect_headings;
+use super::traits::Transformer;
+
+/// Characters of text used as the description when a page has no `description`.
+const EXCERPT_CHARS: usize = 160;
+
+/// Records the structure templates and indexes read off a page: headings from the
+/// rendered HTML, links and the excerpt from the source. Runs after MarkdownRenderer and
+/// before EncryptContent, which clears all three for encrypted notes.
+pub struct Structure;
+
+impl Transformer for Structure {
+    fn transform(&self, mut page: Page) -> Result<Page> {
+        outline_source(&mut page);
+        page.headings = page
+            .html
+            .as_deref()
+            .map(collect_headings)
+            .unwrap_or_default();
+        Ok(page)
+    }
+}
+
+/// Fill `page.links` and `page.excerpt` from its markdown; needs only the frontmatter
+/// parsed, so indexes get them without rendering.
+pub fn outline_source(page: &mut Page) {
+    outline_source_with(page, slugs::style());
+}
+
+/// As [`outline_source`], with internal links slugified in `style` rather than the
+/// engine's.
+pub fn outline_source_with(page: &mut Page, style: SlugStyle) {
+    page.links = page_links_with(&page.content, &page.slug, style);
+    let text = markdown_text(&page.content);
+    page.excerpt = excerpt(&text.split_whitespace().collect::<Vec<_>>().join(" "));
+}
+
+/// The opening of `text`, cut at a word boundary near [`EXCERPT_CHARS`]; `None` if empty.
+pub fn excerpt(text: &str) -> Option<String> {
+    if text.is_empty() {
+        return None;
+    }
+    let Some((cut, _)) = text.char_indices().nth(EXCERPT_CHARS) else {
+        return Some(text.to_string());
+    };
+    let cut = text[..cut].rfind(' ').unwrap_or(cut);
+    Some(format!("{}…", text[..cut].trim_end()))
+}
@@ -4,10 +4,26 @@ use crate::trellis::types::Page;
 
 pub trait Transformer: Send + Sync {
     fn transform(&self, page: Page) -> Result<Page>;
+
+    /// Named in errors from this transformer; the type name by default.
+    fn name(&self) -> &'static str {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
+}
+
+/// What a [`Filter`] makes of one page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Include,
+    /// Left out; `reason` is shown in logs, `trellis check` and dev-mode error pages.
+    Exclude {
+        reason: String,
+    },
 }
 
 pub trait Filter: Send + Sync {
-    fn include(&self, page: &Page) -> bool;
+    fn decide(&self, page: &Page) -> Decision;
 }
 
 pub trait Emitter: Send + Sync {
@@ -1,19 +1,37 @@
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::sync::RwLock;
+use std::time::Instant;
 
-use anyhow::{Context, Result, bail};
-use log::debug;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use tracing::{debug_span, field};
 use walkdir::WalkDir;
 
-use crate::trellis::cache;
-use crate::trellis::config::{SiteConfig, theme_hash};
+use crate::trellis::cache::RenderKey;
+use crate::trellis::config::{ContentFormat, SiteConfig, SlugMode};
+use crate::trellis::error::RenderError;
+use crate::trellis::explorer_fns::ExplorerFns;
+use crate::trellis::html::escape_html;
+use crate::trellis::ignore::IgnoreMatcher;
 use crate::trellis::layout::{
     default_content_page_layout, default_list_page_layout, shared_layout,
 };
-use crate::trellis::plugins::{DraftFilter, PluginRegistry};
-use crate::trellis::types::{Page, RenderedPage, slug_from_path};
+use crate::trellis::plugins::encryption::{EncryptContent, EncryptedAttachments};
+use crate::trellis::plugins::traits::Filter;
+use crate::trellis::plugins::{
+    DraftFilter, PluginRegistry, PublishFilter, ScheduleFilter, TagFilter,
+};
+use crate::trellis::secrets::{ResolveSecrets, Secrets};
+use crate::trellis::slugs::{self, SlugStyle};
+use crate::trellis::types::{
+    Page, PageMetadata, PageOutline, RenderedPage, page_date, slug_from_path,
+};
+use crate::trellis::urls::SiteUrls;
+use crate::trellis::{assets, attachments, cache, git_history, static_files};
 
 pub struct TrellisEngine {
     pub config: SiteConfig,
@@ -23,85 +41,205 @@ pub struct TrellisEngine {
     registry: PluginRegistry,
     content_root: PathBuf,
     cache_root: PathBuf,
+    ignore: IgnoreMatcher,
+    urls: SiteUrls,
+    explorer_fns: ExplorerFns,
+    render_key: RenderKey,
+    /// Slug to source file, for `content.slugify` modes where a slug is not the path.
+    sources: RwLock<HashMap<String, PathBuf>>,
 }
 
 impl TrellisEngine {
+    /// An engine over explicit directories rather than the paths in `config.yml`, which
+    /// resolve against the config file's directory.
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::default()
+    }
+
     pub fn new(config: SiteConfig) -> Result<Self> {
-        let manifest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        let content_root = resolve_path(&manifest, &config.paths.content_root);
-        let cache_root = resolve_path(&manifest, &config.paths.cache_root);
+        let content_root = config.content_root();
+        let cache_root = config.cache_root();
+        info!("Content root: {}", content_root.display());
+        info!("Cache root: {}", cache_root.display());
+        assets::set_templates_root(config.templates_root());
+        assets::extract_defaults()?;
+        slugs::set_style(SlugStyle::from_config(&config.content));
+        git_history::configure(&content_root, config.configuration.dates_from_git);
 
         cache::ensure_cache_root(&cache_root)?;
+        let ignore = IgnoreMatcher::new(&config.configuration.ignore_patterns)
+            .context("compiling configuration.ignore_patterns")?;
+        let urls = SiteUrls::new(config.configuration.base_url.as_deref())?;
+        let secrets = ResolveSecrets::new(
+            Secrets::new(config.secrets_file()),
+            config.encryption.clone(),
+        );
+        if urls.base().is_none() {
+            warn!("configuration.base_url is not set; canonical and OG URLs will be relative");
+        }
 
+        let encrypt = EncryptContent::with_attachments(EncryptedAttachments {
+            static_dirs: config.static_dirs(),
+            output: cache_root.join("static").join(attachments::ENCRYPTED_DIR),
+        })
+        .with_rules(config.encryption.clone());
+        let explorer_fns = ExplorerFns::from_config(&config.layout.explorer);
+        let render_key = RenderKey::new(&config);
+        let mut filters: Vec<Box<dyn Filter>> =
+            vec![Box::new(DraftFilter), Box::new(PublishFilter)];
+        if config.content.hide_scheduled {
+            filters.push(Box::new(ScheduleFilter));
+        }
+        if !config.content.exclude_tags.is_empty() {
+            filters.push(Box::new(TagFilter {
+                tags: config.content.exclude_tags.clone(),
+            }));
+        }
         let shared = shared_layout(&config);
-        let content_layout = default_content_page_layout();
-        let list_layout = default_list_page_layout();
+        let content_layout = config
+            .layout
+            .content
+            .clone()
+            .unwrap_or_else(default_content_page_layout);
+        let list_layout = config
+            .layout
+            .list
+            .clone()
+            .unwrap_or_else(default_list_page_layout);
 
         Ok(Self {
             config,
             shared_layout: shared,
             content_layout,
             list_layout,
-            registry: PluginRegistry::bare_minimum().with_filters(vec![Box::new(DraftFilter)]),
+            registry: PluginRegistry::bare_minimum()
+                .with_filters(filters)
+                .with_early_transformer(Box::new(secrets))
+                .with_encryption(encrypt),
             content_root,
             cache_root,
+            ignore,
+            urls,
+            explorer_fns,
+            render_key,
+            sources: RwLock::default(),
         })
     }
 
-    pub fn render_page(&self, slug: &str) -> Result<RenderedPage> {
+    pub fn render_page(&self, slug: &str) -> Result<RenderedPage, RenderError> {
+        let span = debug_span!(
+            "render_page",
+            slug,
+            cache_hit = field::Empty,
+            bytes = field::Empty,
+            duration_ms = field::Empty,
+        );
+        let _entered = span.enter();
+        let called = Instant::now();
         if self.is_ignored_slug(slug) {
-            return Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("slug {slug} is ignored by configuration"),
-            )
-            .into());
+            return Err(RenderError::Ignored);
         }
         let source_path = self.source_path_for(slug);
         let cache_path = cache::cache_path(&self.cache_root, slug);
-        let styles_root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("templates/assets/styles");
-        let styles_mtime = cache::newest_mtime_with_extension(&styles_root, "scss")
-            .unwrap_or(SystemTime::UNIX_EPOCH);
-        let binary_mtime = cache::binary_mtime();
-        let config_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("config.yml");
-        let config_mtime = fs::metadata(&config_path)
-            .and_then(|m| m.modified())
-            .unwrap_or(SystemTime::UNIX_EPOCH);
-        let theme_hash = theme_hash(&self.config.configuration.theme);
-        let theme_mtime = cache::update_hash_marker(&self.cache_root, "theme", &theme_hash)
-            .unwrap_or(SystemTime::UNIX_EPOCH);
-
-        let use_cache = source_path.exists()
-            && cache_path.exists()
-            && cache::cache_is_fresh(
-                &source_path,
-                &cache_path,
-                &[styles_mtime, binary_mtime, config_mtime, theme_mtime],
-            )?;
-
         let page = self.load_page(slug, &source_path)?;
+        let freshness = cache::check(&self.cache_root, slug, &source_path, &self.render_key)?;
+        if !freshness.is_fresh() {
+            debug!("rendering {slug}: {}", freshness.staleness.reason());
+        }
 
         // Always parse frontmatter (and other metadata) even when reusing cached HTML.
         // We still run the transformer pipeline to populate PageMetadata/frontmatter.
         // If we are using the cache, we overwrite the freshly-rendered HTML with the cached HTML.
-        let Some(mut page) = self.registry.transform(page)? else {
-            bail!("page filtered out by plugins: {slug}");
-        };
+        let started = Instant::now();
+        let mut page = self.registry.transform(page)?;
+        let render_ms = started.elapsed().as_millis() as i64;
 
+        // Encrypted pages were just encrypted again under the passwords resolved now, which
+        // the encryption cache keeps cheap. Their cached copy may predate a rotated
+        // `password_env` or `password_ref`, and would keep the old password working.
+        let encrypted = page.frontmatter.encrypted.unwrap_or(false);
+        let use_cache = freshness.is_fresh() && !encrypted;
         if use_cache {
             page.html = Some(fs::read_to_string(&cache_path)?);
+            // Headings must match the cached HTML; an older cache has no outline, and
+            // then the fresh pipeline's stands in.
+            if let Some(outline) = cache::read_outline(&cache_path) {
+                page.headings = outline.headings;
+                page.links = outline.links;
+                page.excerpt = outline.excerpt;
+            }
+            freshness.refresh_stamp(&self.cache_root, slug);
         }
 
-        let rendered: RenderedPage = page.clone().into();
+        let mut rendered: RenderedPage = page.into();
 
         if !use_cache {
             cache::write_cache(&cache_path, &rendered.html)?;
+            let outline = PageOutline {
+                headings: rendered.headings.clone(),
+                links: rendered.links.clone(),
+                excerpt: rendered.excerpt.clone(),
+            };
+            if let Err(err) = cache::write_outline(&cache_path, &outline) {
+                warn!("caching the outline of {slug}: {err}");
+            }
+            freshness.record_render(&self.cache_root, slug, &self.render_key, render_ms);
         }
 
-        let mut rendered = rendered;
         rendered.cached = Some(use_cache);
+        span.record("cache_hit", use_cache);
+        span.record("bytes", rendered.html.len());
+        span.record("duration_ms", called.elapsed().as_millis() as u64);
         Ok(rendered)
     }
 
+    /// Whether the cached HTML of `slug` is current, and why not when it isn't.
+    pub fn cache_status(&self, slug: &str) -> Result<cache::Freshness> {
+        let source_path = self.source_path_for(slug);
+        Ok(cache::check(
+            &self.cache_root,
+            slug,
+            &source_path,
+            &self.render_key,
+        )?)
+    }
+
+    /// Delete cached HTML, and its index entry, for pages whose source is gone. Returns
+    /// how many were removed.
+    pub fn prune_render_cache(&self) -> usize {
+        let mut removed = 0;
+        for slug in cache::index(&self.cache_root).slugs() {
+            if self.page_exists(&slug) {
+                continue;
+            }
+            match self.drop_cached(&slug) {
+                Ok(()) => removed += 1,
+                Err(err) => warn!("removing the cached HTML of {slug}: {err}"),
+            }
+        }
+        removed
+    }
+
+    /// Remove the cached HTML of `slug`, its outline and its render cache entry.
+    pub fn drop_cached(&self, slug: &str) -> io::Result<()> {
+        let html_path = cache::cache_path(&self.cache_root, slug);
+        for path in [cache::outline_path(&html_path), html_path] {
+            match fs::remove_file(path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        cache::index(&self.cache_root).forget(slug);
+        Ok(())
+    }
+
+    /// The file the note at `slug` is read from, or would be written to: its existing
+    /// source in any content format, otherwise a new file with the first extension.
+    pub fn note_path(&self, slug: &str) -> PathBuf {
+        self.source_path_for(slug)
+    }
+
     /// Check if a source markdown file exists for the given slug.
     /// Cached HTML without a source is treated as missing.
     pub fn page_exists(&self, slug: &str) -> bool {
@@ -112,12 +250,78 @@ impl TrellisEngine {
         source_path.exists()
     }
 
+    /// The configured display/sort date for the page at `slug`; see [`page_date`].
+    pub fn page_date(&self, slug: &str, meta: &PageMetadata) -> Option<DateTime<Utc>> {
+        let file = fs::metadata(self.source_path_for(slug)).ok()?;
+        page_date(meta, &file, self.config.configuration.default_date_type)
+    }
+
+    /// The slug `content.slugify` gives the page once served at `slug`, its raw path, when
+    /// that differs and the page exists; old links are redirected there.
+    pub fn renamed_slug(&self, slug: &str) -> Option<String> {
+        let renamed = slugs::slugify(slug);
+        (renamed != slug && self.page_exists(&renamed)).then_some(renamed)
+    }
+
+    /// Resolve a slug to its source file. Under `passthrough` the slug is the path, tried
+    /// with each configured content extension in order; other modes look it up among the
+    /// files under the content root, rescanning when it is missing or moved.
     fn source_path_for(&self, slug: &str) -> PathBuf {
-        let mut path = self.content_root.join(slug);
-        if path.extension().is_none() {
-            path = path.with_extension("md");
+        if self.config.content.slugify == SlugMode::Passthrough {
+            return self.joined_source_path(slug);
+        }
+        if let Some(path) = self.mapped_source(slug) {
+            return path;
         }
-        path
+        self.scan_sources();
+        self.mapped_source(slug)
+            .unwrap_or_else(|| self.joined_source_path(slug))
+    }
+
+    fn mapped_source(&self, slug: &str) -> Option<PathBuf> {
+        let sources = self
+            .sources
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        sources.get(slug).filter(|path| path.is_file()).cloned()
+    }
+
+    /// Map every content file's slug to its path; on a collision the first file found
+    /// wins, as in [`Self::prebuild_all`].
+    fn scan_sources(&self) {
+        let mut sources = HashMap::new();
+        for entry in WalkDir::new(&self.content_root)
+            .into_iter()
+            .filter_entry(|e| !self.is_ignored_path(e.path()))
+            .filter_map(Result::ok)
+            .filter(|e| e.path().is_file())
+        {
+            if self.config.content.is_content_file(entry.path()) {
+                let slug = slug_from_path(entry.path(), &self.content_root);
+                sources
+                    .entry(slug)
+                    .or_insert_with(|| entry.path().to_path_buf());
+            }
+        }
+        *self
+            .sources
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = sources;
+    }
+
+    /// `slug` joined to the content root: an existing source in any content format,
+    /// otherwise a new file with the first extension.
+    fn joined_source_path(&self, slug: &str) -> PathBuf {
+        let path = self.content_root.join(slug);
+        if path.is_file() && self.config.content.is_content_file(&path) {
+            return path;
+        }
+
+        let content = &self.config.content;
+        content
+            .find_source(&path)
+            .or_else(|| content.candidates(&path).into_iter().next())
+            .unwrap_or(path)
     }
 
     /// Path to the configured cache root (build output).
@@ -130,39 +334,60 @@ impl TrellisEngine {
         &self.content_root
     }
 
-    fn load_page(&self, slug: &str, path: &Path) -> Result<Page> {
+    /// Copy the configured `paths.static_dirs` into `cache_root/static/`, plus
+    /// `paths.fonts_dir` into `static/fonts/` when fonts are self-hosted. Attachments only
+    /// encrypted notes use are left out; those notes serve encrypted copies instead.
+    pub fn sync_static(&self) -> io::Result<()> {
+        let static_root = self.cache_root.join("static");
+        let protected = attachments::protected(&self.cache_root);
+        static_files::sync_static_dirs(&self.config.static_dirs(), &static_root, &protected)?;
+        if self.config.configuration.theme.uses_local_fonts() {
+            static_files::sync_static_dirs(
+                &[self.config.fonts_dir()],
+                &static_root.join("fonts"),
+                &BTreeSet::new(),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn load_page(&self, slug: &str, path: &Path) -> Result<Page, RenderError> {
         if self.is_ignored_slug(slug) {
-            return Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("slug {slug} is ignored by configuration"),
-            )
-            .into());
+            return Err(RenderError::Ignored);
         }
         if path.exists() {
-            let content = fs::read_to_string(path)
-                .with_context(|| format!("reading markdown at {}", path.display()))?;
+            let content = fs::read_to_string(path).map_err(|err| {
+                RenderError::Io(io::Error::new(
+                    err.kind(),
+                    format!("reading markdown at {}: {err}", path.display()),
+                ))
+            })?;
 
             if content.trim().is_empty() {
-                return Err(io::Error::new(
-                    io::ErrorKind::NotFound,
-                    format!("empty markdown for slug {slug}"),
-                )
-                .into());
+                return Err(RenderError::NotFound(format!(
+                    "empty markdown for slug {slug}"
+                )));
+            }
+
+            if self.config.content.format_for(path) == Some(ContentFormat::Plain) {
+                return Ok(plain_text_page(slug, path, &content));
             }
 
             Ok(Page::new(slug.to_string(), path.to_path_buf(), content))
         } else {
-            Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("missing markdown for slug {slug}"),
-            )
-            .into())
+            Err(RenderError::NotFound(format!(
+                "missing markdown for slug {slug}"
+            )))
         }
     }
 
-    /// Pre-render all markdown files under the content root into cache, returning slugs.
+    /// Pre-render all content files under the content root into cache, returning slugs.
     pub fn prebuild_all(&self) -> Result<Vec<String>> {
+        if let Err(err) = git_history::refresh() {
+            warn!("dates from git unavailable: {err:#}");
+        }
         let mut slugs = vec![];
+        let mut sources: HashMap<String, PathBuf> = HashMap::new();
         for entry in WalkDir::new(&self.content_root)
             .into_iter()
             .filter_entry(|e| !self.is_ignored_path(e.path()))
@@ -170,23 +395,29 @@ impl TrellisEngine {
             .filter_map(Result::ok)
             .filter(|e| e.path().is_file())
         {
-            if entry
-                .path()
-                .extension()
-                .map(|ext| ext == "md")
-                .unwrap_or(false)
-            {
+            if self.config.content.is_content_file(entry.path()) {
                 let slug = slug_from_path(entry.path(), &self.content_root);
+                if let Some(existing) = sources.get(&slug) {
+                    warn!(
+                        "slug collision for {slug}: {} and {} (serving {})",
+                        existing.display(),
+                        entry.path().display(),
+                        self.source_path_for(&slug).display()
+                    );
+                    continue;
+                }
+                sources.insert(slug.clone(), entry.path().to_path_buf());
+
                 // Skip filtered pages (e.g., draft notes) during prebuild
                 match self.render_page(&slug) {
                     Ok(_) => slugs.push(slug),
+                    Err(RenderError::Filtered { reason }) => {
+                        debug!("Skipping filtered page {slug}: {reason}");
+                    }
+                    // An empty file has nothing to render, here or when requested.
+                    Err(RenderError::NotFound(what)) => debug!("Skipping {slug}: {what}"),
                     Err(err) => {
-                        // Only log filter-related skips; bubble up real errors
-                        if err.to_string().contains("page filtered out by plugins") {
-                            debug!("Skipping filtered page {slug}");
-                        } else {
-                            return Err(err);
-                        }
+                        return Err(anyhow::Error::new(err).context(format!("rendering {slug}")));
                     }
                 }
             }
@@ -224,13 +455,93 @@ impl TrellisEngine {
     }
 }
 
-fn resolve_path(base: &Path, path: &str) -> PathBuf {
-    let candidate = PathBuf::from(path);
-    if candidate.is_absolute() {
-        candidate
-    } else {
-        base.join(path)
+/// Wrap a plain-text file in a `<pre>` block. CommonMark passes `<pre>` HTML blocks through
+/// untouched (blank lines included), so the markdown pipeline leaves it verbatim.
+fn plain_text_page(slug: &str, path: &Path, content: &str) -> Page {
+    let escaped = escape_html(content)
+        // Keep `:shortcode:` and fence lines literal for the emoji/mermaid rewriters.
+        .replace(':', "&#58;")
+        .replace('`', "&#96;");
+    let body = format!("<pre class=\"plain-text\">{}</pre>\n", escaped.trim_end());
+
+    let mut page = Page::new(slug.to_string(), path.to_path_buf(), body);
+    page.frontmatter.title = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().replace('-', " "));
+    page
+}
+
+/// Sets up a [`TrellisEngine`] from a [`SiteConfig`] and the directories it works in.
+/// The directories replace `config.paths`: static directories default to none, and fonts
+/// are read from `fonts/` in the content root.
+#[derive(Default)]
+pub struct EngineBuilder {
+    config: Option<SiteConfig>,
+    content_root: Option<PathBuf>,
+    cache_root: Option<PathBuf>,
+    templates_root: Option<PathBuf>,
+    static_dirs: Vec<PathBuf>,
+}
+
+impl EngineBuilder {
+    /// Settings other than paths; defaults to [`SiteConfig::default`].
+    pub fn config(mut self, config: SiteConfig) -> Self {
+        self.config = Some(config);
+        self
     }
+
+    pub fn content_root(mut self, path: impl Into<PathBuf>) -> Self {
+        self.content_root = Some(path.into());
+        self
+    }
+
+    pub fn cache_root(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cache_root = Some(path.into());
+        self
+    }
+
+    /// Overrides for the embedded templates; without one the embedded set is used as is.
+    pub fn templates_root(mut self, path: impl Into<PathBuf>) -> Self {
+        self.templates_root = Some(path.into());
+        self
+    }
+
+    pub fn static_dirs(mut self, dirs: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.static_dirs = dirs.into_iter().collect();
+        self
+    }
+
+    pub fn build(self) -> Result<TrellisEngine> {
+        let content_root = self
+            .content_root
+            .context("an engine needs a content root")?;
+        let cache_root = self.cache_root.context("an engine needs a cache root")?;
+        let mut config = self.config.unwrap_or_default();
+        config.paths.fonts_dir = absolute_path(&content_root.join("fonts"))?;
+        config.paths.content_root = absolute_path(&content_root)?;
+        config.paths.cache_root = absolute_path(&cache_root)?;
+        config.paths.templates_root = self
+            .templates_root
+            .as_deref()
+            .map(absolute_path)
+            .transpose()?;
+        config.paths.static_dirs = self
+            .static_dirs
+            .iter()
+            .map(|dir| absolute_path(dir))
+            .collect::<Result<_>>()?;
+        TrellisEngine::new(config)
+    }
+}
+
+/// `path` made absolute against the working directory, so that [`SiteConfig::resolve_path`]
+/// keeps it as given.
+fn absolute_path(path: &Path) -> Result<String> {
+    let path =
+        std::path::absolute(path).with_context(|| format!("resolving {}", path.display()))?;
+    path.to_str()
+        .map(str::to_string)
+        .with_context(|| format!("{} is not valid UTF-8", path.display()))
 }
 
 impl TrellisEngine {
@@ -239,17 +550,25 @@ impl TrellisEngine {
         self.is_ignored_path(&path)
     }
 
-    fn is_ignored_path(&self, path: &Path) -> bool {
-        let patterns = &self.config.configuration.ignore_patterns;
-        let Ok(rel) = path.strip_prefix(&self.content_root) else {
-            return false;
-        };
+    /// Whether `path` (under the content root) matches an ignore pattern.
+    pub fn is_ignored_path(&self, path: &Path) -> bool {
+        self.ignore.is_ignored(path, &self.content_root)
+    }
 
-        rel.components().any(|comp| {
-            comp.as_os_str()
-                .to_str()
-                .map(|s| patterns.iter().any(|p| p == s))
-                .unwrap_or(false)
-        })
+    pub fn ignore_matcher(&self) -> &IgnoreMatcher {
+        &self.ignore
+    }
+
+    /// The plugin pipeline pages render through; its filters decide what is published.
+    pub fn registry(&self) -> &PluginRegistry {
+        &self.registry
+    }
+
+    pub fn urls(&self) -> &SiteUrls {
+        &self.urls
+    }
+
+    pub fn explorer_fns(&self) -> &ExplorerFns {
+        &self.explorer_fns
     }
 }
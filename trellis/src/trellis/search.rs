@@ -0,0 +1,314 @@
+use std::fs;
+use std::path::Path;
+
+use log::debug;
+use markdown::mdast::Node;
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::trellis::config::{ContentConfig, ContentFormat, EncryptionConfig};
+use crate::trellis::html::escape_html;
+use crate::trellis::ignore::IgnoreMatcher;
+use crate::trellis::plugins::PluginRegistry;
+use crate::trellis::types::{Page, slug_from_path};
+
+/// Characters of page text around the first match shown as a result snippet.
+const SNIPPET_CHARS: usize = 180;
+/// Characters kept before the first match, so it is not the snippet's first word.
+const SNIPPET_LEAD: usize = 60;
+/// Query words past this many are ignored.
+const MAX_TERMS: usize = 8;
+
+/// Plain text of every published page, for `/api/search` and the `/search` page.
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    docs: Vec<SearchDocument>,
+}
+
+#[derive(Debug)]
+struct SearchDocument {
+    slug: String,
+    title: String,
+    tags: Vec<String>,
+    text: String,
+    /// ASCII-lowercased copies for matching; byte offsets line up with the originals.
+    title_lower: String,
+    tags_lower: Vec<String>,
+    text_lower: String,
+}
+
+/// One search result. `snippet` is HTML: the page text is escaped and matches are wrapped
+/// in `<mark>`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub slug: String,
+    pub href: String,
+    pub title: String,
+    pub tags: Vec<String>,
+    pub snippet: String,
+}
+
+impl SearchIndex {
+    /// Index the content tree, skipping ignored paths and pages the registry's filters
+    /// exclude. Encrypted notes, by frontmatter or by `encryption` rule, are findable by
+    /// their listed title only; their text and tags never enter the index.
+    pub fn build(
+        content_root: &Path,
+        ignore: &IgnoreMatcher,
+        registry: &PluginRegistry,
+        content_cfg: &ContentConfig,
+        encryption: &EncryptionConfig,
+    ) -> Self {
+        let mut docs = Vec::new();
+        for entry in WalkDir::new(content_root)
+            .into_iter()
+            .filter_entry(|e| !ignore.is_ignored(e.path(), content_root))
+            .filter_map(Result::ok)
+            .filter(|e| e.path().is_file())
+        {
+            let Some(format) = content_cfg.format_for(entry.path()) else {
+                continue;
+            };
+            let slug = slug_from_path(entry.path(), content_root);
+            if slug.starts_with("tags/") {
+                continue;
+            }
+            let content = match fs::read_to_string(entry.path()) {
+                Ok(content) => content,
+                Err(err) => {
+                    debug!("search index skips {}: {err}", entry.path().display());
+                    continue;
+                }
+            };
+            let fallback_title = || slug.rsplit('/').next().unwrap_or(&slug).replace('-', " ");
+
+            let doc = if format == ContentFormat::Plain {
+                if encryption.source_for(&slug).is_some() {
+                    let title = encryption.listed_title(fallback_title());
+                    SearchDocument::new(slug.clone(), title, Vec::new(), "")
+                } else {
+                    SearchDocument::new(slug.clone(), fallback_title(), Vec::new(), &content)
+                }
+            } else {
+                let page = Page::new(slug.clone(), entry.path().to_path_buf(), content);
+                let Ok(Some(page)) = registry.front_matter(page) else {
+                    continue;
+                };
+                let meta = &page.frontmatter;
+                let title = meta.title.clone().unwrap_or_else(fallback_title);
+                if encryption.protects(&slug, meta) {
+                    SearchDocument::new(
+                        slug.clone(),
+                        encryption.listed_title(title),
+                        Vec::new(),
+                        "",
+                    )
+                } else {
+                    SearchDocument::new(
+                        slug.clone(),
+                        title,
+                        meta.tags.clone().unwrap_or_default(),
+                        &markdown_text(&page.content),
+                    )
+                }
+            };
+            docs.push(doc);
+        }
+        docs.sort_by(|a, b| a.slug.cmp(&b.slug));
+        debug!("search index built with {} pages", docs.len());
+        Self { docs }
+    }
+
+    /// Pages containing every word of `query` (ASCII case-insensitive), best first: title
+    /// matches outweigh tag matches, which outweigh matches in the text.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let terms = query_terms(query);
+        if terms.is_empty() || limit == 0 {
+            return Vec::new();
+        }
+
+        let mut scored: Vec<(u32, &SearchDocument)> = self
+            .docs
+            .iter()
+            .filter_map(|doc| Some((doc.score(&terms)?, doc)))
+            .collect();
+        scored.sort_by(|(a_score, a), (b_score, b)| {
+            b_score.cmp(a_score).then_with(|| a.title.cmp(&b.title))
+        });
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, doc)| doc.hit(&terms))
+            .collect()
+    }
+}
+
+impl SearchDocument {
+    fn new(slug: String, title: String, tags: Vec<String>, text: &str) -> Self {
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        Self {
+            title_lower: title.to_ascii_lowercase(),
+            tags_lower: tags.iter().map(|tag| tag.to_ascii_lowercase()).collect(),
+            text_lower: text.to_ascii_lowercase(),
+            slug,
+            title,
+            tags,
+            text,
+        }
+    }
+
+    fn score(&self, terms: &[String]) -> Option<u32> {
+        let mut score = 0;
+        for term in terms {
+            let in_title = self.title_lower.contains(term.as_str());
+            let in_tags = self
+                .tags_lower
+                .iter()
+                .any(|tag| tag.contains(term.as_str()));
+            let in_text = self.text_lower.matches(term.as_str()).take(5).count() as u32;
+            if !in_title && !in_tags && in_text == 0 {
+                return None;
+            }
+            score += u32::from(in_title) * 10 + u32::from(in_tags) * 6 + in_text;
+        }
+        Some(score)
+    }
+
+    fn hit(&self, terms: &[String]) -> SearchHit {
+        SearchHit {
+            href: if self.slug == "index" {
+                "/".into()
+            } else {
+                format!("/{}", self.slug)
+            },
+            slug: self.slug.clone(),
+            title: self.title.clone(),
+            tags: self.tags.clone(),
+            snippet: self.snippet(terms),
+        }
+    }
+
+    /// About [`SNIPPET_CHARS`] of text starting a little before the first match.
+    fn snippet(&self, terms: &[String]) -> String {
+        let first = terms
+            .iter()
+            .filter_map(|term| self.text_lower.find(term.as_str()))
+            .min()
+            .unwrap_or(0);
+        let mut start = floor_char_boundary(&self.text, first.saturating_sub(SNIPPET_LEAD));
+        // Start on a word rather than partway through one.
+        if start > 0 {
+            if let Some(space) = self.text[start..first].find(' ') {
+                start += space + 1;
+            }
+        }
+        let end = self.text[start..]
+            .char_indices()
+            .nth(SNIPPET_CHARS)
+            .map_or(self.text.len(), |(offset, _)| start + offset);
+
+        let mut snippet = String::new();
+        if start > 0 {
+            snippet.push('…');
+        }
+        snippet.push_str(&highlight(
+            &self.text[start..end],
+            &self.text_lower[start..end],
+            terms,
+        ));
+        if end < self.text.len() {
+            snippet.push('…');
+        }
+        snippet
+    }
+}
+
+/// Lowercased, deduplicated query words.
+fn query_terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for word in query.split_whitespace().take(MAX_TERMS) {
+        let term = word.to_ascii_lowercase();
+        if !terms.contains(&term) {
+            terms.push(term);
+        }
+    }
+    terms
+}
+
+/// `text` HTML-escaped with every occurrence of a term wrapped in `<mark>`. `lower` is
+/// `text` ASCII-lowercased, so offsets found in it are valid in `text`.
+fn highlight(text: &str, lower: &str, terms: &[String]) -> String {
+    let mut out = String::new();
+    let mut pos = 0;
+    while pos < text.len() {
+        let next = terms
+            .iter()
+            .filter_map(|term| {
+                let at = lower[pos..].find(term.as_str())?;
+                Some((pos + at, term.len()))
+            })
+            // Earliest match; the longest term wins a tie.
+            .min_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+        let Some((at, len)) = next else {
+            break;
+        };
+        out.push_str(&escape_html(&text[pos..at]));
+        out.push_str("<mark>");
+        out.push_str(&escape_html(&text[at..at + len]));
+        out.push_str("</mark>");
+        pos = at + len;
+    }
+    out.push_str(&escape_html(&text[pos..]));
+    out
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// The readable text of a markdown document: prose, inline code and code blocks, without
+/// frontmatter, raw HTML or link targets.
+pub fn markdown_text(source: &str) -> String {
+    let options = markdown::ParseOptions {
+        constructs: markdown::Constructs {
+            frontmatter: true,
+            ..markdown::Constructs::gfm()
+        },
+        ..markdown::ParseOptions::gfm()
+    };
+    let Ok(tree) = markdown::to_mdast(source, &options) else {
+        return String::new();
+    };
+    let mut text = String::new();
+    collect_text(&tree, &mut text);
+    text
+}
+
+fn collect_text(node: &Node, out: &mut String) {
+    match node {
+        Node::Text(text) => out.push_str(&text.value),
+        Node::InlineCode(code) => out.push_str(&code.value),
+        Node::Code(code) => out.push_str(&code.value),
+        Node::Yaml(_) | Node::Toml(_) | Node::Html(_) => {}
+        _ => {
+            for child in node.children().into_iter().flatten() {
+                collect_text(child, out);
+            }
+        }
+    }
+    // Keep words from adjacent blocks (paragraphs, list items, cells) apart.
+    if matches!(
+        node,
+        Node::Paragraph(_)
+            | Node::Heading(_)
+            | Node::Code(_)
+            | Node::ListItem(_)
+            | Node::TableCell(_)
+            | Node::Break(_)
+    ) {
+        out.push(' ');
+    }
+}
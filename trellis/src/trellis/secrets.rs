@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use crate::trellis::config::{EncryptionConfig, PasswordSource};
+use crate::trellis::plugins::traits::Transformer;
+use crate::trellis::types::{Page, PasswordSpec};
+
+/// Out-of-band passwords for encrypted notes.
+///
+/// Frontmatter names a secret instead of containing it: `password_env: NOTE_KEY` reads an
+/// environment variable, `password_ref: work-notes` looks the name up in the configured
+/// `secrets.yml` (a flat `name: password` mapping kept outside the content root). The file
+/// is read on each lookup so edits apply without a restart.
+#[derive(Debug, Clone, Default)]
+pub struct Secrets {
+    file: Option<PathBuf>,
+}
+
+impl Secrets {
+    pub fn new(file: Option<PathBuf>) -> Self {
+        Self { file }
+    }
+
+    pub fn from_env(&self, name: &str) -> Result<String> {
+        match std::env::var(name) {
+            Ok(value) if !value.is_empty() => Ok(value),
+            _ => bail!("password_env {name} is not set"),
+        }
+    }
+
+    pub fn from_file(&self, name: &str) -> Result<String> {
+        let Some(path) = &self.file else {
+            bail!("password_ref {name} used but paths.secrets_file is not configured");
+        };
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("reading secrets file {}", path.display()))?;
+        let secrets: BTreeMap<String, String> = serde_yaml::from_str(&contents)
+            .with_context(|| format!("parsing secrets file {}", path.display()))?;
+        secrets
+            .get(name)
+            .filter(|value| !value.is_empty())
+            .cloned()
+            .ok_or_else(|| anyhow!("password_ref {name} not found in {}", path.display()))
+    }
+}
+
+/// Resolves `password_env` / `password_ref` into the page password ahead of
+/// [`EncryptContent`](super::plugins::encryption::EncryptContent). Pages whose frontmatter
+/// names no password take one from the `encryption` rules. A reference that can't be
+/// resolved fails the render rather than publishing the note unencrypted.
+pub struct ResolveSecrets {
+    secrets: Secrets,
+    encryption: EncryptionConfig,
+}
+
+impl ResolveSecrets {
+    pub fn new(secrets: Secrets, encryption: EncryptionConfig) -> Self {
+        Self {
+            secrets,
+            encryption,
+        }
+    }
+}
+
+impl Transformer for ResolveSecrets {
+    fn transform(&self, mut page: Page) -> Result<Page> {
+        if let Some(specs) = page.frontmatter.passwords.take() {
+            if specs.is_empty() {
+                bail!(
+                    "encrypting {}: `passwords` lists no usable password",
+                    page.slug
+                );
+            }
+            let resolved = specs
+                .into_iter()
+                .map(|spec| match spec {
+                    PasswordSpec::Env(name) => {
+                        self.secrets.from_env(&name).map(PasswordSpec::Literal)
+                    }
+                    PasswordSpec::Ref(name) => {
+                        self.secrets.from_file(&name).map(PasswordSpec::Literal)
+                    }
+                    literal => Ok(literal),
+                })
+                .collect::<Result<Vec<_>>>()
+                .with_context(|| format!("encrypting {}", page.slug))?;
+            page.frontmatter.passwords = Some(resolved);
+        }
+
+        let meta = &page.frontmatter;
+        let password = if let Some(name) = &meta.password_ref {
+            self.secrets.from_file(name)
+        } else if let Some(name) = &meta.password_env {
+            self.secrets.from_env(name)
+        } else if meta.password.is_some() || meta.passwords.is_some() {
+            return Ok(page);
+        } else {
+            match self.encryption.source_for(&page.slug) {
+                Some(PasswordSource::Ref(name)) => self.secrets.from_file(name),
+                Some(PasswordSource::Env(name)) => self.secrets.from_env(name),
+                None => return Ok(page),
+            }
+        };
+
+        let password = password.with_context(|| format!("encrypting {}", page.slug))?;
+        page.frontmatter.password = Some(password);
+        Ok(page)
+    }
+}
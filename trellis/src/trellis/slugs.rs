@@ -0,0 +1,143 @@
+use std::path::Path;
+use std::sync::RwLock;
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::trellis::config::{ContentConfig, SlugMode};
+
+/// How [`slugify`] shapes paths: `content.slugify` and `content.transliterate`, set when the
+/// engine starts so link resolution and indexes agree with the served URLs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlugStyle {
+    pub mode: SlugMode,
+    pub transliterate: bool,
+}
+
+impl SlugStyle {
+    pub const PASSTHROUGH: Self = Self {
+        mode: SlugMode::Passthrough,
+        transliterate: false,
+    };
+
+    pub fn from_config(content: &ContentConfig) -> Self {
+        Self {
+            mode: content.slugify,
+            transliterate: content.transliterate,
+        }
+    }
+}
+
+/// Longest slug [`validate`] accepts.
+const MAX_SLUG_LEN: usize = 512;
+
+static STYLE: RwLock<SlugStyle> = RwLock::new(SlugStyle::PASSTHROUGH);
+
+pub fn set_style(style: SlugStyle) {
+    *STYLE
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = style;
+}
+
+pub fn style() -> SlugStyle {
+    *STYLE
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// `path`, a `/`-separated note path without its extension, as a slug in the configured
+/// style. Slugs come back unchanged, so it is safe to apply twice.
+pub fn slugify(path: &str) -> String {
+    slugify_with(path, style())
+}
+
+pub fn slugify_with(path: &str, style: SlugStyle) -> String {
+    if style.mode == SlugMode::Passthrough {
+        return path.to_string();
+    }
+    path.split('/')
+        .map(|segment| slugify_segment(segment, style))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Check a slug given for a new or rewritten note, over the notes API or `trellis new`: `/`-separated segments of letters, digits, spaces,
+/// `-`, `_` and `.`. No segment may be empty or start with a dot, which rules out `..`
+/// and hidden files, and the slug must leave off the file extension.
+pub fn validate(slug: &str, content: &ContentConfig) -> Result<(), String> {
+    if slug.is_empty() || slug.len() > MAX_SLUG_LEN {
+        return Err(format!("slugs are 1-{MAX_SLUG_LEN} bytes"));
+    }
+    let segments_ok = slug.split('/').all(|segment| {
+        !segment.is_empty()
+            && !segment.starts_with('.')
+            && segment.trim() == segment
+            && segment
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ' '))
+    });
+    if !segments_ok {
+        return Err(
+            "slug segments are letters, digits, spaces, '-', '_' or '.', and may not be \
+             empty or start with '.'"
+                .into(),
+        );
+    }
+    if content.is_content_file(Path::new(slug)) {
+        return Err("leave the file extension off the slug".into());
+    }
+    Ok(())
+}
+
+fn slugify_segment(segment: &str, style: SlugStyle) -> String {
+    if matches!(segment, "" | "." | "..") {
+        return segment.to_string();
+    }
+    let normalized: String = segment.nfc().collect();
+    let text = if style.transliterate {
+        deunicode::deunicode(&normalized)
+    } else {
+        normalized
+    };
+    let slug = match style.mode {
+        SlugMode::Passthrough => text.clone(),
+        SlugMode::Obsidian => obsidian(&text),
+        SlugMode::LowerDash => lower_dash(&text),
+    };
+    // A name of nothing but punctuation would otherwise vanish from the path.
+    if slug.is_empty() { text } else { slug }
+}
+
+/// As Obsidian Publish and Quartz form URLs: case is kept, whitespace becomes `-`, `&`
+/// becomes `-and-`, `%` becomes `-percent`, and `?` and `#` are dropped.
+fn obsidian(segment: &str) -> String {
+    let mut slug = String::with_capacity(segment.len());
+    for c in segment.chars() {
+        match c {
+            c if c.is_whitespace() => slug.push('-'),
+            '&' => slug.push_str("-and-"),
+            '%' => slug.push_str("-percent"),
+            '?' | '#' => {}
+            c => slug.push(c),
+        }
+    }
+    slug
+}
+
+/// Lowercase letters, digits and dots; each run of whitespace, `_` and `-` becomes one
+/// `-`, and other punctuation is dropped.
+fn lower_dash(segment: &str) -> String {
+    let mut slug = String::with_capacity(segment.len());
+    let mut dash = false;
+    for c in segment.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() || c == '.' {
+            if dash && !slug.is_empty() {
+                slug.push('-');
+            }
+            dash = false;
+            slug.push(c);
+        } else if c.is_whitespace() || matches!(c, '_' | '-') {
+            dash = true;
+        }
+    }
+    slug
+}
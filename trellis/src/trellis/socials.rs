@@ -0,0 +1,110 @@
+/// A social platform's display name and the inner markup of its 24×24 stroke icon.
+pub struct SocialIcon {
+    pub label: &'static str,
+    pub svg: &'static str,
+}
+
+/// Platforms with a dedicated icon, keyed by the lowercase `platform` in `config.yml`.
+const ICONS: &[(&[&str], SocialIcon)] = &[
+    (
+        &["github"],
+        SocialIcon {
+            label: "GitHub",
+            svg: r#"<path d="M15 22v-4a4.8 4.8 0 0 0-1-3.5c3 0 6-2 6-5.5.08-1.25-.27-2.48-1-3.5.28-1.15.28-2.35 0-3.5 0 0-1 0-3 1.5-2.64-.5-5.36-.5-8 0C6 2 5 2 5 2c-.3 1.15-.3 2.35 0 3.5A5.403 5.403 0 0 0 4 9c0 3.5 3 5.5 6 5.5-.39.49-.68 1.05-.85 1.65-.17.6-.22 1.23-.15 1.85v4"/><path d="M9 18c-4.51 2-5-2-7-2"/>"#,
+        },
+    ),
+    (
+        &["gitlab"],
+        SocialIcon {
+            label: "GitLab",
+            svg: r#"<path d="m22 13.29-3.33-10a.42.42 0 0 0-.14-.18.38.38 0 0 0-.22-.11.39.39 0 0 0-.23.07.42.42 0 0 0-.14.18l-2.26 6.67H8.32L6.1 3.26a.42.42 0 0 0-.1-.18.38.38 0 0 0-.26-.08.39.39 0 0 0-.23.07.42.42 0 0 0-.14.18L2 13.29a.74.74 0 0 0 .27.83L12 21l9.69-6.88a.71.71 0 0 0 .31-.83Z"/>"#,
+        },
+    ),
+    (
+        &["mastodon", "fediverse"],
+        SocialIcon {
+            label: "Mastodon",
+            svg: r#"<path d="M21 8.5c0-4.5-3-5.5-9-5.5S3 4 3 8.5v4c0 5 2 7.5 7.5 7.5 1.5 0 3-.2 4.5-.7v-2.1c-1.5.5-3 .7-4.5.6-1.6-.1-2.5-.8-2.5-2.3 2 .5 4 .6 6 .5 4-.2 6-1.5 6-5.5z"/><path d="M8 12V9a2 2 0 0 1 4 0v3"/><path d="M12 9a2 2 0 0 1 4 0v3"/>"#,
+        },
+    ),
+    (
+        &["bluesky"],
+        SocialIcon {
+            label: "Bluesky",
+            svg: r#"<path d="M12 11c-1.5-3-5.5-7-8-7-1.5 0-1.5 2-1 5 .4 2.4 2.5 3.5 5 3-3 .8-3.5 3-1.5 5s4 .5 5.5-3c1.5 3.5 3.5 5 5.5 3s1.5-4.2-1.5-5c2.5.5 4.6-.6 5-3 .5-3 .5-5-1-5-2.5 0-6.5 4-8 7z"/>"#,
+        },
+    ),
+    (
+        &["twitter", "x"],
+        SocialIcon {
+            label: "X (Twitter)",
+            svg: r#"<path d="M4 4l16 16"/><path d="M20 4 13.6 10.4"/><path d="M10.4 13.6 4 20"/>"#,
+        },
+    ),
+    (
+        &["linkedin"],
+        SocialIcon {
+            label: "LinkedIn",
+            svg: r#"<path d="M16 8a6 6 0 0 1 6 6v7h-4v-7a2 2 0 0 0-2-2 2 2 0 0 0-2 2v7h-4v-7a6 6 0 0 1 6-6z"/><rect width="4" height="12" x="2" y="9"/><circle cx="4" cy="4" r="2"/>"#,
+        },
+    ),
+    (
+        &["youtube"],
+        SocialIcon {
+            label: "YouTube",
+            svg: r#"<path d="M2.5 17a24.12 24.12 0 0 1 0-10 2 2 0 0 1 1.4-1.4 49.56 49.56 0 0 1 16.2 0A2 2 0 0 1 21.5 7a24.12 24.12 0 0 1 0 10 2 2 0 0 1-1.4 1.4 49.55 49.55 0 0 1-16.2 0A2 2 0 0 1 2.5 17"/><path d="m10 15 5-3-5-3z"/>"#,
+        },
+    ),
+    (
+        &["instagram"],
+        SocialIcon {
+            label: "Instagram",
+            svg: r#"<rect width="20" height="20" x="2" y="2" rx="5" ry="5"/><path d="M16 11.37A4 4 0 1 1 12.63 8 4 4 0 0 1 16 11.37z"/><line x1="17.5" x2="17.51" y1="6.5" y2="6.5"/>"#,
+        },
+    ),
+    (
+        &["discord"],
+        SocialIcon {
+            label: "Discord",
+            svg: r#"<path d="M8 5.5A15 15 0 0 0 4.5 6.5C2.5 10 2 13.5 2.3 17a15 15 0 0 0 4.7 2.5l1-2a10 10 0 0 1-1.8-.9"/><path d="M16 5.5a15 15 0 0 1 3.5 1c2 3.5 2.5 7 2.2 10.5a15 15 0 0 1-4.7 2.5l-1-2a10 10 0 0 0 1.8-.9"/><path d="M6.5 16.5c3.5 1.5 7.5 1.5 11 0"/><circle cx="9" cy="12" r="1"/><circle cx="15" cy="12" r="1"/>"#,
+        },
+    ),
+    (
+        &["rss", "feed"],
+        SocialIcon {
+            label: "RSS feed",
+            svg: r#"<path d="M4 11a9 9 0 0 1 9 9"/><path d="M4 4a16 16 0 0 1 16 16"/><circle cx="5" cy="19" r="1"/>"#,
+        },
+    ),
+    (
+        &["email", "mail"],
+        SocialIcon {
+            label: "Email",
+            svg: r#"<rect width="20" height="16" x="2" y="4" rx="2"/><path d="m22 7-8.97 5.7a1.94 1.94 0 0 1-2.06 0L2 7"/>"#,
+        },
+    ),
+];
+
+/// Used for platforms without a dedicated icon.
+const GENERIC: SocialIcon = SocialIcon {
+    label: "Website",
+    svg: r#"<circle cx="12" cy="12" r="10"/><path d="M12 2a14.5 14.5 0 0 0 0 20 14.5 14.5 0 0 0 0-20"/><path d="M2 12h20"/>"#,
+};
+
+/// The icon for `platform` (case-insensitive), or a globe for anything unknown.
+pub fn icon(platform: &str) -> &'static SocialIcon {
+    let platform = platform.trim().to_ascii_lowercase();
+    ICONS
+        .iter()
+        .find(|(names, _)| names.contains(&platform.as_str()))
+        .map(|(_, icon)| icon)
+        .unwrap_or(&GENERIC)
+}
+
+/// Whether `platform` has a dedicated icon.
+pub fn is_known(platform: &str) -> bool {
+    let platform = platform.trim().to_ascii_lowercase();
+    ICONS
+        .iter()
+        .any(|(names, _)| names.contains(&platform.as_str()))
+}
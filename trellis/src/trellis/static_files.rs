@@ -0,0 +1,94 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+use walkdir::WalkDir;
+
+use crate::trellis::cache;
+
+/// Files under `static/` that Trellis generates itself; user copies never replace them.
+pub const GENERATED_STATIC_FILES: &[&str] = &["content-index.json", "graph.json"];
+
+/// Records which files in the output came from `static_dirs`, so removals can be mirrored.
+const MANIFEST_NAME: &str = ".static-manifest";
+
+/// Mirror every configured static directory into `target` (normally `cache_root/static`).
+///
+/// Subpaths are preserved, unchanged files (same size, not older) are skipped, and files
+/// that disappear from the sources are deleted from the output. Later directories win when
+/// two sources provide the same path. Paths in `exclude` are treated as if absent.
+pub fn sync_static_dirs(
+    sources: &[PathBuf],
+    target: &Path,
+    exclude: &BTreeSet<String>,
+) -> io::Result<()> {
+    fs::create_dir_all(target)?;
+
+    let manifest_path = target.join(MANIFEST_NAME);
+    let previous: BTreeSet<String> = fs::read_to_string(&manifest_path)
+        .map(|s| s.lines().map(str::to_string).collect())
+        .unwrap_or_default();
+    let mut current = BTreeSet::new();
+
+    for source in sources {
+        if !source.is_dir() {
+            debug!("static dir {} does not exist; skipping", source.display());
+            continue;
+        }
+
+        for entry in WalkDir::new(source)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|e| e.path().is_file())
+        {
+            let Ok(rel) = entry.path().strip_prefix(source) else {
+                continue;
+            };
+            let rel_key = rel.to_string_lossy().replace('\\', "/");
+
+            if exclude.contains(&rel_key) {
+                continue;
+            }
+            if GENERATED_STATIC_FILES.contains(&rel_key.as_str()) {
+                warn!(
+                    "static file {} conflicts with a generated asset; keeping the generated file",
+                    entry.path().display()
+                );
+                continue;
+            }
+
+            let dest = target.join(rel);
+            if !is_unchanged(entry.path(), &dest) {
+                let bytes = fs::read(entry.path())?;
+                cache::write_atomic(&dest, &bytes, false)?;
+            }
+            current.insert(rel_key);
+        }
+    }
+
+    for stale in previous.difference(&current) {
+        let path = target.join(stale);
+        if path.is_file() {
+            debug!("removing stale static file {}", path.display());
+            fs::remove_file(&path)?;
+        }
+    }
+
+    let manifest = current.into_iter().collect::<Vec<_>>().join("\n");
+    cache::write_atomic(&manifest_path, manifest.as_bytes(), false)
+}
+
+fn is_unchanged(src: &Path, dest: &Path) -> bool {
+    let (Ok(src_meta), Ok(dest_meta)) = (fs::metadata(src), fs::metadata(dest)) else {
+        return false;
+    };
+    if src_meta.len() != dest_meta.len() {
+        return false;
+    }
+    match (src_meta.modified(), dest_meta.modified()) {
+        (Ok(src_time), Ok(dest_time)) => dest_time >= src_time,
+        _ => false,
+    }
+}
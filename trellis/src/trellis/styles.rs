@@ -1,101 +1,325 @@
 use std::{
-    path::Path,
+    fs,
+    path::{Path, PathBuf},
     sync::{OnceLock, RwLock},
-    time::SystemTime,
+    time::{Instant, SystemTime},
 };
 
-use log::warn;
+use anyhow::{Context, Result, anyhow};
+use lightningcss::stylesheet::{MinifyOptions, ParserOptions, PrinterOptions, StyleSheet};
+use lightningcss::targets::{Browsers, Targets};
+use log::{debug, info, warn};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::{debug_span, field};
 
-use crate::trellis::{SiteConfig, cache, config::ThemeConfig};
+use crate::trellis::{
+    SiteConfig, assets, cache,
+    config::{
+        Breakpoints, CssDelivery, SYNTAX_TOKEN_KEYS, ThemeConfig, ThemeDefaultMode, ThemePalette,
+        syntax_theme, theme_hash,
+    },
+    fonts,
+};
 
-pub fn compiled_styles(cfg: &SiteConfig) -> String {
-    static STYLES: OnceLock<RwLock<StylesCache>> = OnceLock::new();
+static STYLES: OnceLock<RwLock<StylesCache>> = OnceLock::new();
 
-    let scss_mtime = latest_scss_mtime();
-    let cache = STYLES.get_or_init(|| {
-        RwLock::new(StylesCache {
-            css: compile_scss(cfg),
-            mtime: scss_mtime,
-        })
-    });
+/// The compiled site CSS as a page references it: inline, or the URL of the hashed
+/// stylesheet when `theme.css_delivery` is `external`.
+pub struct PageStyles {
+    pub inline: Option<String>,
+    pub href: Option<String>,
+    /// The SCSS compile failure, shown as an overlay in dev mode only.
+    pub error: Option<ScssError>,
+}
+
+/// A grass error with the location it points at.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScssError {
+    pub file: String,
+    /// 1-based; `None` for errors without a source position, such as I/O failures.
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl ScssError {
+    fn from_grass(path: &Path, err: &grass::Error) -> Self {
+        match err.kind() {
+            grass::ErrorKind::ParseError { message, loc, .. } => Self {
+                file: loc.file.name().to_string(),
+                line: Some(loc.begin.line + 1),
+                column: Some(loc.begin.column + 1),
+                message,
+            },
+            _ => Self {
+                file: path.display().to_string(),
+                line: None,
+                column: None,
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for ScssError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.file)?;
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, ":{line}:{column}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// SCSS that compiled with an error still yields CSS (the theme variables, or the base
+/// styles without the user's file), so pages render; `error` says what was left out.
+pub struct CompiledScss {
+    pub css: String,
+    pub error: Option<ScssError>,
+}
+
+pub fn page_styles(cfg: &SiteConfig) -> PageStyles {
+    let dev_mode = cfg.server.dev_mode;
+    let scss_mtime = latest_scss_mtime(cfg);
+    // Keyed on the theme too, so a reloaded config recompiles the theme variables.
+    let theme = theme_hash(&cfg.configuration.theme);
+    let cache = STYLES.get_or_init(|| RwLock::new(StylesCache::build(cfg, scss_mtime, &theme)));
+    // In dev mode a failed compile is retried on every request rather than kept until
+    // the next mtime change, so fixing an import outside the watched files also recovers.
+    let stale = |guard: &StylesCache| {
+        guard.mtime < scss_mtime || guard.theme != theme || (dev_mode && guard.error.is_some())
+    };
 
     if let Ok(guard) = cache.read() {
-        if guard.mtime >= scss_mtime {
-            return guard.css.clone();
+        if !stale(&guard) {
+            return guard.page_styles(dev_mode);
         }
     }
 
     if let Ok(mut guard) = cache.write() {
-        if guard.mtime < scss_mtime {
-            guard.css = compile_scss(cfg);
-            guard.mtime = scss_mtime;
+        if stale(&guard) {
+            *guard = StylesCache::build(cfg, scss_mtime, &theme);
         }
-        return guard.css.clone();
+        return guard.page_styles(dev_mode);
     }
 
     // Fallback in case the lock is poisoned.
-    compile_scss(cfg)
+    let compiled = compile_scss(cfg);
+    PageStyles {
+        inline: Some(compiled.css),
+        href: None,
+        error: compiled.error.filter(|_| dev_mode),
+    }
+}
+
+/// Whether the last SCSS compile failed, for the health endpoint.
+pub fn styles_degraded() -> bool {
+    STYLES
+        .get()
+        .and_then(|cache| cache.read().ok())
+        .is_some_and(|guard| guard.error.is_some())
 }
 
 struct StylesCache {
     css: String,
+    /// URL of the written stylesheet in external mode.
+    href: Option<String>,
+    error: Option<ScssError>,
     mtime: SystemTime,
+    theme: String,
+}
+
+impl StylesCache {
+    fn build(cfg: &SiteConfig, mtime: SystemTime, theme: &str) -> Self {
+        let CompiledScss {
+            css: compiled,
+            error,
+        } = compile_scss(cfg);
+        let targets = &cfg.configuration.theme.css_targets;
+        let css = match minify_css(&compiled, targets) {
+            Ok(css) => {
+                info!(
+                    "Compiled styles: {} -> {} bytes after lightningcss ({targets})",
+                    compiled.len(),
+                    css.len()
+                );
+                css
+            }
+            Err(err) => {
+                warn!("{err:#}; serving grass output");
+                compiled
+            }
+        };
+        let href = match cfg.configuration.theme.css_delivery {
+            CssDelivery::Inline => None,
+            CssDelivery::External => write_stylesheet(&cfg.cache_root(), &css)
+                .map_err(|err| warn!("{err:#}; inlining styles instead"))
+                .ok(),
+        };
+        Self {
+            css,
+            href,
+            error,
+            mtime,
+            theme: theme.to_string(),
+        }
+    }
+
+    fn page_styles(&self, dev_mode: bool) -> PageStyles {
+        let error = self.error.clone().filter(|_| dev_mode);
+        match &self.href {
+            Some(href) => PageStyles {
+                inline: None,
+                href: Some(href.clone()),
+                error,
+            },
+            None => PageStyles {
+                inline: Some(self.css.clone()),
+                href: None,
+                error,
+            },
+        }
+    }
+}
+
+/// Minify `css` and add prefixes / lower newer syntax for the browserslist `query`.
+fn minify_css(css: &str, query: &str) -> Result<String> {
+    let targets = Targets::from(parse_targets(query)?);
+    let mut sheet = StyleSheet::parse(css, ParserOptions::default())
+        .map_err(|err| anyhow!("parsing compiled CSS: {err}"))?;
+    sheet
+        .minify(MinifyOptions {
+            targets,
+            ..Default::default()
+        })
+        .map_err(|err| anyhow!("minifying CSS: {err}"))?;
+    let out = sheet
+        .to_css(PrinterOptions {
+            minify: true,
+            targets,
+            ..Default::default()
+        })
+        .map_err(|err| anyhow!("printing CSS: {err}"))?;
+    Ok(out.code)
+}
+
+fn parse_targets(query: &str) -> Result<Option<Browsers>> {
+    Browsers::from_browserslist([query])
+        .map_err(|err| anyhow!("invalid theme.css_targets {query:?}: {err}"))
+}
+
+/// Whether `query` is a browserslist query lightningcss understands.
+pub fn check_css_targets(query: &str) -> Result<()> {
+    parse_targets(query).map(|_| ())
+}
+
+/// Write `css` to `static/index.{hash}.css` and delete older hashed copies.
+fn write_stylesheet(cache_root: &Path, css: &str) -> Result<String> {
+    let dir = cache_root.join("static");
+    fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+
+    let hash = format!("{:x}", Sha256::digest(css.as_bytes()));
+    let file_name = format!("index.{}.css", &hash[..16]);
+    let path = dir.join(&file_name);
+    if !path.is_file() {
+        cache::write_atomic(&path, css.as_bytes(), false)
+            .with_context(|| format!("writing stylesheet {}", path.display()))?;
+    }
+
+    for entry in fs::read_dir(&dir)?.filter_map(Result::ok) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let hashed = name
+            .strip_prefix("index.")
+            .and_then(|rest| rest.strip_suffix(".css"))
+            .is_some_and(|hash| hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()));
+        if hashed && name != file_name {
+            debug!("removing stale stylesheet {name}");
+            if let Err(err) = fs::remove_file(entry.path()) {
+                warn!("failed to remove stale stylesheet {name}: {err}");
+            }
+        }
+    }
+
+    Ok(format!("/static/{file_name}"))
 }
 
 /// CSS variable declarations derived from the active theme, mirroring Quartz's joinStyles.
+///
+/// `theme.default_mode` picks the palette for visitors without a `saved-theme` attribute
+/// (no script yet, or no JavaScript at all): `auto` follows `prefers-color-scheme`. An
+/// explicit `saved-theme` always wins. Each block also sets `color-scheme`, which scripts
+/// read to learn the mode in effect.
 pub fn theme_css_variables(theme: &ThemeConfig) -> String {
     const DEFAULT_SANS: &str = "system-ui, \"Segoe UI\", Roboto, Helvetica, Arial, sans-serif, \"Apple Color Emoji\", \"Segoe UI Emoji\", \"Segoe UI Symbol\"";
     const DEFAULT_MONO: &str = "ui-monospace, SFMono-Regular, SF Mono, Menlo, monospace";
 
+    let light = palette_variables(
+        &theme.colors.light_mode,
+        &theme.syntax.light,
+        "github-light",
+    );
+    let dark = palette_variables(&theme.colors.dark_mode, &theme.syntax.dark, "github-dark");
+
+    let palettes = match theme.default_mode {
+        ThemeDefaultMode::Light => format!(
+            r#":root {{
+  color-scheme: light;
+{light}}}
+
+:root[saved-theme="dark"] {{
+  color-scheme: dark;
+{dark}}}
+"#
+        ),
+        ThemeDefaultMode::Dark => format!(
+            r#":root {{
+  color-scheme: dark;
+{dark}}}
+
+:root[saved-theme="light"] {{
+  color-scheme: light;
+{light}}}
+"#
+        ),
+        ThemeDefaultMode::Auto => format!(
+            r#":root {{
+  color-scheme: light dark;
+{light}}}
+
+:root[saved-theme="dark"] {{
+  color-scheme: dark;
+{dark}}}
+
+@media (prefers-color-scheme: dark) {{
+  :root:not([saved-theme="light"]) {{
+    color-scheme: dark;
+{dark}  }}
+}}
+"#
+        ),
+    };
+
     format!(
         r#"
 :root {{
-  --light: {l_light};
-  --lightgray: {l_lightgray};
-  --gray: {l_gray};
-  --darkgray: {l_darkgray};
-  --dark: {l_dark};
-  --secondary: {l_secondary};
-  --tertiary: {l_tertiary};
-  --highlight: {l_highlight};
-  --textHighlight: {l_text_highlight};
-
   --titleFont: "{title}", {sans};
   --headerFont: "{header}", {sans};
   --bodyFont: "{body}", {sans};
   --codeFont: "{code}", {mono};
 }}
 
-:root[saved-theme=\"dark\"] {{
-  --light: {d_light};
-  --lightgray: {d_lightgray};
-  --gray: {d_gray};
-  --darkgray: {d_darkgray};
-  --dark: {d_dark};
-  --secondary: {d_secondary};
-  --tertiary: {d_tertiary};
-  --highlight: {d_highlight};
-  --textHighlight: {d_text_highlight};
+{palettes}
+@media print {{
+  :root,
+  :root[saved-theme],
+  :root:not([saved-theme="light"]) {{
+    color-scheme: light;
+{light}  }}
 }}
 "#,
-        l_light = theme.colors.light_mode.light,
-        l_lightgray = theme.colors.light_mode.lightgray,
-        l_gray = theme.colors.light_mode.gray,
-        l_darkgray = theme.colors.light_mode.darkgray,
-        l_dark = theme.colors.light_mode.dark,
-        l_secondary = theme.colors.light_mode.secondary,
-        l_tertiary = theme.colors.light_mode.tertiary,
-        l_highlight = theme.colors.light_mode.highlight,
-        l_text_highlight = theme.colors.light_mode.text_highlight,
-        d_light = theme.colors.dark_mode.light,
-        d_lightgray = theme.colors.dark_mode.lightgray,
-        d_gray = theme.colors.dark_mode.gray,
-        d_darkgray = theme.colors.dark_mode.darkgray,
-        d_dark = theme.colors.dark_mode.dark,
-        d_secondary = theme.colors.dark_mode.secondary,
-        d_tertiary = theme.colors.dark_mode.tertiary,
-        d_highlight = theme.colors.dark_mode.highlight,
-        d_text_highlight = theme.colors.dark_mode.text_highlight,
         title = theme.typography.header,
         header = theme.typography.header,
         body = theme.typography.body,
@@ -104,36 +328,238 @@ pub fn theme_css_variables(theme: &ThemeConfig) -> String {
         mono = DEFAULT_MONO
     )
 }
-pub fn compile_scss(cfg: &SiteConfig) -> String {
-    let theme_vars = theme_css_variables(&cfg.configuration.theme);
+
+/// Palette and `--code-*` declarations for one mode.
+fn palette_variables(palette: &ThemePalette, syntax: &str, syntax_fallback: &str) -> String {
+    format!(
+        "  --light: {};\n  --lightgray: {};\n  --gray: {};\n  --darkgray: {};\n  --dark: {};\n  --secondary: {};\n  --tertiary: {};\n  --highlight: {};\n  --textHighlight: {};\n{}",
+        palette.light,
+        palette.lightgray,
+        palette.gray,
+        palette.darkgray,
+        palette.dark,
+        palette.secondary,
+        palette.tertiary,
+        palette.highlight,
+        palette.text_highlight,
+        syntax_variables(syntax, syntax_fallback)
+    )
+}
+
+/// `--code-*` declarations for the named syntax theme, or `fallback` if it is unknown.
+fn syntax_variables(name: &str, fallback: &str) -> String {
+    let Some(theme) = syntax_theme(name).or_else(|| syntax_theme(fallback)) else {
+        return String::new();
+    };
+    SYNTAX_TOKEN_KEYS
+        .iter()
+        .zip(theme.colors)
+        .map(|(key, color)| format!("  --code-{key}: {color};\n"))
+        .collect()
+}
+
+pub fn compile_scss(cfg: &SiteConfig) -> CompiledScss {
+    let span = debug_span!(
+        "compile_scss",
+        bytes = field::Empty,
+        failed = field::Empty,
+        duration_ms = field::Empty,
+    );
+    let _entered = span.enter();
+    let started = Instant::now();
+    let compiled = compile(cfg);
+    span.record("bytes", compiled.css.len());
+    span.record("failed", compiled.error.is_some());
+    span.record("duration_ms", started.elapsed().as_millis() as u64);
+    compiled
+}
+
+fn compile(cfg: &SiteConfig) -> CompiledScss {
+    let theme = &cfg.configuration.theme;
+    let mut theme_vars = theme_css_variables(theme);
+    if theme.uses_local_fonts() {
+        theme_vars = format!(
+            "{}{theme_vars}",
+            fonts::font_face_css(theme, &cfg.fonts_dir())
+        );
+    }
     let scss_path = scss_entry_path();
-    let include_path = scss_path
-        .parent()
-        .map(|p| p.to_path_buf())
-        .unwrap_or_else(|| scss_root());
-
-    match grass::from_path(
-        &scss_path,
-        &grass::Options::default()
-            .load_path(include_path)
-            .style(grass::OutputStyle::Compressed),
-    ) {
+    // Imports resolve against the override first, then the embedded defaults.
+    let options = assets::search_roots()
+        .into_iter()
+        .fold(grass::Options::default(), |opts, root| {
+            opts.load_path(root.join(SCSS_DIR))
+        })
+        .style(grass::OutputStyle::Compressed);
+
+    let base = match grass::from_string(with_breakpoints(theme, &scss_path), &options) {
         Ok(css) => format!("{theme_vars}\n{css}"),
         Err(err) => {
             warn!("Failed to compile SCSS at {:?}: {err}", scss_path);
-            theme_vars
+            return CompiledScss {
+                css: theme_vars,
+                error: Some(ScssError::from_grass(&scss_path, &err)),
+            };
+        }
+    };
+
+    // The user file shares the include path, so it can `@use "variables"`; the theme's
+    // CSS variables are already declared by `base`.
+    let Some(user_path) = cfg.custom_scss().filter(|path| path.is_file()) else {
+        return CompiledScss {
+            css: base,
+            error: None,
+        };
+    };
+    match grass::from_string(with_breakpoints(theme, &user_path), &options) {
+        Ok(css) => CompiledScss {
+            css: format!("{base}\n{css}"),
+            error: None,
+        },
+        Err(err) => {
+            warn!(
+                "Failed to compile custom SCSS at {:?}, using base styles only: {err}",
+                user_path
+            );
+            CompiledScss {
+                css: base,
+                error: Some(ScssError::from_grass(&user_path, &err)),
+            }
         }
     }
 }
 
-fn scss_root() -> std::path::PathBuf {
-    Path::new(env!("CARGO_MANIFEST_DIR")).join("templates/assets/styles/")
+const SCSS_DIR: &str = "assets/styles";
+
+/// An entry stylesheet that loads `path` after configuring `variables.scss` with the
+/// theme's breakpoints. Sass only accepts the configuration on the module's first load, so
+/// the `@use ... with` has to come before `path` pulls it in. Values that are not plain
+/// lengths (validation reports them) keep the built-in defaults.
+fn with_breakpoints(theme: &ThemeConfig, path: &Path) -> String {
+    let defaults = Breakpoints::default();
+    let length = |value: &str, fallback: String| {
+        if is_css_length(value) {
+            value.trim().to_string()
+        } else {
+            fallback
+        }
+    };
+    let mobile = length(&theme.breakpoints.mobile, defaults.mobile);
+    let desktop = length(&theme.breakpoints.desktop, defaults.desktop);
+    let url = path
+        .to_string_lossy()
+        .replace('\\', "/")
+        .replace('"', "\\\"");
+    format!(
+        "@use \"variables\" with ($breakpoints: (mobile: {mobile}, desktop: {desktop}));\n@use \"{url}\";\n"
+    )
 }
 
-fn scss_entry_path() -> std::path::PathBuf {
-    scss_root().join("custom.scss")
+/// A non-negative `px`, `em` or `rem` length, as `$breakpoints` expects.
+pub fn is_css_length(value: &str) -> bool {
+    static LENGTH: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^\d+(\.\d+)?(px|em|rem)$").expect("css length regex"));
+    LENGTH.is_match(value.trim())
 }
 
-fn latest_scss_mtime() -> SystemTime {
-    cache::newest_mtime_with_extension(&scss_root(), "scss").unwrap_or(SystemTime::UNIX_EPOCH)
+fn scss_entry_path() -> PathBuf {
+    assets::resolve(Path::new(SCSS_DIR).join("custom.scss"))
+}
+
+/// Newest of the built-in SCSS sources and the user stylesheet.
+pub fn latest_scss_mtime(cfg: &SiteConfig) -> SystemTime {
+    let user_mtime = cfg
+        .custom_scss()
+        .and_then(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    assets::newest_mtime(SCSS_DIR, "scss").max(user_mtime)
+}
+
+/// Make frontmatter CSS safe to drop into a `<style>` element: anything that could close
+/// the element is removed, as are `@import`s of remote stylesheets.
+pub fn sanitize_page_css(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = find_ascii_ci(rest, "@import") {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find(';')
+            .map(|i| start + i + 1)
+            .unwrap_or(rest.len());
+        let statement = &rest[start..end];
+        let target = statement["@import".len()..]
+            .trim_start()
+            .trim_start_matches("url(")
+            .trim_start_matches(['"', '\''])
+            .to_ascii_lowercase();
+        if target.starts_with("http:") || target.starts_with("https:") || target.starts_with("//") {
+            warn!("dropping remote {} from page style", statement.trim());
+        } else {
+            out.push_str(statement);
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+
+    while let Some(start) = find_ascii_ci(&out, "</style") {
+        out.replace_range(start..start + "</style".len(), "");
+    }
+    out
+}
+
+fn find_ascii_ci(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn theme(default_mode: ThemeDefaultMode) -> ThemeConfig {
+        let mut theme = SiteConfig::default().configuration.theme;
+        theme.default_mode = default_mode;
+        theme
+    }
+
+    #[test]
+    fn the_dark_mode_selector_is_not_escaped() {
+        for mode in [
+            ThemeDefaultMode::Light,
+            ThemeDefaultMode::Dark,
+            ThemeDefaultMode::Auto,
+        ] {
+            let css = theme_css_variables(&theme(mode));
+            assert!(!css.contains('\\'), "{css}");
+            assert!(
+                css.contains(":root[saved-theme=\"dark\"]")
+                    || css.contains(":root[saved-theme=\"light\"]"),
+                "{css}"
+            );
+        }
+    }
+
+    #[test]
+    fn the_dark_mode_block_survives_a_grass_compile() {
+        let css = theme_css_variables(&theme(ThemeDefaultMode::Auto));
+        let compiled = grass::from_string(css, &grass::Options::default()).unwrap();
+        let selector = compiled
+            .lines()
+            .find(|line| line.starts_with(":root[saved-theme") && line.contains("dark"))
+            .unwrap_or_else(|| panic!("no dark-mode block in {compiled}"));
+        assert!(!selector.contains('\\'), "{selector}");
+        assert!(compiled.contains("color-scheme: dark;"), "{compiled}");
+    }
+
+    #[test]
+    fn auto_mode_declares_both_color_schemes() {
+        let css = theme_css_variables(&theme(ThemeDefaultMode::Auto));
+        assert!(css.contains("color-scheme: light dark;"), "{css}");
+        assert!(css.contains("@media (prefers-color-scheme: dark)"), "{css}");
+
+        let css = theme_css_variables(&theme(ThemeDefaultMode::Light));
+        assert!(!css.contains("color-scheme: light dark;"), "{css}");
+    }
 }
@@ -0,0 +1,76 @@
+use serde::Serialize;
+
+use crate::trellis::layout::TocConfig;
+use crate::trellis::types::Heading;
+
+/// One table-of-contents entry and the entries nested under it.
+#[derive(Debug, Clone, Serialize)]
+pub struct TocEntry {
+    pub id: String,
+    pub text: String,
+    /// 0 for the page's highest heading level.
+    pub depth: u8,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TocEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TocContext {
+    pub title: String,
+    /// Top-level entries shown straight away.
+    pub entries: Vec<TocEntry>,
+    /// Top-level entries past `layout.toc.fold_after`, behind a "show more" fold.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub more: Vec<TocEntry>,
+    pub collapse_by_default: bool,
+}
+
+/// The table of contents for a page's headings, or `None` when it has fewer than
+/// `layout.toc.min_headings` headings within `layout.toc.max_depth` levels.
+pub fn toc_context(headings: &[Heading], cfg: &TocConfig) -> Option<TocContext> {
+    let top = headings.iter().map(|h| h.depth).min()?;
+    let shown: Vec<&Heading> = headings
+        .iter()
+        .filter(|h| h.depth - top < cfg.max_depth.max(1))
+        .collect();
+    if shown.len() < cfg.min_headings.max(1) {
+        return None;
+    }
+
+    let mut entries = nest(&shown, top);
+    let more = if cfg.fold_after > 0 && entries.len() > cfg.fold_after {
+        entries.split_off(cfg.fold_after)
+    } else {
+        Vec::new()
+    };
+
+    Some(TocContext {
+        title: cfg.title.clone(),
+        entries,
+        more,
+        collapse_by_default: cfg.collapse_by_default,
+    })
+}
+
+/// Build the tree: each heading nests under the closest earlier heading of a higher level.
+fn nest(headings: &[&Heading], top: u8) -> Vec<TocEntry> {
+    let mut roots: Vec<TocEntry> = Vec::new();
+    for heading in headings {
+        let entry = TocEntry {
+            id: heading.id.clone(),
+            text: heading.text.clone(),
+            depth: heading.depth - top,
+            children: Vec::new(),
+        };
+        let mut siblings = &mut roots;
+        while siblings
+            .last()
+            .is_some_and(|parent| parent.depth < entry.depth)
+        {
+            let last = siblings.len() - 1;
+            siblings = &mut siblings[last].children;
+        }
+        siblings.push(entry);
+    }
+    roots
+}
@@ -1,7 +1,12 @@
+use std::fs;
 use std::path::{Path, PathBuf};
 
-use chrono::{DateTime, Utc};
-use serde::Serialize;
+use chrono::{DateTime, Locale, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::trellis::config::DefaultDateType;
+use crate::trellis::git_history;
+use crate::trellis::slugs::slugify;
 
 #[derive(Clone, Debug, Serialize, Default)]
 pub struct PageMetadata {
@@ -14,17 +19,67 @@ pub struct PageMetadata {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub updated: Option<DateTime<Utc>>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub tags: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub word_count: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub encrypted: Option<bool>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Cleared by `EncryptContent` and never serialized, so neither the password nor
+    /// which notes carry one shows up in templates or JSON.
+    #[serde(skip_serializing)]
     pub password: Option<String>,
+    /// Environment variable holding the password; never serialized.
+    #[serde(skip)]
+    pub password_env: Option<String>,
+    /// Key in the secrets file holding the password; never serialized.
+    #[serde(skip)]
+    pub password_ref: Option<String>,
+    /// `passwords:` in frontmatter: each recipient gets a password of their own. Never
+    /// serialized.
+    #[serde(skip)]
+    pub passwords: Option<Vec<PasswordSpec>>,
+    /// Shown under the password field of an encrypted note. Never serialized, so it stays
+    /// out of the content index.
+    #[serde(skip)]
+    pub password_hint: Option<String>,
+    /// On-demand custom scripts (`scripts.on_demand`) this page needs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scripts: Option<Vec<String>>,
+    /// Page-only CSS from the `style` key, already read from disk when it names a file.
+    /// Never serialized, so it stays out of the content index.
+    #[serde(skip)]
+    pub style: Option<String>,
+    /// `false` hides the comments component on this page; `true` shows native comments
+    /// when the layout has no comments component.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comments: Option<bool>,
+    /// Name of an entry in the config's `layouts:` map to render this page with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    /// Top-level handlebars template (`resume` for `templates/resume.hbs`) to render this
+    /// page with instead of `page` (or `index` at the site root).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub draft: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub publish: Option<bool>,
+    /// Sort key in the nav and explorer: lower comes first, unweighted pages last.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<f64>,
+    /// `nav: false` or `hide_nav: true`: left out of the nav and explorer, still served.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hide_nav: Option<bool>,
+}
+
+/// One entry of a note's `passwords:` list: the password itself, or where to read it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PasswordSpec {
+    Literal(String),
+    Env(String),
+    Ref(String),
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -32,9 +87,22 @@ pub struct Page {
     pub slug: String,
     pub source_path: PathBuf,
     pub frontmatter: PageMetadata,
+    /// Frontmatter keys with no field in [`PageMetadata`], such as `author` or `series`,
+    /// for templates to read as `article.frontmatter`.
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub extra: serde_json::Map<String, serde_json::Value>,
     pub content: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub html: Option<String>,
+    /// Headings of the rendered HTML, filled once MarkdownRenderer has run.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub headings: Vec<Heading>,
+    /// Links in the source, in order; filled with the excerpt right after the frontmatter.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<ResolvedLink>,
+    /// Opening of the page's text, for descriptions when the frontmatter has none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub excerpt: Option<String>,
 }
 
 impl Page {
@@ -43,8 +111,12 @@ impl Page {
             slug,
             source_path,
             frontmatter: PageMetadata::default(),
+            extra: serde_json::Map::new(),
             content,
             html: None,
+            headings: Vec::new(),
+            links: Vec::new(),
+            excerpt: None,
         }
     }
 }
@@ -54,6 +126,17 @@ pub struct RenderedPage {
     pub slug: String,
     pub html: String,
     pub frontmatter: PageMetadata,
+    /// See [`Page::extra`].
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+    /// See [`Page::headings`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub headings: Vec<Heading>,
+    /// See [`Page::links`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<ResolvedLink>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub excerpt: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cached: Option<bool>,
 }
@@ -66,14 +149,124 @@ impl From<Page> for RenderedPage {
             slug: page.slug,
             html,
             frontmatter: page.frontmatter,
+            extra: page.extra,
+            headings: page.headings,
+            links: page.links,
+            excerpt: page.excerpt,
             cached: None,
         }
     }
 }
 
+/// A heading in rendered page HTML that carries an `id`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heading {
+    /// 1 for `<h1>` through 6 for `<h6>`.
+    pub depth: u8,
+    pub text: String,
+    pub id: String,
+}
+
+/// One link in a page's source.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolvedLink {
+    /// A slug for [`LinkKind::Internal`], the name as written for a wikilink, the URL for
+    /// an external link and `#fragment` for an anchor.
+    pub target: String,
+    pub kind: LinkKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkKind {
+    /// A markdown link into the content tree, resolved against the page's folder.
+    Internal,
+    /// `[[name]]`: which page it means depends on every slug in the tree, so the name is
+    /// kept as written and resolved by whoever has the tree at hand.
+    Wikilink,
+    /// A URL with a scheme, such as `https:` or `mailto:`.
+    External,
+    /// `#fragment` on the same page.
+    Anchor,
+}
+
+/// What the render cache keeps next to a page's HTML, so a cache hit has the same
+/// headings, links and excerpt as a fresh render.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PageOutline {
+    pub headings: Vec<Heading>,
+    pub links: Vec<ResolvedLink>,
+    pub excerpt: Option<String>,
+}
+
+/// The slug of the note at `path`, shaped by `content.slugify`; see [`slugify`].
 pub fn slug_from_path(path: &Path, content_root: &Path) -> String {
     path.strip_prefix(content_root)
         .ok()
-        .and_then(|p| p.with_extension("").to_str().map(|s| s.replace('\\', "/")))
+        .and_then(|p| {
+            p.with_extension("")
+                .to_str()
+                .map(|s| slugify(&s.replace('\\', "/")))
+        })
         .unwrap_or_else(|| "index".to_string())
 }
+
+/// The date shown for and used to order a page, chosen by `configuration.default_date_type`:
+/// frontmatter `created`, the source file's modification time, or frontmatter `published`.
+/// With `dates_from_git`, `modified` is the page's `updated` date, which FrontMatter has
+/// filled from git when the frontmatter has none.
+pub fn page_date(
+    meta: &PageMetadata,
+    file: &fs::Metadata,
+    date_type: DefaultDateType,
+) -> Option<DateTime<Utc>> {
+    match date_type {
+        DefaultDateType::Created => meta.created,
+        DefaultDateType::Modified if git_history::enabled() => meta.updated,
+        DefaultDateType::Modified => file.modified().ok().map(DateTime::<Utc>::from),
+        DefaultDateType::Published => meta.published,
+    }
+}
+
+/// Newest first; pages without a date sort after every dated page.
+pub fn cmp_page_dates(a: Option<DateTime<Utc>>, b: Option<DateTime<Utc>>) -> std::cmp::Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => b.cmp(&a),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    }
+}
+
+/// Reading time for `words` at 200 words a minute, never under a minute.
+pub fn reading_minutes(words: u64) -> u64 {
+    words.div_ceil(200).max(1)
+}
+
+/// Words in rendered HTML, ignoring markup: tag names and attributes don't count.
+pub fn count_words(html: &str) -> u64 {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                text.push(' ');
+            }
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count() as u64
+}
+
+/// `date` rendered with a chrono `format`, month and day names taken from `locale`
+/// (`configuration.locale`, e.g. `en-US` or `de_DE`); unknown locales fall back to POSIX.
+pub fn format_date(date: DateTime<Utc>, format: &str, locale: &str) -> String {
+    let locale = Locale::try_from(locale.replace('-', "_").as_str()).unwrap_or(Locale::POSIX);
+    date.format_localized(format, locale).to_string()
+}
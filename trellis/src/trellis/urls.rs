@@ -0,0 +1,67 @@
+use anyhow::{Result, anyhow};
+
+/// Page URLs built from `configuration.base_url`.
+///
+/// With no base URL configured every link degrades to a root-relative path, so callers can
+/// use these helpers unconditionally.
+#[derive(Debug, Clone, Default)]
+pub struct SiteUrls {
+    /// Normalized origin plus optional path prefix, without a trailing slash.
+    base: Option<String>,
+}
+
+impl SiteUrls {
+    pub fn new(base_url: Option<&str>) -> Result<Self> {
+        let base = match base_url.map(str::trim).filter(|url| !url.is_empty()) {
+            Some(url) => Some(normalize_base_url(url).map_err(|err| anyhow!("base_url: {err}"))?),
+            None => None,
+        };
+        Ok(Self { base })
+    }
+
+    pub fn base(&self) -> Option<&str> {
+        self.base.as_deref()
+    }
+
+    /// URL of `slug` exactly as given (e.g. `notes/index`, `static/graph.json`).
+    pub fn absolute(&self, slug: &str) -> String {
+        let path = slug.trim_start_matches('/');
+        match &self.base {
+            Some(base) => format!("{base}/{path}"),
+            None => format!("/{path}"),
+        }
+    }
+
+    /// Preferred URL of the page at `slug`: `index` pages collapse to their folder.
+    pub fn canonical(&self, slug: &str) -> String {
+        let slug = slug.trim_matches('/');
+        if slug == "index" {
+            return self.absolute("");
+        }
+        match slug.strip_suffix("/index") {
+            Some(folder) => self.absolute(&format!("{folder}/")),
+            None => self.absolute(slug),
+        }
+    }
+}
+
+/// Check that `url` is an absolute http(s) URL and strip any trailing slash.
+pub fn normalize_base_url(url: &str) -> std::result::Result<String, String> {
+    let url = url.trim();
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .ok_or_else(|| format!("expected an absolute http(s) URL, found {url:?}"))?;
+
+    let host = rest.split('/').next().unwrap_or_default();
+    if host.is_empty() || host.contains(char::is_whitespace) {
+        return Err(format!("expected a host after the scheme, found {url:?}"));
+    }
+    if rest.contains(['?', '#']) {
+        return Err(format!(
+            "base URL must not contain a query or fragment, found {url:?}"
+        ));
+    }
+
+    Ok(url.trim_end_matches('/').to_string())
+}
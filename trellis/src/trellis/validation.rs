@@ -0,0 +1,447 @@
+use std::fmt;
+use std::net::IpAddr;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::trellis::config::{self, SiteConfig, ThemePalette};
+use crate::trellis::layout::{self, LayoutComponent};
+use crate::trellis::{explorer_fns, ignore, socials, styles, urls};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A single problem found in `config.yml`, located by its dotted key path.
+#[derive(Debug, Clone)]
+pub struct ConfigIssue {
+    pub severity: Severity,
+    pub path: String,
+    pub message: String,
+}
+
+impl ConfigIssue {
+    pub fn error(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        if self.path.is_empty() {
+            write!(f, "{level}: {}", self.message)
+        } else {
+            write!(f, "{level}: {}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// Check a config document (already merged and preset-expanded) for type errors, with the
+/// exact key path and expected type, and unknown keys. Returns the parsed config when it
+/// deserializes.
+pub fn validate_document(value: serde_yaml::Value) -> (Option<SiteConfig>, Vec<ConfigIssue>) {
+    let mut issues = Vec::new();
+
+    // Unknown keys are collected on a separate pass so a type error doesn't hide them.
+    let _ = serde_ignored::deserialize::<_, _, SiteConfig>(value.clone(), |ignored| {
+        issues.push(ConfigIssue::warning(ignored.to_string(), "unknown key"));
+    });
+
+    match serde_path_to_error::deserialize::<_, SiteConfig>(value) {
+        Ok(config) => {
+            issues.extend(validate(&config));
+            (Some(config), issues)
+        }
+        Err(err) => {
+            issues.push(ConfigIssue::error(
+                err.path().to_string(),
+                err.inner().to_string(),
+            ));
+            (None, issues)
+        }
+    }
+}
+
+/// Semantic checks that types alone can't express.
+pub fn validate(config: &SiteConfig) -> Vec<ConfigIssue> {
+    let mut issues = Vec::new();
+
+    let colors = &config.configuration.theme.colors;
+    check_palette(
+        "configuration.theme.colors.light_mode",
+        &colors.light_mode,
+        &mut issues,
+    );
+    check_palette(
+        "configuration.theme.colors.dark_mode",
+        &colors.dark_mode,
+        &mut issues,
+    );
+
+    if let Some(base_url) = &config.configuration.base_url {
+        if let Err(message) = urls::normalize_base_url(base_url) {
+            issues.push(ConfigIssue::error("configuration.base_url", message));
+        }
+    }
+
+    let newsletter = &config.newsletter;
+    let mails = newsletter.sendmail_command.is_some() || newsletter.smtp.is_some();
+    if newsletter.enabled && mails && config.configuration.base_url.is_none() {
+        issues.push(ConfigIssue::error(
+            "configuration.base_url",
+            "required to link newsletter confirmation mail back to the site",
+        ));
+    }
+
+    let font_origin = &config.configuration.theme.font_origin;
+    if !config::FONT_ORIGINS.contains(&font_origin.as_str()) {
+        issues.push(ConfigIssue::error(
+            "configuration.theme.font_origin",
+            format!(
+                "expected one of {}, found {font_origin:?}",
+                config::FONT_ORIGINS.join(", ")
+            ),
+        ));
+    }
+
+    for (i, pattern) in config.configuration.ignore_patterns.iter().enumerate() {
+        if let Err(err) = ignore::check_pattern(pattern) {
+            issues.push(ConfigIssue::error(
+                format!("configuration.ignore_patterns[{i}]"),
+                format!("invalid glob: {err}"),
+            ));
+        }
+    }
+
+    let layout = &config.layout;
+    if let Some(shared) = &layout.shared {
+        check_components(
+            "layout.shared.head",
+            std::slice::from_ref(&shared.head),
+            &mut issues,
+        );
+        check_components("layout.shared.header", &shared.header, &mut issues);
+        check_components(
+            "layout.shared.footer",
+            std::slice::from_ref(&shared.footer),
+            &mut issues,
+        );
+        check_components("layout.shared.after_body", &shared.after_body, &mut issues);
+    }
+    let named = config
+        .layouts
+        .iter()
+        .map(|(name, page)| (format!("layouts.{name}"), Some(page)));
+    let pages = [("content", &layout.content), ("list", &layout.list)]
+        .into_iter()
+        .map(|(name, page)| (format!("layout.{name}"), page.as_ref()));
+    for (prefix, page) in pages.chain(named) {
+        if let Some(page) = page {
+            check_components(
+                &format!("{prefix}.before_body"),
+                &page.before_body,
+                &mut issues,
+            );
+            check_components(&format!("{prefix}.left"), &page.left, &mut issues);
+            check_components(&format!("{prefix}.right"), &page.right, &mut issues);
+            check_components(
+                &format!("{prefix}.after_body"),
+                &page.after_body,
+                &mut issues,
+            );
+        }
+    }
+
+    let graph = &config.layout.graph;
+    for (view, d3) in [("local", &graph.local), ("global", &graph.global)] {
+        let path = |key: &str| format!("layout.graph.{view}.{key}");
+        if d3.depth < -1 {
+            issues.push(ConfigIssue::error(
+                path("depth"),
+                format!("expected -1 (whole graph) or more, found {}", d3.depth),
+            ));
+        }
+        let positive = [
+            ("scale", d3.scale),
+            ("font_size", d3.font_size),
+            ("opacity_scale", d3.opacity_scale),
+        ];
+        for (key, value) in positive {
+            if value.is_nan() || value <= 0.0 {
+                issues.push(ConfigIssue::error(
+                    path(key),
+                    format!("expected a number above 0, found {value}"),
+                ));
+            }
+        }
+        let non_negative = [
+            ("repel_force", d3.repel_force),
+            ("center_force", d3.center_force),
+            ("link_distance", d3.link_distance as f32),
+        ];
+        for (key, value) in non_negative {
+            if value.is_nan() || value < 0.0 {
+                issues.push(ConfigIssue::error(
+                    path(key),
+                    format!("expected 0 or more, found {value}"),
+                ));
+            }
+        }
+    }
+
+    let explorer = &config.layout.explorer;
+    let fns = [
+        (
+            "layout.explorer.sort_fn",
+            explorer_fns::expand_sort_preset(&explorer.sort_fn),
+        ),
+        ("layout.explorer.filter_fn", explorer.filter_fn.clone()),
+        ("layout.explorer.map_fn", explorer.map_fn.clone()),
+    ];
+    for (path, source) in fns {
+        if let Err(err) = explorer_fns::check_fn(&source) {
+            issues.push(ConfigIssue::warning(
+                path,
+                format!("{err}; using the built-in default instead"),
+            ));
+        }
+    }
+
+    for (i, social) in config.layout.footer.socials.iter().enumerate() {
+        let path = format!("layout.footer.socials[{i}]");
+        if social.url.trim().is_empty() {
+            issues.push(ConfigIssue::warning(
+                format!("{path}.url"),
+                "empty; this link is left out of the footer",
+            ));
+        }
+        if !socials::is_known(&social.platform) {
+            issues.push(ConfigIssue::warning(
+                format!("{path}.platform"),
+                format!(
+                    "no icon for {:?}; a generic globe is shown",
+                    social.platform
+                ),
+            ));
+        }
+    }
+
+    let syntax = &config.configuration.theme.syntax;
+    for (mode, name) in [("light", &syntax.light), ("dark", &syntax.dark)] {
+        if config::syntax_theme(name).is_none() {
+            let available: Vec<&str> = config::SYNTAX_THEMES.iter().map(|t| t.name).collect();
+            issues.push(ConfigIssue::warning(
+                format!("configuration.theme.syntax.{mode}"),
+                format!(
+                    "unknown syntax theme {name:?}; using github-{mode}. Available: {}",
+                    available.join(", ")
+                ),
+            ));
+        }
+    }
+
+    let breakpoints = &config.configuration.theme.breakpoints;
+    for (key, value) in [
+        ("mobile", &breakpoints.mobile),
+        ("desktop", &breakpoints.desktop),
+    ] {
+        if !styles::is_css_length(value) {
+            issues.push(ConfigIssue::error(
+                format!("configuration.theme.breakpoints.{key}"),
+                format!("expected a px, em or rem length such as \"800px\", found {value:?}"),
+            ));
+        }
+    }
+
+    let targets = &config.configuration.theme.css_targets;
+    if let Err(err) = styles::check_css_targets(targets) {
+        issues.push(ConfigIssue::warning(
+            "configuration.theme.css_targets",
+            format!("{err}; CSS is minified without prefixing"),
+        ));
+    }
+
+    if let Some(path) = config.custom_scss() {
+        if !path.is_file() {
+            issues.push(ConfigIssue::warning(
+                "configuration.theme.custom_scss",
+                format!("{} does not exist", path.display()),
+            ));
+        }
+    }
+
+    let analytics = &config.analytics;
+    if analytics.provider.is_some() && analytics.html.is_none() && analytics.site_id.is_none() {
+        issues.push(ConfigIssue::error(
+            "analytics.site_id",
+            "required when analytics.provider is set",
+        ));
+    }
+
+    if let Some(secrets) = config.secrets_file() {
+        if secrets.starts_with(config.content_root()) {
+            issues.push(ConfigIssue::error(
+                "paths.secrets_file",
+                "must be outside the content root so it is never published",
+            ));
+        }
+    }
+
+    if config.server.port == 0 {
+        issues.push(ConfigIssue::error(
+            "server.port",
+            "expected a port between 1 and 65535",
+        ));
+    }
+    if config.server.socket_mode_bits().is_none() {
+        issues.push(ConfigIssue::error(
+            "server.socket_mode",
+            format!(
+                "expected octal permissions such as \"0660\", found {:?}",
+                config.server.socket_mode
+            ),
+        ));
+    }
+    for proxy in &config.server.trusted_proxies {
+        if proxy.trim().parse::<IpAddr>().is_err() {
+            issues.push(ConfigIssue::error(
+                "server.trusted_proxies",
+                format!("expected an IP address such as \"127.0.0.1\", found {proxy:?}"),
+            ));
+        }
+    }
+    if config.server.max_payload_mb == 0 {
+        issues.push(ConfigIssue::error(
+            "server.max_payload_mb",
+            "expected a positive number of megabytes",
+        ));
+    }
+    if config.content.extensions.is_empty() && config.content.mappings.is_empty() {
+        issues.push(ConfigIssue::error(
+            "content.extensions",
+            "at least one content extension is required",
+        ));
+    }
+
+    issues
+}
+
+fn check_palette(prefix: &str, palette: &ThemePalette, issues: &mut Vec<ConfigIssue>) {
+    let entries = [
+        ("light", &palette.light),
+        ("lightgray", &palette.lightgray),
+        ("gray", &palette.gray),
+        ("darkgray", &palette.darkgray),
+        ("dark", &palette.dark),
+        ("secondary", &palette.secondary),
+        ("tertiary", &palette.tertiary),
+        ("highlight", &palette.highlight),
+        ("text_highlight", &palette.text_highlight),
+    ];
+
+    for (key, value) in entries {
+        if !is_css_color(value) {
+            issues.push(ConfigIssue::error(
+                format!("{prefix}.{key}"),
+                format!("expected a CSS color (e.g. #284b63 or rgba(...)), found {value:?}"),
+            ));
+        }
+    }
+}
+
+fn is_css_color(value: &str) -> bool {
+    static COLOR_RE: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(
+            r"(?i)^(#([0-9a-f]{3,4}|[0-9a-f]{6}|[0-9a-f]{8})|(rgb|rgba|hsl|hsla)\([^()]*\)|var\(--[\w-]+\)|[a-z]+)$",
+        )
+        .expect("css color regex")
+    });
+    COLOR_RE.is_match(value.trim())
+}
+
+/// Report invalid `condition.slug` globs in `ConditionalRender` components and `Flex`
+/// options that cannot go into a stylesheet, however deeply they are nested.
+fn check_components(path: &str, list: &[LayoutComponent], issues: &mut Vec<ConfigIssue>) {
+    for (i, component) in list.iter().enumerate() {
+        let path = format!("{path}[{i}]");
+        match component {
+            LayoutComponent::ConditionalRender(cfg) => {
+                if let Some(pattern) = &cfg.condition.slug {
+                    if let Err(err) = layout::slug_glob(pattern) {
+                        issues.push(ConfigIssue::error(
+                            format!("{path}.config.condition.slug"),
+                            format!("invalid glob: {err}"),
+                        ));
+                    }
+                }
+                check_components(
+                    &format!("{path}.config"),
+                    std::slice::from_ref(cfg.component.as_ref()),
+                    issues,
+                );
+            }
+            LayoutComponent::Flex(cfg) => {
+                let container = [
+                    ("direction", &cfg.direction),
+                    ("wrap", &cfg.wrap),
+                    ("gap", &cfg.gap),
+                ];
+                check_css_values(&format!("{path}.config"), &container, issues);
+                for (j, item) in cfg.components.iter().enumerate() {
+                    let values = [
+                        ("basis", &item.basis),
+                        ("align", &item.align),
+                        ("justify", &item.justify),
+                    ];
+                    check_css_values(&format!("{path}.config.components[{j}]"), &values, issues);
+                    check_components(
+                        &format!("{path}.config.components[{j}]"),
+                        std::slice::from_ref(&item.component),
+                        issues,
+                    );
+                }
+            }
+            LayoutComponent::MobileOnly(inner) | LayoutComponent::DesktopOnly(inner) => {
+                check_components(
+                    &format!("{path}.config"),
+                    std::slice::from_ref(inner.as_ref()),
+                    issues,
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+fn check_css_values(path: &str, values: &[(&str, &Option<String>)], issues: &mut Vec<ConfigIssue>) {
+    for (key, value) in values {
+        if let Some(value) = value.as_deref().filter(|v| !layout::is_safe_css_value(v)) {
+            issues.push(ConfigIssue::warning(
+                format!("{path}.{key}"),
+                format!("{value:?} cannot be used in CSS (contains ; {{ }} < > or \\); ignored"),
+            ));
+        }
+    }
+}
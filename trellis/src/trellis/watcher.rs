@@ -0,0 +1,94 @@
+use std::path::Path;
+
+use log::{error, info, warn};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::trellis::content_index::generate_content_index;
+use crate::trellis::{SiteConfig, reload_engine, trellis_engine};
+
+/// Watch `config.yml` (and the active profile overlay) and hot-swap the engine when it changes.
+///
+/// The parent directory is watched rather than the file itself so editors that save via
+/// rename are still picked up. Invalid edits are logged and the previous config keeps
+/// serving. Server settings (`host`, `port`, payload limits, CORS) are bound at startup
+/// and still require a restart. The returned watcher must be kept alive.
+pub fn watch_config() -> notify::Result<RecommendedWatcher> {
+    let config_path = SiteConfig::config_path();
+    // The active profile's overlay lives next to the base file and reloads the same way.
+    let file_names: Vec<_> = std::iter::once(config_path.clone())
+        .chain(SiteConfig::profile().map(|p| SiteConfig::profile_path(&config_path, &p)))
+        .filter_map(|path| path.file_name().map(|s| s.to_os_string()))
+        .collect();
+    let watch_dir = config_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| ".".into());
+
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        let event = match res {
+            Ok(event) => event,
+            Err(err) => {
+                warn!("config watcher error: {err}");
+                return;
+            }
+        };
+
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+            return;
+        }
+        let touches_config = event
+            .paths
+            .iter()
+            .filter_map(|p| p.file_name())
+            .any(|name| file_names.iter().any(|n| n == name));
+        if !touches_config {
+            return;
+        }
+
+        match reload_engine() {
+            Ok(()) => {
+                info!("Reloaded configuration from {}", config_path.display());
+                let engine = trellis_engine();
+                if let Err(err) = generate_content_index(
+                    engine.content_root(),
+                    engine.cache_root(),
+                    engine.ignore_matcher(),
+                    engine.registry(),
+                    &engine.config,
+                ) {
+                    error!("failed to regenerate content index: {err}");
+                }
+                if let Err(err) = engine.sync_static() {
+                    error!("failed to copy static directories: {err}");
+                }
+            }
+            Err(err) => error!("Keeping previous configuration: {err:#}"),
+        }
+    })?;
+
+    watcher.watch(&watch_dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
+
+/// Watch the configured `paths.static_dirs` and mirror changes into the build output.
+///
+/// Directories are read once when the watcher starts; changing `static_dirs` in
+/// `config.yml` triggers a one-off sync on reload but the watch set needs a restart.
+pub fn watch_static_dirs() -> notify::Result<RecommendedWatcher> {
+    let dirs = trellis_engine().config.static_dirs();
+
+    let mut watcher = notify::recommended_watcher(|res: notify::Result<Event>| match res {
+        Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+            if let Err(err) = trellis_engine().sync_static() {
+                error!("failed to copy static directories: {err}");
+            }
+        }
+        Ok(_) => {}
+        Err(err) => warn!("static dir watcher error: {err}"),
+    })?;
+
+    for dir in dirs.iter().filter(|d| d.is_dir()) {
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+    }
+    Ok(watcher)
+}
@@ -0,0 +1,44 @@
+// Dismissible announcement bars. Dismissal is stored per banner id, so a banner with a
+// new id shows again.
+const STORAGE_PREFIX = "trellis-banner-dismissed:";
+
+function isDismissed(id: string): boolean {
+  try {
+    return localStorage.getItem(STORAGE_PREFIX + id) === "1";
+  } catch {
+    return false;
+  }
+}
+
+function dismiss(banner: HTMLElement, id: string): void {
+  banner.hidden = true;
+  try {
+    localStorage.setItem(STORAGE_PREFIX + id, "1");
+  } catch {
+    // Storage unavailable (private mode); the banner just returns on the next page.
+  }
+}
+
+function setupBanners(): void {
+  for (const banner of document.querySelectorAll<HTMLElement>(
+    ".trellis-banner[data-banner-id]"
+  )) {
+    const id = banner.dataset.bannerId ?? "";
+    if (isDismissed(id)) {
+      banner.hidden = true;
+      continue;
+    }
+    const button = banner.querySelector<HTMLButtonElement>(".banner-dismiss");
+    if (!button || !button.hidden) continue;
+    button.hidden = false;
+    button.addEventListener("click", () => dismiss(banner, id), { once: true });
+  }
+}
+
+if (document.readyState === "loading") {
+  document.addEventListener("DOMContentLoaded", setupBanners, { once: true });
+} else {
+  setupBanners();
+}
+
+document.addEventListener("nav", setupBanners);
@@ -0,0 +1,179 @@
+// Loads the giscus / utterances client into `.trellis-comments` once it nears the
+// viewport, and keeps the widget's theme in step with the site's color scheme. Native
+// comment forms are posted with fetch so the page stays put.
+const ORIGINS: Record<string, string> = {
+  giscus: "https://giscus.app",
+  utterances: "https://utteranc.es",
+};
+
+function isDark(): boolean {
+  return getComputedStyle(document.documentElement).colorScheme === "dark";
+}
+
+function themeFor(container: HTMLElement): string {
+  return (
+    (isDark() ? container.dataset.darkTheme : container.dataset.lightTheme) ?? ""
+  );
+}
+
+function loadProvider(container: HTMLElement): void {
+  const src = container.dataset.src;
+  if (!src || container.dataset.loaded) return;
+  container.dataset.loaded = "true";
+
+  const script = document.createElement("script");
+  script.src = src;
+  script.async = true;
+  script.crossOrigin = "anonymous";
+  const attrs = JSON.parse(container.dataset.attrs ?? "{}") as Record<
+    string,
+    string
+  >;
+  for (const [name, value] of Object.entries(attrs)) {
+    script.setAttribute(name, value);
+  }
+  const theme = themeFor(container);
+  script.setAttribute(
+    container.dataset.provider === "giscus" ? "data-theme" : "theme",
+    theme
+  );
+  // Not `data-spa-preserve`: each page gets its own thread.
+  container.appendChild(script);
+}
+
+function syncTheme(): void {
+  for (const container of document.querySelectorAll<HTMLElement>(
+    ".trellis-comments"
+  )) {
+    const provider = container.dataset.provider ?? "";
+    const iframe = container.querySelector("iframe");
+    const origin = ORIGINS[provider];
+    if (!iframe?.contentWindow || !origin) continue;
+    const theme = themeFor(container);
+    const message =
+      provider === "giscus"
+        ? { giscus: { setConfig: { theme } } }
+        : { type: "set-theme", theme };
+    iframe.contentWindow.postMessage(message, origin);
+  }
+}
+
+interface PostedComment {
+  id: number;
+  name: string;
+  html: string;
+  created_at: string;
+  day: string;
+}
+
+function appendComment(section: HTMLElement, comment: PostedComment): void {
+  const item = document.createElement("li");
+  item.className = "trellis-comment";
+  item.id = `comment-${comment.id}`;
+  const meta = document.createElement("p");
+  meta.className = "trellis-comment__meta";
+  const name = document.createElement("strong");
+  name.textContent = comment.name;
+  const time = document.createElement("time");
+  time.dateTime = comment.created_at;
+  time.textContent = comment.day;
+  meta.append(name, " ", time);
+  const body = document.createElement("div");
+  body.className = "trellis-comment__body";
+  // Rendered by the server with raw HTML escaped.
+  body.innerHTML = comment.html;
+  item.append(meta, body);
+  section.querySelector(".trellis-comments__list")?.appendChild(item);
+}
+
+async function postComment(event: SubmitEvent): Promise<void> {
+  const form = event.currentTarget as HTMLFormElement;
+  const section = form.closest<HTMLElement>(".trellis-comments--native");
+  const status = form.querySelector<HTMLElement>(".trellis-comments__status");
+  const button = form.querySelector<HTMLButtonElement>("button[type=submit]");
+  if (!section || !status) return;
+  event.preventDefault();
+
+  const fields = new FormData(form);
+  const payload = {
+    name: fields.get("name") ?? "",
+    email: fields.get("email") ?? "",
+    body: fields.get("body") ?? "",
+    website: fields.get("website") ?? "",
+  };
+  if (button) button.disabled = true;
+  status.textContent = "Posting…";
+  try {
+    const response = await fetch(form.action, {
+      method: "POST",
+      headers: { "Content-Type": "application/json", Accept: "application/json" },
+      body: JSON.stringify(payload),
+    });
+    const reply = (await response.json().catch(() => ({}))) as {
+      status?: string;
+      error?: string;
+      comment?: PostedComment;
+    };
+    if (!response.ok) {
+      status.textContent = reply.error
+        ? `Not posted: ${reply.error}.`
+        : "Not posted; please try again later.";
+      return;
+    }
+    form.reset();
+    if (reply.status === "approved" && reply.comment) {
+      appendComment(section, reply.comment);
+      status.textContent = "Posted.";
+    } else {
+      status.textContent = "Thanks! Your comment will appear once it is approved.";
+    }
+  } catch {
+    status.textContent = "Not posted; please check your connection.";
+  } finally {
+    if (button) button.disabled = false;
+  }
+}
+
+const visibility = new IntersectionObserver(
+  (entries) => {
+    for (const entry of entries) {
+      if (!entry.isIntersecting) continue;
+      visibility.unobserve(entry.target);
+      loadProvider(entry.target as HTMLElement);
+    }
+  },
+  { rootMargin: "400px" }
+);
+
+function setupComments(): void {
+  visibility.disconnect();
+  for (const container of document.querySelectorAll<HTMLElement>(
+    ".trellis-comments"
+  )) {
+    if (container.dataset.provider === "native") {
+      const form = container.querySelector("form");
+      if (form && !form.dataset.bound) {
+        form.dataset.bound = "true";
+        form.addEventListener("submit", postComment);
+      }
+      continue;
+    }
+    visibility.observe(container);
+  }
+}
+
+new MutationObserver(syncTheme).observe(document.documentElement, {
+  attributes: true,
+  attributeFilter: ["saved-theme"],
+});
+window
+  .matchMedia("(prefers-color-scheme: dark)")
+  .addEventListener("change", syncTheme);
+
+if (document.readyState === "loading") {
+  document.addEventListener("DOMContentLoaded", setupComments, { once: true });
+} else {
+  setupComments();
+}
+
+document.addEventListener("nav", setupComments);
@@ -10,10 +10,20 @@ type DecodePlanStep = {
 };
 
 type EncryptedDataset = DOMStringMap & {
-  salt: string;
+  /** Version 1 only: the password's PBKDF2 salt. */
+  salt?: string;
   nonce: string;
   ciphertext: string;
   iterations?: string;
+  /** Version 2 only: JSON array of the content key wrapped per password. */
+  keys?: string;
+  version?: string;
+};
+
+type WrappedKey = {
+  salt: string;
+  nonce: string;
+  key: string;
 };
 
 function glyphLine(length = 48): string {
@@ -129,23 +139,11 @@ function runDecodeEffect(
   });
 }
 
-async function decryptPayload(
-  dataset: EncryptedDataset,
-  password: string
-): Promise<string> {
-  const salt = b64ToBytes(dataset.salt);
-  const nonce = b64ToBytes(dataset.nonce);
-  const ciphertext = b64ToBytes(dataset.ciphertext);
-  const saltBuf = salt.buffer as ArrayBuffer;
-  const nonceBuf = nonce.buffer as ArrayBuffer;
-  const cipherBuf = ciphertext.buffer as ArrayBuffer;
-
-  if (!salt.length || !nonce.length || !ciphertext.length) {
-    throw new Error("Missing cipher payload");
-  }
-
-  const iterations = Number(dataset.iterations) || 120000;
-
+async function passwordKey(
+  password: string,
+  salt: Uint8Array,
+  iterations: number
+): Promise<CryptoKey> {
   const baseKey = await crypto.subtle.importKey(
     "raw",
     encoder.encode(password),
@@ -154,10 +152,10 @@ async function decryptPayload(
     ["deriveKey"]
   );
 
-  const aesKey = await crypto.subtle.deriveKey(
+  return crypto.subtle.deriveKey(
     {
       name: "PBKDF2",
-      salt: saltBuf,
+      salt: salt.buffer as ArrayBuffer,
       iterations,
       hash: "SHA-256",
     },
@@ -166,6 +164,51 @@ async function decryptPayload(
     false,
     ["decrypt"]
   );
+}
+
+// Version 2 notes have several passwords: each one unwraps the same random content key.
+async function unwrapContentKey(
+  keysJson: string,
+  password: string,
+  iterations: number
+): Promise<CryptoKey> {
+  const wraps = JSON.parse(keysJson) as WrappedKey[];
+  for (const wrap of wraps) {
+    const wrapKey = await passwordKey(password, b64ToBytes(wrap.salt), iterations);
+    try {
+      const raw = await crypto.subtle.decrypt(
+        { name: "AES-GCM", iv: b64ToBytes(wrap.nonce).buffer as ArrayBuffer },
+        wrapKey,
+        b64ToBytes(wrap.key).buffer as ArrayBuffer
+      );
+      return crypto.subtle.importKey("raw", raw, "AES-GCM", false, ["decrypt"]);
+    } catch {
+      // Not this recipient's password; try the next wrap.
+    }
+  }
+  throw new Error("No key unwraps with this password");
+}
+
+async function decryptPayload(
+  dataset: EncryptedDataset,
+  password: string
+): Promise<{ html: string; key: CryptoKey }> {
+  const multi = dataset.version === "2";
+  const salt = b64ToBytes(dataset.salt || "");
+  const nonce = b64ToBytes(dataset.nonce);
+  const ciphertext = b64ToBytes(dataset.ciphertext);
+  const nonceBuf = nonce.buffer as ArrayBuffer;
+  const cipherBuf = ciphertext.buffer as ArrayBuffer;
+
+  if ((!multi && !salt.length) || !nonce.length || !ciphertext.length) {
+    throw new Error("Missing cipher payload");
+  }
+
+  const iterations = Number(dataset.iterations) || 120000;
+
+  const aesKey = multi
+    ? await unwrapContentKey(dataset.keys || "[]", password, iterations)
+    : await passwordKey(password, salt, iterations);
 
   const plaintext = await crypto.subtle.decrypt(
     { name: "AES-GCM", iv: nonceBuf },
@@ -173,7 +216,33 @@ async function decryptPayload(
     cipherBuf
   );
 
-  return decoder.decode(plaintext);
+  return { html: decoder.decode(plaintext), key: aesKey };
+}
+
+// Attachments are served encrypted (nonce, then ciphertext) under the note's key; swap
+// each one in as a blob URL once the note is unlocked.
+async function decryptAttachments(root: HTMLElement, key: CryptoKey) {
+  const pending = Array.from(root.querySelectorAll<HTMLElement>("[data-enc-src]")).map(
+    async (el) => {
+      const { encSrc, encAttr, encType } = el.dataset;
+      if (!encSrc || !encAttr) return;
+      try {
+        const res = await fetch(encSrc);
+        if (!res.ok) throw new Error(`HTTP ${res.status}`);
+        const data = new Uint8Array(await res.arrayBuffer());
+        const plain = await crypto.subtle.decrypt(
+          { name: "AES-GCM", iv: data.slice(0, 12) },
+          key,
+          data.slice(12)
+        );
+        const blob = new Blob([plain], { type: encType || "application/octet-stream" });
+        el.setAttribute(encAttr, URL.createObjectURL(blob));
+      } catch (err) {
+        console.warn("Failed to decrypt attachment", encSrc, err);
+      }
+    }
+  );
+  await Promise.all(pending);
 }
 
 function initEncryptedNote(note: HTMLElement) {
@@ -224,7 +293,7 @@ function initEncryptedNote(note: HTMLElement) {
     setStatus("Decrypting…");
 
     try {
-      const plaintext = await decryptPayload(
+      const { html: plaintext, key } = await decryptPayload(
         note.dataset as EncryptedDataset,
         password
       );
@@ -235,6 +304,7 @@ function initEncryptedNote(note: HTMLElement) {
       if (body) {
         body.innerHTML = plaintext;
         body.hidden = false;
+        void decryptAttachments(body, key);
       }
 
       if (decode) {
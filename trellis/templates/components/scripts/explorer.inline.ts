@@ -1,5 +1,12 @@
 import { FileTrieNode, type ContentEntry } from "../../util/fileTrie";
-import { resolveRelative, simplifySlug, type FullSlug } from "../../util/path";
+import {
+  getFullSlug,
+  joinSegments,
+  pathToRoot,
+  resolveRelative,
+  simplifySlug,
+  type FullSlug,
+} from "../../util/path";
 
 declare const fetchData:
   | Promise<Record<string, ContentEntry>>
@@ -27,6 +34,46 @@ type DataFns = Partial<
 
 type ContentNode = FileTrieNode<ContentEntry>;
 
+/** One node of `GET /api/explorer`; a folder's slug has no `/index`. */
+type ExplorerTreeNode = {
+  name: string;
+  slug: string;
+  isFolder: boolean;
+  weight?: number;
+  children?: ExplorerTreeNode[];
+};
+
+let explorerTree: Promise<ContentEntry[]> | undefined;
+
+/** The compact tree from the server as content entries, so the trie builds the same way. */
+function flattenTree(nodes: ExplorerTreeNode[], out: ContentEntry[] = []): ContentEntry[] {
+  for (const node of nodes) {
+    const slug = node.isFolder ? `${node.slug}/index` : node.slug;
+    out.push({ slug, filePath: slug, title: node.name, weight: node.weight });
+    if (node.children) flattenTree(node.children, out);
+  }
+  return out;
+}
+
+/**
+ * Entries for the trie: the compact tree when the server says it is enough, else (or
+ * when the endpoint is missing, as in static exports) the full content index.
+ */
+async function loadEntries(source: string | undefined): Promise<ContentEntry[]> {
+  if (source === "api") {
+    explorerTree ??= fetch(joinSegments(pathToRoot(getFullSlug(window)), "api/explorer"))
+      .then((res) => (res.ok ? (res.json() as Promise<ExplorerTreeNode[]>) : Promise.reject()))
+      .then((tree) => flattenTree(tree));
+    try {
+      return await explorerTree;
+    } catch {
+      explorerTree = undefined;
+    }
+  }
+  if (typeof fetchData === "undefined") throw new Error("no content index");
+  return Object.values(await fetchData);
+}
+
 const isElement = (target: EventTarget | null): target is Element =>
   target instanceof Element;
 
@@ -271,26 +318,22 @@ async function setupExplorer(currentSlug: FullSlug) {
       serializedExplorerState.map((entry) => [entry.path, entry.collapsed])
     );
 
-    // If fetchData is missing (e.g., offline or contentIndex not emitted), keep
-    // server-rendered list and still wire toggles.
-    if (typeof fetchData === "undefined") {
-      applyStateToServerTree(explorer, opts);
-      attachFolderToggles(explorer, opts);
-      continue;
-    }
-
+    // Without the tree endpoint or the content index (offline, or the index not emitted),
+    // keep the server-rendered list and still wire toggles.
     let trie: ContentNode;
     try {
-      const data = await fetchData;
-      const entries = [...Object.entries(data)] as [string, ContentEntry][];
-      trie = FileTrieNode.fromEntries(entries);
+      const entries = await loadEntries(explorer.dataset.source);
+      trie = FileTrieNode.fromEntries(entries.map((entry) => [entry.slug, entry]));
     } catch (err) {
-      console.warn("Explorer: failed to load content index", err);
+      console.warn("Explorer: failed to load explorer data", err);
       applyStateToServerTree(explorer, opts);
       attachFolderToggles(explorer, opts);
       continue;
     }
 
+    // `nav: false` / `hide_nav: true` pages never show, whatever filterFn says.
+    trie.filter((node) => !node.data?.hideNav);
+
     // Apply functions in order
     for (const fn of opts.order) {
       switch (fn) {
@@ -363,7 +406,36 @@ async function setupExplorer(currentSlug: FullSlug) {
   }
 }
 
+function saveFolderState(folderPath: string, collapsed: boolean) {
+  const entry = currentExplorerState.find((item) => item.path === folderPath);
+  if (entry) {
+    entry.collapsed = collapsed;
+  } else {
+    currentExplorerState.push({ path: folderPath, collapsed });
+  }
+  localStorage.setItem("fileTree", JSON.stringify(currentExplorerState));
+}
+
+// The server-rendered fallback tree uses <details>, which toggles natively; only saved
+// state needs applying and recording.
+function hydrateServerDetails(explorer: Element) {
+  const folders = explorer.querySelectorAll<HTMLDetailsElement>("details.folder-details");
+  for (const details of folders) {
+    const folderPath = details.dataset.folderpath || "";
+    const saved = currentExplorerState.find((item) => item.path === folderPath);
+    // Folders holding the current page are rendered open and stay open.
+    if (saved && !details.querySelector('a[aria-current="page"]')) {
+      details.open = !saved.collapsed;
+    }
+
+    const onToggle = () => saveFolderState(folderPath, !details.open);
+    details.addEventListener("toggle", onToggle);
+    registerCleanup?.(() => details.removeEventListener("toggle", onToggle));
+  }
+}
+
 function applyStateToServerTree(explorer: Element, opts: ExplorerOptions) {
+  hydrateServerDetails(explorer);
   const folderContainers = explorer.querySelectorAll<HTMLElement>(".folder-container");
   for (const container of folderContainers) {
     const folderOuter = findFolderOuter(container);
@@ -1,16 +1,26 @@
 import { registerEscapeHandler, removeAllChildren } from "./util";
-import { getFullSlug, resolveRelative, simplifySlug } from "../../util/path";
-
-declare const fetchData: Promise<Record<string, ContentDetails>> | undefined;
-
-type ContentDetails = {
-  slug: string;
-  filePath: string;
-  title?: string;
-  links?: string[];
-  tags?: string[];
+import {
+  getFullSlug,
+  joinSegments,
+  pathToRoot,
+  resolveRelative,
+  simplifySlug,
+} from "../../util/path";
+
+/** `static/graph.json`, written with the content index. */
+type GraphJson = {
+  nodes: Array<{ id: string; title: string; group: string; tag?: boolean }>;
+  links: Array<{ source: string; target: string }>;
 };
 
+let graphJson: Promise<GraphJson | undefined> | null = null;
+function fetchGraph(): Promise<GraphJson | undefined> {
+  graphJson ??= fetch(joinSegments(pathToRoot(getFullSlug(window)), "static/graph.json"))
+    .then((res) => (res.ok ? (res.json() as Promise<GraphJson>) : undefined))
+    .catch(() => undefined);
+  return graphJson;
+}
+
 const loadDeps = (() => {
   let cache: Promise<[any, any, any]> | null = null;
   return () => {
@@ -99,41 +109,21 @@ async function renderGraph(graph: HTMLElement, fullSlug: string) {
     enableRadial,
   } = JSON.parse(graph.dataset["cfg"] ?? "{}");
 
-  if (!fetchData) {
+  const graphJson = await fetchGraph();
+  if (!graphJson) {
     return () => {};
   }
 
-  const data: Map<string, ContentDetails> = new Map(
-    Object.entries<ContentDetails>(await fetchData).map(([k, v]) => [
-      simplifySlug(k as any) as string,
-      v,
-    ])
+  const data = new Map(graphJson.nodes.map((node) => [node.id, node]));
+  // graph.json already drops tags both graphs remove; this graph may hide more.
+  const removed = new Set(
+    (removeTags as string[]).map((tag) => simplifySlug(("tags/" + tag) as any) as string)
   );
-
-  const links: Array<{ source: string; target: string }> = [];
-  const tags: string[] = [];
-  const validLinks = new Set(data.keys());
+  const hidden = (id: string) =>
+    data.get(id)?.tag === true && (!showTags || removed.has(id));
+  const links = graphJson.links.filter((l) => !hidden(l.source) && !hidden(l.target));
 
   const tweens = new Map<string, TweenNode>();
-  for (const [source, details] of data.entries()) {
-    const outgoing = details.links ?? [];
-    for (const dest of outgoing) {
-      if (validLinks.has(dest)) {
-        links.push({ source: source, target: dest });
-      }
-    }
-    if (showTags) {
-      const localTags = (details.tags ?? [])
-        .filter((tag) => !removeTags.includes(tag))
-        .map((tag) => simplifySlug(("tags/" + tag) as any) as string);
-
-      tags.push(...localTags.filter((tag) => !tags.includes(tag)));
-      for (const tag of localTags) {
-        links.push({ source: source, target: tag });
-      }
-    }
-  }
-
   const neighbourhood = new Set<string>();
   const wl: Array<string> = [slug, "__SENTINEL"];
   if (depth >= 0) {
@@ -153,18 +143,17 @@ async function renderGraph(graph: HTMLElement, fullSlug: string) {
       }
     }
   } else {
-    validLinks.forEach((id) => neighbourhood.add(id));
-    if (showTags) tags.forEach((tag) => neighbourhood.add(tag));
+    data.forEach((_, id) => {
+      if (!hidden(id)) neighbourhood.add(id);
+    });
   }
 
   const nodes = [...neighbourhood].map((url) => {
-    const text = url.startsWith("tags/")
-      ? "#" + url.substring(5)
-      : data.get(url)?.title ?? url;
+    const node = data.get(url);
     return {
       id: url,
-      text,
-      tags: data.get(url)?.tags ?? [],
+      text: node?.tag ? "#" + node.title : node?.title ?? url,
+      group: node?.group ?? "",
     };
   });
   const graphData = {
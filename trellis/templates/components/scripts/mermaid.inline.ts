@@ -242,8 +242,10 @@ async function hydrateMermaid(): Promise<void> {
       {}
     );
 
+    // The theme CSS sets color-scheme for whichever palette is in effect, including the
+    // configured default and prefers-color-scheme when no theme has been saved.
     const darkMode =
-      document.documentElement.getAttribute("saved-theme") === "dark";
+      getComputedStyle(document.documentElement).colorScheme === "dark";
     mermaid.initialize({
       startOnLoad: false,
       securityLevel: "loose",
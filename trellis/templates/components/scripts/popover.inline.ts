@@ -0,0 +1,126 @@
+// Hover previews for internal links. A page's `richContent` from the content index is
+// shown when the index carries it (`content_index.rich_content`); otherwise the page is
+// fetched once and its article reused for later hovers.
+import { fetchCanonical } from "./util";
+import type { ContentEntry } from "../../util/fileTrie";
+
+declare const fetchData: Promise<Record<string, ContentEntry>> | undefined;
+
+const GAP_PX = 8;
+const previews = new Map<string, Promise<HTMLElement | null>>();
+
+function slugOf(url: URL): string {
+  const path = decodeURIComponent(url.pathname).replace(/^\/+|\/+$/g, "");
+  return path === "" ? "index" : path.replace(/\.html$/, "");
+}
+
+function isPreviewable(link: HTMLAnchorElement): boolean {
+  if (link.dataset.noPopover !== undefined || link.target === "_blank") return false;
+  const url = new URL(link.href, window.location.href);
+  if (url.origin !== window.location.origin) return false;
+  return url.pathname !== window.location.pathname;
+}
+
+async function fromIndex(slug: string): Promise<string | undefined> {
+  const index = await fetchData;
+  if (!index) return undefined;
+  const entry = index[slug] ?? index[`${slug}/index`];
+  return entry?.richContent;
+}
+
+async function fromPage(url: URL): Promise<string | undefined> {
+  const res = await fetchCanonical(`${url.origin}${url.pathname}`);
+  if (!res.ok || !res.headers.get("content-type")?.startsWith("text/html")) {
+    return undefined;
+  }
+  const doc = new DOMParser().parseFromString(await res.text(), "text/html");
+  return doc.querySelector("article")?.innerHTML;
+}
+
+async function buildPreview(url: URL): Promise<HTMLElement | null> {
+  const html =
+    (await fromIndex(slugOf(url))) ?? (await fromPage(url).catch(() => undefined));
+  if (!html) return null;
+  const popover = document.createElement("div");
+  popover.className = "popover";
+  const inner = document.createElement("div");
+  inner.className = "popover-inner";
+  inner.dataset.contentType = "text/html";
+  // From our own index or page, already sanitized when it came from the index.
+  inner.innerHTML = html;
+  // Previews are for reading; ids would clash with the page's own headings.
+  for (const el of inner.querySelectorAll("[id]")) {
+    el.setAttribute("data-id", el.id);
+    el.removeAttribute("id");
+  }
+  popover.appendChild(inner);
+  return popover;
+}
+
+function position(popover: HTMLElement, link: HTMLAnchorElement): void {
+  const rect = link.getBoundingClientRect();
+  const width = popover.offsetWidth;
+  const height = popover.offsetHeight;
+  const x = Math.max(0, Math.min(rect.left, window.innerWidth - width));
+  const below = rect.bottom + GAP_PX;
+  const y =
+    below + height > window.innerHeight ? Math.max(0, rect.top - height - GAP_PX) : below;
+  popover.style.transform = `translate(${x}px, ${y}px)`;
+}
+
+async function showPreview(link: HTMLAnchorElement): Promise<void> {
+  const url = new URL(link.href, window.location.href);
+  const key = `${url.origin}${url.pathname}`;
+  let preview = previews.get(key);
+  if (!preview) {
+    preview = buildPreview(url);
+    previews.set(key, preview);
+  }
+  const popover = await preview;
+  if (!popover || !link.matches(":hover")) return;
+
+  if (!popover.isConnected) document.body.appendChild(popover);
+  hidePreviews();
+  position(popover, link);
+  popover.classList.add("active-popover");
+
+  const inner = popover.querySelector<HTMLElement>(".popover-inner");
+  const anchor = url.hash.slice(1);
+  if (inner && anchor) {
+    const id = CSS.escape(decodeURIComponent(anchor));
+    const target = inner.querySelector<HTMLElement>(`[data-id="${id}"]`);
+    if (target) inner.scroll({ top: target.offsetTop - 12, behavior: "instant" });
+  }
+}
+
+function hidePreviews(): void {
+  for (const el of document.querySelectorAll(".active-popover")) {
+    el.classList.remove("active-popover");
+  }
+}
+
+function setupPopovers(): void {
+  for (const link of document.querySelectorAll<HTMLAnchorElement>("article a[href]")) {
+    if (link.dataset.popoverReady === "true" || !isPreviewable(link)) continue;
+    link.dataset.popoverReady = "true";
+    const enter = () => void showPreview(link);
+    link.addEventListener("mouseenter", enter);
+    link.addEventListener("mouseleave", hidePreviews);
+    window.addCleanup?.(() => {
+      link.removeEventListener("mouseenter", enter);
+      link.removeEventListener("mouseleave", hidePreviews);
+    });
+  }
+}
+
+if (document.readyState === "loading") {
+  document.addEventListener("DOMContentLoaded", setupPopovers, { once: true });
+} else {
+  setupPopovers();
+}
+
+document.addEventListener("nav", () => {
+  document.querySelectorAll(".popover").forEach((el) => el.remove());
+  previews.clear();
+  setupPopovers();
+});
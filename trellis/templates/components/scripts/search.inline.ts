@@ -0,0 +1,249 @@
+// Live search over /api/search: results drop down under the box as you type, arrow keys
+// and enter pick one, escape closes. A configured hotkey opens the same box full-screen.
+// Without this script the form submits to the server-rendered /search page.
+import { removeAllChildren } from "./util";
+
+interface SearchHit {
+  slug: string;
+  href: string;
+  title: string;
+  tags: string[];
+  /** Escaped page text with matches wrapped in `<mark>`. */
+  snippet: string;
+}
+
+interface Hotkey {
+  key: string;
+  ctrl: boolean;
+  alt: boolean;
+  shift: boolean;
+}
+
+const DEBOUNCE_MS = 150;
+const ENDPOINT = "/api/search";
+
+function parseHotkey(spec: string): Hotkey | null {
+  const parts = spec.toLowerCase().split("+");
+  const key = parts.pop()?.trim();
+  if (!key) return null;
+  return {
+    key,
+    ctrl: parts.includes("ctrl"),
+    alt: parts.includes("alt"),
+    shift: parts.includes("shift"),
+  };
+}
+
+function matchesHotkey(e: KeyboardEvent, hotkey: Hotkey): boolean {
+  // `ctrl` also matches ⌘ so `ctrl+k` works as cmd+k on macOS.
+  return (
+    e.key.toLowerCase() === hotkey.key &&
+    (e.ctrlKey || e.metaKey) === hotkey.ctrl &&
+    e.altKey === hotkey.alt &&
+    (hotkey.key.length > 1 ? e.shiftKey === hotkey.shift : true)
+  );
+}
+
+function isTyping(target: EventTarget | null): boolean {
+  const el = target as HTMLElement | null;
+  if (!el) return false;
+  return (
+    el.isContentEditable ||
+    el.tagName === "INPUT" ||
+    el.tagName === "TEXTAREA" ||
+    el.tagName === "SELECT"
+  );
+}
+
+function setupSearch(root: HTMLElement): void {
+  if (root.dataset.ready === "true") return;
+  const input = root.querySelector<HTMLInputElement>(".search-bar");
+  const results = root.querySelector<HTMLUListElement>(".search-results");
+  const backdrop = root.querySelector<HTMLElement>(".search-backdrop");
+  if (!input || !results) return;
+  root.dataset.ready = "true";
+
+  const limit = Number(root.dataset.limit) || 8;
+  let hotkeys: Hotkey[] = [];
+  try {
+    hotkeys = (JSON.parse(root.dataset.hotkeys ?? "[]") as string[])
+      .map(parseHotkey)
+      .filter((hotkey): hotkey is Hotkey => hotkey !== null);
+  } catch {
+    hotkeys = [];
+  }
+
+  const listId = `search-results-${Math.random().toString(36).slice(2, 8)}`;
+  results.id = listId;
+  input.setAttribute("aria-controls", listId);
+
+  let hits: SearchHit[] = [];
+  let active = -1;
+  let timer: number | undefined;
+  let controller: AbortController | null = null;
+
+  function setOpen(open: boolean): void {
+    results!.hidden = !open;
+    input!.setAttribute("aria-expanded", open ? "true" : "false");
+  }
+
+  function setActive(index: number): void {
+    const items = results!.querySelectorAll<HTMLLIElement>("li[role=option]");
+    if (items.length === 0) return;
+    active = (index + items.length) % items.length;
+    items.forEach((item, i) => {
+      const selected = i === active;
+      item.classList.toggle("active", selected);
+      item.setAttribute("aria-selected", selected ? "true" : "false");
+      if (selected) {
+        input!.setAttribute("aria-activedescendant", item.id);
+        item.scrollIntoView({ block: "nearest" });
+      }
+    });
+  }
+
+  function render(query: string): void {
+    removeAllChildren(results!);
+    active = -1;
+    input!.removeAttribute("aria-activedescendant");
+    if (!query) {
+      setOpen(false);
+      return;
+    }
+    if (hits.length === 0) {
+      const empty = document.createElement("li");
+      empty.className = "search-empty";
+      empty.textContent = `No results for "${query}"`;
+      results!.appendChild(empty);
+      setOpen(true);
+      return;
+    }
+    hits.forEach((hit, i) => {
+      const item = document.createElement("li");
+      item.id = `${listId}-${i}`;
+      item.setAttribute("role", "option");
+      item.setAttribute("aria-selected", "false");
+      const link = document.createElement("a");
+      link.href = hit.href;
+      link.className = "internal";
+      const title = document.createElement("span");
+      title.className = "search-result-title";
+      title.textContent = hit.title;
+      const snippet = document.createElement("span");
+      snippet.className = "search-result-snippet";
+      // Escaped by the server; only `<mark>` tags are markup.
+      snippet.innerHTML = hit.snippet;
+      link.append(title, snippet);
+      link.addEventListener("click", () => close());
+      item.appendChild(link);
+      results!.appendChild(item);
+    });
+    setOpen(true);
+  }
+
+  async function query(text: string): Promise<void> {
+    controller?.abort();
+    const q = text.trim();
+    if (!q) {
+      hits = [];
+      render("");
+      return;
+    }
+    controller = new AbortController();
+    try {
+      const url = `${ENDPOINT}?q=${encodeURIComponent(q)}&limit=${limit}`;
+      const res = await fetch(url, { signal: controller.signal });
+      if (!res.ok) return;
+      const data = (await res.json()) as { results: SearchHit[] };
+      hits = data.results ?? [];
+      render(q);
+    } catch (err) {
+      if ((err as Error).name !== "AbortError") console.warn("search failed", err);
+    }
+  }
+
+  function onInput(): void {
+    window.clearTimeout(timer);
+    timer = window.setTimeout(() => void query(input!.value), DEBOUNCE_MS);
+  }
+
+  function openFullscreen(): void {
+    root.classList.add("fullscreen");
+    if (backdrop) backdrop.hidden = false;
+    input!.focus();
+    input!.select();
+  }
+
+  function close(): void {
+    setOpen(false);
+    root.classList.remove("fullscreen");
+    if (backdrop) backdrop.hidden = true;
+  }
+
+  function onKeydown(e: KeyboardEvent): void {
+    switch (e.key) {
+      case "ArrowDown":
+        e.preventDefault();
+        if (results!.hidden && hits.length > 0) setOpen(true);
+        setActive(active + 1);
+        break;
+      case "ArrowUp":
+        e.preventDefault();
+        setActive(active - 1);
+        break;
+      case "Enter": {
+        const hit = hits[active];
+        if (active >= 0 && hit) {
+          e.preventDefault();
+          close();
+          window.location.assign(hit.href);
+        }
+        // Otherwise the form submits to the /search results page.
+        break;
+      }
+      case "Escape":
+        e.preventDefault();
+        close();
+        input!.blur();
+        break;
+    }
+  }
+
+  function onDocumentKeydown(e: KeyboardEvent): void {
+    if (isTyping(e.target)) return;
+    if (hotkeys.some((hotkey) => matchesHotkey(e, hotkey))) {
+      e.preventDefault();
+      openFullscreen();
+    }
+  }
+
+  function onDocumentClick(e: MouseEvent): void {
+    if (!root.contains(e.target as Node) || e.target === backdrop) close();
+  }
+
+  input.addEventListener("input", onInput);
+  input.addEventListener("keydown", onKeydown);
+  input.addEventListener("focus", () => {
+    if (hits.length > 0 && input.value.trim()) setOpen(true);
+  });
+  document.addEventListener("keydown", onDocumentKeydown);
+  document.addEventListener("click", onDocumentClick);
+  window.addCleanup?.(() => {
+    document.removeEventListener("keydown", onDocumentKeydown);
+    document.removeEventListener("click", onDocumentClick);
+  });
+}
+
+function setupAll(): void {
+  for (const root of document.querySelectorAll<HTMLElement>(".search")) {
+    setupSearch(root);
+  }
+}
+
+if (document.readyState === "loading") {
+  document.addEventListener("DOMContentLoaded", setupAll, { once: true });
+} else {
+  setupAll();
+}
+
+document.addEventListener("nav", setupAll);
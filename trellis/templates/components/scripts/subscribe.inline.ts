@@ -0,0 +1,58 @@
+// Posts newsletter sign-up forms with fetch and reports the outcome in place. Without
+// this script the form posts normally and the server answers with a page of its own.
+async function subscribe(event: SubmitEvent): Promise<void> {
+  const form = event.currentTarget as HTMLFormElement;
+  const status = form.querySelector<HTMLElement>(".trellis-subscribe__status");
+  const button = form.querySelector<HTMLButtonElement>("button[type=submit]");
+  if (!status) return;
+  event.preventDefault();
+
+  const fields = new FormData(form);
+  const payload = {
+    email: fields.get("email") ?? "",
+    website: fields.get("website") ?? "",
+  };
+  if (button) button.disabled = true;
+  status.textContent = "Subscribing…";
+  try {
+    const response = await fetch(form.action, {
+      method: "POST",
+      headers: { "Content-Type": "application/json", Accept: "application/json" },
+      body: JSON.stringify(payload),
+    });
+    const reply = (await response.json().catch(() => ({}))) as {
+      message?: string;
+      error?: string;
+    };
+    if (!response.ok) {
+      status.textContent = reply.error
+        ? `Not subscribed: ${reply.error}.`
+        : "Not subscribed; please try again later.";
+      return;
+    }
+    form.reset();
+    status.textContent = reply.message ?? "Subscribed.";
+  } catch {
+    status.textContent = "Not subscribed; please check your connection.";
+  } finally {
+    if (button) button.disabled = false;
+  }
+}
+
+function setupSubscribe(): void {
+  for (const form of document.querySelectorAll<HTMLFormElement>(
+    ".trellis-subscribe__form"
+  )) {
+    if (form.dataset.bound) continue;
+    form.dataset.bound = "true";
+    form.addEventListener("submit", subscribe);
+  }
+}
+
+if (document.readyState === "loading") {
+  document.addEventListener("DOMContentLoaded", setupSubscribe, { once: true });
+} else {
+  setupSubscribe();
+}
+
+document.addEventListener("nav", setupSubscribe);
@@ -0,0 +1,46 @@
+// Scroll-spy for the server-rendered table of contents: entries whose heading has
+// scrolled past the bottom of the viewport are marked `in-view`.
+const observer = new IntersectionObserver((entries) => {
+  for (const entry of entries) {
+    const link = document.querySelector(
+      `.toc a[data-for="${CSS.escape(entry.target.id)}"]`
+    );
+    const windowHeight = entry.rootBounds?.height;
+    if (!link || !windowHeight) continue;
+    link.classList.toggle(
+      "in-view",
+      entry.boundingClientRect.y < windowHeight
+    );
+  }
+});
+
+function toggleToc(this: HTMLElement) {
+  this.classList.toggle("collapsed");
+  const collapsed = this.classList.contains("collapsed");
+  this.setAttribute("aria-expanded", collapsed ? "false" : "true");
+  const content = this.nextElementSibling as HTMLElement | null;
+  if (content) content.hidden = collapsed;
+}
+
+function setupToc(): void {
+  for (const toc of document.getElementsByClassName("toc")) {
+    const button = toc.querySelector("button.toc-header") as HTMLElement | null;
+    if (!button) continue;
+    button.addEventListener("click", toggleToc);
+    window.addCleanup?.(() => button.removeEventListener("click", toggleToc));
+  }
+
+  observer.disconnect();
+  const headers = document.querySelectorAll(
+    ".page-content :is(h1, h2, h3, h4, h5, h6)[id]"
+  );
+  for (const header of headers) observer.observe(header);
+}
+
+if (document.readyState === "loading") {
+  document.addEventListener("DOMContentLoaded", setupToc, { once: true });
+} else {
+  setupToc();
+}
+
+document.addEventListener("nav", setupToc);
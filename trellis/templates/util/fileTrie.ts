@@ -4,6 +4,22 @@ export type ContentEntry = {
   slug: string;
   filePath: string;
   title?: string;
+  links?: string[];
+  tags?: string[];
+  /** RFC 3339, per `configuration.default_date_type`. */
+  date?: string;
+  description?: string;
+  /** Minutes. */
+  readingTime?: number;
+  wordCount?: number;
+  /** Full plain text; only present when `content_index.fields` lists `content`. */
+  content?: string;
+  /** Sanitized opening of the rendered page; only with `content_index.rich_content`. */
+  richContent?: string;
+  /** Frontmatter `weight`; lower sorts first, unweighted entries after weighted ones. */
+  weight?: number;
+  /** Frontmatter `nav: false` or `hide_nav: true`. */
+  hideNav?: boolean;
 };
 
 export class FileTrieNode<T extends ContentEntry = ContentEntry> {
@@ -0,0 +1,230 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+const HELLO: &str = "---\ntitle: Hello\n---\n\nHello from the test vault. See [[about]].\n";
+const ABOUT: &str = "---\ntitle: About\n---\n\nBack to [[hello]].\n";
+
+/// A site directory with the repository's `config.yml`, its paths pointed at `content/`
+/// and `cache/` inside the directory, and `files` as the content.
+fn site(files: &[(&str, &str)]) -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = include_str!("../config.yml").to_string();
+    for (from, to) in [
+        ("content_root: ../content/", "content_root: content/"),
+        ("cache_root: ../.build/", "cache_root: cache/"),
+        ("  templates_root: ./templates/\n", ""),
+        ("- ../content/static/", "- content/static/"),
+        ("fonts_dir: ../content/fonts/", "fonts_dir: content/fonts/"),
+    ] {
+        assert!(config.contains(from), "config.yml no longer has {from:?}");
+        config = config.replace(from, to);
+    }
+    fs::write(dir.path().join("config.yml"), config).unwrap();
+    write(dir.path(), "content/static/robots.txt", "User-agent: *\n");
+    for (path, markdown) in files {
+        write(dir.path(), &format!("content/{path}"), markdown);
+    }
+    dir
+}
+
+fn write(dir: &Path, path: &str, contents: &str) {
+    let path = dir.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+/// The binary, run in `dir` against its config and a database of its own.
+fn trellis(dir: &Path) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_trellis"));
+    cmd.current_dir(dir)
+        .arg("--config")
+        .arg(dir.join("config.yml"))
+        .env("DATABASE_URL", dir.join("trellis.db"))
+        .env_remove("TRELLIS_CONFIG")
+        .env_remove("TRELLIS_ENV")
+        .env_remove("TRELLIS_BASE_DIR");
+    cmd
+}
+
+fn run(cmd: &mut Command) -> (Output, String) {
+    let output = cmd.output().unwrap();
+    let log = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    (output, log)
+}
+
+/// A server started by the test; killed when dropped.
+struct Server(Child);
+
+impl Server {
+    fn start(cmd: &mut Command) -> Self {
+        Self(
+            cmd.stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap(),
+        )
+    }
+
+    /// Wait until `connect` succeeds, for up to a minute.
+    fn wait_for<T>(&mut self, mut connect: impl FnMut() -> std::io::Result<T>) -> T {
+        let deadline = Instant::now() + Duration::from_secs(60);
+        loop {
+            if let Ok(conn) = connect() {
+                return conn;
+            }
+            if let Some(status) = self.0.try_wait().unwrap() {
+                panic!("the server exited with {status}");
+            }
+            assert!(Instant::now() < deadline, "the server never came up");
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Send a bare HTTP/1.1 GET for `path` over `stream` and return the whole response.
+fn http_get(mut stream: impl Read + Write, path: &str) -> String {
+    write!(
+        stream,
+        "GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn build_renders_pages_and_the_content_index() {
+    let dir = site(&[("hello.md", HELLO), ("about.md", ABOUT)]);
+
+    let (output, log) = run(trellis(dir.path()).arg("build"));
+    assert!(output.status.success(), "{log}");
+    let cache = dir.path().join("cache");
+    let html = fs::read_to_string(cache.join("hello.html")).unwrap();
+    assert!(html.contains("Hello from the test vault."));
+    assert!(cache.join("about.html").is_file());
+    let index = fs::read_to_string(cache.join("static/content-index.json")).unwrap();
+    assert!(index.contains("\"hello\""));
+    assert!(cache.join("static/robots.txt").is_file());
+}
+
+#[test]
+fn check_fails_on_a_broken_link() {
+    let dir = site(&[("hello.md", HELLO), ("about.md", ABOUT)]);
+    let (output, log) = run(trellis(dir.path()).arg("check"));
+    assert!(output.status.success(), "{log}");
+
+    write(dir.path(), "content/stray.md", "Off to [[nowhere]].\n");
+    let (output, log) = run(trellis(dir.path()).arg("check"));
+    assert!(!output.status.success());
+    assert!(log.contains("broken link in stray"), "{log}");
+}
+
+#[test]
+fn check_fails_on_invalid_frontmatter() {
+    let dir = site(&[
+        ("hello.md", HELLO),
+        ("bad.md", "---\ntitle: [unclosed\n---\n\nBody.\n"),
+    ]);
+    let (output, log) = run(trellis(dir.path()).arg("check"));
+    assert!(!output.status.success());
+    assert!(log.contains("invalid frontmatter"), "{log}");
+}
+
+#[test]
+fn new_scaffolds_a_note_once() {
+    let dir = site(&[("hello.md", HELLO)]);
+
+    let (output, log) = run(trellis(dir.path()).args(["new", "ideas/first-idea"]));
+    assert!(output.status.success(), "{log}");
+    let note = fs::read_to_string(dir.path().join("content/ideas/first-idea.md")).unwrap();
+    assert!(note.starts_with("---\n"));
+    assert!(note.contains("title: First Idea"), "{note}");
+    let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    assert!(note.contains("created: "), "{note}");
+    assert!(note.contains(&today), "{note}");
+
+    let (output, log) = run(trellis(dir.path()).args(["new", "ideas/first-idea"]));
+    assert!(!output.status.success());
+    assert!(log.contains("already exists"), "{log}");
+}
+
+#[test]
+fn new_refuses_slugs_outside_the_vault() {
+    let dir = site(&[("hello.md", HELLO)]);
+
+    for slug in ["../../escaped", "ideas/../../escaped", ".hidden/escaped"] {
+        let (output, log) = run(trellis(dir.path()).args(["new", slug]));
+        assert!(!output.status.success(), "{slug}: {log}");
+        assert!(log.contains("invalid slug"), "{slug}: {log}");
+    }
+    assert!(!dir.path().join("escaped.md").exists());
+    assert!(!dir.path().parent().unwrap().join("escaped.md").exists());
+}
+
+#[test]
+fn serve_answers_on_the_given_port() {
+    let dir = site(&[("hello.md", HELLO)]);
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let mut server = Server::start(trellis(dir.path()).args([
+        "serve",
+        "--host",
+        "127.0.0.1",
+        "--port",
+        &port.to_string(),
+    ]));
+    let stream = server.wait_for(|| TcpStream::connect(("127.0.0.1", port)));
+    let response = http_get(stream, "/hello");
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.contains("Hello from the test vault."));
+}
+
+#[cfg(unix)]
+#[test]
+fn serve_answers_on_a_unix_socket() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixStream;
+
+    let dir = site(&[("hello.md", HELLO)]);
+    let config_path = dir.path().join("config.yml");
+    let config = fs::read_to_string(&config_path).unwrap();
+    let commented = "  # socket_path: /run/trellis/trellis.sock";
+    assert!(config.contains(commented));
+    fs::write(
+        &config_path,
+        config.replace(commented, "  socket_path: trellis.sock"),
+    )
+    .unwrap();
+
+    let socket = dir.path().join("trellis.sock");
+    let mut server = Server::start(trellis(dir.path()).arg("serve"));
+    let stream = server.wait_for(|| UnixStream::connect(&socket));
+    let response = http_get(stream, "/hello");
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.contains("Hello from the test vault."));
+    let mode = fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o660);
+}
@@ -0,0 +1,874 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::SystemTime;
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use chrono::{DateTime, Utc};
+use trellis::test_support::TestSite;
+use trellis::trellis::config::{BundleOutput, CssDelivery, DefaultDateType, SlugMode};
+use trellis::trellis::error::RenderError;
+use trellis::trellis::layout::{LayoutComponent, RecentNotesConfig, default_content_page_layout};
+use trellis::{RenderOptions, RenderedFragment, SiteConfig, render_markdown};
+
+const HELLO: &str = "---\ntitle: Hello\n---\n\n# Greeting\n\nHello from the test vault.\n";
+
+/// The body of a GET for `path` against the site's app.
+async fn get(site: &TestSite, path: &str) -> (StatusCode, String) {
+    let app = test::init_service(site.app()).await;
+    let res = test::call_service(&app, test::TestRequest::get().uri(path).to_request()).await;
+    let status = res.status();
+    let body = test::read_body(res).await;
+    (status, String::from_utf8_lossy(&body).into_owned())
+}
+
+#[actix_web::test]
+async fn renders_a_note() {
+    let site = TestSite::new([("notes/hello.md", HELLO)]).await.unwrap();
+
+    let page = site.engine.render_page("notes/hello").unwrap();
+    assert_eq!(page.frontmatter.title.as_deref(), Some("Hello"));
+    assert!(page.html.contains("Hello from the test vault."));
+    assert_eq!(page.headings.len(), 1);
+    assert_eq!(page.headings[0].text, "Greeting");
+
+    let (status, body) = get(&site, "/notes/hello").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Hello from the test vault."));
+}
+
+#[actix_web::test]
+async fn missing_and_draft_notes_are_not_found() {
+    let site = TestSite::new([
+        ("hello.md", HELLO),
+        ("secret.md", "---\ndraft: true\n---\n\nNot yet.\n"),
+    ])
+    .await
+    .unwrap();
+
+    assert!(
+        site.engine
+            .render_page("secret")
+            .unwrap_err()
+            .is_not_found()
+    );
+    assert!(
+        site.engine
+            .render_page("nowhere")
+            .unwrap_err()
+            .is_not_found()
+    );
+    assert_eq!(get(&site, "/secret").await.0, StatusCode::NOT_FOUND);
+    assert_eq!(get(&site, "/nowhere").await.0, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn reuses_cached_html_until_the_source_changes() {
+    let site = TestSite::new([("hello.md", HELLO)]).await.unwrap();
+
+    let first = site.engine.render_page("hello").unwrap();
+    assert_eq!(first.cached, Some(false));
+    let second = site.engine.render_page("hello").unwrap();
+    assert_eq!(second.cached, Some(true));
+    assert_eq!(second.html, first.html);
+    assert_eq!(second.headings, first.headings);
+
+    site.write("hello.md", "---\ntitle: Hello\n---\n\nRewritten.\n")
+        .unwrap();
+    let third = site.engine.render_page("hello").unwrap();
+    assert_eq!(third.cached, Some(false));
+    assert!(third.html.contains("Rewritten."));
+}
+
+#[actix_web::test]
+async fn lists_backlinks_on_the_linked_note() {
+    let site = TestSite::new([
+        (
+            "alpha.md",
+            "---\ntitle: Alpha\n---\n\nSee [[beta]] for more.\n",
+        ),
+        (
+            "beta.md",
+            "---\ntitle: Beta\n---\n\nNothing links out from here.\n",
+        ),
+    ])
+    .await
+    .unwrap();
+
+    let alpha = site.engine.render_page("alpha").unwrap();
+    assert!(alpha.links.iter().any(|link| link.target == "beta"));
+
+    let (status, body) = get(&site, "/beta").await;
+    assert_eq!(status, StatusCode::OK);
+    let backlinks = body
+        .split_once("class=\"backlinks\"")
+        .map(|(_, rest)| rest)
+        .expect("beta has a backlinks section");
+    assert!(backlinks.contains("Alpha"));
+}
+
+#[actix_web::test]
+async fn tags_responses_with_a_request_id() {
+    let site = TestSite::new([("hello.md", HELLO)]).await.unwrap();
+    let app = test::init_service(site.app()).await;
+
+    let res = test::call_service(&app, test::TestRequest::get().uri("/hello").to_request()).await;
+    let id = res.headers().get("x-request-id").expect("a request id");
+    assert!(!id.is_empty());
+
+    let req = test::TestRequest::get()
+        .uri("/hello")
+        .insert_header(("x-request-id", "proxy-42"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.headers().get("x-request-id").unwrap(), "proxy-42");
+}
+
+#[actix_web::test]
+async fn names_folders_by_meta_file_or_title_case() {
+    let site = TestSite::new([
+        (
+            "machine-learning/intro.md",
+            "---\ntitle: Intro\n---\n\nGradients.\n",
+        ),
+        ("apis-sdks/_meta.yml", "title: APIs & SDKs\n"),
+        ("apis-sdks/rest.md", "---\ntitle: REST\n---\n\nVerbs.\n"),
+    ])
+    .await
+    .unwrap();
+
+    let (status, body) = get(&site, "/machine-learning/intro").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Machine Learning"));
+
+    let (status, body) = get(&site, "/apis-sdks/rest").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("APIs &amp; SDKs") || body.contains("APIs & SDKs"));
+    assert!(!body.contains("apis sdks"));
+}
+
+#[actix_web::test]
+async fn renders_with_the_template_named_in_frontmatter() {
+    let mut site = TestSite::new([
+        (
+            "resume.md",
+            "---\ntitle: CV\ntemplate: resume\n---\n\nExperience.\n",
+        ),
+        (
+            "typo.md",
+            "---\ntitle: Typo\ntemplate: nonexistent\n---\n\nStill here.\n",
+        ),
+    ])
+    .await
+    .unwrap();
+    site.write_template(
+        "resume.hbs",
+        "<main class=\"resume\">{{article.title}}: {{{article.html}}}</main>",
+    )
+    .unwrap();
+
+    let (status, body) = get(&site, "/resume").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.starts_with("<main class=\"resume\">CV: "));
+    assert!(body.contains("Experience."));
+
+    let (status, body) = get(&site, "/typo").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("class=\"page\""));
+    assert!(body.contains("Still here."));
+}
+
+const SHOWCASE: &str = "---\ntitle: Showcase\ntags: [demo]\n---\n\n# Overview :rocket:\n\n\
+> [!note] Heads up\n> Callouts render.\n\n```mermaid\ngraph TD; A-->B\n```\n\n\
+See [[alpha]], [the beta note](../Beta Note.md) and [docs](https://example.com).\n\n\
+## Details\n\nMore text.\n";
+
+/// `render_markdown` of the showcase, checked against what `render_page` gives for the
+/// same note at `slug`.
+fn round_trip(site: &TestSite, slug: &str) -> RenderedFragment {
+    let page = site.engine.render_page(slug).unwrap();
+    let options = RenderOptions::for_site(&site.engine.config, slug);
+    let fragment = render_markdown(SHOWCASE, &options).unwrap();
+
+    assert_eq!(fragment.html, page.html);
+    assert_eq!(fragment.headings, page.headings);
+    assert_eq!(fragment.links, page.links);
+    assert_eq!(fragment.excerpt, page.excerpt);
+    assert_eq!(fragment.extra, page.extra);
+    assert_eq!(
+        serde_json::to_value(&fragment.frontmatter).unwrap(),
+        serde_json::to_value(&page.frontmatter).unwrap()
+    );
+    fragment
+}
+
+#[actix_web::test]
+async fn render_markdown_matches_render_page() {
+    let site = TestSite::new([("notes/showcase.md", SHOWCASE)])
+        .await
+        .unwrap();
+    let fragment = round_trip(&site, "notes/showcase");
+    assert!(fragment.html.contains("callout"));
+    assert!(fragment.html.contains("🚀"));
+    assert!(fragment.html.contains("mermaid"));
+    assert!(fragment.links.iter().any(|link| link.target == "Beta Note"));
+}
+
+#[actix_web::test]
+async fn render_markdown_follows_the_site_slug_style() {
+    let mut config = SiteConfig::default();
+    config.content.slugify = SlugMode::LowerDash;
+    let site = TestSite::with_config([("notes/showcase.md", SHOWCASE)], config)
+        .await
+        .unwrap();
+    let fragment = round_trip(&site, "notes/showcase");
+    assert!(fragment.links.iter().any(|link| link.target == "beta-note"));
+}
+
+const DATED: &str = "---\ntitle: Dated\ncreated: 2024-01-02T00:00:00Z\n\
+published: 2024-03-04T00:00:00Z\n---\n\nSome text.\n";
+
+fn at(date: &str) -> DateTime<Utc> {
+    date.parse().unwrap()
+}
+
+async fn dated_site(date_type: DefaultDateType, dates_from_git: bool) -> TestSite {
+    let mut config = SiteConfig::default();
+    config.configuration.default_date_type = date_type;
+    config.configuration.dates_from_git = dates_from_git;
+    TestSite::with_config([("dated.md", DATED)], config)
+        .await
+        .unwrap()
+}
+
+/// The date `dated` is shown and sorted by, after a build has read the git history.
+fn shown_date(site: &TestSite) -> Option<DateTime<Utc>> {
+    site.engine.prebuild_all().unwrap();
+    let page = site.engine.render_page("dated").unwrap();
+    site.engine.page_date("dated", &page.frontmatter)
+}
+
+/// Run git in `dir` as a fixed author, with commits dated `date`.
+fn git(dir: &Path, date: &str, args: &[&str]) {
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(["-c", "commit.gpgsign=false"])
+        .args(args)
+        .env("GIT_AUTHOR_DATE", date)
+        .env("GIT_COMMITTER_DATE", date)
+        .status()
+        .unwrap();
+    assert!(status.success(), "git {args:?}");
+}
+
+#[actix_web::test]
+async fn created_and_published_dates_come_from_frontmatter() {
+    let site = dated_site(DefaultDateType::Created, false).await;
+    assert_eq!(shown_date(&site), Some(at("2024-01-02T00:00:00Z")));
+    drop(site);
+
+    let site = dated_site(DefaultDateType::Published, false).await;
+    assert_eq!(shown_date(&site), Some(at("2024-03-04T00:00:00Z")));
+}
+
+#[actix_web::test]
+async fn modified_dates_come_from_the_file() {
+    let site = dated_site(DefaultDateType::Modified, false).await;
+    let modified = at("2024-05-06T00:00:00Z");
+    fs::File::options()
+        .write(true)
+        .open(site.content_root().join("dated.md"))
+        .unwrap()
+        .set_modified(SystemTime::from(modified))
+        .unwrap();
+    assert_eq!(shown_date(&site), Some(modified));
+}
+
+#[actix_web::test]
+async fn modified_dates_come_from_git_with_dates_from_git() {
+    let site = dated_site(DefaultDateType::Modified, true).await;
+    let committed = "2024-07-08T00:00:00Z";
+    git(site.content_root(), committed, &["init", "-q"]);
+    git(site.content_root(), committed, &["add", "-A"]);
+    git(
+        site.content_root(),
+        committed,
+        &["commit", "-q", "-m", "Add dated"],
+    );
+    assert_eq!(shown_date(&site), Some(at(committed)));
+}
+
+#[actix_web::test]
+async fn a_rotated_password_is_not_served_from_the_cache() {
+    let secrets = tempfile::tempdir().unwrap();
+    let secrets_file = secrets.path().join("secrets.yml");
+    fs::write(&secrets_file, "diary: first\n").unwrap();
+    let mut config = SiteConfig::default();
+    config.paths.secrets_file = Some(secrets_file.display().to_string());
+    let site = TestSite::with_config(
+        [(
+            "diary.md",
+            "---\ntitle: Diary\npassword_ref: diary\n---\n\nDear diary.\n",
+        )],
+        config,
+    )
+    .await
+    .unwrap();
+
+    let first = site.engine.render_page("diary").unwrap();
+    assert!(!first.html.contains("Dear diary."));
+    let again = site.engine.render_page("diary").unwrap();
+    assert_eq!(again.html, first.html);
+
+    fs::write(&secrets_file, "diary: second\n").unwrap();
+    let rotated = site.engine.render_page("diary").unwrap();
+    assert_eq!(rotated.cached, Some(false));
+    assert_ne!(rotated.html, first.html);
+}
+
+/// Where each of `needles` first appears in `haystack`, in order.
+fn positions(haystack: &str, needles: &[&str]) -> Vec<usize> {
+    needles
+        .iter()
+        .map(|needle| {
+            haystack
+                .find(needle)
+                .unwrap_or_else(|| panic!("no {needle}"))
+        })
+        .collect()
+}
+
+#[actix_web::test]
+async fn footer_links_keep_their_yaml_order() {
+    let ordered = [
+        "href=\"/about\"",
+        "href=\"/now\"",
+        "href=\"/contact\"",
+        "href=\"/index.xml\"",
+    ];
+    for yaml in [
+        "About: /about\nNow: /now\nContact: /contact\nRSS: /index.xml\n",
+        "- { text: About, href: /about }\n- { text: Now, href: /now }\n\
+         - { text: Contact, href: /contact }\n- { text: RSS, href: /index.xml }\n",
+    ] {
+        let mut config = SiteConfig::default();
+        config.layout.footer.links = serde_yaml::from_str(yaml).unwrap();
+        let site = TestSite::with_config([("hello.md", HELLO)], config)
+            .await
+            .unwrap();
+
+        let (status, body) = get(&site, "/hello").await;
+        assert_eq!(status, StatusCode::OK);
+        let footer = &body[body.find("<footer").expect("a footer")..];
+        let found = positions(footer, &ordered);
+        assert!(found.is_sorted(), "{yaml}: {found:?}");
+    }
+}
+
+/// The bodies of GETs for `paths` against one instance of the site's app, each of which
+/// must succeed.
+async fn get_all(site: &TestSite, paths: &[&str]) -> Vec<String> {
+    let app = test::init_service(site.app()).await;
+    let mut bodies = Vec::new();
+    for path in paths {
+        let res = test::call_service(&app, test::TestRequest::get().uri(path).to_request()).await;
+        assert!(res.status().is_success(), "{path}: {}", res.status());
+        let body = test::read_body(res).await;
+        bodies.push(String::from_utf8_lossy(&body).into_owned());
+    }
+    bodies
+}
+
+/// What the site publishes about its pages as JSON, besides search results.
+const PUBLISHED_JSON: &[&str] = &[
+    "/static/content-index.json",
+    "/static/graph.json",
+    "/api/explorer",
+    "/api/orphans",
+];
+
+/// The hits `/api/search` answers for `query`.
+async fn search(site: &TestSite, query: &str) -> Vec<serde_json::Value> {
+    let body = get_all(site, &[&format!("/api/search?q={query}")]).await;
+    let response: serde_json::Value = serde_json::from_str(&body[0]).unwrap();
+    response["results"].as_array().unwrap().clone()
+}
+
+#[actix_web::test]
+async fn drafts_appear_nowhere_in_the_published_json() {
+    let site = TestSite::new([
+        ("hello.md", HELLO),
+        (
+            "roadmap.md",
+            "---\ntitle: Secret Roadmap\ndraft: true\n---\n\nLaunch plans. Back to [[hello]].\n",
+        ),
+    ])
+    .await
+    .unwrap();
+
+    let bodies = get_all(&site, PUBLISHED_JSON).await;
+    assert!(bodies[0].contains("\"hello\""), "{}", bodies[0]);
+    for (path, body) in PUBLISHED_JSON.iter().zip(&bodies) {
+        assert!(!body.to_lowercase().contains("roadmap"), "{path}: {body}");
+    }
+    assert!(search(&site, "Launch").await.is_empty());
+    assert!(search(&site, "Roadmap").await.is_empty());
+
+    let (_, hello) = get(&site, "/hello").await;
+    assert!(!hello.to_lowercase().contains("roadmap"));
+}
+
+/// The backlinks section of the page at `path`, empty when it has none.
+async fn backlinks(site: &TestSite, path: &str) -> String {
+    let (status, body) = get(site, path).await;
+    assert_eq!(status, StatusCode::OK, "{path}");
+    body.split_once("class=\"backlinks\"")
+        .map(|(_, rest)| rest.split("</ul>").next().unwrap_or(rest).to_string())
+        .unwrap_or_default()
+}
+
+#[actix_web::test]
+async fn relative_links_and_wikilinks_resolve_against_the_source_page() {
+    let site = TestSite::new([
+        (
+            "projects/alpha/notes.md",
+            "---\ntitle: Alpha Notes\n---\n\nSee [the design](./design.md), \
+             [the roadmap](../roadmap.md), [the API](specs/api.md), [[glossary]] \
+             and [the top](#top).\n",
+        ),
+        (
+            "projects/alpha/design.md",
+            "---\ntitle: Design\n---\n\nBoxes.\n",
+        ),
+        (
+            "projects/alpha/specs/api.md",
+            "---\ntitle: API\n---\n\nCalls.\n",
+        ),
+        (
+            "projects/roadmap.md",
+            "---\ntitle: Roadmap\n---\n\nLater.\n",
+        ),
+        ("design.md", "---\ntitle: Root Design\n---\n\nElsewhere.\n"),
+        (
+            "reference/glossary.md",
+            "---\ntitle: Glossary\n---\n\nWords.\n",
+        ),
+        (
+            "archive/old/glossary.md",
+            "---\ntitle: Old Glossary\n---\n\nOld words.\n",
+        ),
+    ])
+    .await
+    .unwrap();
+    let expected = [
+        "projects/alpha/design",
+        "projects/alpha/specs/api",
+        "projects/roadmap",
+        "reference/glossary",
+    ];
+
+    let bodies = get_all(&site, &["/static/content-index.json", "/static/graph.json"]).await;
+    let index: serde_json::Value = serde_json::from_str(&bodies[0]).unwrap();
+    let links: Vec<&str> = index["projects/alpha/notes"]["links"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|link| link.as_str().unwrap())
+        .collect();
+    assert_eq!(links, expected);
+
+    let graph: serde_json::Value = serde_json::from_str(&bodies[1]).unwrap();
+    let mut edges: Vec<&str> = graph["links"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|link| link["source"] == "projects/alpha/notes")
+        .map(|link| link["target"].as_str().unwrap())
+        .collect();
+    edges.sort();
+    assert_eq!(edges, expected);
+
+    for target in expected {
+        let section = backlinks(&site, &format!("/{target}")).await;
+        assert!(section.contains("Alpha Notes"), "{target}: {section}");
+    }
+    for unlinked in ["/design", "/archive/old/glossary"] {
+        assert!(!backlinks(&site, unlinked).await.contains("Alpha Notes"));
+    }
+}
+
+/// The `href` of every link in `html` marked as the current page.
+fn current_page_links(html: &str) -> Vec<&str> {
+    html.match_indices("aria-current=\"page\"")
+        .filter_map(|(at, _)| {
+            let tag = &html[html[..at].rfind('<')?..at];
+            if !tag.starts_with("<a") {
+                return None;
+            }
+            let (_, href) = tag.split_once("href=\"")?;
+            href.split('"').next()
+        })
+        .collect()
+}
+
+#[actix_web::test]
+async fn top_level_pages_are_nav_leaves_and_highlighted() {
+    let site = TestSite::new([
+        ("index.md", "---\ntitle: Home\n---\n\nWelcome.\n"),
+        ("about.md", "---\ntitle: About Me\n---\n\nWho I am.\n"),
+        ("now.md", "---\ntitle: Now\n---\n\nWhat I do.\n"),
+        (
+            "projects/alpha.md",
+            "---\ntitle: Alpha\n---\n\nA project.\n",
+        ),
+    ])
+    .await
+    .unwrap();
+
+    let body = get_all(&site, &["/api/explorer"]).await;
+    let tree: Vec<serde_json::Value> = serde_json::from_str(&body[0]).unwrap();
+    let entry = |slug: &str| {
+        tree.iter()
+            .find(|entry| entry["slug"] == slug)
+            .unwrap_or_else(|| panic!("no top-level {slug} in {tree:?}"))
+    };
+    for (slug, title) in [("about", "About Me"), ("now", "Now")] {
+        assert_eq!(entry(slug)["name"], title);
+        assert_eq!(entry(slug)["isFolder"], false);
+        assert!(entry(slug).get("children").is_none());
+    }
+    assert_eq!(entry("projects")["isFolder"], true);
+    assert_eq!(entry("projects")["children"][0]["slug"], "projects/alpha");
+
+    for path in ["/about", "/projects/alpha"] {
+        let (_, html) = get(&site, path).await;
+        let current = current_page_links(&html);
+        assert!(!current.is_empty(), "{path}");
+        assert!(
+            current.iter().all(|href| href.ends_with(path)),
+            "{current:?}"
+        );
+    }
+}
+
+const DIARY: &str = "---\ntitle: Diary\ndescription: Where we meet\ntags: [secrets]\n\
+                     password: hunter2\n---\n\n\
+                     Meet me by the old mill at midnight. Back to [[hello]].\n";
+
+/// A site with `DIARY` encrypted beside `HELLO`, its scripts and styles served as files so
+/// pages hold only their own markup. Encrypted notes are listed as "Locked note", and
+/// pages list recent notes besides their neighbours.
+async fn diary_site() -> TestSite {
+    let mut config = SiteConfig::default();
+    config.bundler.output = BundleOutput::External;
+    config.configuration.theme.css_delivery = CssDelivery::External;
+    config.encryption.placeholder_title = Some("Locked note".into());
+    let mut layout = default_content_page_layout();
+    layout
+        .after_body
+        .push(LayoutComponent::RecentNotes(RecentNotesConfig::default()));
+    config.layout.content = Some(layout);
+    TestSite::with_config([("hello.md", HELLO), ("diary.md", DIARY)], config)
+        .await
+        .unwrap()
+}
+
+/// `html` without the decrypt form of an encrypted note, which asks for a password by
+/// name.
+fn without_decrypt_form(html: &str) -> String {
+    let Some(start) = html.find("<div class=\"encrypted-note__chrome\">") else {
+        return html.to_string();
+    };
+    let end = html[start..]
+        .find("<div class=\"encrypted-note__decode\"")
+        .map_or(html.len(), |at| start + at);
+    format!("{}{}", &html[..start], &html[end..])
+}
+
+#[actix_web::test]
+async fn passwords_never_reach_pages_or_published_json() {
+    let site = diary_site().await;
+
+    let page = site.engine.render_page("diary").unwrap();
+    assert_eq!(page.frontmatter.password, None);
+    let frontmatter = serde_json::to_string(&page.frontmatter).unwrap();
+    assert!(!frontmatter.contains("password"), "{frontmatter}");
+
+    let mut paths = vec!["/diary", "/hello"];
+    paths.extend(PUBLISHED_JSON);
+    for (path, body) in paths.iter().zip(get_all(&site, &paths).await) {
+        assert!(!body.contains("hunter2"), "{path}");
+        let body = without_decrypt_form(&body).to_lowercase();
+        assert!(!body.contains("password"), "{path}: {body}");
+    }
+    for hit in search(&site, "Diary").await {
+        assert!(
+            !hit.to_string().to_lowercase().contains("password"),
+            "{hit}"
+        );
+    }
+}
+
+#[actix_web::test]
+async fn encrypted_bodies_stay_out_of_the_index_graph_and_search() {
+    let site = diary_site().await;
+
+    let mut paths = vec!["/diary"];
+    paths.extend(PUBLISHED_JSON);
+    let bodies = get_all(&site, &paths).await;
+    for (path, body) in paths.iter().zip(&bodies) {
+        assert!(!body.contains("old mill"), "{path}: {body}");
+        assert!(!body.contains("midnight"), "{path}: {body}");
+    }
+
+    let index: serde_json::Value = serde_json::from_str(&bodies[1]).unwrap();
+    assert!(index["diary"].is_object(), "{index}");
+    assert!(
+        index["diary"]
+            .get("links")
+            .is_none_or(|links| links == &serde_json::json!([]))
+    );
+    let graph: serde_json::Value = serde_json::from_str(&bodies[2]).unwrap();
+    let edges = graph["links"].as_array().unwrap();
+    assert!(
+        edges.iter().all(|edge| edge["source"] != "diary"),
+        "{graph}"
+    );
+
+    assert!(search(&site, "midnight").await.is_empty());
+    assert!(!backlinks(&site, "/hello").await.contains("Diary"));
+
+    // Hello lists the diary as its neighbour, in recent notes and in the explorer, by the
+    // placeholder alone; no tag page lists it.
+    let paths = ["/hello", "/api/explorer", "/tags/secrets"];
+    let bodies = get_all(&site, &paths).await;
+    for (path, body) in paths.iter().zip(&bodies) {
+        assert!(!body.contains("Diary"), "{path}: {body}");
+        assert!(!body.contains("Where we meet"), "{path}: {body}");
+    }
+    assert!(bodies[0].contains("Locked note"), "{}", bodies[0]);
+    assert!(bodies[1].contains("Locked note"), "{}", bodies[1]);
+    assert!(!bodies[2].contains("/diary"), "{}", bodies[2]);
+}
+
+#[actix_web::test]
+async fn a_missing_or_empty_page_answers_404() {
+    let site = TestSite::new([("hello.md", HELLO), ("empty.md", "  \n")])
+        .await
+        .unwrap();
+
+    for slug in ["nowhere", "empty"] {
+        let err = site.engine.render_page(slug).unwrap_err();
+        assert!(matches!(err, RenderError::NotFound(_)), "{slug}: {err}");
+        assert_eq!(
+            get(&site, &format!("/{slug}")).await.0,
+            StatusCode::NOT_FOUND
+        );
+    }
+}
+
+#[actix_web::test]
+async fn a_filtered_page_answers_404() {
+    let site = TestSite::new([("secret.md", "---\ndraft: true\n---\n\nNot yet.\n")])
+        .await
+        .unwrap();
+
+    let err = site.engine.render_page("secret").unwrap_err();
+    assert!(
+        matches!(&err, RenderError::Filtered { reason } if reason.contains("draft")),
+        "{err}"
+    );
+    assert_eq!(get(&site, "/secret").await.0, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn an_ignored_page_answers_404() {
+    let site = TestSite::new([("private/plans.md", "---\ntitle: Plans\n---\n\nHidden.\n")])
+        .await
+        .unwrap();
+
+    let err = site.engine.render_page("private/plans").unwrap_err();
+    assert!(matches!(err, RenderError::Ignored), "{err}");
+    assert_eq!(get(&site, "/private/plans").await.0, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn an_unreadable_page_answers_500() {
+    let site = TestSite::new([("hello.md", HELLO)]).await.unwrap();
+    fs::write(site.content_root().join("broken.md"), [0xff, 0xfe, 0x00]).unwrap();
+
+    let err = site.engine.render_page("broken").unwrap_err();
+    assert!(matches!(err, RenderError::Io(_)), "{err}");
+    assert_eq!(
+        get(&site, "/broken").await.0,
+        StatusCode::INTERNAL_SERVER_ERROR
+    );
+}
+
+#[actix_web::test]
+async fn a_failing_plugin_answers_500() {
+    let site = TestSite::new([(
+        "locked.md",
+        "---\ntitle: Locked\npassword_env: TRELLIS_TEST_UNSET_PASSWORD\n---\n\nShut.\n",
+    )])
+    .await
+    .unwrap();
+
+    let err = site.engine.render_page("locked").unwrap_err();
+    assert!(
+        matches!(
+            err,
+            RenderError::Transform {
+                plugin: "ResolveSecrets",
+                ..
+            }
+        ),
+        "{err}"
+    );
+    assert_eq!(
+        get(&site, "/locked").await.0,
+        StatusCode::INTERNAL_SERVER_ERROR
+    );
+}
+
+#[actix_web::test]
+async fn a_failing_template_answers_500() {
+    let mut site = TestSite::new([(
+        "resume.md",
+        "---\ntitle: CV\ntemplate: resume\n---\n\nExperience.\n",
+    )])
+    .await
+    .unwrap();
+    site.write_template(
+        "resume.hbs",
+        "<main>{{> components/no-such-partial}}</main>",
+    )
+    .unwrap();
+
+    assert!(site.engine.render_page("resume").is_ok());
+    let (status, body) = get(&site, "/resume").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(body.contains("template error"), "{body}");
+}
+
+/// A JSON POST of `body` to `path` from `peer`, whose `X-Forwarded-For` claims it came
+/// from `forwarded_for`.
+fn post_from(
+    path: &str,
+    peer: &str,
+    forwarded_for: &str,
+    body: &serde_json::Value,
+) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri(path)
+        .peer_addr(peer.parse().unwrap())
+        .insert_header(("x-forwarded-for", forwarded_for))
+        .set_json(body)
+}
+
+#[actix_web::test]
+async fn a_forged_forwarded_for_does_not_reset_the_comment_limit() {
+    let mut config = SiteConfig::default();
+    config.native_comments.per_hour = 2;
+    let guestbook = "---\ntitle: Guestbook\ncomments: true\n---\n\nSay hello.\n";
+    let site = TestSite::with_config([("guestbook.md", guestbook)], config)
+        .await
+        .unwrap();
+    let app = test::init_service(site.app()).await;
+
+    let comment = serde_json::json!({ "name": "Ann", "body": "Hello!" });
+    let mut statuses = Vec::new();
+    for forged in ["198.51.100.1", "198.51.100.2", "198.51.100.3"] {
+        let req = post_from(
+            "/comments/guestbook",
+            "203.0.113.10:50000",
+            forged,
+            &comment,
+        );
+        statuses.push(test::call_service(&app, req.to_request()).await.status());
+    }
+    assert!(statuses[..2].iter().all(|s| s.is_success()), "{statuses:?}");
+    assert_eq!(statuses[2], StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[actix_web::test]
+async fn a_forged_forwarded_for_does_not_reset_the_subscribe_limit() {
+    let mut config = SiteConfig::default();
+    config.newsletter.enabled = true;
+    config.newsletter.per_hour = 1;
+    config.server.trusted_proxies = vec!["127.0.0.1".into()];
+    let site = TestSite::with_config([("hello.md", HELLO)], config)
+        .await
+        .unwrap();
+    let app = test::init_service(site.app()).await;
+
+    let signup = |n: u32| serde_json::json!({ "email": format!("reader{n}@example.com") });
+    let direct = [
+        post_from(
+            "/subscribe",
+            "203.0.113.11:50000",
+            "198.51.100.1",
+            &signup(1),
+        ),
+        post_from(
+            "/subscribe",
+            "203.0.113.11:50001",
+            "198.51.100.2",
+            &signup(2),
+        ),
+    ];
+    let mut statuses = Vec::new();
+    for req in direct {
+        statuses.push(test::call_service(&app, req.to_request()).await.status());
+    }
+    assert_eq!(statuses, [StatusCode::OK, StatusCode::TOO_MANY_REQUESTS]);
+
+    // Through the trusted proxy each forwarded client has a limit of its own.
+    let proxied = [
+        post_from("/subscribe", "127.0.0.1:40000", "198.51.100.20", &signup(3)),
+        post_from("/subscribe", "127.0.0.1:40000", "198.51.100.21", &signup(4)),
+        post_from("/subscribe", "127.0.0.1:40000", "198.51.100.21", &signup(5)),
+    ];
+    let mut statuses = Vec::new();
+    for req in proxied {
+        statuses.push(test::call_service(&app, req.to_request()).await.status());
+    }
+    assert_eq!(
+        statuses,
+        [
+            StatusCode::OK,
+            StatusCode::OK,
+            StatusCode::TOO_MANY_REQUESTS
+        ]
+    );
+}
+
+#[actix_web::test]
+async fn confirmation_mail_never_links_to_the_request_host() {
+    let mut config = SiteConfig::default();
+    config.newsletter.enabled = true;
+    config.newsletter.sendmail_command = Some("false".into());
+    let issues = trellis::trellis::validation::validate(&config);
+    assert!(
+        issues
+            .iter()
+            .any(|issue| issue.is_error() && issue.path == "configuration.base_url"),
+        "{issues:?}"
+    );
+
+    let site = TestSite::with_config([("hello.md", HELLO)], config)
+        .await
+        .unwrap();
+    let app = test::init_service(site.app()).await;
+    let req = test::TestRequest::post()
+        .uri("/subscribe")
+        .peer_addr("203.0.113.12:50000".parse().unwrap())
+        .insert_header(("host", "evil.example"))
+        .set_json(serde_json::json!({ "email": "victim@example.com" }))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}